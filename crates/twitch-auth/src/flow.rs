@@ -0,0 +1,212 @@
+//! OAuth authorization code flow: the authorization URL and the server receiving the
+//! callback Twitch redirects the user to.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use twitch_oauth2::client::Client;
+use twitch_oauth2::{Scope, UserToken, UserTokenBuilder};
+use url::Url;
+use warp::{serve, Filter, Reply};
+
+use crate::{Credentials, Error};
+
+/// The redirect URL of the application should point to this address
+pub const DEFAULT_CALLBACK_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 3000));
+
+/// Query of the callback, either the state and code or the error
+pub type Callback = HashMap<String, String>;
+
+type Sender = mpsc::Sender<Callback>;
+type Receiver = mpsc::Receiver<Callback>;
+
+/// Authorization the user has been asked for, completed with the callback
+pub struct Authorization {
+    builder: UserTokenBuilder,
+    url: Url,
+}
+
+impl Authorization {
+    /// `force_verify` makes Twitch ask the user even if they authorized the application before
+    #[must_use]
+    pub fn new(
+        credentials: &Credentials,
+        scopes: &[Scope],
+        force_verify: bool,
+        redirect_url: Url,
+    ) -> Self {
+        let mut builder = UserTokenBuilder::new(
+            credentials.client_id.clone(),
+            credentials.client_secret.clone(),
+            redirect_url,
+        )
+        .set_scopes(scopes.to_vec())
+        .force_verify(force_verify);
+        let (url, _) = builder.generate_url();
+
+        Authorization { builder, url }
+    }
+
+    /// URL the user authorizes the application on
+    #[must_use]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Exchange the code of the callback for the token
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the callback does not belong to this authorization, the user
+    /// denied it or Twitch refuses to issue the token
+    pub async fn complete<C: Client>(
+        self,
+        client: &C,
+        callback: &Callback,
+    ) -> Result<UserToken, Error> {
+        self.verify_csrf_token(callback)?;
+
+        let (state, code) = extract_code(callback)?;
+
+        self.builder
+            .get_user_token(client, state, code)
+            .await
+            .map_err(Error::twitch)
+    }
+
+    fn verify_csrf_token(&self, callback: &Callback) -> Result<(), Error> {
+        match callback.get("state") {
+            Some(csrf) if self.builder.csrf_is_valid(csrf) => Ok(()),
+            Some(_) => Err(Error::CsrfTokenMismatch),
+            None => Err(Error::NoCsrfToken),
+        }
+    }
+}
+
+pub fn callback_channel() -> (Sender, Receiver) {
+    mpsc::channel(1)
+}
+
+/// Serve `auth/twitch/callback` on `addr` until the first callback is passed to `tx`
+pub async fn run_callback_server(addr: SocketAddr, tx: Sender) {
+    let cancel = CancellationToken::new();
+    let callback = warp::path!("auth" / "twitch" / "callback")
+        .and(warp::query::<Callback>())
+        .and(with_sender(tx))
+        .and(with_stop_channel(cancel.clone()))
+        .and_then(callback_handler);
+    let (_, server) = serve(callback).bind_with_graceful_shutdown(addr, async move {
+        cancel.cancelled().await;
+    });
+
+    server.await;
+    tracing::info!("Finish auth server");
+}
+
+/// Extract the state and code from the URL a user was redirected to after authorizing the application.
+fn extract_code(callback: &Callback) -> Result<(&str, &str), Error> {
+    if let Some(error) = callback.get("error") {
+        let reason = callback.get("error_description").unwrap_or(error);
+
+        Err(Error::Denied(reason.clone()))
+    } else if let (Some(state), Some(code)) = (callback.get("state"), callback.get("code")) {
+        Ok((state, code))
+    } else {
+        Err(Error::NoCode)
+    }
+}
+
+async fn callback_handler(
+    query: Callback,
+    sender: Sender,
+    cancellation_token: CancellationToken,
+) -> Result<impl Reply, Infallible> {
+    if let Err(e) = sender.send(query).await {
+        return Ok(warp::reply::with_status(
+            e.to_string(),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    cancellation_token.cancel();
+    Ok(warp::reply::with_status(
+        "Success".to_string(),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+fn with_sender(sender: Sender) -> impl Filter<Extract = (Sender,), Error = Infallible> + Clone {
+    warp::any().map(move || sender.clone())
+}
+
+fn with_stop_channel(
+    cancellation_token: CancellationToken,
+) -> impl Filter<Extract = (CancellationToken,), Error = Infallible> + Clone {
+    warp::any().map(move || cancellation_token.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorization_response_is_parsed() {
+        let callback = HashMap::from([
+            (String::from("state"), String::from("csrf")),
+            (String::from("code"), String::from("auth-code")),
+        ]);
+
+        assert_eq!(extract_code(&callback).unwrap(), ("csrf", "auth-code"));
+        assert!(matches!(extract_code(&HashMap::new()), Err(Error::NoCode)));
+    }
+
+    #[test]
+    fn denied_authorization_keeps_the_reason() {
+        let callback = HashMap::from([
+            (String::from("error"), String::from("access_denied")),
+            (
+                String::from("error_description"),
+                String::from("The user denied you access"),
+            ),
+        ]);
+
+        assert!(matches!(
+            extract_code(&callback),
+            Err(Error::Denied(reason)) if reason == "The user denied you access"
+        ));
+    }
+
+    #[test]
+    fn callback_of_another_authorization_is_rejected() {
+        let authorization = Authorization::new(
+            &Credentials::new("client-id", "client-secret"),
+            &[Scope::ChatRead],
+            false,
+            Url::parse("http://localhost:3000/auth/twitch/callback").unwrap(),
+        );
+        let state = authorization
+            .url()
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+
+        assert!(authorization
+            .verify_csrf_token(&HashMap::from([(String::from("state"), state)]))
+            .is_ok());
+        assert!(matches!(
+            authorization.verify_csrf_token(&HashMap::from([(
+                String::from("state"),
+                String::from("forged")
+            )])),
+            Err(Error::CsrfTokenMismatch)
+        ));
+        assert!(matches!(
+            authorization.verify_csrf_token(&HashMap::new()),
+            Err(Error::NoCsrfToken)
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! Twitch user tokens for bots and tools: the OAuth authorization code flow, token
+//! storage, validation and refresh.
+//!
+//! [`TokenManager`] ties them together: it loads the saved token from a [`TokenStore`],
+//! validates or refreshes it, and asks the user to authorize the application through
+//! [`Authorization`] when nothing is saved yet.
+//!
+//! ```no_run
+//! use twitch_auth::{Credentials, FileStore, Scope, TokenManager};
+//!
+//! # async fn run(client: impl twitch_auth::Client) -> Result<(), twitch_auth::Error> {
+//! let tokens = TokenManager::new(
+//!     Credentials::new("client-id", "client-secret"),
+//!     FileStore::new("token.json"),
+//! );
+//! let redirect_url = "http://localhost:3000/auth/twitch/callback".parse().unwrap();
+//! let token = tokens
+//!     .load_or_authorize(&client, &[Scope::ChatRead], redirect_url)
+//!     .await?;
+//!
+//! println!("Authorized as {}", token.login);
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt::Formatter;
+use std::io;
+
+pub use crate::flow::{
+    callback_channel, run_callback_server, Authorization, Callback, DEFAULT_CALLBACK_ADDR,
+};
+pub use crate::manager::{AutoRefresh, Credentials, TokenManager};
+pub use crate::store::{FileStore, MemoryStore, TokenStore};
+pub use crate::token::Token;
+pub use twitch_oauth2::client::Client;
+pub use twitch_oauth2::{Scope, UserToken};
+
+mod flow;
+mod manager;
+mod store;
+mod token;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The token cannot be loaded or saved
+    Io(io::Error),
+    /// The callback has no CSRF token
+    NoCsrfToken,
+    /// The CSRF token of the callback does not match one of the authorization URL
+    CsrfTokenMismatch,
+    /// The user has not authorized the application
+    Denied(String),
+    /// The callback has neither the code nor the error
+    NoCode,
+    /// The callback server stopped without a callback
+    NoCallback,
+    /// Twitch refused to issue, validate or refresh the token
+    Twitch(BoxError),
+}
+
+impl Error {
+    fn twitch(error: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Self::Twitch(Box::new(error))
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Unable to access the token: {e}"),
+            Self::NoCsrfToken => write!(f, "No CSRF token in the response"),
+            Self::CsrfTokenMismatch => write!(
+                f,
+                "CSRF token in the response does not match one from the request"
+            ),
+            Self::Denied(reason) => write!(f, "The authorization was denied: {reason}"),
+            Self::NoCode => write!(f, "No authorization code in the response"),
+            Self::NoCallback => write!(f, "The callback server stopped without a response"),
+            Self::Twitch(e) => write!(f, "Twitch refused the token: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Twitch(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
@@ -0,0 +1,400 @@
+//! Token of one account: loaded, validated, refreshed and saved to its store.
+use core::time::Duration;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use twitch_oauth2::client::Client;
+use twitch_oauth2::{ClientId, ClientSecret, Scope, TwitchToken, UserToken};
+use url::Url;
+
+use crate::flow::{callback_channel, run_callback_server, DEFAULT_CALLBACK_ADDR};
+use crate::{Authorization, Error, Token, TokenStore};
+
+/// The token is refreshed this long before it expires
+const REFRESH_MARGIN: Duration = Duration::from_secs(300);
+/// Delay before another attempt of a failed refresh
+const REFRESH_RETRY: Duration = Duration::from_secs(30);
+
+/// Client ID and secret of the Twitch application
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub client_id: ClientId,
+    pub client_secret: ClientSecret,
+}
+
+impl Credentials {
+    pub fn new(client_id: impl Into<ClientId>, client_secret: impl Into<ClientSecret>) -> Self {
+        Credentials {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+        }
+    }
+}
+
+pub struct TokenManager<S> {
+    credentials: Credentials,
+    store: S,
+    callback_addr: SocketAddr,
+}
+
+impl<S: TokenStore> TokenManager<S> {
+    pub fn new(credentials: Credentials, store: S) -> Self {
+        TokenManager {
+            credentials,
+            store,
+            callback_addr: DEFAULT_CALLBACK_ADDR,
+        }
+    }
+
+    /// Address the callback server of [`TokenManager::authorize`] listens on
+    #[must_use]
+    pub fn with_callback_addr(mut self, callback_addr: SocketAddr) -> Self {
+        self.callback_addr = callback_addr;
+        self
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Ask the user to authorize the application, the token is not saved
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the user denies the authorization or Twitch refuses to issue the token
+    pub async fn authorize<C: Client>(
+        &self,
+        client: &C,
+        scopes: &[Scope],
+        force_verify: bool,
+        redirect_url: Url,
+    ) -> Result<UserToken, Error> {
+        let authorization =
+            Authorization::new(&self.credentials, scopes, force_verify, redirect_url);
+        let (tx, mut rx) = callback_channel();
+        let callback_server = tokio::spawn(run_callback_server(self.callback_addr, tx));
+
+        println!(
+            "Visit this URL to authorize Twitch access: {}",
+            authorization.url()
+        );
+
+        let Some(callback) = rx.recv().await else {
+            return Err(Error::NoCallback);
+        };
+        let _ = callback_server.await;
+
+        authorization.complete(client, &callback).await
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the token cannot be saved
+    pub fn save(&self, token: &UserToken) -> io::Result<()> {
+        self.store.save(&Token::from(token))
+    }
+
+    /// Saved token validated, or refreshed and saved again if it is expired
+    ///
+    /// Returns `None` if nothing is saved yet
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the token cannot be loaded or Twitch refuses to validate it
+    pub async fn load<C: Client>(&self, client: &C) -> Result<Option<UserToken>, Error> {
+        let Some(token) = self.store.load()? else {
+            return Ok(None);
+        };
+        let (user_token, refreshed) = token.validate(client, &self.credentials).await?;
+
+        if refreshed {
+            self.save(&user_token)?;
+        }
+
+        Ok(Some(user_token))
+    }
+
+    /// Saved token, or a new one the user is asked to authorize if nothing is saved,
+    /// the saved token cannot be read or lacks one of the `scopes`
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the token cannot be validated, authorized or saved
+    pub async fn load_or_authorize<C: Client>(
+        &self,
+        client: &C,
+        scopes: &[Scope],
+        redirect_url: Url,
+    ) -> Result<UserToken, Error> {
+        match self.load(client).await {
+            Ok(Some(user_token)) => {
+                let missing = missing_scopes(&user_token, scopes);
+
+                if missing.is_empty() {
+                    return Ok(user_token);
+                }
+
+                tracing::warn!(
+                    "The saved token of {} lacks {}, authorizing again",
+                    user_token.login,
+                    missing.join(", ")
+                );
+            }
+            Ok(None) => {}
+            Err(Error::Io(e)) => tracing::warn!("Unable to read the saved token: {e}"),
+            Err(e) => return Err(e),
+        }
+
+        let user_token = self.authorize(client, scopes, false, redirect_url).await?;
+        self.save(&user_token)?;
+
+        Ok(user_token)
+    }
+
+    /// Refresh the token in place and save it
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if Twitch refuses to refresh the token or it cannot be saved
+    pub async fn refresh<C: Client>(&self, client: &C, token: &mut UserToken) -> Result<(), Error> {
+        token.refresh_token(client).await.map_err(Error::twitch)?;
+        self.save(token)?;
+
+        Ok(())
+    }
+
+    /// Keep refreshing the token shortly before it expires until the returned handle is dropped
+    pub fn spawn_auto_refresh<C>(self: Arc<Self>, client: C, mut token: UserToken) -> AutoRefresh
+    where
+        S: 'static,
+        C: Client + 'static,
+    {
+        let (tx, rx) = watch::channel(token.clone());
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(token.expires_in().saturating_sub(REFRESH_MARGIN)).await;
+
+                match self.refresh(&client, &mut token).await {
+                    Ok(()) => {
+                        tx.send_replace(token.clone());
+                    }
+                    Err(e) => {
+                        tracing::warn!("Unable to refresh the token of {}: {e}", token.login);
+                        tokio::time::sleep(REFRESH_RETRY).await;
+                    }
+                }
+            }
+        });
+
+        AutoRefresh { token: rx, task }
+    }
+}
+
+/// Requested `scopes` the token was not granted, e.g. the ones added after it was authorized
+fn missing_scopes<'a>(token: &UserToken, scopes: &'a [Scope]) -> Vec<&'a str> {
+    scopes
+        .iter()
+        .filter(|scope| !token.scopes().contains(scope))
+        .map(Scope::as_str)
+        .collect()
+}
+
+/// Token refreshed in the background by [`TokenManager::spawn_auto_refresh`]
+pub struct AutoRefresh {
+    token: watch::Receiver<UserToken>,
+    task: JoinHandle<()>,
+}
+
+impl AutoRefresh {
+    /// The latest token
+    #[must_use]
+    pub fn token(&self) -> UserToken {
+        self.token.borrow().clone()
+    }
+
+    /// Receiver notified about every refreshed token
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<UserToken> {
+        self.token.clone()
+    }
+}
+
+impl Drop for AutoRefresh {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+
+    use chrono::Utc;
+    use twitch_oauth2::{AccessToken, RefreshToken};
+
+    use super::*;
+    use crate::MemoryStore;
+
+    const VALIDATE_RESPONSE: &str = r#"{
+        "client_id": "client-id",
+        "login": "twitch_auth_test",
+        "scopes": ["chat:read"],
+        "user_id": "141981764",
+        "expires_in": 5520838
+    }"#;
+    const REFRESH_RESPONSE: &str = r#"{
+        "access_token": "refreshed-access",
+        "refresh_token": "refreshed-refresh",
+        "expires_in": 14400,
+        "scope": ["chat:read"],
+        "token_type": "bearer"
+    }"#;
+
+    /// Twitch answering the OAuth requests, records the paths of the requests
+    #[derive(Default)]
+    struct MockTwitch {
+        paths: Mutex<Vec<String>>,
+    }
+
+    impl Client for MockTwitch {
+        type Error = io::Error;
+
+        fn req(
+            &self,
+            request: http::Request<Vec<u8>>,
+        ) -> Pin<Box<dyn Future<Output = Result<http::Response<Vec<u8>>, io::Error>> + Send + '_>>
+        {
+            let path = request.uri().path().to_string();
+            let body = match path.as_str() {
+                "/oauth2/validate" => VALIDATE_RESPONSE,
+                _ => REFRESH_RESPONSE,
+            };
+
+            self.paths.lock().unwrap().push(path);
+
+            Box::pin(async move { Ok(http::Response::new(body.as_bytes().to_vec())) })
+        }
+    }
+
+    fn manager(store: MemoryStore) -> Arc<TokenManager<MemoryStore>> {
+        Arc::new(TokenManager::new(
+            Credentials::new("client-id", "client-secret"),
+            store,
+        ))
+    }
+
+    fn saved_token(valid_till: chrono::DateTime<Utc>) -> Token {
+        Token {
+            access_token: AccessToken::from("saved-access"),
+            refresh_token: Some(RefreshToken::from("saved-refresh")),
+            created_at: valid_till - chrono::Duration::hours(4),
+            valid_till,
+            scopes: Some(vec![Scope::ChatRead]),
+        }
+    }
+
+    #[tokio::test]
+    async fn nothing_is_loaded_from_an_empty_store() {
+        let client = MockTwitch::default();
+
+        assert!(manager(MemoryStore::default())
+            .load(&client)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(client.paths.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_refreshed_and_saved() {
+        let client = MockTwitch::default();
+        let tokens = manager(MemoryStore::with_token(saved_token(
+            Utc::now() - chrono::Duration::hours(1),
+        )));
+        let token = tokens.load(&client).await.unwrap().unwrap();
+        let saved = tokens.store().load().unwrap().unwrap();
+
+        assert_eq!(token.access_token.as_str(), "refreshed-access");
+        assert_eq!(token.login.as_str(), "twitch_auth_test");
+        assert_eq!(saved.access_token.as_str(), "refreshed-access");
+        assert_eq!(
+            *client.paths.lock().unwrap(),
+            ["/oauth2/token", "/oauth2/validate"]
+        );
+    }
+
+    #[tokio::test]
+    async fn token_with_the_scopes_is_loaded() {
+        let tokens = manager(MemoryStore::with_token(saved_token(
+            Utc::now() + chrono::Duration::hours(1),
+        )));
+        let token = tokens
+            .load_or_authorize(
+                &MockTwitch::default(),
+                &[Scope::ChatRead],
+                Url::parse("http://localhost:3000").unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(token.access_token.as_str(), "saved-access");
+    }
+
+    #[tokio::test]
+    async fn token_missing_a_scope_is_authorized_again() {
+        let store = MemoryStore::with_token(saved_token(Utc::now() + chrono::Duration::hours(1)));
+        let tokens = TokenManager::new(Credentials::new("client-id", "client-secret"), store)
+            .with_callback_addr(SocketAddr::from(([127, 0, 0, 1], 0)));
+        let client = MockTwitch::default();
+        let authorize = tokens.load_or_authorize(
+            &client,
+            &[Scope::ChatRead, Scope::BitsRead],
+            Url::parse("http://localhost:3000").unwrap(),
+        );
+
+        // the saved token is not returned, the user is waited for to authorize instead
+        assert!(tokio::time::timeout(Duration::from_millis(200), authorize)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn elapsed_token_is_refreshed_in_the_background() {
+        let tokens = manager(MemoryStore::default());
+        let token = UserToken::from_existing_unchecked(
+            "expired-access",
+            Some("saved-refresh".into()),
+            "client-id",
+            ClientSecret::new(String::from("client-secret")),
+            "twitch_auth_test".into(),
+            "141981764".into(),
+            Some(vec![Scope::ChatRead]),
+            Some(Duration::ZERO),
+        );
+        let refresh = Arc::clone(&tokens).spawn_auto_refresh(MockTwitch::default(), token);
+        let mut refreshed = refresh.subscribe();
+
+        refreshed.changed().await.unwrap();
+
+        assert_eq!(refresh.token().access_token.as_str(), "refreshed-access");
+        assert_eq!(
+            tokens
+                .store()
+                .load()
+                .unwrap()
+                .unwrap()
+                .access_token
+                .as_str(),
+            "refreshed-access"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Places the token is kept between the starts of the application.
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{fs, io};
+
+use crate::Token;
+
+/// Storage backend of [`crate::TokenManager`]
+pub trait TokenStore: Send + Sync {
+    /// Saved token, `None` if nothing is saved yet
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the saved token cannot be read
+    fn load(&self) -> io::Result<Option<Token>>;
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the token cannot be saved
+    fn save(&self, token: &Token) -> io::Result<()>;
+}
+
+/// Token kept as JSON in a file
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStore { path: path.into() }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TokenStore for FileStore {
+    fn load(&self) -> io::Result<Option<Token>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(serde_json::from_reader(io::BufReader::new(file))?))
+    }
+
+    fn save(&self, token: &Token) -> io::Result<()> {
+        let file = fs::File::create(&self.path)?;
+
+        serde_json::to_writer(io::BufWriter::new(file), token)?;
+
+        Ok(())
+    }
+}
+
+/// Token kept for the lifetime of the process only
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    token: Mutex<Option<Token>>,
+}
+
+impl MemoryStore {
+    #[must_use]
+    pub fn with_token(token: Token) -> Self {
+        MemoryStore {
+            token: Mutex::new(Some(token)),
+        }
+    }
+}
+
+impl TokenStore for MemoryStore {
+    fn load(&self) -> io::Result<Option<Token>> {
+        Ok(self.token.lock().unwrap().clone())
+    }
+
+    fn save(&self, token: &Token) -> io::Result<()> {
+        *self.token.lock().unwrap() = Some(token.clone());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use twitch_oauth2::AccessToken;
+
+    use super::*;
+
+    #[test]
+    fn file_store_keeps_the_token() {
+        let store = FileStore::new(
+            std::env::temp_dir().join(format!("twitch-auth-{}.json", std::process::id())),
+        );
+
+        assert!(store.load().unwrap().is_none());
+
+        store
+            .save(&Token {
+                access_token: AccessToken::from("saved-access"),
+                refresh_token: None,
+                created_at: Utc::now(),
+                valid_till: Utc::now(),
+                scopes: None,
+            })
+            .unwrap();
+
+        let token = store.load().unwrap().unwrap();
+        let _ = fs::remove_file(store.path());
+
+        assert_eq!(token.access_token.as_str(), "saved-access");
+    }
+}
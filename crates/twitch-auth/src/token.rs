@@ -0,0 +1,111 @@
+//! Saved form of a user token.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use twitch_oauth2::client::Client;
+use twitch_oauth2::{AccessToken, RefreshToken, Scope, TwitchToken, UserToken};
+
+use crate::{Credentials, Error};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Token {
+    #[allow(clippy::struct_field_names)]
+    pub access_token: AccessToken,
+    #[allow(clippy::struct_field_names)]
+    pub refresh_token: Option<RefreshToken>,
+    pub created_at: DateTime<Utc>,
+    pub valid_till: DateTime<Utc>,
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl From<UserToken> for Token {
+    fn from(value: UserToken) -> Self {
+        From::from(&value)
+    }
+}
+
+impl From<&UserToken> for Token {
+    fn from(value: &UserToken) -> Self {
+        let now = Utc::now();
+        let valid_till = now + value.expires_in();
+
+        Self {
+            access_token: value.access_token.clone(),
+            refresh_token: value.refresh_token.clone(),
+            created_at: now,
+            valid_till,
+            scopes: Some(value.scopes().to_vec()),
+        }
+    }
+}
+
+impl Token {
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.valid_till < Utc::now()
+    }
+
+    /// Validate the token, or refresh it if it is expired, returns whether it was refreshed
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if Twitch refuses to validate or refresh the token
+    pub async fn validate<C: Client>(
+        &self,
+        client: &C,
+        credentials: &Credentials,
+    ) -> Result<(UserToken, bool), Error> {
+        if self.is_expired() {
+            Ok((self.refresh_expired(client, credentials).await?, true))
+        } else {
+            let user_token =
+                from_existing(client, &self.access_token, &self.refresh_token, credentials).await?;
+
+            Ok((user_token, false))
+        }
+    }
+
+    async fn refresh_expired<C: Client>(
+        &self,
+        client: &C,
+        credentials: &Credentials,
+    ) -> Result<UserToken, Error> {
+        let mut user_token = UserToken::from_existing_unchecked(
+            self.access_token.clone(),
+            self.refresh_token.clone(),
+            credentials.client_id.clone(),
+            Some(credentials.client_secret.clone()),
+            "".into(),
+            "".into(),
+            self.scopes.clone(),
+            None,
+        );
+        user_token
+            .refresh_token(client)
+            .await
+            .map_err(Error::twitch)?;
+        // need this to properly retrieve username, user ID and expires_in info
+        from_existing(
+            client,
+            &user_token.access_token,
+            &user_token.refresh_token,
+            credentials,
+        )
+        .await
+    }
+}
+
+async fn from_existing<C: Client>(
+    client: &C,
+    access_token: &AccessToken,
+    refresh_token: &Option<RefreshToken>,
+    credentials: &Credentials,
+) -> Result<UserToken, Error> {
+    UserToken::from_existing(
+        client,
+        access_token.clone(),
+        refresh_token.clone(),
+        credentials.client_secret.clone(),
+    )
+    .await
+    .map_err(Error::twitch)
+}
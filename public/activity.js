@@ -0,0 +1,46 @@
+const REFRESH_INTERVAL_MS = 30000;
+
+function drawLine(ctx, points, key, max, color) {
+    const stepX = ctx.canvas.width / Math.max(points.length - 1, 1);
+
+    ctx.strokeStyle = color;
+    ctx.lineWidth = 2;
+    ctx.beginPath();
+    points.forEach((point, idx) => {
+        const x = idx * stepX;
+        const y = ctx.canvas.height - (point[key] / max) * (ctx.canvas.height - 10);
+
+        if (idx === 0) {
+            ctx.moveTo(x, y);
+        } else {
+            ctx.lineTo(x, y);
+        }
+    });
+    ctx.stroke();
+}
+
+async function refresh() {
+    const response = await fetch("api/activity");
+    const series = await response.json();
+    const points = series.points;
+    const canvas = document.getElementById("chart");
+    const ctx = canvas.getContext("2d");
+    const max = Math.max(1, ...points.map((point) => point.messages));
+
+    ctx.clearRect(0, 0, canvas.width, canvas.height);
+    drawLine(ctx, points, "messages", max, "#16fefe");
+    drawLine(ctx, points, "unique_chatters", max, "#bf94ff");
+
+    if (points.length > 0) {
+        const peak = points.reduce((best, point) => (point.messages > best.messages ? point : best));
+        const peakTime = new Date(peak.minute).toLocaleTimeString();
+
+        document.getElementById("peak").textContent =
+            `Peak: ${peak.messages} messages from ${peak.unique_chatters} chatters at ${peakTime}`;
+    }
+}
+
+window.onload = function () {
+    refresh();
+    setInterval(refresh, REFRESH_INTERVAL_MS);
+}
@@ -0,0 +1,277 @@
+const REFRESH_INTERVAL_MS = 5000;
+let notes = {};
+let selectedUser = null;
+
+function renderNotes() {
+    const container = document.getElementById("notes");
+
+    container.replaceChildren();
+    Object.keys(notes).sort().forEach((user) => {
+        const block = document.createElement("div");
+        const title = document.createElement("h3");
+
+        block.className = user === selectedUser ? "user-notes selected" : "user-notes";
+        block.id = `notes-${user}`;
+        title.textContent = user;
+        block.appendChild(title);
+        notes[user].forEach((note) => {
+            const text = document.createElement("p");
+            const meta = document.createElement("div");
+
+            meta.className = "meta";
+            meta.textContent = `${note.author}, ${new Date(note.created_at).toLocaleString()}`;
+            text.textContent = note.text;
+            text.appendChild(meta);
+            block.appendChild(text);
+        });
+        container.appendChild(block);
+    });
+}
+
+function renderModLog(entries) {
+    const body = document.querySelector("#modlog tbody");
+
+    body.replaceChildren();
+    entries.reverse().forEach((entry) => {
+        const row = document.createElement("tr");
+        const user = entry.user_name.toLowerCase();
+        const userNotes = notes[user] || [];
+        const markers = [];
+
+        if (entry.first_message) {
+            markers.push("first message");
+        }
+        if (entry.new_account) {
+            markers.push(`account ${entry.account_age_days}d old`);
+        }
+        if (entry.copypasta) {
+            markers.push("copy-pasta");
+        }
+        if (entry.suspected_evasion) {
+            markers.push(`evading ${entry.suspected_evasion}?`);
+        }
+        if (entry.language_rule) {
+            markers.push(`language ${entry.language}`);
+        }
+        row.className = markers.length > 0 ? "flagged" : "";
+        [
+            new Date(entry.timestamp).toLocaleTimeString(),
+            markers.length > 0 ? `${entry.user_name} (${markers.join(", ")})` : entry.user_name,
+            entry.text,
+        ].forEach((value) => {
+            const cell = document.createElement("td");
+
+            cell.textContent = value;
+            row.appendChild(cell);
+        });
+
+        const notesCell = document.createElement("td");
+
+        notesCell.className = "notes";
+        notesCell.textContent = userNotes.length > 0 ? `📝 ${userNotes.length}` : "";
+        notesCell.onclick = () => {
+            selectedUser = user;
+            renderNotes();
+            document.getElementById(`notes-${user}`)?.scrollIntoView();
+        };
+        row.appendChild(notesCell);
+        body.appendChild(row);
+    });
+}
+
+function renderAlerts(alerts) {
+    const list = document.getElementById("alerts");
+
+    list.replaceChildren();
+    alerts.reverse().forEach((alert) => {
+        const item = document.createElement("li");
+        const until = new Date(alert.ignored_until);
+
+        item.className = until > new Date() ? "active" : "";
+        item.textContent = `${new Date(alert.timestamp).toLocaleTimeString()} ${alert.user_name}: `
+            + `${alert.commands} commands, ignored until ${until.toLocaleTimeString()}`;
+        list.appendChild(item);
+    });
+}
+
+async function alertAction(id, action) {
+    await fetch(`api/alerts/queue/${id}/${action}`, {method: "POST"});
+    refresh();
+}
+
+function renderAlertQueue(alerts) {
+    const list = document.getElementById("alert-queue");
+
+    list.replaceChildren();
+    alerts.forEach((alert) => {
+        const item = document.createElement("li");
+        const skip = document.createElement("button");
+        const replay = document.createElement("button");
+
+        item.className = alert.skipped ? "skipped" : "";
+        item.textContent = `#${alert.id} ${new Date(alert.received_at).toLocaleTimeString()} `
+            + `${alert.kind} ${alert.user_name}${alert.replays > 0 ? ` (replayed ${alert.replays})` : ""} `;
+        skip.textContent = "skip";
+        skip.onclick = () => alertAction(alert.id, "skip");
+        replay.textContent = "replay";
+        replay.onclick = () => alertAction(alert.id, "replay");
+        item.append(skip, replay);
+        list.appendChild(item);
+    });
+}
+
+async function reviewCreditMessage(id, review) {
+    await fetch(`api/credits/messages/${id}/${review}`, {method: "POST"});
+    refresh();
+}
+
+function renderCreditMessages(messages) {
+    const list = document.getElementById("credit-messages");
+
+    list.replaceChildren();
+    messages.forEach((message) => {
+        const item = document.createElement("li");
+        const approve = document.createElement("button");
+        const reject = document.createElement("button");
+
+        item.className = message.review;
+        item.textContent = `${new Date(message.submitted_at).toLocaleTimeString()} `
+            + `${message.user_name}: ${message.text} `;
+        approve.textContent = "approve";
+        approve.disabled = message.review === "approved";
+        approve.onclick = () => reviewCreditMessage(message.id, "approve");
+        reject.textContent = "reject";
+        reject.disabled = message.review === "rejected";
+        reject.onclick = () => reviewCreditMessage(message.id, "reject");
+        item.append(approve, reject);
+        list.appendChild(item);
+    });
+}
+
+function renderLapses(lapses) {
+    const list = document.getElementById("lapses");
+
+    list.replaceChildren();
+    lapses.forEach((lapse) => {
+        const item = document.createElement("li");
+
+        item.textContent = `${new Date(lapse.ended_at).toLocaleDateString()} ${lapse.user_name}: `
+            + `${lapse.months} months, tier ${lapse.tier}${lapse.is_gift ? ", gifted" : ""}`;
+        list.appendChild(item);
+    });
+}
+
+function renderOverlayUrls(sources) {
+    const list = document.getElementById("overlay-urls");
+
+    list.replaceChildren();
+    sources.forEach((source) => {
+        const item = document.createElement("li");
+
+        item.textContent = `${source.name} (${source.width}x${source.height}): ${source.url}`;
+        list.appendChild(item);
+    });
+}
+
+async function rotateOverlayKey() {
+    const response = await fetch("api/overlay/rotate", {method: "POST"});
+
+    if (response.ok) {
+        renderOverlayUrls(await response.json());
+    }
+}
+
+function renderDelegations(delegations) {
+    const list = document.getElementById("delegations");
+
+    list.replaceChildren();
+    delegations.forEach((delegation) => {
+        const item = document.createElement("li");
+        const remove = document.createElement("button");
+
+        item.textContent = `${delegation.login} (${delegation.scopes.join(", ")}) `;
+        remove.textContent = "remove";
+        remove.onclick = async () => {
+            await fetch(`api/delegations/${delegation.user_id}/remove`, {method: "POST"});
+            renderDelegations(await (await fetch("api/delegations")).json());
+        };
+        item.appendChild(remove);
+        list.appendChild(item);
+    });
+}
+
+// the co-host opens the URL and authorizes the bot for their own channel
+async function startDelegation() {
+    const response = await fetch("api/delegations", {method: "POST"});
+    const target = document.getElementById("delegation-url");
+
+    target.textContent = response.ok
+        ? `Send this URL to the co-host: ${(await response.json()).url}`
+        : await response.text();
+}
+
+async function refresh() {
+    const flagged = document.getElementById("flagged").checked;
+    const [modlog, allNotes, alerts, lapses, alertQueue, creditMessages, delegations] = await Promise.all([
+        fetch(`api/modlog?flagged=${flagged}`).then((response) => response.json()),
+        fetch("api/notes").then((response) => response.json()),
+        fetch("api/alerts").then((response) => response.json()),
+        fetch("api/lapses").then((response) => response.json()),
+        fetch("api/alerts/queue").then((response) => response.json()),
+        fetch("api/credits/messages").then((response) => response.json()),
+        fetch("api/delegations").then((response) => response.json()),
+    ]);
+
+    notes = allNotes;
+    renderModLog(modlog);
+    renderNotes();
+    renderAlerts(alerts);
+    renderLapses(lapses);
+    renderAlertQueue(alertQueue);
+    renderCreditMessages(creditMessages);
+    renderDelegations(delegations);
+}
+
+async function setStreamerPrivacy(enabled) {
+    await fetch("api/streamer-privacy", {
+        method: "POST",
+        headers: {"Content-Type": "application/json"},
+        body: JSON.stringify({enabled}),
+    });
+    refresh();
+}
+
+function renderSafeMode(status) {
+    const banner = document.getElementById("safe-mode");
+
+    banner.hidden = !status.active;
+    banner.textContent = `Safe mode: the bot crashed ${status.crashes} times recently, `
+        + `${status.disabled.join(" and ")} are turned off. Fix the cause and restart the bot.`;
+}
+
+// the secret of `?key=` is kept in a cookie the API requests carry
+function keepAdminKey() {
+    const params = new URLSearchParams(location.search);
+    const key = params.get("key");
+
+    if (key) {
+        document.cookie = `hewpme_admin=${key}; path=/; SameSite=Strict`;
+        params.delete("key");
+        history.replaceState(null, "", params.size ? `?${params}` : location.pathname);
+    }
+}
+
+window.onload = async () => {
+    keepAdminKey();
+    const privacy = document.getElementById("streamer-privacy");
+
+    privacy.checked = (await (await fetch("api/streamer-privacy")).json()).enabled;
+    privacy.onchange = () => setStreamerPrivacy(privacy.checked);
+    document.getElementById("flagged").onchange = refresh;
+    document.getElementById("rotate-overlay-key").onclick = rotateOverlayKey;
+    document.getElementById("start-delegation").onclick = startDelegation;
+    renderOverlayUrls(await (await fetch("api/overlay/urls")).json());
+    renderSafeMode(await (await fetch("api/safe-mode")).json());
+    refresh();
+    setInterval(refresh, REFRESH_INTERVAL_MS);
+};
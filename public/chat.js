@@ -0,0 +1,130 @@
+const MAX_MESSAGES = 50;
+const RECONNECT_DELAY_MS = 3000;
+
+function addMarker(element, className, text) {
+    const marker = document.createElement("span");
+
+    marker.className = `marker ${className}`;
+    marker.textContent = text;
+    element.appendChild(marker);
+}
+
+function showMessage(entry) {
+    const messages = document.getElementById("messages");
+    const message = document.createElement("div");
+    const name = document.createElement("span");
+    const text = document.createElement("span");
+
+    message.className = "message";
+    if (entry.first_message) {
+        message.classList.add("first-message");
+        addMarker(message, "first", "first message");
+    }
+    if (entry.highlighted) {
+        message.classList.add("highlighted");
+        addMarker(message, "highlight", "highlighted");
+    }
+    if (entry.new_account) {
+        message.classList.add("new-account");
+        addMarker(message, "account", `account ${entry.account_age_days}d old`);
+    }
+
+    for (const badge of entry.badges || []) {
+        if (badge.image) {
+            const image = document.createElement("img");
+
+            image.className = "badge";
+            image.src = badge.image;
+            image.alt = badge.name;
+            message.appendChild(image);
+        }
+    }
+
+    name.className = "name";
+    name.textContent = entry.user_name;
+    if (entry.color) {
+        name.style.color = entry.color;
+    }
+    text.textContent = entry.text;
+    message.append(name, text);
+    messages.appendChild(message);
+
+    while (messages.children.length > MAX_MESSAGES) {
+        messages.firstChild.remove();
+    }
+}
+
+function showPreview(preview) {
+    const messages = document.getElementById("messages");
+    const card = document.createElement("a");
+    const title = document.createElement("div");
+
+    card.className = "preview";
+    card.href = preview.url;
+    if (preview.image) {
+        const image = document.createElement("img");
+
+        image.src = preview.image;
+        card.appendChild(image);
+    }
+    title.className = "title";
+    title.textContent = preview.site_name ? `${preview.site_name}: ${preview.title}` : preview.title;
+    card.appendChild(title);
+    if (preview.description) {
+        const description = document.createElement("div");
+
+        description.className = "description";
+        description.textContent = preview.description;
+        card.appendChild(description);
+    }
+    messages.appendChild(card);
+
+    while (messages.children.length > MAX_MESSAGES) {
+        messages.firstChild.remove();
+    }
+}
+
+// `/highlights` shows only the messages highlighted with channel points
+function highlightsOnly() {
+    return document.body.hasAttribute("data-highlights");
+}
+
+function handleEvent(event) {
+    if (event.type === "chat_message") {
+        if (event.highlighted || !highlightsOnly()) {
+            showMessage(event);
+        }
+    } else if (event.type === "link_preview" && !highlightsOnly()) {
+        showPreview(event);
+    }
+}
+
+function connect() {
+    if (new URLSearchParams(location.search).get("transport") === "sse") {
+        // reconnects by itself, resuming after the last received event
+        const source = new EventSource(`/events/stream${location.search}`);
+
+        source.onmessage = (message) => handleEvent(JSON.parse(message.data));
+        return;
+    }
+
+    const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    const socket = new WebSocket(`${protocol}//${location.host}/ws/overlay${location.search}`);
+
+    socket.onmessage = (message) => handleEvent(JSON.parse(message.data));
+    socket.onclose = () => setTimeout(connect, RECONNECT_DELAY_MS);
+}
+
+window.onload = async () => {
+    // the token of the page URL also opens the chat history
+    const params = new URLSearchParams(location.search);
+
+    params.set("highlighted", highlightsOnly());
+
+    const response = await fetch(`api/chat/recent?${params}`);
+
+    if (response.ok) {
+        (await response.json()).forEach(showMessage);
+    }
+    connect();
+};
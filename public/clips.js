@@ -0,0 +1,28 @@
+function clipElement(clip) {
+    const element = document.createElement("div");
+    const player = document.createElement("iframe");
+    const title = document.createElement("a");
+    const details = document.createElement("div");
+
+    element.className = "clip";
+    // Twitch only plays the embedded clips on the page of the given parent domain
+    player.src = `${clip.embed_url}&parent=${location.hostname}`;
+    player.allowFullscreen = true;
+    title.href = clip.url;
+    title.target = "_blank";
+    title.textContent = clip.title;
+    details.className = "details";
+    details.textContent = `${clip.creator_name} · ${new Date(clip.created_at).toLocaleTimeString()}`
+        + ` · ${Math.round(clip.duration)} s · ${clip.view_count} views`;
+    element.append(player, title, details);
+
+    return element;
+}
+
+window.onload = async () => {
+    const response = await fetch("api/clips");
+    const clips = await response.json();
+
+    document.getElementById("empty").hidden = clips.length > 0;
+    document.getElementById("clips").replaceChildren(...clips.map(clipElement));
+};
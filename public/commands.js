@@ -0,0 +1,21 @@
+window.onload = async () => {
+    const response = await fetch("api/commands");
+    const body = document.querySelector("#commands tbody");
+
+    (await response.json()).forEach((command) => {
+        const row = document.createElement("tr");
+
+        [
+            command.name,
+            command.description,
+            command.permission,
+            command.cooldown_sec === null ? "" : `${command.cooldown_sec} s`,
+        ].forEach((value) => {
+            const cell = document.createElement("td");
+
+            cell.textContent = value;
+            row.appendChild(cell);
+        });
+        body.appendChild(row);
+    });
+};
@@ -0,0 +1,52 @@
+const RECONNECT_DELAY_MS = 3000;
+// rows kept on the page, the oldest ones are dropped
+const MAX_ROWS = 1000;
+let socket = null;
+
+function addRecord(record) {
+    const body = document.querySelector("#logs tbody");
+    const row = document.createElement("tr");
+
+    row.className = record.level;
+    [new Date(record.time).toLocaleTimeString(), record.level, record.target, record.message].forEach((text) => {
+        const cell = document.createElement("td");
+
+        cell.textContent = text;
+        row.appendChild(cell);
+    });
+    body.appendChild(row);
+
+    while (body.rows.length > MAX_ROWS) {
+        body.deleteRow(0);
+    }
+
+    if (document.getElementById("follow").checked) {
+        row.scrollIntoView();
+    }
+}
+
+function connect() {
+    const level = document.getElementById("level").value;
+    const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    const current = new WebSocket(`${protocol}//${location.host}/ws/logs?level=${level}`);
+
+    // the kept records come again on the new connection
+    document.querySelector("#logs tbody").replaceChildren();
+    current.onmessage = (message) => addRecord(JSON.parse(message.data));
+    current.onclose = () => {
+        if (socket === current) {
+            setTimeout(connect, RECONNECT_DELAY_MS);
+        }
+    };
+    socket = current;
+}
+
+window.onload = () => {
+    document.getElementById("level").onchange = () => {
+        const previous = socket;
+
+        connect();
+        previous.close();
+    };
+    connect();
+};
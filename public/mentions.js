@@ -0,0 +1,58 @@
+const REFRESH_INTERVAL_MS = 5000;
+let latest = null;
+
+function renderMentions(mentions) {
+    const body = document.querySelector("#mentions tbody");
+
+    body.replaceChildren();
+    mentions.forEach((mention) => {
+        const row = document.createElement("tr");
+
+        // the mentions since the page was refreshed last are highlighted
+        row.className = latest !== null && mention.received_at > latest ? "new" : "";
+        [
+            new Date(mention.received_at).toLocaleTimeString(),
+            mention.user_name,
+            mention.words.join(", "),
+            mention.text,
+        ].forEach((value) => {
+            const cell = document.createElement("td");
+
+            cell.textContent = value;
+            row.appendChild(cell);
+        });
+        body.appendChild(row);
+    });
+
+    if (mentions.length > 0) {
+        latest = mentions[0].received_at;
+    }
+}
+
+async function refresh() {
+    const response = await fetch("api/mentions");
+
+    // the mentions are private, the page needs the admin secret
+    document.getElementById("unauthorized").hidden = response.status !== 401;
+    if (response.ok) {
+        renderMentions(await response.json());
+    }
+}
+
+// the secret of `?key=` is kept in a cookie the API requests carry
+function keepAdminKey() {
+    const params = new URLSearchParams(location.search);
+    const key = params.get("key");
+
+    if (key) {
+        document.cookie = `hewpme_admin=${key}; path=/; SameSite=Strict`;
+        params.delete("key");
+        history.replaceState(null, "", params.size ? `?${params}` : location.pathname);
+    }
+}
+
+window.onload = () => {
+    keepAdminKey();
+    refresh();
+    setInterval(refresh, REFRESH_INTERVAL_MS);
+};
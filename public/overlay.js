@@ -0,0 +1,191 @@
+const ALERT_DURATION_MS = 6000;
+const RECONNECT_DELAY_MS = 3000;
+const POLL_RESULTS_DURATION_MS = 15000;
+const queue = [];
+let showing = false;
+/// Alert on the screen, it is removed early when skipped
+let current = null;
+/// Countdown of the running ad break
+let adBreakTimer = null;
+
+function showNext() {
+    const event = queue.shift();
+
+    current = null;
+
+    if (event === undefined) {
+        showing = false;
+        return;
+    }
+
+    const alerts = document.getElementById("alerts");
+    const alert = document.createElement("div");
+
+    showing = true;
+    alert.className = ["milestone", "intro"].includes(event.type) ? `alert ${event.type}` : "alert";
+    alert.textContent = event.text;
+    (event.emotes || []).forEach((emote) => {
+        const image = document.createElement("img");
+
+        image.src = emote.image;
+        image.alt = emote.name;
+        alert.appendChild(image);
+    });
+    alerts.appendChild(alert);
+    current = {
+        id: event.type === "alert" ? event.id : null,
+        element: alert,
+        timer: setTimeout(() => {
+            alert.remove();
+            showNext();
+        }, ALERT_DURATION_MS),
+    };
+}
+
+function skipAlert(id) {
+    const queued = queue.findIndex((event) => event.type === "alert" && event.id === id);
+
+    if (queued >= 0) {
+        queue.splice(queued, 1);
+    }
+
+    if (current !== null && current.id === id) {
+        clearTimeout(current.timer);
+        current.element.remove();
+        showNext();
+    }
+}
+
+function renderPoll(results, ended) {
+    const poll = document.getElementById("poll");
+    const question = document.createElement("div");
+
+    poll.replaceChildren(question);
+    question.className = "question";
+    question.textContent = results.question;
+
+    results.options.forEach((option, idx) => {
+        const share = results.total > 0 ? Math.round(option.votes * 100 / results.total) : 0;
+        const row = document.createElement("div");
+        const bar = document.createElement("div");
+        const label = document.createElement("span");
+
+        row.className = "option";
+        bar.className = "bar";
+        bar.style.width = `${share}%`;
+        label.textContent = `${idx + 1}. ${option.option} — ${option.votes} (${share}%)`;
+        row.append(bar, label);
+        poll.appendChild(row);
+    });
+
+    poll.classList.toggle("ended", ended);
+    poll.hidden = false;
+}
+
+function showAdBreak(seconds) {
+    const adBreak = document.getElementById("ad-break");
+    let left = seconds;
+    const render = () => {
+        adBreak.textContent = `Ads: ${left} s`;
+    };
+
+    clearInterval(adBreakTimer);
+    render();
+    adBreak.hidden = false;
+    adBreakTimer = setInterval(() => {
+        left -= 1;
+
+        if (left <= 0) {
+            clearInterval(adBreakTimer);
+            adBreak.hidden = true;
+            return;
+        }
+
+        render();
+    }, 1000);
+}
+
+function handleEvent(event) {
+    switch (event.type) {
+        case "announcement":
+        case "milestone":
+        case "emotes_unlocked":
+        case "intro":
+        case "alert":
+            queue.push(event);
+            if (!showing) {
+                showNext();
+            }
+            break;
+        case "alert_skipped":
+            skipAlert(event.id);
+            break;
+        case "poll_updated":
+            renderPoll(event.results, false);
+            break;
+        case "poll_ended":
+            renderPoll(event.results, true);
+            setTimeout(() => {
+                document.getElementById("poll").hidden = true;
+            }, POLL_RESULTS_DURATION_MS);
+            break;
+        case "lurkers_updated": {
+            const lurkers = document.getElementById("lurkers");
+
+            lurkers.textContent = `👀 ${event.count}`;
+            lurkers.hidden = event.count === 0;
+            break;
+        }
+        case "followers_updated": {
+            const followers = document.getElementById("followers");
+
+            followers.textContent = `❤ ${event.count}`;
+            followers.hidden = false;
+            break;
+        }
+        case "credits_roll": {
+            const credits = document.getElementById("credits");
+
+            credits.src = "/";
+            credits.hidden = false;
+            break;
+        }
+        case "credits_roll_ended": {
+            const credits = document.getElementById("credits");
+
+            credits.hidden = true;
+            credits.removeAttribute("src");
+            break;
+        }
+        case "ad_break":
+            showAdBreak(event.seconds);
+            break;
+        case "twitch_outage":
+            document.getElementById("outage").hidden = !event.active;
+            break;
+        case "chat_message":
+        case "link_preview":
+            // shown by the chat overlay
+            break;
+        default:
+            console.log("unknown overlay event", event);
+    }
+}
+
+function connect() {
+    if (new URLSearchParams(location.search).get("transport") === "sse") {
+        // reconnects by itself, resuming after the last received event
+        const source = new EventSource(`/events/stream${location.search}`);
+
+        source.onmessage = (message) => handleEvent(JSON.parse(message.data));
+        return;
+    }
+
+    const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    const socket = new WebSocket(`${protocol}//${location.host}/ws/overlay${location.search}`);
+
+    socket.onmessage = (message) => handleEvent(JSON.parse(message.data));
+    socket.onclose = () => setTimeout(connect, RECONNECT_DELAY_MS);
+}
+
+window.onload = connect;
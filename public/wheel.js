@@ -0,0 +1,109 @@
+const RECONNECT_DELAY_MS = 3000;
+// how long the result stays on the screen after the wheel stops
+const RESULT_DELAY_MS = 5000;
+const COLORS = ["#9147ff", "#bf94ff", "#772ce8", "#5c16c5"];
+// full turns before the wheel slows down on the prize
+const TURNS = 5;
+
+let hideTimer = null;
+
+function totalWeight(prizes) {
+    return prizes.reduce((total, prize) => total + prize.weight, 0);
+}
+
+function draw(canvas, prizes, rotation) {
+    const context = canvas.getContext("2d");
+    const radius = canvas.width / 2;
+    const total = totalWeight(prizes);
+    let start = rotation - Math.PI / 2;
+
+    context.clearRect(0, 0, canvas.width, canvas.height);
+    prizes.forEach((prize, index) => {
+        const angle = (prize.weight / total) * 2 * Math.PI;
+
+        if (angle === 0) {
+            return;
+        }
+
+        context.beginPath();
+        context.moveTo(radius, radius);
+        context.arc(radius, radius, radius, start, start + angle);
+        context.fillStyle = COLORS[index % COLORS.length];
+        context.fill();
+
+        context.save();
+        context.translate(radius, radius);
+        context.rotate(start + angle / 2);
+        context.fillStyle = "#efeff1";
+        context.font = "bold 22px sans-serif";
+        context.textAlign = "right";
+        context.fillText(prize.label, radius - 20, 8);
+        context.restore();
+
+        start += angle;
+    });
+}
+
+// rotation bringing the middle of the prize under the pointer at the top
+function stopRotation(prizes, index) {
+    const total = totalWeight(prizes);
+    const before = prizes.slice(0, index).reduce((sum, prize) => sum + prize.weight, 0);
+    const middle = ((before + prizes[index].weight / 2) / total) * 2 * Math.PI;
+
+    return TURNS * 2 * Math.PI - middle;
+}
+
+function spin(event) {
+    const wheel = document.getElementById("wheel");
+    const canvas = document.getElementById("canvas");
+    const result = document.getElementById("result");
+    const target = stopRotation(event.prizes, event.index);
+    const started = performance.now();
+
+    clearTimeout(hideTimer);
+    result.textContent = "";
+    wheel.hidden = false;
+
+    const frame = (now) => {
+        const progress = Math.min((now - started) / event.duration_ms, 1);
+        // ease out cubic
+        const eased = 1 - Math.pow(1 - progress, 3);
+
+        draw(canvas, event.prizes, target * eased);
+        if (progress < 1) {
+            requestAnimationFrame(frame);
+            return;
+        }
+
+        result.textContent = `${event.user_name}: ${event.prizes[event.index].label}`;
+        hideTimer = setTimeout(() => {
+            wheel.hidden = true;
+        }, RESULT_DELAY_MS);
+    };
+
+    requestAnimationFrame(frame);
+}
+
+function handleEvent(event) {
+    if (event.type === "wheel_spin") {
+        spin(event);
+    }
+}
+
+function connect() {
+    if (new URLSearchParams(location.search).get("transport") === "sse") {
+        // reconnects by itself, resuming after the last received event
+        const source = new EventSource(`/events/stream${location.search}`);
+
+        source.onmessage = (message) => handleEvent(JSON.parse(message.data));
+        return;
+    }
+
+    const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    const socket = new WebSocket(`${protocol}//${location.host}/ws/overlay${location.search}`);
+
+    socket.onmessage = (message) => handleEvent(JSON.parse(message.data));
+    socket.onclose = () => setTimeout(connect, RECONNECT_DELAY_MS);
+}
+
+window.onload = connect;
@@ -0,0 +1,133 @@
+//! Per-minute chat activity of the current session.
+//!
+//! Only the last `HEWPME_ACTIVITY_MAX_MINUTES` are kept in memory, older minutes
+//! are spilled to disk.
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::config;
+use crate::limits::StoreLimit;
+
+#[derive(Default)]
+struct MinuteBucket {
+    messages: u64,
+    chatters: HashSet<Arc<str>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ActivityPoint {
+    pub minute: DateTime<Utc>,
+    pub messages: u64,
+    pub unique_chatters: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ActivitySeries {
+    pub session_start: DateTime<Utc>,
+    pub points: Vec<ActivityPoint>,
+}
+
+pub struct ActivityTracker {
+    session_start: DateTime<Utc>,
+    buckets: Mutex<BTreeMap<DateTime<Utc>, MinuteBucket>>,
+    limit: StoreLimit,
+}
+
+pub type SafeActivityTracker = Arc<ActivityTracker>;
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        ActivityTracker {
+            session_start: Utc::now(),
+            buckets: Mutex::new(BTreeMap::new()),
+            limit: StoreLimit::new("activity", config::get_activity_max_minutes()),
+        }
+    }
+}
+
+impl ActivityTracker {
+    pub async fn record_message(&self, chatter: &Arc<str>) {
+        let minute = current_minute();
+        let mut guard = self.buckets.lock().await;
+
+        if !guard.contains_key(&minute) {
+            let overflow = self.limit.overflow(guard.len());
+            let spilled = (0..overflow)
+                .filter_map(|_| guard.pop_first())
+                .map(|(minute, bucket)| ActivityPoint {
+                    minute,
+                    messages: bucket.messages,
+                    unique_chatters: bucket.chatters.len(),
+                })
+                .collect::<Vec<_>>();
+
+            if !spilled.is_empty() {
+                self.limit.spill(spilled);
+            }
+        }
+
+        let bucket = guard.entry(minute).or_default();
+
+        bucket.messages += 1;
+        if !bucket.chatters.contains(chatter) {
+            bucket.chatters.insert(Arc::clone(chatter));
+        }
+
+        self.limit.report(guard.len());
+    }
+
+    /// Drop the chatter from the unique chatters of every minute
+    pub async fn forget(&self, chatter: &str) {
+        for bucket in self.buckets.lock().await.values_mut() {
+            bucket.chatters.remove(chatter);
+        }
+    }
+
+    /// Time series of the session with silent minutes filled with zeroes
+    pub async fn series(&self) -> ActivitySeries {
+        let guard = self.buckets.lock().await;
+        let mut points = Vec::new();
+
+        if let Some(first) = guard.keys().next() {
+            let mut minute = *first;
+            let last = current_minute();
+
+            while minute <= last {
+                let point = guard.get(&minute).map_or(
+                    ActivityPoint {
+                        minute,
+                        messages: 0,
+                        unique_chatters: 0,
+                    },
+                    |bucket| ActivityPoint {
+                        minute,
+                        messages: bucket.messages,
+                        unique_chatters: bucket.chatters.len(),
+                    },
+                );
+
+                points.push(point);
+                minute += TimeDelta::minutes(1);
+            }
+        }
+
+        ActivitySeries {
+            session_start: self.session_start,
+            points,
+        }
+    }
+}
+
+fn current_minute() -> DateTime<Utc> {
+    Utc::now()
+        .duration_trunc(TimeDelta::minutes(1))
+        .expect("Minute truncation cannot overflow")
+}
+
+pub fn create_activity_tracker() -> SafeActivityTracker {
+    Arc::new(ActivityTracker::default())
+}
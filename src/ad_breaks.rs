@@ -0,0 +1,140 @@
+//! Warning of the viewers about the ad breaks started on the channel.
+//!
+//! `twitch_api` does not know `channel.ad_break.begin` yet, so the EventSub client
+//! creates its subscription and reads its notifications from the raw JSON. The chat
+//! gets `HEWPME_AD_BREAK_MESSAGE` or the default warning, the overlays get an
+//! `ad_break` event. The subscription requires the channel:read:ads permission.
+use serde::Deserialize;
+
+use crate::events::{self, BotEvent};
+use crate::helper::BotState;
+use crate::overlay::{self, OverlayEvent};
+use crate::{config, i18n, metrics};
+
+pub const EVENT_TYPE: &str = "channel.ad_break.begin";
+pub const VERSION: &str = "1";
+const NOTIFICATION_MESSAGE_TYPE: &str = "notification";
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AdBreak {
+    pub duration_seconds: u64,
+    /// Started by Twitch on schedule rather than by the broadcaster
+    pub is_automatic: bool,
+}
+
+/// Ad break of the EventSub message, `None` for the other messages
+#[must_use]
+pub fn parse(frame: &str) -> Option<AdBreak> {
+    #[derive(Deserialize)]
+    struct Frame {
+        metadata: Metadata,
+        payload: Payload,
+    }
+
+    #[derive(Deserialize)]
+    struct Metadata {
+        message_type: String,
+        subscription_type: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Payload {
+        event: Option<AdBreak>,
+    }
+
+    // most messages are not ad breaks, they are not parsed twice
+    if !frame.contains(EVENT_TYPE) {
+        return None;
+    }
+
+    let frame: Frame = serde_json::from_str(frame).ok()?;
+
+    if frame.metadata.message_type != NOTIFICATION_MESSAGE_TYPE
+        || frame.metadata.subscription_type.as_deref() != Some(EVENT_TYPE)
+    {
+        return None;
+    }
+
+    frame.payload.event
+}
+
+/// Warn the chat and the overlays that the ads are running
+pub fn began(state: &BotState, ad_break: &AdBreak) {
+    let seconds = ad_break.duration_seconds;
+
+    tracing::info!(
+        "ad break for {seconds} s, {}",
+        if ad_break.is_automatic {
+            "automatic"
+        } else {
+            "started by the broadcaster"
+        }
+    );
+    metrics::increment("ad_break");
+
+    let args: [(&str, &(dyn std::fmt::Display + Sync)); 1] = [("seconds", &seconds)];
+    let text = match config::get_ad_break_message() {
+        Some(template) => i18n::fill(&template, &args),
+        None => i18n::render(state.languages.channel(), "ad_break.warning", &args),
+    };
+
+    state.say(text);
+    overlay::push(
+        &state.overlay,
+        OverlayEvent::AdBreak {
+            seconds,
+            automatic: ad_break.is_automatic,
+        },
+    );
+    events::publish(&state.bus, BotEvent::AdBreak { seconds });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn ad_break_is_parsed_from_its_notification_only() {
+        let frame = |message_type: &str| {
+            json!({
+                "metadata": {
+                    "message_id": "befa7b53-d79d-478f-86b9-120f112b044e",
+                    "message_type": message_type,
+                    "message_timestamp": "2022-11-16T10:11:12.464757833Z",
+                    "subscription_type": EVENT_TYPE,
+                    "subscription_version": VERSION
+                },
+                "payload": {
+                    "subscription": {},
+                    "event": {
+                        "duration_seconds": 60,
+                        "started_at": "2019-11-16T10:11:12.634234626Z",
+                        "is_automatic": false,
+                        "broadcaster_user_id": "1337",
+                        "broadcaster_user_login": "cool_user",
+                        "broadcaster_user_name": "Cool_User",
+                        "requester_user_id": "1337",
+                        "requester_user_login": "cool_user",
+                        "requester_user_name": "Cool_User"
+                    }
+                }
+            })
+            .to_string()
+        };
+
+        assert_eq!(
+            parse(&frame("notification")),
+            Some(AdBreak {
+                duration_seconds: 60,
+                is_automatic: false,
+            })
+        );
+        assert_eq!(parse(&frame("revocation")), None);
+        assert_eq!(
+            parse(r#"{"metadata":{"message_type":"session_keepalive"},"payload":{}}"#),
+            None
+        );
+    }
+}
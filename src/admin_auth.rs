@@ -0,0 +1,78 @@
+//! Secret of the admin page and the API changing the bot or reading the viewer data.
+//!
+//! The secret is `HEWPME_ADMIN_TOKEN`, or one generated into `admin_token.json` without
+//! it. Scripts pass it in the `X-Admin-Token` header, the admin page opened with
+//! `?key=<secret>` (see `hewpme admin-url`) keeps it in a cookie its requests carry.
+use std::sync::OnceLock;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::storage::JsonStore;
+
+const SECRET_FILE_NAME: &str = "admin_token.json";
+const SECRET_LEN: usize = 32;
+/// Request header of the secret
+pub(crate) const HEADER: &str = "x-admin-token";
+/// Cookie of the secret set by the admin page
+pub(crate) const COOKIE: &str = "hewpme_admin";
+
+static SECRET: OnceLock<Secret> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct StoredSecret {
+    secret: String,
+}
+
+struct Secret {
+    value: String,
+    /// Key of the comparison, the signatures are compared in constant time
+    key: hmac::Key,
+}
+
+/// The secret of the admin routes
+#[must_use]
+pub fn secret() -> &'static str {
+    &stored().value
+}
+
+/// Whether the request carrying `token` may use the admin routes
+pub(crate) fn is_authorized(token: Option<&str>) -> bool {
+    let secret = stored();
+
+    token.is_some_and(|token| {
+        let expected = hmac::sign(&secret.key, secret.value.as_bytes());
+
+        hmac::verify(&secret.key, token.as_bytes(), expected.as_ref()).is_ok()
+    })
+}
+
+fn stored() -> &'static Secret {
+    SECRET.get_or_init(|| {
+        let value = config::get_admin_token().unwrap_or_else(|| {
+            let mut stored = JsonStore::<StoredSecret>::open(SECRET_FILE_NAME);
+
+            if stored.secret.is_empty() {
+                let secret = URL_SAFE_NO_PAD.encode(rand::random::<[u8; SECRET_LEN]>());
+
+                if let Err(e) = stored.try_update(|stored| stored.secret.clone_from(&secret)) {
+                    tracing::warn!(
+                        "Unable to save the admin secret, it ends with the session: {e}"
+                    );
+                }
+
+                secret
+            } else {
+                stored.secret.clone()
+            }
+        });
+
+        Secret {
+            value,
+            key: hmac::Key::new(hmac::HMAC_SHA256, &rand::random::<[u8; SECRET_LEN]>()),
+        }
+    })
+}
@@ -0,0 +1,247 @@
+//! Follow, subscription and raid alerts shown by the `/overlay`.
+//!
+//! The latest alerts are kept with their IDs so that the streamer can fire a missed
+//! one again or skip an inappropriate one, with `!replayalert`/`!skipalert` in the
+//! chat or on `/api/alerts/queue`.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::i18n;
+use crate::overlay::{self, OverlayEvent};
+
+const KEPT_ALERTS: usize = 50;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Follow,
+    Subscribe,
+    Raid,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueuedAlert {
+    pub id: u64,
+    pub kind: AlertKind,
+    /// Unknown for the raids
+    #[serde(skip)]
+    pub user_id: Option<String>,
+    pub user_name: String,
+    /// Shown by the overlay, it contains the user name
+    #[serde(skip)]
+    pub text: String,
+    pub received_at: DateTime<Local>,
+    pub skipped: bool,
+    /// How many times the alert was fired again
+    pub replays: u32,
+}
+
+#[derive(Default)]
+pub struct AlertQueue {
+    /// Oldest first
+    alerts: Mutex<VecDeque<QueuedAlert>>,
+    /// The IDs are not reused when the alerts of a user are forgotten
+    last_id: AtomicU64,
+}
+
+pub type SafeAlertQueue = Arc<AlertQueue>;
+
+impl AlertQueue {
+    async fn add(
+        &self,
+        kind: AlertKind,
+        user_id: Option<String>,
+        user_name: String,
+        text: String,
+    ) -> QueuedAlert {
+        let mut alerts = self.alerts.lock().await;
+        let alert = QueuedAlert {
+            id: self.last_id.fetch_add(1, Ordering::Relaxed) + 1,
+            kind,
+            user_id,
+            user_name,
+            text,
+            received_at: Local::now(),
+            skipped: false,
+            replays: 0,
+        };
+
+        if alerts.len() == KEPT_ALERTS {
+            alerts.pop_front();
+        }
+
+        alerts.push_back(alert.clone());
+
+        alert
+    }
+
+    /// Kept alerts, newest first
+    pub async fn recent(&self) -> Vec<QueuedAlert> {
+        self.alerts.lock().await.iter().rev().cloned().collect()
+    }
+
+    /// Mark the alert as skipped, the latest one not skipped yet if `id` is `None`
+    async fn mark_skipped(&self, id: Option<u64>) -> Option<QueuedAlert> {
+        let mut alerts = self.alerts.lock().await;
+        let alert = match id {
+            Some(id) => alerts.iter_mut().find(|alert| alert.id == id),
+            None => alerts.iter_mut().rev().find(|alert| !alert.skipped),
+        }?;
+
+        alert.skipped = true;
+
+        Some(alert.clone())
+    }
+
+    /// Count the replay of the alert, the latest one if `id` is `None`
+    async fn mark_replayed(&self, id: Option<u64>) -> Option<QueuedAlert> {
+        let mut alerts = self.alerts.lock().await;
+        let alert = match id {
+            Some(id) => alerts.iter_mut().find(|alert| alert.id == id),
+            None => alerts.back_mut(),
+        }?;
+
+        alert.skipped = false;
+        alert.replays += 1;
+
+        Some(alert.clone())
+    }
+
+    pub async fn forget(&self, user_id: &str) {
+        self.alerts
+            .lock()
+            .await
+            .retain(|alert| alert.user_id.as_deref() != Some(user_id));
+    }
+}
+
+pub fn create_alert_queue() -> SafeAlertQueue {
+    Arc::new(AlertQueue::default())
+}
+
+/// Queue the follows, subscriptions and raids and show them on the overlay
+pub async fn run_alert_queue(state: BotState) {
+    let mut events = state.bus.subscribe();
+
+    loop {
+        let lang = state.languages.channel();
+        let (kind, user_id, user_name, text) = match events.recv().await {
+            Ok(BotEvent::Follow { user_id, user_name }) => {
+                let text = i18n::render(lang, "alert.follow", &[("name", &user_name)]);
+
+                (AlertKind::Follow, Some(user_id), user_name, text)
+            }
+            Ok(BotEvent::Subscribe { user_id, user_name }) => {
+                let text = i18n::render(lang, "alert.subscribe", &[("name", &user_name)]);
+
+                (AlertKind::Subscribe, Some(user_id), user_name, text)
+            }
+            Ok(BotEvent::Raid {
+                user_name, viewers, ..
+            }) => {
+                let text = i18n::render(
+                    lang,
+                    "alert.raid",
+                    &[("name", &user_name), ("viewers", &viewers)],
+                );
+
+                (AlertKind::Raid, None, user_name, text)
+            }
+            Ok(
+                BotEvent::ChatMessage { .. }
+                | BotEvent::CategoryChanged { .. }
+                | BotEvent::StreamOnline
+                | BotEvent::StreamOffline
+                | BotEvent::Resubscribe { .. }
+                | BotEvent::GiftSubscriptions { .. }
+                | BotEvent::Cheer { .. }
+                | BotEvent::AdBreak { .. },
+            ) => continue,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("alert queue lagged, {skipped} events skipped");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let alert = state.alert_queue.add(kind, user_id, user_name, text).await;
+
+        show(&state, &alert);
+    }
+}
+
+/// Skip the alert on the overlay, the latest one not skipped yet if `id` is `None`
+pub async fn skip(state: &BotState, id: Option<u64>) -> Option<QueuedAlert> {
+    let alert = state.alert_queue.mark_skipped(id).await?;
+
+    tracing::info!("alert #{} skipped", alert.id);
+    overlay::push(&state.overlay, OverlayEvent::AlertSkipped { id: alert.id });
+
+    Some(alert)
+}
+
+/// Fire the alert again, the latest one if `id` is `None`
+pub async fn replay(state: &BotState, id: Option<u64>) -> Option<QueuedAlert> {
+    let alert = state.alert_queue.mark_replayed(id).await?;
+
+    tracing::info!("alert #{} replayed", alert.id);
+    show(state, &alert);
+
+    Some(alert)
+}
+
+fn show(state: &BotState, alert: &QueuedAlert) {
+    overlay::push(
+        &state.overlay,
+        OverlayEvent::Alert {
+            id: alert.id,
+            kind: alert.kind,
+            text: alert.text.clone(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn follow(queue: &AlertQueue, user_id: &str) -> QueuedAlert {
+        queue
+            .add(
+                AlertKind::Follow,
+                Some(user_id.to_string()),
+                user_id.to_string(),
+                format!("{user_id} just followed!"),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn latest_alert_is_skipped_and_replayed_by_default() {
+        let queue = AlertQueue::default();
+
+        follow(&queue, "1").await;
+        follow(&queue, "2").await;
+
+        assert_eq!(queue.mark_skipped(None).await.unwrap().id, 2);
+        assert_eq!(queue.mark_skipped(None).await.unwrap().id, 1);
+        assert!(queue.mark_skipped(None).await.is_none());
+
+        let replayed = queue.mark_replayed(None).await.unwrap();
+
+        assert_eq!(
+            (replayed.id, replayed.skipped, replayed.replays),
+            (2, false, 1)
+        );
+
+        queue.forget("2").await;
+
+        assert_eq!(follow(&queue, "3").await.id, 3);
+    }
+}
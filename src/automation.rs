@@ -0,0 +1,500 @@
+//! Named pipelines running actions on the channel events.
+//!
+//! The pipelines are read from `automation.toml` in the application directory on
+//! start and on `/api/automation/reload`, e.g.
+//!
+//! ```toml
+//! [pipelines.big_raid]
+//! on = "raid"
+//! when = { min_viewers = 10 }
+//! actions = [
+//!     { say = "Welcome {name} and {viewers} raiders!" },
+//!     { add_to_list = { list = "raiders", value = "{name}" } },
+//!     { webhook = "https://example.com/raid" },
+//!     { overlay = "Raid from {name}!" },
+//!     { increment_kv = "raids" },
+//! ]
+//!
+//! [pipelines.giveaway]
+//! on = "chat_message"
+//! when = { starts_with = "!join" }
+//! cooldown_sec = 0
+//! actions = [{ add_to_list = { list = "giveaway", value = "{name}" } }]
+//!
+//! [pipelines.stream_start]
+//! on = "stream_online"
+//! actions = [
+//!     "reset_session",
+//!     { say = "The stream has started, welcome!" },
+//!     { start_timers = ["socials"] },
+//!     { intro = "Welcome to {kv:run}!" },
+//! ]
+//! ```
+//!
+//! A pipeline runs when an event of the `on` kind meets every condition of `when`,
+//! `{name}`, `{text}` and `{viewers}` in the actions are filled from the event and
+//! `{kv:<key>}` from [`crate::kv`].
+//! The lists are kept in `automation_lists.json` and served on `/api/automation/lists`.
+//! `reset_session` clears the session lists of the credits, `start_timers` starts the
+//! named timers of [`crate::scheduler`] waiting for a start.
+//! The webhooks are called in the background so a slow endpoint does not hold the events.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::events::{BotEvent, EventKind};
+use crate::helper::BotState;
+use crate::overlay::{self, OverlayEvent};
+use crate::storage::JsonStore;
+use crate::{config, i18n, kv, metrics, scheduler, sessions};
+
+const PIPELINES_FILE_NAME: &str = "automation.toml";
+const LISTS_FILE_NAME: &str = "automation_lists.json";
+/// Keeps a chat pipeline from firing on every message of a spam wave
+const DEFAULT_COOLDOWN_SEC: u64 = 5;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Default)]
+struct PipelineFile {
+    #[serde(default)]
+    pipelines: BTreeMap<String, Pipeline>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    pub on: EventKind,
+    #[serde(default)]
+    pub when: Condition,
+    pub actions: Vec<Action>,
+    /// Seconds before the pipeline runs again
+    #[serde(default = "default_cooldown_sec")]
+    pub cooldown_sec: u64,
+}
+
+fn default_cooldown_sec() -> u64 {
+    DEFAULT_COOLDOWN_SEC
+}
+
+/// All the set conditions have to be met, the ones the event has no data for are not
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Condition {
+    /// Text of the chat message contains it, case-insensitively
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    /// Text of the chat message starts with it, case-insensitively
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_with: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderator: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_viewers: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Action {
+    /// Post the message to the chat
+    Say(String),
+    AddToList {
+        list: String,
+        value: String,
+    },
+    /// POST the event as JSON to the URL
+    Webhook(String),
+    /// Show the text as an announcement on the overlay
+    Overlay(String),
+    SetKv {
+        key: String,
+        value: String,
+    },
+    /// Add one to the number stored under the key
+    IncrementKv(String),
+    /// Clear the session lists, e.g. of the chat before the stream
+    ResetSession,
+    /// Start the timers of the names
+    StartTimers(Vec<String>),
+    /// Play the intro animation with the text on the overlay
+    Intro(String),
+}
+
+/// Data of an event the conditions and the actions use
+#[derive(Debug, Default)]
+struct Facts<'a> {
+    user_name: Option<&'a str>,
+    text: Option<&'a str>,
+    viewers: Option<u64>,
+    is_moderator: bool,
+}
+
+impl<'a> Facts<'a> {
+    fn of(event: &'a BotEvent) -> Self {
+        match event {
+            BotEvent::ChatMessage {
+                user_name,
+                text,
+                is_moderator,
+                ..
+            } => Facts {
+                user_name: Some(user_name),
+                text: Some(text),
+                is_moderator: *is_moderator,
+                ..Facts::default()
+            },
+            BotEvent::Follow { user_name, .. }
+            | BotEvent::Subscribe { user_name, .. }
+            | BotEvent::Resubscribe { user_name, .. } => Facts {
+                user_name: Some(user_name),
+                ..Facts::default()
+            },
+            BotEvent::GiftSubscriptions { user_name, .. } | BotEvent::Cheer { user_name, .. } => {
+                Facts {
+                    user_name: user_name.as_deref(),
+                    ..Facts::default()
+                }
+            }
+            BotEvent::Raid {
+                user_name, viewers, ..
+            } => Facts {
+                user_name: Some(user_name),
+                viewers: Some(*viewers),
+                ..Facts::default()
+            },
+            BotEvent::CategoryChanged { category } => Facts {
+                text: Some(category),
+                ..Facts::default()
+            },
+            BotEvent::StreamOnline | BotEvent::StreamOffline | BotEvent::AdBreak { .. } => {
+                Facts::default()
+            }
+        }
+    }
+
+    fn fill(&self, template: &str) -> String {
+        let name = self.user_name.unwrap_or_default();
+        let text = self.text.unwrap_or_default();
+        let viewers = self.viewers.unwrap_or_default();
+        let args: [(&str, &(dyn Display + Sync)); 3] =
+            [("name", &name), ("text", &text), ("viewers", &viewers)];
+
+        kv::expand(&i18n::fill(template, &args))
+    }
+}
+
+impl Condition {
+    fn matches(&self, facts: &Facts) -> bool {
+        let text = facts.text.map(str::to_lowercase);
+
+        self.contains.as_ref().is_none_or(|needle| {
+            text.as_ref()
+                .is_some_and(|text| text.contains(&needle.to_lowercase()))
+        }) && self.starts_with.as_ref().is_none_or(|prefix| {
+            text.as_ref()
+                .is_some_and(|text| text.starts_with(&prefix.to_lowercase()))
+        }) && self.user.as_ref().is_none_or(|user| {
+            facts
+                .user_name
+                .is_some_and(|name| name.eq_ignore_ascii_case(user))
+        }) && self
+            .moderator
+            .is_none_or(|moderator| facts.is_moderator == moderator)
+            && self.min_viewers.is_none_or(|min_viewers| {
+                facts.viewers.is_some_and(|viewers| viewers >= min_viewers)
+            })
+    }
+}
+
+#[derive(Default)]
+pub struct Automation {
+    pipelines: Mutex<BTreeMap<String, Pipeline>>,
+    last_runs: Mutex<HashMap<String, Instant>>,
+}
+
+pub type SafeAutomation = Arc<Automation>;
+
+impl Automation {
+    /// Read the pipelines again, returns how many there are
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be parsed, the loaded pipelines are kept then
+    pub async fn reload(&self) -> io::Result<usize> {
+        let path = config::get_app_directory_path().join(PIPELINES_FILE_NAME);
+        let pipelines = match fs::read_to_string(&path) {
+            Ok(content) => parse(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        let count = pipelines.len();
+
+        *self.pipelines.lock().await = pipelines;
+        self.last_runs.lock().await.clear();
+        tracing::info!("{count} automation pipelines loaded");
+
+        Ok(count)
+    }
+
+    pub async fn pipelines(&self) -> BTreeMap<String, Pipeline> {
+        self.pipelines.lock().await.clone()
+    }
+
+    /// Pipelines to run for the event, their cooldown starts
+    async fn triggered(&self, event: &BotEvent) -> Vec<(String, Pipeline)> {
+        let facts = Facts::of(event);
+        let now = Instant::now();
+        let mut last_runs = self.last_runs.lock().await;
+
+        self.pipelines
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, pipeline)| pipeline.on == event.kind() && pipeline.when.matches(&facts))
+            .filter(|(name, pipeline)| {
+                let cooldown = Duration::from_secs(pipeline.cooldown_sec);
+                let ready = last_runs
+                    .get(*name)
+                    .is_none_or(|last_run| now.duration_since(*last_run) >= cooldown);
+
+                if ready {
+                    last_runs.insert((*name).clone(), now);
+                }
+
+                ready
+            })
+            .map(|(name, pipeline)| (name.clone(), pipeline.clone()))
+            .collect()
+    }
+}
+
+pub fn create_automation() -> SafeAutomation {
+    Arc::new(Automation::default())
+}
+
+/// Lists filled by the `add_to_list` actions
+#[must_use]
+pub fn lists() -> BTreeMap<String, Vec<String>> {
+    JsonStore::<BTreeMap<String, Vec<String>>>::open(LISTS_FILE_NAME).clone()
+}
+
+/// Run the pipelines on the events of the bus
+pub async fn run_automation(state: BotState) {
+    if let Err(e) = state.automation.reload().await {
+        tracing::warn!("Unable to load {PIPELINES_FILE_NAME}: {e}");
+    }
+
+    let mut events = state.bus.subscribe();
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("automation lagged, {skipped} events skipped");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        // the other instances see the same events
+        if !state.instance.is_leader() {
+            continue;
+        }
+
+        for (name, pipeline) in state.automation.triggered(&event).await {
+            tracing::debug!("automation pipeline {name} runs");
+            metrics::increment("automation_pipeline");
+
+            for action in &pipeline.actions {
+                run_action(&state, &name, &event, action).await;
+            }
+        }
+    }
+}
+
+async fn run_action(state: &BotState, pipeline: &str, event: &BotEvent, action: &Action) {
+    let facts = Facts::of(event);
+
+    match action {
+        Action::Say(template) => state.say(facts.fill(template)),
+        Action::AddToList { list, value } => {
+            let value = facts.fill(value);
+
+            JsonStore::<BTreeMap<String, Vec<String>>>::open(LISTS_FILE_NAME).update(|lists| {
+                let entries = lists.entry(list.clone()).or_default();
+
+                if !entries.contains(&value) {
+                    entries.push(value);
+                }
+            });
+        }
+        Action::Webhook(url) => {
+            let body = json!({
+                "pipeline": pipeline,
+                "user_name": facts.user_name,
+                "text": facts.text,
+                "viewers": facts.viewers,
+            });
+            let client = match webhook_client() {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::warn!("Unable to create the webhook client: {e}");
+                    return;
+                }
+            };
+            let request = client.post(url).json(&body).send();
+            let pipeline = pipeline.to_owned();
+
+            tokio::spawn(async move {
+                match metrics::timed("automation_webhook", request).await {
+                    Ok(response) => {
+                        if let Err(e) = response.error_for_status() {
+                            tracing::warn!(
+                                "Webhook of pipeline {pipeline} rejected the event: {e}"
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Unable to call the webhook of pipeline {pipeline}: {e}");
+                    }
+                }
+            });
+        }
+        Action::Overlay(template) => overlay::push(
+            &state.overlay,
+            OverlayEvent::Announcement {
+                text: facts.fill(template),
+            },
+        ),
+        Action::SetKv { key, value } => {
+            if let Err(e) = kv::set(key, &facts.fill(value)) {
+                tracing::warn!("Pipeline {pipeline} is unable to set {key}: {e}");
+            }
+        }
+        Action::IncrementKv(key) => {
+            if let Err(e) = kv::increment(key, 1) {
+                tracing::warn!("Pipeline {pipeline} is unable to increment {key}: {e}");
+            }
+        }
+        Action::ResetSession => sessions::reset(state).await,
+        Action::StartTimers(names) => {
+            for name in names {
+                scheduler::start_timer(state, name);
+            }
+        }
+        Action::Intro(template) => overlay::push(
+            &state.overlay,
+            OverlayEvent::Intro {
+                text: facts.fill(template),
+            },
+        ),
+    }
+}
+
+fn webhook_client() -> reqwest::Result<&'static reqwest::Client> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+fn parse(content: &str) -> io::Result<BTreeMap<String, Pipeline>> {
+    toml::from_str::<PipelineFile>(content)
+        .map(|file| file.pipelines)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINES: &str = r#"
+        [pipelines.big_raid]
+        on = "raid"
+        when = { min_viewers = 10 }
+        actions = [{ say = "Welcome {name} and {viewers} raiders!" }]
+
+        [pipelines.giveaway]
+        on = "chat_message"
+        when = { starts_with = "!JOIN", moderator = false }
+        cooldown_sec = 0
+        actions = [{ add_to_list = { list = "giveaway", value = "{name}" } }]
+
+        [pipelines.stream_start]
+        on = "stream_online"
+        actions = ["reset_session", { start_timers = ["socials"] }, { intro = "Hi!" }]
+    "#;
+
+    fn raid(viewers: u64) -> BotEvent {
+        BotEvent::Raid {
+            user_id: String::from("2"),
+            user_name: String::from("Raider"),
+            viewers,
+        }
+    }
+
+    fn message(text: &str, is_moderator: bool) -> BotEvent {
+        BotEvent::ChatMessage {
+            user_id: String::from("1"),
+            user_name: String::from("Viewer"),
+            text: text.to_string(),
+            is_moderator,
+            is_broadcaster: false,
+        }
+    }
+
+    async fn triggered(automation: &Automation, event: &BotEvent) -> Vec<String> {
+        automation
+            .triggered(event)
+            .await
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn pipelines_run_on_matching_events() {
+        let automation = Automation::default();
+
+        *automation.pipelines.lock().await = parse(PIPELINES).unwrap();
+
+        assert_eq!(triggered(&automation, &raid(42)).await, ["big_raid"]);
+        // cooled down
+        assert!(triggered(&automation, &raid(42)).await.is_empty());
+        assert!(triggered(&automation, &raid(5)).await.is_empty());
+        assert_eq!(
+            triggered(&automation, &message("!join please", false)).await,
+            ["giveaway"]
+        );
+        assert_eq!(
+            triggered(&automation, &message("!join again", false)).await,
+            ["giveaway"]
+        );
+        assert!(triggered(&automation, &message("!join", true))
+            .await
+            .is_empty());
+        assert_eq!(
+            triggered(&automation, &BotEvent::StreamOnline).await,
+            ["stream_start"]
+        );
+        assert_eq!(
+            Facts::of(&raid(42)).fill("Welcome {name} and {viewers} raiders!"),
+            "Welcome Raider and 42 raiders!"
+        );
+        assert!(parse("[pipelines.typo]\non = \"raid\"\nactoins = []").is_err());
+    }
+}
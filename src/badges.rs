@@ -0,0 +1,167 @@
+//! Chat badges and name colors of the chatters for the chat overlay and the credits.
+//!
+//! The badges and the color come with every chat message, the badge images are
+//! looked up in Helix Get Global Chat Badges and Get Channel Chat Badges, the channel
+//! badges replace the global ones, e.g. the subscriber badges of the channel.
+//! The images are fetched in the background, the messages seen before have no images.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use twitch_api::helix::chat::{BadgeSet, GetChannelChatBadgesRequest, GetGlobalChatBadgesRequest};
+use twitch_api::helix::HelixClient;
+
+use crate::eventsub::get_eventsub_token;
+use crate::metrics;
+use crate::transport::create_api_client;
+
+/// How often the badge images are fetched again, e.g. for new subscriber badges
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Delay before fetching again after a failure
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const SUBSCRIBER_BADGE: &str = "subscriber";
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatBadge {
+    /// Badge set, e.g. `subscriber` or `moderator`
+    pub name: String,
+    /// Version in the set, e.g. the subscription months for `subscriber`
+    pub version: String,
+    /// `None` if the image is not known
+    pub image: Option<String>,
+}
+
+/// How the user appears in the chat
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatStyle {
+    /// Name color as `#RRGGBB`, `None` if the user has not chosen one
+    pub color: Option<String>,
+    pub badges: Vec<ChatBadge>,
+}
+
+impl ChatStyle {
+    /// Image of the subscriber badge, shown in the credits
+    #[must_use]
+    pub fn subscriber_badge(&self) -> Option<&str> {
+        self.badges
+            .iter()
+            .find(|badge| badge.name == SUBSCRIBER_BADGE)
+            .and_then(|badge| badge.image.as_deref())
+    }
+}
+
+#[derive(Default)]
+struct BadgeCache {
+    /// Image of the badge set and version
+    images: HashMap<(String, String), String>,
+    next_fetch: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct BadgeImages {
+    cache: Mutex<BadgeCache>,
+}
+
+pub type SafeBadgeImages = Arc<BadgeImages>;
+
+impl BadgeImages {
+    /// Style of the user with the images of the badges known so far
+    pub async fn style<'a, I>(self: &Arc<Self>, color: Option<String>, badges: I) -> ChatStyle
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut cache = self.cache.lock().await;
+
+        if cache
+            .next_fetch
+            .is_none_or(|next_fetch| next_fetch <= Instant::now())
+        {
+            // the next messages do not start another fetch while this one runs
+            cache.next_fetch = Some(Instant::now() + RETRY_INTERVAL);
+            self.refresh();
+        }
+
+        let badges = badges
+            .into_iter()
+            .map(|(name, version)| ChatBadge {
+                name: name.to_string(),
+                version: version.to_string(),
+                image: cache
+                    .images
+                    .get(&(name.to_string(), version.to_string()))
+                    .cloned(),
+            })
+            .collect();
+
+        ChatStyle { color, badges }
+    }
+
+    /// Fetch the images in the background, a failed fetch is retried after `RETRY_INTERVAL`
+    fn refresh(self: &Arc<Self>) {
+        let images = Arc::clone(self);
+
+        tokio::spawn(async move {
+            if let Some(fetched) = fetch_badge_images().await {
+                let mut cache = images.cache.lock().await;
+
+                cache.images = fetched;
+                cache.next_fetch = Some(Instant::now() + REFRESH_INTERVAL);
+            }
+        });
+    }
+}
+
+/// Images of the global badges and of the channel ones, `None` if they cannot be fetched
+async fn fetch_badge_images() -> Option<HashMap<(String, String), String>> {
+    let Some(token) = get_eventsub_token().await else {
+        tracing::debug!("no EventSub token yet, badge images are not fetched");
+        return None;
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let global = metrics::timed(
+        "helix_get_global_chat_badges",
+        client.req_get(GetGlobalChatBadgesRequest::new(), &token),
+    )
+    .await;
+    let channel = metrics::timed(
+        "helix_get_channel_chat_badges",
+        client.req_get(
+            GetChannelChatBadgesRequest::broadcaster_id(&token.user_id),
+            &token,
+        ),
+    )
+    .await;
+
+    match (global, channel) {
+        (Ok(global), Ok(channel)) => {
+            Some(badge_images(global.data.into_iter().chain(channel.data)))
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::warn!("Unable to fetch the chat badges: {e}");
+            None
+        }
+    }
+}
+
+/// Images of the badge versions, a later set replaces the versions of an earlier one
+fn badge_images(sets: impl IntoIterator<Item = BadgeSet>) -> HashMap<(String, String), String> {
+    let mut images = HashMap::new();
+
+    for set in sets {
+        for version in set.versions {
+            images.insert(
+                (set.set_id.to_string(), version.id.to_string()),
+                version.image_url_2x,
+            );
+        }
+    }
+
+    images
+}
+
+pub fn create_badge_images() -> SafeBadgeImages {
+    Arc::new(BadgeImages::default())
+}
@@ -0,0 +1,233 @@
+//! Viewer birthdays and follow anniversaries announced when the stream goes online.
+//!
+//! They are announced once a day, a stream back online on the same day is not
+//! announced again. The follow dates are fetched once and kept for the next streams.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{Datelike, Local, NaiveDate};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex};
+use twitch_api::helix::channels::{Follower, GetChannelFollowersRequest};
+use twitch_api::helix::{self, HelixClient};
+
+use crate::events::BotEvent;
+use crate::eventsub::{get_channel_user_id, get_eventsub_token};
+use crate::helper::BotState;
+use crate::overlay::{self, OverlayEvent};
+use crate::storage::JsonStore;
+use crate::transport::create_api_client;
+use crate::{i18n, metrics};
+
+const BIRTHDAYS_FILE_NAME: &str = "birthdays.json";
+const FOLLOWERS_PAGE_SIZE: usize = 100;
+
+/// Follower names with the day they followed on
+type FollowDates = Arc<Vec<(String, NaiveDate)>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Birthday {
+    name: String,
+    month: u32,
+    day: u32,
+}
+
+pub struct BirthdayBook {
+    store: Mutex<JsonStore<HashMap<String, Birthday>>>,
+    /// Fetched for the first announcement
+    follow_dates: Mutex<Option<FollowDates>>,
+    announced_on: Mutex<Option<NaiveDate>>,
+}
+
+pub type SafeBirthdayBook = Arc<BirthdayBook>;
+
+impl BirthdayBook {
+    fn open() -> Self {
+        BirthdayBook {
+            store: Mutex::new(JsonStore::open(BIRTHDAYS_FILE_NAME)),
+            follow_dates: Mutex::new(None),
+            announced_on: Mutex::new(None),
+        }
+    }
+
+    /// Remember the birthday given in the `MM-DD` format
+    pub async fn register(&self, user_id: &str, name: &str, date: &str) -> Option<(u32, u32)> {
+        let (month, day) = parse_month_day(date)?;
+
+        self.store.lock().await.update(|birthdays| {
+            birthdays.insert(
+                user_id.to_string(),
+                Birthday {
+                    name: name.to_string(),
+                    month,
+                    day,
+                },
+            );
+        });
+
+        Some((month, day))
+    }
+
+    /// Month and day of the user's birthday
+    pub async fn birthday_of(&self, user_id: &str) -> Option<(u32, u32)> {
+        self.store
+            .lock()
+            .await
+            .get(user_id)
+            .map(|birthday| (birthday.month, birthday.day))
+    }
+
+    pub async fn forget(&self, user_id: &str) -> bool {
+        self.store
+            .lock()
+            .await
+            .update(|birthdays| birthdays.remove(user_id).is_some())
+    }
+
+    async fn celebrating_on(&self, date: NaiveDate) -> Vec<String> {
+        let guard = self.store.lock().await;
+        let mut names: Vec<_> = guard
+            .values()
+            .filter(|birthday| birthday.month == date.month() && birthday.day == date.day())
+            .map(|birthday| birthday.name.clone())
+            .collect();
+
+        names.sort();
+
+        names
+    }
+
+    /// Followers with their follow dates, fetched on the first call
+    async fn follow_dates(&self) -> Result<FollowDates, Box<dyn std::error::Error>> {
+        let mut cached = self.follow_dates.lock().await;
+
+        if let Some(ref follow_dates) = *cached {
+            return Ok(Arc::clone(follow_dates));
+        }
+
+        let follow_dates = Arc::new(fetch_follow_dates().await?);
+
+        *cached = Some(Arc::clone(&follow_dates));
+
+        Ok(follow_dates)
+    }
+}
+
+pub fn create_birthday_book() -> SafeBirthdayBook {
+    Arc::new(BirthdayBook::open())
+}
+
+fn parse_month_day(date: &str) -> Option<(u32, u32)> {
+    let (month, day) = date.split_once('-')?;
+    let month = month.parse().ok()?;
+    let day = day.parse().ok()?;
+
+    // leap year, so that 02-29 is accepted
+    NaiveDate::from_ymd_opt(2000, month, day).map(|_| (month, day))
+}
+
+/// Announce the birthdays and follow anniversaries every time the stream goes online
+pub async fn run_announcements(state: BotState) {
+    let mut events = state.bus.subscribe();
+
+    loop {
+        match events.recv().await {
+            Ok(BotEvent::StreamOnline) => {
+                if state.instance.is_leader() {
+                    announce_session_start(&state).await;
+                }
+            }
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Announce today's birthdays and follow anniversaries in chat and on the overlay
+async fn announce_session_start(state: &BotState) {
+    let today = Local::now().date_naive();
+
+    if state.birthdays.announced_on.lock().await.replace(today) == Some(today) {
+        tracing::debug!("birthdays are already announced today");
+        return;
+    }
+
+    let lang = state.languages.channel();
+    let mut announcements: Vec<_> = state
+        .birthdays
+        .celebrating_on(today)
+        .await
+        .into_iter()
+        .map(|name| i18n::render(lang, "birthday.announce", &[("name", &name)]))
+        .collect();
+
+    match state.birthdays.follow_dates().await {
+        Ok(follow_dates) => {
+            let anniversaries = follow_anniversaries(&follow_dates, today);
+
+            announcements.extend(anniversaries.into_iter().map(|(name, years)| {
+                i18n::render(
+                    lang,
+                    "anniversary.announce",
+                    &[
+                        ("name", &name),
+                        ("years", &years),
+                        ("years_word", &i18n::years_word(lang, years)),
+                    ],
+                )
+            }));
+        }
+        Err(e) => tracing::warn!("Unable to get follow anniversaries: {e}"),
+    }
+
+    for text in announcements {
+        state.say(text.as_str());
+        overlay::push(&state.overlay, OverlayEvent::Announcement { text });
+    }
+}
+
+async fn fetch_follow_dates() -> Result<Vec<(String, NaiveDate)>, Box<dyn std::error::Error>> {
+    let client = HelixClient::with_client(create_api_client());
+    let token = get_eventsub_token()
+        .await
+        .ok_or("EventSub token is not available")?;
+    let broadcaster_id = get_channel_user_id(&client, &token).await;
+    let request =
+        GetChannelFollowersRequest::broadcaster_id(&broadcaster_id).first(FOLLOWERS_PAGE_SIZE);
+    let followers: Vec<Follower> = metrics::timed(
+        "helix_get_channel_followers",
+        helix::make_stream(request, &token, &client, std::collections::VecDeque::from)
+            .try_collect(),
+    )
+    .await?;
+
+    Ok(followers
+        .into_iter()
+        .filter_map(|follower| {
+            let followed_at =
+                chrono::DateTime::parse_from_rfc3339(follower.followed_at.as_str()).ok()?;
+
+            Some((
+                follower.user_name.to_string(),
+                followed_at.with_timezone(&Local).date_naive(),
+            ))
+        })
+        .collect())
+}
+
+/// Followers who followed on this day in an earlier year, with the number of years
+fn follow_anniversaries(
+    follow_dates: &[(String, NaiveDate)],
+    today: NaiveDate,
+) -> Vec<(String, i32)> {
+    follow_dates
+        .iter()
+        .filter_map(|(name, followed_on)| {
+            let years = today.year() - followed_on.year();
+
+            (years > 0 && followed_on.month() == today.month() && followed_on.day() == today.day())
+                .then(|| (name.clone(), years))
+        })
+        .collect()
+}
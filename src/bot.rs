@@ -0,0 +1,460 @@
+//! Entry point of the bot for the binary and for downstream Rust users.
+//!
+//! ```no_run
+//! hewpme::Bot::new()
+//!     .on_event(hewpme::EventKind::Follow, |ctx, event| async move {
+//!         if let hewpme::BotEvent::Follow { user_name, .. } = event {
+//!             ctx.say(format!("Welcome, {user_name}!"));
+//!         }
+//!     })
+//!     .register_command("!hello", |ctx, command| async move {
+//!         ctx.say(format!("Hello, {}!", command.user_name));
+//!     })
+//!     .run();
+//! ```
+//!
+//! A binary of its own can run only some of the clients on the state of
+//! [`Bot::start`], e.g. the chat client without the EventSub one and the web server:
+//!
+//! ```no_run
+//! # async fn embed() -> Result<(), hewpme::InstanceError> {
+//! hewpme::chat::acquire_chat_token().await;
+//!
+//! let (state, chat_outbox) = hewpme::Bot::new().start().await?;
+//!
+//! hewpme::chat::run_twitch_irc_client(state, chat_outbox).await;
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::broadcast;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::chat::{self, run_twitch_irc_client};
+use crate::commands::{CommandGroup, CommandInfo, CommandRegistry, Permission, CONFIRM_COMMAND};
+use crate::config::{self, Feature};
+use crate::events::{BotEvent, EventKind};
+use crate::eventsub::{self, run_eventsub_client};
+use crate::health::Status;
+use crate::helper::{create_bot_state, BotState, ChatOutboxReceiver};
+use crate::instance::InstanceError;
+use crate::{
+    i18n, instance, logs, metrics, outage, redact, safe_mode, server, sessions, startup, storage,
+};
+
+type EventHandler = Arc<dyn Fn(Context, BotEvent) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Handle passed to the custom handlers to act on the channel
+#[derive(Clone)]
+pub struct Context {
+    state: BotState,
+}
+
+impl Context {
+    /// Post a message to the channel chat
+    pub fn say<T: Into<String>>(&self, message: T) {
+        self.state.say(message);
+    }
+}
+
+/// Chat message that invoked a registered command
+#[derive(Debug, Clone)]
+pub struct ChatCommand {
+    pub user_id: String,
+    pub user_name: String,
+    /// Words following the command name
+    pub args: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct Bot {
+    event_handlers: Vec<(EventKind, EventHandler)>,
+    commands: CommandRegistry,
+}
+
+impl Bot {
+    #[must_use]
+    pub fn new() -> Self {
+        Bot::default()
+    }
+
+    /// Run `handler` for every event of the given kind
+    #[must_use]
+    pub fn on_event<F, Fut>(mut self, kind: EventKind, handler: F) -> Self
+    where
+        F: Fn(Context, BotEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.event_handlers.push((
+            kind,
+            Arc::new(move |ctx, event| handler(ctx, event).boxed()),
+        ));
+
+        self
+    }
+
+    /// Run `handler` for chat messages starting with `name`, e.g. `!hello`.
+    /// Built-in commands with the same name are still handled by the bot, except
+    /// `!ban` which is replaced.
+    #[must_use]
+    pub fn register_command<F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(Context, ChatCommand) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.commands.register(
+            name,
+            Arc::new(move |ctx, command| handler(ctx, command).boxed()),
+        );
+
+        self
+    }
+
+    /// Describe the command for `!help` and the `/commands` page,
+    /// moderator-only commands are not run for other viewers
+    #[must_use]
+    pub fn describe_command(
+        mut self,
+        name: &str,
+        description: &str,
+        permission: Permission,
+    ) -> Self {
+        self.commands.describe(CommandInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+            permission,
+            cooldown_sec: None,
+        });
+
+        self
+    }
+
+    /// Run the command only after the user confirms it with `!confirm <code>`,
+    /// e.g. a mass moderation action
+    #[must_use]
+    pub fn confirm_command(mut self, name: &str) -> Self {
+        self.commands.require_confirmation(name);
+
+        self
+    }
+
+    /// Enable `commands` only while streaming in one of `categories`,
+    /// built-in commands can be restricted as well
+    #[must_use]
+    pub fn command_group(mut self, name: &str, categories: &[&str], commands: &[&str]) -> Self {
+        self.commands.add_group(CommandGroup {
+            name: name.to_string(),
+            categories: categories.iter().map(ToString::to_string).collect(),
+            commands: commands.iter().map(ToString::to_string).collect(),
+        });
+
+        self
+    }
+
+    /// Lock the bot instance of the channel and create the state the clients share,
+    /// the registered event and command handlers run on it from now on
+    ///
+    /// The chat messages posted on the state are sent by the chat client run with
+    /// the returned receiver.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if another bot already runs for the channel
+    pub async fn start(mut self) -> Result<(BotState, ChatOutboxReceiver), InstanceError> {
+        let instance = instance::acquire()?;
+        let event_handlers = std::mem::take(&mut self.event_handlers);
+
+        self.commands.load_groups();
+
+        for info in chat::builtin_commands(i18n::channel_language()) {
+            self.commands.describe(info);
+        }
+
+        chat::register_builtin_handlers(&mut self.commands);
+
+        let (state, chat_outbox) =
+            create_bot_state(self.commands, instance.clone(), storage::open_storage());
+        // subscribe before the clients start so that no event is missed
+        let events = state.bus.subscribe();
+        let session_events = state.bus.subscribe();
+
+        tokio::spawn(instance::run_takeover(instance));
+        tokio::spawn(outage::run_outage_monitor(state.clone()));
+        tokio::spawn(dispatch(event_handlers, state.clone(), events));
+        tokio::spawn(sessions::run_session_lists(state.clone(), session_events));
+
+        Ok((state, chat_outbox))
+    }
+
+    /// Acquire the tokens and resolve the channel, then start the chat and EventSub
+    /// clients and the web server, blocks until they exit or Ctrl-C or SIGTERM stops
+    /// the bot. The EventSub client and the web server are not started if their
+    /// features are turned off in the config. After repeated crashes the bot starts in
+    /// safe mode, without chat messages and moderation actions.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the config is not valid, another bot already runs for the channel,
+    /// the async runtime cannot be created, a startup stage times out or one of the
+    /// clients panics
+    pub fn run(self) {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer())
+            .with(logs::layer())
+            .with(LevelFilter::INFO)
+            .init();
+
+        if let Err(e) = config::validate() {
+            tracing::error!("{e}");
+            panic!("{e}");
+        }
+
+        if let Some(profile) = config::get_profile() {
+            tracing::info!("using the {profile} config profile");
+        }
+
+        let (state, chat_outbox) = match rt.block_on(self.start()) {
+            Ok(started) => started,
+            Err(e) => {
+                tracing::error!("{e}");
+                panic!("{e}");
+            }
+        };
+
+        // a follower would count the run of the leader as a crash
+        let leader = state.instance.is_leader();
+
+        if leader {
+            safe_mode::record_start();
+            rt.spawn(safe_mode::run_stability_check());
+        }
+
+        let prepared = rt.block_on(startup::prepare());
+        tracing::info!("startup: services...");
+
+        let server_state = state.clone();
+        let eventsub_state = state.clone();
+        let mut handles = Vec::new();
+
+        if config::is_feature_enabled(Feature::WebServer) {
+            handles.push(rt.spawn(async move {
+                // a follower does not serve the pages, the port is taken by the leader
+                server_state.instance.wait_leader().await;
+                server::run_server(server_state).await;
+            }));
+        } else {
+            tracing::info!("web server is disabled");
+        }
+
+        if !config::is_feature_enabled(Feature::EventSub) {
+            tracing::info!("EventSub client is disabled");
+        } else if let Some((token, user_id)) = prepared.eventsub {
+            handles.push(rt.spawn(async move {
+                run_eventsub_client(eventsub_state, token, user_id).await;
+            }));
+        } else {
+            // the chat notices stand in for the subscriptions, the followers are missed
+            eventsub_state.health.set(
+                eventsub::HEALTH_COMPONENT,
+                Status::Degraded,
+                Some("no EventSub token, the subscriptions are taken from the chat"),
+            );
+        }
+
+        // the chat is read for the credits even if the chat bot is disabled
+        handles.push(rt.spawn(async move {
+            run_twitch_irc_client(state, chat_outbox).await;
+        }));
+
+        rt.block_on(async {
+            let clients = async {
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            };
+
+            tokio::select! {
+                () = clients => {}
+                () = shutdown_signal() => tracing::info!("shutting down"),
+            }
+        });
+
+        // a stop requested by the user is not a crash
+        if leader {
+            safe_mode::record_stop();
+        }
+    }
+}
+
+/// Ctrl-C, or SIGTERM of a service manager
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Unable to handle SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(e) = result {
+                tracing::warn!("Unable to handle Ctrl-C: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+        () = terminate => {}
+    }
+}
+
+async fn dispatch(
+    event_handlers: Vec<(EventKind, EventHandler)>,
+    state: BotState,
+    mut events: broadcast::Receiver<BotEvent>,
+) {
+    let ctx = Context { state };
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("event handlers lagged, {skipped} events skipped");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        for (_, handler) in event_handlers
+            .iter()
+            .filter(|(kind, _)| *kind == event.kind())
+        {
+            tokio::spawn(handler(ctx.clone(), event.clone()));
+        }
+
+        if let BotEvent::ChatMessage {
+            user_id,
+            user_name,
+            text,
+            is_moderator,
+            is_broadcaster,
+        } = event
+        {
+            let commands = &ctx.state.commands;
+            let mut words = text.split_whitespace();
+            let first = words.next();
+
+            if first == Some(CONFIRM_COMMAND) {
+                if ctx.state.instance.is_leader() && config::is_feature_enabled(Feature::ChatBot) {
+                    run_confirmed(&ctx, &user_id, words.next().unwrap_or_default()).await;
+                }
+
+                continue;
+            }
+
+            let Some((name, handler)) =
+                first.and_then(|name| Some((name, commands.handler(name)?)))
+            else {
+                continue;
+            };
+
+            let permitted = commands
+                .info(name)
+                .is_none_or(|info| info.permission.allows(is_moderator, is_broadcaster));
+
+            if !permitted {
+                tracing::debug!("{} is not allowed to use {name}", redact::Name(&user_name));
+                continue;
+            }
+
+            if !ctx.state.instance.is_leader() || !config::is_feature_enabled(Feature::ChatBot) {
+                continue;
+            }
+
+            if ctx.state.command_guard.is_ignored(&user_id).await {
+                tracing::debug!("ignoring the commands of {}", redact::Name(&user_name));
+                continue;
+            }
+
+            if !commands.is_enabled(name, ctx.state.stream.category().await.as_deref()) {
+                tracing::debug!("{name} is disabled in the current stream category");
+                continue;
+            }
+
+            let command = ChatCommand {
+                user_id,
+                user_name,
+                args: words.map(String::from).collect(),
+            };
+
+            if commands.needs_confirmation(name) {
+                request_confirmation(&ctx, name, command).await;
+                continue;
+            }
+
+            tokio::spawn(handler(ctx.clone(), command));
+        }
+    }
+}
+
+/// Hold the command and ask the user to confirm it
+async fn request_confirmation(ctx: &Context, name: &str, command: ChatCommand) {
+    let state = &ctx.state;
+    let lang = state.languages.for_user(&command.user_id).await;
+    let user_name = command.user_name.clone();
+    let code = state.confirmations.request(name, command).await;
+
+    tracing::info!(
+        "{name} of {} waits for confirmation",
+        redact::Name(&user_name)
+    );
+    ctx.say(i18n::render(
+        lang,
+        "confirm.required",
+        &[
+            ("name", &user_name),
+            ("command", &name),
+            ("code", &code),
+            ("seconds", &state.confirmations.timeout().as_secs()),
+        ],
+    ));
+}
+
+/// Run the command of the user confirmed with `!confirm <code>`
+async fn run_confirmed(ctx: &Context, user_id: &str, code: &str) {
+    let state = &ctx.state;
+    let confirmed = state.confirmations.confirm(user_id, code).await;
+    let Some((name, handler, command)) = confirmed.and_then(|(name, command)| {
+        let handler = state.commands.handler(&name)?.clone();
+
+        Some((name, handler, command))
+    }) else {
+        let lang = state.languages.for_user(user_id).await;
+
+        ctx.say(i18n::render(lang, "confirm.unknown", &[]));
+        return;
+    };
+
+    tracing::info!(
+        "{name} of {} is confirmed",
+        redact::Name(&command.user_name)
+    );
+    metrics::increment("command_confirmed");
+    tokio::spawn(handler(ctx.clone(), command));
+}
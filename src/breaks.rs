@@ -0,0 +1,100 @@
+//! Breaks announced by switching the broadcasting software to a break scene.
+//!
+//! The scene is reported on `/api/scene` with the admin secret, e.g. by an OBS script.
+//! On a scene of `HEWPME_BREAK_SCENES` the bot posts `HEWPME_BREAK_MESSAGE` and turns on
+//! the slow mode for `HEWPME_BREAK_SLOW_MODE_SEC`, on any other scene it posts
+//! `HEWPME_BREAK_END_MESSAGE` and puts the slow mode back to what it was before the break.
+//! A slow mode at least as strict turned on by a moderator before the break is left as it is.
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::helper::BotState;
+use crate::{config, i18n, moderation};
+
+#[derive(Debug, Clone, Copy)]
+struct OngoingBreak {
+    /// The slow mode wait time from before the break, `Some(None)` for no slow mode,
+    /// `None` if the break did not change the slow mode
+    previous_slow_mode: Option<Option<u64>>,
+}
+
+#[derive(Default)]
+pub struct BreakTracker {
+    ongoing: Mutex<Option<OngoingBreak>>,
+}
+
+pub type SafeBreakTracker = Arc<BreakTracker>;
+
+pub fn create_break_tracker() -> SafeBreakTracker {
+    Arc::new(BreakTracker::default())
+}
+
+fn is_break_scene(scene: &str) -> bool {
+    config::get_break_scenes()
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(scene))
+}
+
+/// Turn on the slow mode for the break, returns the slow mode to restore after it
+async fn start_slow_mode(wait_sec: u64) -> Option<Option<u64>> {
+    let Some(settings) = moderation::chat_settings().await else {
+        tracing::warn!("Unable to read the chat settings, the slow mode is left as it is");
+        return None;
+    };
+    let previous = settings
+        .slow_mode
+        .then_some(settings.slow_mode_wait_time)
+        .flatten();
+
+    if previous.is_some_and(|previous| previous >= wait_sec) {
+        return None;
+    }
+
+    moderation::set_slow_mode(Some(wait_sec))
+        .await
+        .then_some(previous)
+}
+
+/// Start or end the break on a scene change
+pub async fn scene_changed(state: &BotState, scene: &str) {
+    // the other instances get the same scene changes
+    if !state.instance.is_leader() {
+        return;
+    }
+
+    let mut ongoing = state.breaks.ongoing.lock().await;
+    let lang = state.languages.channel();
+
+    match (*ongoing, is_break_scene(scene)) {
+        (None, true) => {
+            let wait_sec = config::get_break_slow_mode_sec();
+            let previous_slow_mode = if wait_sec > 0 {
+                start_slow_mode(wait_sec).await
+            } else {
+                None
+            };
+
+            tracing::info!("break started in scene {scene}");
+            *ongoing = Some(OngoingBreak { previous_slow_mode });
+            state.say(
+                config::get_break_message()
+                    .unwrap_or_else(|| i18n::render(lang, "break.started", &[])),
+            );
+        }
+        (Some(OngoingBreak { previous_slow_mode }), false) => {
+            tracing::info!("break ended in scene {scene}");
+            *ongoing = None;
+
+            if let Some(previous) = previous_slow_mode {
+                moderation::set_slow_mode(previous).await;
+            }
+
+            state.say(
+                config::get_break_end_message()
+                    .unwrap_or_else(|| i18n::render(lang, "break.ended", &[])),
+            );
+        }
+        _ => (),
+    }
+}
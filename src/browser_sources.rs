@@ -0,0 +1,68 @@
+//! Browser source URLs of the pages served by the bot, ready to be pasted into OBS.
+//!
+//! The URLs point to the LAN address of the machine running the bot, so that the
+//! sources also work when OBS runs on another PC. The overlays connected to the
+//! WebSocket carry a token of their own if the overlays are protected.
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+use serde::Serialize;
+
+use crate::config::{self, Feature};
+use crate::overlay_auth;
+
+/// Any routable address, used to pick the outgoing interface, nothing is sent to it
+const ROUTE_PROBE_ADDR: &str = "192.0.2.1:80";
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BrowserSource {
+    pub name: &'static str,
+    pub url: String,
+    /// Recommended size of the source in OBS
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Browser sources of the enabled pages
+#[must_use]
+pub fn browser_sources() -> Vec<BrowserSource> {
+    let base = format!("http://{}:{}", local_ip(), config::get_server_port());
+    // the pages connected to the overlay WebSocket get the token
+    let mut pages = vec![
+        ("credits", "/", 1920, 1080, false),
+        ("credits (avatars)", "/?layout=grid", 1920, 1080, false),
+    ];
+
+    if config::is_feature_enabled(Feature::Overlays) {
+        pages.push(("alerts", "/overlay", 1920, 1080, true));
+        pages.push(("chat", "/chat", 400, 800, true));
+        pages.push(("wheel", "/wheel", 800, 800, true));
+
+        if config::get_highlights_page_enabled() {
+            pages.push(("highlights", "/highlights", 400, 800, true));
+        }
+    }
+
+    pages
+        .into_iter()
+        .map(|(name, path, width, height, connected)| BrowserSource {
+            name,
+            url: match overlay_auth::issue(name).filter(|_| connected) {
+                Some(token) => format!("{base}{path}?token={token}"),
+                None => format!("{base}{path}"),
+            },
+            width,
+            height,
+        })
+        .collect()
+}
+
+/// Address of the interface used for the outgoing traffic, the loopback one if there is none
+fn local_ip() -> IpAddr {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            // connecting a UDP socket only selects the route
+            socket.connect(ROUTE_PROBE_ADDR)?;
+            socket.local_addr()
+        })
+        .map_or(IpAddr::V4(Ipv4Addr::LOCALHOST), |addr| addr.ip())
+}
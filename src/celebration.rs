@@ -0,0 +1,212 @@
+//! Emote-only chat celebrating a big gift bomb or a huge raid.
+//!
+//! With `HEWPME_CELEBRATION` a gift of `HEWPME_CELEBRATION_GIFTS` subscriptions or more,
+//! or a raid of `HEWPME_CELEBRATION_RAID_VIEWERS` viewers or more, turns on the
+//! emote-only chat for `HEWPME_CELEBRATION_SEC` and announces it, then the chat is
+//! reverted. An emote-only chat turned on by a moderator is left as it is. The revert is
+//! retried until it succeeds, and done on the next start if the bot stopped before it.
+//! Requires the moderator:manage:announcements permission for the announcements, the
+//! plain chat message is sent without it.
+use std::future;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use twitch_api::helix::chat::AnnouncementColor;
+
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::storage::JsonStore;
+use crate::{config, i18n, metrics, moderation};
+
+/// Whether the emote-only chat of a celebration is on and still has to be reverted
+const STATE_FILE_NAME: &str = "celebration.json";
+const REVERT_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Event worth the celebration
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Celebration {
+    Gifts {
+        user_name: Option<String>,
+        count: u64,
+    },
+    Raid {
+        user_name: String,
+        viewers: u64,
+    },
+}
+
+/// The celebration of the event if it reaches the thresholds
+fn celebration(event: &BotEvent, min_gifts: u64, min_raid_viewers: u64) -> Option<Celebration> {
+    match event {
+        BotEvent::GiftSubscriptions {
+            user_name, count, ..
+        } if *count >= min_gifts => Some(Celebration::Gifts {
+            user_name: user_name.clone(),
+            count: *count,
+        }),
+        BotEvent::Raid {
+            user_name, viewers, ..
+        } if *viewers >= min_raid_viewers => Some(Celebration::Raid {
+            user_name: user_name.clone(),
+            viewers: *viewers,
+        }),
+        _ => None,
+    }
+}
+
+pub async fn run_celebrations(state: BotState) {
+    if !config::get_celebration_enabled() {
+        return;
+    }
+
+    let mut events = state.bus.subscribe();
+    // the previous run stopped in the middle of a celebration
+    let mut revert_at = (*JsonStore::<bool>::open(STATE_FILE_NAME) && state.instance.is_leader())
+        .then(Instant::now);
+
+    loop {
+        let revert = async {
+            match revert_at {
+                Some(until) => tokio::time::sleep_until(until).await,
+                None => future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Some(celebration) = celebration(
+                    &event,
+                    config::get_celebration_gifts(),
+                    config::get_celebration_raid_viewers(),
+                ) else {
+                    continue;
+                };
+
+                // a celebration running already is not extended
+                if revert_at.is_some() || !state.instance.is_leader() {
+                    continue;
+                }
+
+                // the emote-only chat of a moderator is not the bot's to turn off
+                match moderation::chat_settings().await {
+                    Some(settings) if !settings.emote_mode => {}
+                    _ => continue,
+                }
+
+                if moderation::set_emote_mode(true).await {
+                    let duration = config::get_celebration_duration();
+
+                    tracing::info!("celebrating {celebration:?} for {} s", duration.as_secs());
+                    metrics::increment("celebration");
+                    save_reverting(true);
+                    revert_at = Some(Instant::now() + duration);
+                    announce(&state, &text(&state, &celebration, duration.as_secs())).await;
+                }
+            },
+            () = revert => {
+                if !moderation::set_emote_mode(false).await {
+                    revert_at = Some(Instant::now() + REVERT_RETRY_DELAY);
+                    continue;
+                }
+
+                revert_at = None;
+                save_reverting(false);
+                announce(
+                    &state,
+                    &i18n::render(state.languages.channel(), "celebration.ended", &[]),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+fn save_reverting(reverting: bool) {
+    if let Err(e) =
+        JsonStore::<bool>::open(STATE_FILE_NAME).try_update(|stored| *stored = reverting)
+    {
+        tracing::warn!("Unable to save the celebration state: {e}");
+    }
+}
+
+fn text(state: &BotState, celebration: &Celebration, seconds: u64) -> String {
+    let lang = state.languages.channel();
+
+    match celebration {
+        Celebration::Gifts {
+            user_name: Some(user_name),
+            count,
+        } => i18n::render(
+            lang,
+            "celebration.gifts",
+            &[("name", user_name), ("count", count), ("seconds", &seconds)],
+        ),
+        Celebration::Gifts {
+            user_name: None,
+            count,
+        } => i18n::render(
+            lang,
+            "celebration.anonymous_gifts",
+            &[("count", count), ("seconds", &seconds)],
+        ),
+        Celebration::Raid { user_name, viewers } => i18n::render(
+            lang,
+            "celebration.raid",
+            &[
+                ("name", user_name),
+                ("viewers", viewers),
+                ("seconds", &seconds),
+            ],
+        ),
+    }
+}
+
+/// Highlighted announcement, a plain chat message if it cannot be sent
+async fn announce(state: &BotState, text: &str) {
+    if !moderation::send_announcement(text, AnnouncementColor::Purple).await {
+        state.say(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_big_events_are_celebrated() {
+        let gifts = |count: u64| BotEvent::GiftSubscriptions {
+            user_id: None,
+            user_name: None,
+            count,
+        };
+        let raid = |viewers: u64| BotEvent::Raid {
+            user_id: String::from("2"),
+            user_name: String::from("Raider"),
+            viewers,
+        };
+
+        assert_eq!(
+            celebration(&gifts(100), 100, 500),
+            Some(Celebration::Gifts {
+                user_name: None,
+                count: 100,
+            })
+        );
+        assert_eq!(celebration(&gifts(50), 100, 500), None);
+        assert_eq!(
+            celebration(&raid(800), 100, 500),
+            Some(Celebration::Raid {
+                user_name: String::from("Raider"),
+                viewers: 800,
+            })
+        );
+        assert_eq!(celebration(&raid(20), 100, 500), None);
+        assert_eq!(celebration(&BotEvent::StreamOnline, 100, 500), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Stream information asked from Helix for the `!uptime`, `!title` and `!game`
+//! chat commands.
+//!
+//! Each command has a channel-wide cooldown of `HEWPME_INFO_COOLDOWN_SEC`, the
+//! commands used during it are ignored.
+use chrono::{DateTime, Utc};
+use twitch_api::helix::streams::GetStreamsRequest;
+use twitch_api::helix::HelixClient;
+use twitch_api::types::UserIdRef;
+
+use crate::eventsub::get_eventsub_token;
+use crate::metrics;
+use crate::transport::create_api_client;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    pub title: String,
+    pub category: String,
+}
+
+/// Title and category of the own channel
+///
+/// # Errors
+///
+/// Will return `Err` if there is no EventSub token yet or Helix fails
+pub async fn channel() -> Result<Channel, String> {
+    let token = get_eventsub_token()
+        .await
+        .ok_or_else(|| String::from("no EventSub token"))?;
+    let client = HelixClient::with_client(create_api_client());
+    let channel = metrics::timed(
+        "helix_get_channel_information",
+        client.get_channel_from_id(&token.user_id, &token),
+    )
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("channel {} is not found", token.user_id))?;
+
+    Ok(Channel {
+        title: channel.title,
+        category: channel.game_name.to_string(),
+    })
+}
+
+/// Start of the stream, `None` if the channel is offline
+///
+/// # Errors
+///
+/// Will return `Err` if there is no EventSub token yet or Helix fails
+pub async fn live_since() -> Result<Option<DateTime<Utc>>, String> {
+    let token = get_eventsub_token()
+        .await
+        .ok_or_else(|| String::from("no EventSub token"))?;
+    let client = HelixClient::with_client(create_api_client());
+    let ids: &[&UserIdRef] = &[token.user_id.as_ref()];
+    let stream = metrics::timed(
+        "helix_get_streams",
+        client.req_get(GetStreamsRequest::user_ids(ids), &token),
+    )
+    .await
+    .map_err(|e| e.to_string())?
+    .data
+    .into_iter()
+    .next();
+
+    stream
+        .map(|stream| {
+            DateTime::parse_from_rfc3339(stream.started_at.as_str())
+                .map(|started_at| started_at.with_timezone(&Utc))
+                .map_err(|e| e.to_string())
+        })
+        .transpose()
+}
+
+/// Whole hours and the minutes left of the uptime
+#[must_use]
+pub fn hours_minutes(uptime: chrono::Duration) -> (i64, i64) {
+    let minutes = uptime.num_minutes().max(0);
+
+    (minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_is_split_into_hours_and_minutes() {
+        assert_eq!(hours_minutes(chrono::Duration::seconds(59)), (0, 0));
+        assert_eq!(hours_minutes(chrono::Duration::minutes(135)), (2, 15));
+        // the clocks of Twitch and of the bot may disagree a little
+        assert_eq!(hours_minutes(chrono::Duration::seconds(-5)), (0, 0));
+    }
+}
@@ -1,17 +1,85 @@
-/// Requires the following permissions:
-/// - channel:read:subscriptions
-/// - moderator:read:followers
-use std::{env, io};
+//! Twitch IRC client reading the channel chat and running the built-in commands.
+//!
+//! Requires the following permissions:
+//! - channel:read:subscriptions
+//! - moderator:read:followers
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use chrono::{Local, Utc};
+use futures::FutureExt;
+use twitch_auth::TokenStore;
 use twitch_irc::login::{RefreshingLoginCredentials, TokenStorage, UserAccessToken};
-use twitch_irc::message::ServerMessage::Privmsg;
-use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
+use twitch_irc::message::ServerMessage::{ClearChat, Privmsg, Reconnect, UserNotice};
+use twitch_irc::message::{ClearChatAction, PrivmsgMessage, UserNoticeEvent};
+use twitch_irc::{ClientConfig, TwitchIRCClient};
 use twitch_oauth2::Scope;
 
-use crate::config;
-use crate::helper::ChattersList;
-use crate::utils::{CreateContext, Token, Wrapper};
+use crate::alert_queue;
+use crate::automation;
+use crate::birthdays;
+use crate::celebration;
+use crate::channel_info;
+use crate::chat_language;
+use crate::clips;
+use crate::commands::{BuiltinHandler, CommandInfo, CommandRegistry, Permission};
+use crate::config::{self, Feature};
+use crate::cooldown::Cooldowns;
+use crate::copypasta;
+use crate::credit_messages::{self, SubmitError};
+use crate::custom_commands::{self, CustomCommandError};
+use crate::emotes;
+use crate::events::{self, BotEvent};
+use crate::eventsub;
+use crate::health::Status;
+use crate::helper::{BotState, ChatOutboxReceiver};
+use crate::i18n::{self, Lang};
+use crate::idle;
+use crate::kv::{self, KvError};
+use crate::lapses;
+use crate::mentions;
+use crate::metrics;
+use crate::moderation::{self, ChatMode, CommandVerdict};
+use crate::modlog::ChatEntry;
+use crate::notes;
+use crate::notifications::NotificationKind;
+use crate::outage::{self, Source};
+use crate::outbox;
+use crate::overlay::{self, OverlayEvent};
+use crate::poll;
+use crate::presence;
+use crate::privacy;
+use crate::protection;
+use crate::raid_train::{self, RaidTrainError};
+use crate::raids;
+use crate::redact;
+use crate::scheduler;
+use crate::settings;
+use crate::shoutouts::{self, ShoutoutError};
+use crate::text_files;
+use crate::transport::{create_api_client, ChatTransport};
+use crate::utils::{self, Token};
+use crate::wheel::{self, SpinError, SpinSource};
+
+const COINFLIP_TIMEOUT_SEC: u32 = 30;
+const VANISH_TIMEOUT_SEC: u32 = 1;
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Delay before the second join, doubled after every unconfirmed one up to the maximum
+const JOIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+const JOIN_RETRY_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+const HEALTH_COMPONENT: &str = "chat";
+/// How often the held messages are sent again while the chat is down
+const OUTBOX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// Only the latest notes fit into a chat message
+const NOTES_IN_REPLY: usize = 3;
+const COMMANDS_PAGE_SIZE: usize = 10;
+/// `msg-id` tag of the messages highlighted with channel points,
+/// EventSub has no redemption event for this built-in reward
+const HIGHLIGHTED_MESSAGE_ID: &str = "highlighted-message";
+/// Words of a message the built-in commands look at
+const COMMAND_WORDS: usize = 3;
 
 #[derive(Debug)]
 struct ChatTokenStorage;
@@ -22,18 +90,32 @@ impl TokenStorage for ChatTokenStorage {
     type UpdateError = io::Error;
 
     async fn load_token(&mut self) -> Result<UserAccessToken, Self::LoadError> {
-        let chat_config = config::get_chat_config_file();
-        let token = match Token::from_file(chat_config.clone()) {
-            Err(_) => {
+        let tokens = utils::token_manager(config::get_chat_config_file());
+        let token = match tokens.store().load() {
+            Ok(Some(token)) => token,
+            Ok(None) | Err(_) => {
                 let scopes = [Scope::ChatRead, Scope::ChatEdit];
-                let token_create_ctx = CreateContext::new(&scopes, false, config::REDIRECT_URL);
-                let token_handler = Wrapper::new(token_create_ctx).await;
-                let token: Token = token_handler.get_user_token().into();
-                token.save(chat_config)?;
+                let user_token = tokens
+                    .authorize(&create_api_client(), &scopes, false, utils::redirect_url())
+                    .await
+                    .map_err(io::Error::other)?;
+
+                if let Some(bot_account) = config::get_bot_account() {
+                    if !user_token.login.as_str().eq_ignore_ascii_case(&bot_account) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "chat token belongs to {}, not to the bot account {bot_account}",
+                                user_token.login
+                            ),
+                        ));
+                    }
+                }
+
+                tokens.save(&user_token)?;
 
-                token
+                Token::from(&user_token)
             }
-            Ok(token) => token,
         };
 
         Ok(UserAccessToken {
@@ -45,68 +127,1648 @@ impl TokenStorage for ChatTokenStorage {
     }
 
     async fn update_token(&mut self, token: &UserAccessToken) -> Result<(), Self::UpdateError> {
-        let chat_config = config::get_chat_config_file();
-        Ok(Token::from(token).save(chat_config)?)
+        let valid_till = token
+            .expires_at
+            .unwrap_or(token.created_at + Duration::from_secs(600));
+        let tokens = utils::token_manager(config::get_chat_config_file());
+
+        tokens.store().save(&Token {
+            access_token: token.access_token.clone().into(),
+            refresh_token: Some(token.refresh_token.clone().into()),
+            created_at: token.created_at,
+            valid_till,
+            scopes: None,
+        })
+    }
+}
+
+/// Make sure the chat token is saved before the IRC client starts,
+/// requests a new one with the chat permissions if needed
+///
+/// # Panics
+///
+/// Will panic if the new token cannot be saved
+pub async fn acquire_chat_token() {
+    ChatTokenStorage
+        .load_token()
+        .await
+        .expect("Unable to save the chat token");
+}
+
+/// Split the first words of a command borrowing from the message text,
+/// the last word keeps the rest of the message, e.g. the text of a note
+fn command_words<'a, 'b>(text: &'a str, buffer: &'b mut [&'a str; COMMAND_WORDS]) -> &'b [&'a str] {
+    let mut rest = text.trim();
+    let mut count = 0;
+
+    while !rest.is_empty() && count < COMMAND_WORDS {
+        let (word, tail) = if count + 1 == COMMAND_WORDS {
+            (rest, "")
+        } else {
+            rest.split_once(char::is_whitespace).unwrap_or((rest, ""))
+        };
+
+        buffer[count] = word;
+        rest = tail.trim_start();
+        count += 1;
+    }
+
+    &buffer[..count]
+}
+
+/// Register the built-in commands the chat client runs on the message itself and
+/// `!ban`, which runs on the event bus to be confirmed with `!confirm`. A handler
+/// registered with the name of `!ban` replaces it.
+pub(crate) fn register_builtin_handlers(commands: &mut CommandRegistry) {
+    let builtins: [(&str, BuiltinHandler); 32] = [
+        ("!coinflip", |command| coinflip_command(command).boxed()),
+        ("!vanish", |command| vanish_command(command).boxed()),
+        ("!clip", |command| clip_command(command).boxed()),
+        ("!uptime", |command| info_command(command).boxed()),
+        ("!title", |command| info_command(command).boxed()),
+        ("!game", |command| info_command(command).boxed()),
+        ("!spin", |command| spin_command(command).boxed()),
+        ("!streak", |command| streak_command(command).boxed()),
+        ("!birthday", |command| birthday_command(command).boxed()),
+        ("!lang", |command| lang_command(command).boxed()),
+        ("!lurk", |command| lurk_command(command).boxed()),
+        ("!unlurk", |command| unlurk_command(command).boxed()),
+        ("!next", |command| next_command(command).boxed()),
+        ("!credit", |command| credit_command(command).boxed()),
+        ("!forgetme", |command| forget_me_command(command).boxed()),
+        ("!commands", |command| commands_command(command).boxed()),
+        ("!help", |command| help_command(command).boxed()),
+        ("!ping", |command| ping_command(command).boxed()),
+        ("!poll", |command| poll_command(command).boxed()),
+        ("!endpoll", |command| end_poll_command(command).boxed()),
+        ("!followmode", |command| {
+            follow_mode_command(command).boxed()
+        }),
+        ("!note", |command| note_command(command).boxed()),
+        ("!notes", |command| notes_command(command).boxed()),
+        ("!set", |command| set_command(command).boxed()),
+        ("!kv", |command| kv_command(command).boxed()),
+        ("!addcom", |command| add_command(command).boxed()),
+        ("!editcom", |command| add_command(command).boxed()),
+        ("!delcom", |command| delete_command(command).boxed()),
+        ("!skipalert", |command| skip_alert_command(command).boxed()),
+        ("!replayalert", |command| {
+            replay_alert_command(command).boxed()
+        }),
+        ("!raidnext", |command| raid_next_command(command).boxed()),
+        ("!so", |command| shoutout_command(command).boxed()),
+    ];
+
+    for (name, handler) in builtins {
+        commands.register_builtin(name, handler);
+    }
+
+    if commands.handler("!ban").is_some() {
+        return;
+    }
+
+    commands.register(
+        "!ban",
+        Arc::new(|ctx, _| {
+            async move {
+                ctx.say(i18n::render(i18n::channel_language(), "ban.warning", &[]));
+            }
+            .boxed()
+        }),
+    );
+}
+
+/// Description of the commands handled by the chat client itself
+pub(crate) fn builtin_commands(lang: Lang) -> Vec<CommandInfo> {
+    let vanish_cooldown = Some(config::get_vanish_cooldown().as_secs());
+    let clip_cooldown = Some(config::get_clip_cooldown().as_secs());
+    let info_cooldown = Some(config::get_info_cooldown().as_secs());
+    let credit_cooldown = Some(config::get_credit_cooldown().as_secs());
+
+    [
+        ("!coinflip", Permission::Everyone, None),
+        ("!vanish", Permission::Everyone, vanish_cooldown),
+        ("!clip", Permission::Everyone, clip_cooldown),
+        ("!uptime", Permission::Everyone, info_cooldown),
+        ("!title", Permission::Everyone, info_cooldown),
+        ("!game", Permission::Everyone, info_cooldown),
+        ("!spin", Permission::Everyone, None),
+        ("!streak", Permission::Everyone, None),
+        ("!birthday", Permission::Everyone, None),
+        ("!lang", Permission::Everyone, None),
+        ("!lurk", Permission::Everyone, None),
+        ("!unlurk", Permission::Everyone, None),
+        ("!next", Permission::Everyone, None),
+        ("!credit", Permission::Everyone, credit_cooldown),
+        ("!forgetme", Permission::Everyone, None),
+        ("!ban", Permission::Everyone, None),
+        ("!commands", Permission::Everyone, None),
+        ("!help", Permission::Everyone, None),
+        ("!ping", Permission::Everyone, None),
+        ("!confirm", Permission::Everyone, None),
+        ("!poll", Permission::Moderator, None),
+        ("!endpoll", Permission::Moderator, None),
+        ("!followmode", Permission::Moderator, None),
+        ("!note", Permission::Moderator, None),
+        ("!notes", Permission::Moderator, None),
+        ("!set", Permission::Broadcaster, None),
+        ("!kv", Permission::Moderator, None),
+        ("!addcom", Permission::Moderator, None),
+        ("!editcom", Permission::Moderator, None),
+        ("!delcom", Permission::Moderator, None),
+        ("!skipalert", Permission::Moderator, None),
+        ("!replayalert", Permission::Moderator, None),
+        ("!raidnext", Permission::Moderator, None),
+        ("!so", Permission::Moderator, None),
+    ]
+    .into_iter()
+    .map(|(name, permission, cooldown_sec)| CommandInfo {
+        name: name.to_string(),
+        description: i18n::render(lang, &format!("command.{}", &name[1..]), &[]),
+        permission,
+        cooldown_sec,
+    })
+    .collect()
+}
+
+/// Text of `!commands`, only the commands the chatter may use are listed
+async fn commands_reply(
+    state: &BotState,
+    lang: Lang,
+    message: &PrivmsgMessage,
+    page: usize,
+) -> String {
+    let category = state.stream.category().await;
+    let names: Vec<String> = state
+        .commands
+        .list(category.as_deref())
+        .into_iter()
+        .filter(|info| is_allowed(info.permission, message))
+        .map(|info| info.name)
+        .collect();
+    let pages = names.len().div_ceil(COMMANDS_PAGE_SIZE).max(1);
+    let page = page.clamp(1, pages);
+    let commands = names
+        .iter()
+        .skip((page - 1) * COMMANDS_PAGE_SIZE)
+        .take(COMMANDS_PAGE_SIZE)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut reply = i18n::render(
+        lang,
+        "commands.list",
+        &[("page", &page), ("pages", &pages), ("commands", &commands)],
+    );
+
+    if let Some(url) = config::get_public_url() {
+        reply.push_str(". ");
+        reply.push_str(&i18n::render(
+            lang,
+            "commands.more",
+            &[("url", &format!("{}/commands", url.trim_end_matches('/')))],
+        ));
+    }
+
+    reply
+}
+
+/// Text of `!help <command>`
+fn help_reply(state: &BotState, lang: Lang, name: &str) -> String {
+    let name = if name.starts_with('!') {
+        name.to_string()
+    } else {
+        format!("!{name}")
+    };
+    let Some(info) = state.commands.info(&name) else {
+        return i18n::render(lang, "help.unknown", &[("name", &name)]);
+    };
+    let mut description = info.description.clone();
+
+    let restriction = match info.permission {
+        Permission::Everyone => None,
+        Permission::Moderator => Some("help.moderator"),
+        Permission::Broadcaster => Some("help.broadcaster"),
+    };
+
+    if let Some(key) = restriction {
+        description.push_str(&format!(" ({})", i18n::render(lang, key, &[])));
+    }
+
+    if let Some(seconds) = info.cooldown_sec {
+        description.push_str(&format!(
+            " ({})",
+            i18n::render(lang, "help.cooldown", &[("seconds", &seconds)])
+        ));
+    }
+
+    i18n::render(
+        lang,
+        "help.command",
+        &[("name", &name), ("description", &description)],
+    )
+}
+
+/// Reply to `!addcom`, `!editcom` and `!delcom`
+fn custom_command_reply(
+    lang: Lang,
+    name: &str,
+    done: &str,
+    result: Result<(), CustomCommandError>,
+) -> String {
+    match result {
+        Ok(()) => i18n::render(lang, done, &[("name", &name)]),
+        Err(CustomCommandError::Save(e)) => {
+            tracing::error!("Unable to save {name}: {e}");
+            i18n::render(lang, "customcom.failed", &[("name", &name)])
+        }
+        Err(e) => i18n::render(
+            lang,
+            "customcom.invalid",
+            &[("name", &name), ("reason", &e)],
+        ),
+    }
+}
+
+/// Reply of `!uptime`, `!title` or `!game`
+async fn info_reply(lang: Lang, command: &str) -> String {
+    let reply = if command == "!uptime" {
+        channel_info::live_since().await.map(|since| match since {
+            Some(since) => {
+                let (hours, minutes) = channel_info::hours_minutes(Utc::now() - since);
+
+                i18n::render(
+                    lang,
+                    "info.uptime",
+                    &[("hours", &hours), ("minutes", &minutes)],
+                )
+            }
+            None => i18n::render(lang, "info.offline", &[]),
+        })
+    } else {
+        channel_info::channel().await.map(|channel| {
+            if command == "!title" {
+                i18n::render(lang, "info.title", &[("title", &channel.title)])
+            } else {
+                i18n::render(lang, "info.category", &[("category", &channel.category)])
+            }
+        })
+    };
+
+    reply.unwrap_or_else(|e| {
+        tracing::warn!("Unable to answer {command}: {e}");
+        i18n::render(lang, "info.failed", &[])
+    })
+}
+
+/// Reply of `!next`/`!raidnext` when there is no stop to name or raid
+fn raid_train_reply(lang: Lang, error: &RaidTrainError) -> String {
+    match error {
+        RaidTrainError::NoTrain => i18n::render(lang, "raid_train.none", &[]),
+        RaidTrainError::NoLiveStop => i18n::render(lang, "raid_train.no_live", &[]),
+        RaidTrainError::Helix(e) => {
+            tracing::warn!("Unable to find the next stop of the raid train: {e}");
+            i18n::render(lang, "raid_train.failed", &[])
+        }
+    }
+}
+
+/// Alert ID of `!skipalert`/`!replayalert`, e.g. `#12`, `None` for the latest alert
+fn alert_id(args: &[&str]) -> Result<Option<u64>, ()> {
+    args.first()
+        .map(|id| id.trim_start_matches('#').parse().map_err(drop))
+        .transpose()
+}
+
+fn is_broadcaster(message: &PrivmsgMessage) -> bool {
+    message
+        .badges
+        .iter()
+        .any(|badge| badge.name == "broadcaster")
+}
+
+/// Whether the sender may use a command of the permission
+fn is_allowed(permission: Permission, message: &PrivmsgMessage) -> bool {
+    permission.allows(is_moderator(message), is_broadcaster(message))
+}
+
+fn is_moderator(message: &PrivmsgMessage) -> bool {
+    message
+        .badges
+        .iter()
+        .any(|badge| badge.name == "moderator" || badge.name == "broadcaster")
+}
+
+/// Bring the lurker back to the chat, returns `false` if the user was not lurking
+async fn end_lurk(state: &BotState, message: &PrivmsgMessage) -> bool {
+    let count = {
+        let mut lurkers = state.lurkers.lock().await;
+
+        if lurkers.remove(&message.sender.id).is_none() {
+            return false;
+        }
+
+        lurkers.len()
+    };
+
+    overlay::push(&state.overlay, OverlayEvent::LurkersUpdated { count });
+
+    true
+}
+
+/// Text of `!ping`: the delay of the message on the way from Twitch, the time it took
+/// the bot to handle it and the latest chat reply and Helix call durations
+fn ping_reply(lang: Lang, message: &PrivmsgMessage, received_at: Instant) -> String {
+    // the clocks of Twitch and of the bot may differ slightly
+    let chat_ms = (Utc::now() - message.server_timestamp)
+        .num_milliseconds()
+        .max(0);
+    let processing_ms = received_at.elapsed().as_millis();
+    let latest = |prefix| {
+        metrics::latest(prefix).map_or_else(
+            || i18n::render(lang, "ping.unknown", &[]),
+            |(name, elapsed)| format!("{} ms ({name})", elapsed.as_millis()),
+        )
+    };
+
+    i18n::render(
+        lang,
+        "ping.reply",
+        &[
+            ("chat", &chat_ms),
+            ("processing", &processing_ms),
+            ("reply", &latest("chat_reply")),
+            ("helix", &latest("helix_")),
+        ],
+    )
+}
+
+/// Reply to the message, channel emotes in the text are expanded
+async fn send_reply(client: &ChatClient, state: &BotState, message: &PrivmsgMessage, text: String) {
+    if !state.instance.is_leader() || !config::is_feature_enabled(Feature::ChatBot) {
+        return;
+    }
+
+    let text = state.emotes.expand(&text).await;
+
+    if let Err(e) = metrics::timed("chat_reply", client.say_in_reply_to(message, text)).await {
+        tracing::warn!(
+            "Unable to reply to {}: {e}",
+            redact::Name(&message.sender.name)
+        );
+    }
+}
+
+/// Cooldowns of the built-in commands kept by the chat client
+struct BuiltinCooldowns {
+    vanish: Cooldowns,
+    clip: Cooldowns,
+    /// Keyed by the command, each of them has its own cooldown
+    info: Cooldowns,
+    credit: Cooldowns,
+}
+
+impl BuiltinCooldowns {
+    fn new() -> Self {
+        BuiltinCooldowns {
+            vanish: Cooldowns::new(config::get_vanish_cooldown()),
+            clip: Cooldowns::new(config::get_clip_cooldown()),
+            info: Cooldowns::new(config::get_info_cooldown()),
+            credit: Cooldowns::new(config::get_credit_cooldown()),
+        }
+    }
+}
+
+/// Chat message that invoked a built-in command
+pub(crate) struct BuiltinCommand<'a> {
+    state: &'a BotState,
+    client: &'a ChatClient,
+    message: &'a PrivmsgMessage,
+    /// Command name and the words following it, see [`command_words`]
+    words: &'a [&'a str],
+    /// Language of the sender
+    lang: Lang,
+    received_at: Instant,
+    cooldowns: &'a mut BuiltinCooldowns,
+}
+
+impl BuiltinCommand<'_> {
+    fn sender_id(&self) -> &str {
+        self.message.sender.id.as_str()
+    }
+
+    fn sender_name(&self) -> &str {
+        self.message.sender.name.as_str()
+    }
+
+    /// Words following the command name
+    fn args(&self) -> &[&str] {
+        self.words.get(1..).unwrap_or_default()
+    }
+
+    async fn reply(&self, text: String) {
+        send_reply(self.client, self.state, self.message, text).await;
+    }
+
+    async fn reply_with(&self, key: &str) {
+        self.reply(i18n::render(self.lang, key, &[])).await;
+    }
+}
+
+async fn coinflip_command(command: BuiltinCommand<'_>) {
+    let coin_flip = rand::random::<bool>();
+
+    if coin_flip {
+        // ban user
+        moderation::timeout_user(command.sender_id(), "Ты проиграл!", COINFLIP_TIMEOUT_SEC).await;
+    } else {
+        command.reply_with("coinflip.lucky").await;
+    }
+}
+
+async fn vanish_command(command: BuiltinCommand<'_>) {
+    let cooldowns = &mut command.cooldowns.vanish;
+
+    cooldowns.set_period(config::get_vanish_cooldown());
+
+    match cooldowns.try_use(command.message.sender.id.as_str()) {
+        Ok(()) => {
+            moderation::timeout_user(command.sender_id(), "!vanish", VANISH_TIMEOUT_SEC).await;
+        }
+        Err(left) => {
+            command
+                .reply(i18n::render(
+                    command.lang,
+                    "vanish.cooldown",
+                    &[("seconds", &(left.as_secs() + 1))],
+                ))
+                .await;
+        }
+    }
+}
+
+async fn clip_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let cooldown = &mut command.cooldowns.clip;
+
+    cooldown.set_period(config::get_clip_cooldown());
+
+    // the channel of a co-host, the own one is clipped without it
+    let channel = command
+        .words
+        .get(1)
+        .map(|name| name.trim_start_matches('@'))
+        .filter(|name| !name.eq_ignore_ascii_case(&config::get_channel_name()));
+    let reply = match channel {
+        Some(channel) if !utils::is_delegated(channel) => {
+            i18n::render(lang, "clip.not_delegated", &[("channel", &channel)])
+        }
+        _ => match cooldown.try_use("!clip") {
+            Ok(()) => match clips::create(&command.state.clips, channel).await {
+                Some(url) => i18n::render(lang, "clip.created", &[("url", &url)]),
+                None => i18n::render(lang, "clip.failed", &[]),
+            },
+            Err(left) => i18n::render(lang, "clip.cooldown", &[("seconds", &(left.as_secs() + 1))]),
+        },
+    };
+
+    command.reply(reply).await;
+}
+
+/// `!uptime`, `!title` and `!game`
+async fn info_command(command: BuiltinCommand<'_>) {
+    let name = command.words[0];
+    let cooldowns = &mut command.cooldowns.info;
+
+    cooldowns.set_period(config::get_info_cooldown());
+
+    // the commands used during the cooldown are ignored, not answered
+    if cooldowns.try_use(name).is_ok() {
+        let reply = info_reply(command.lang, name).await;
+
+        command.reply(reply).await;
+    }
+}
+
+async fn spin_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let reply = match wheel::spin(
+        command.state,
+        command.sender_id(),
+        command.message.sender.login.as_str(),
+        command.sender_name(),
+        SpinSource::Points,
+    )
+    .await
+    {
+        // the result is announced when the wheel stops
+        Ok(_) => None,
+        Err(SpinError::NoPrizes) => Some(i18n::render(lang, "wheel.no_prizes", &[])),
+        Err(SpinError::NotEnoughPoints { balance }) => Some(i18n::render(
+            lang,
+            "wheel.not_enough_points",
+            &[("cost", &config::get_wheel_cost()), ("balance", &balance)],
+        )),
+        Err(e) => {
+            tracing::error!("Unable to spin the wheel: {e}");
+            Some(i18n::render(lang, "wheel.failed", &[]))
+        }
+    };
+
+    if let Some(reply) = reply {
+        command.reply(reply).await;
+    }
+}
+
+async fn streak_command(command: BuiltinCommand<'_>) {
+    let streak = command
+        .state
+        .streaks
+        .get_streak(command.sender_id())
+        .await
+        .unwrap_or_default();
+
+    command
+        .reply(i18n::render(
+            command.lang,
+            "streak.status",
+            &[("current", &streak.current), ("longest", &streak.longest)],
+        ))
+        .await;
+}
+
+async fn birthday_command(command: BuiltinCommand<'_>) {
+    let Some(date) = command.args().first() else {
+        command.reply_with("birthday.usage").await;
+        return;
+    };
+    let reply = match command
+        .state
+        .birthdays
+        .register(command.sender_id(), command.sender_name(), date)
+        .await
+    {
+        Some((month, day)) => i18n::render(
+            command.lang,
+            "birthday.saved",
+            &[
+                ("day", &format!("{day:02}")),
+                ("month", &format!("{month:02}")),
+            ],
+        ),
+        None => i18n::render(command.lang, "birthday.usage", &[]),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn lang_command(command: BuiltinCommand<'_>) {
+    match command
+        .args()
+        .first()
+        .and_then(|code| code.parse::<Lang>().ok())
+    {
+        Some(lang) => {
+            command
+                .state
+                .languages
+                .set_for_user(command.sender_id(), lang)
+                .await;
+            command.reply(i18n::render(lang, "lang.set", &[])).await;
+        }
+        None => {
+            let languages = Lang::ALL.map(Lang::code).join(", ");
+
+            command
+                .reply(i18n::render(
+                    command.lang,
+                    "lang.usage",
+                    &[("languages", &languages)],
+                ))
+                .await;
+        }
+    }
+}
+
+async fn poll_command(command: BuiltinCommand<'_>) {
+    let state = command.state;
+    let lang = command.lang;
+    let definition = command.message.message_text.trim_start_matches("!poll");
+    let reply = match poll::parse_poll_definition(definition) {
+        Some((question, options)) => {
+            let listed = options
+                .iter()
+                .enumerate()
+                .map(|(idx, option)| format!("{}. {option}", idx + 1))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            match state.poll.start(question.clone(), options).await {
+                Some(results) => {
+                    overlay::push(&state.overlay, OverlayEvent::PollUpdated { results });
+                    i18n::render(
+                        state.languages.channel(),
+                        "poll.started",
+                        &[("question", &question), ("options", &listed)],
+                    )
+                }
+                None => i18n::render(lang, "poll.running", &[]),
+            }
+        }
+        None => i18n::render(lang, "poll.usage", &[]),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn end_poll_command(command: BuiltinCommand<'_>) {
+    let state = command.state;
+    let channel_lang = state.languages.channel();
+    let reply = match state.poll.end().await {
+        Some(results) => {
+            let winners = results.winners();
+            let reply = match winners.first() {
+                Some(_) => i18n::render(
+                    channel_lang,
+                    "poll.ended",
+                    &[
+                        ("question", &results.question),
+                        ("winner", &winners.join(", ")),
+                        (
+                            "votes",
+                            &results
+                                .options
+                                .iter()
+                                .map(|option| option.votes)
+                                .max()
+                                .unwrap_or_default(),
+                        ),
+                        ("total", &results.total),
+                    ],
+                ),
+                None => i18n::render(
+                    channel_lang,
+                    "poll.no_votes",
+                    &[("question", &results.question)],
+                ),
+            };
+
+            overlay::push(&state.overlay, OverlayEvent::PollEnded { results });
+
+            reply
+        }
+        None => i18n::render(command.lang, "poll.not_running", &[]),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn follow_mode_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let reply = match command.args().first() {
+        Some(&mode @ ("on" | "off")) => {
+            let enabled = mode == "on";
+
+            if !moderation::set_chat_mode(ChatMode::FollowersOnly, enabled).await {
+                i18n::render(lang, "followmode.failed", &[])
+            } else if enabled {
+                i18n::render(lang, "followmode.on", &[])
+            } else {
+                i18n::render(lang, "followmode.off", &[])
+            }
+        }
+        _ => i18n::render(lang, "followmode.usage", &[]),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn set_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let [key, value] = *command.args() else {
+        command.reply_with("set.usage").await;
+        return;
+    };
+    let reply = match command.state.settings.set_from_chat(key, value).await {
+        Ok(()) => i18n::render(lang, "set.done", &[("key", &key), ("value", &value)]),
+        Err(settings::Error::UnknownSetting(_)) => i18n::render(lang, "set.usage", &[]),
+        Err(settings::Error::InvalidValue { reason, .. }) => {
+            i18n::render(lang, "set.invalid", &[("key", &key), ("reason", &reason)])
+        }
+        Err(e) => {
+            tracing::error!("Unable to change {key} from the chat: {e}");
+            i18n::render(lang, "set.failed", &[])
+        }
+    };
+
+    command.reply(reply).await;
+}
+
+async fn forget_me_command(command: BuiltinCommand<'_>) {
+    if command.args().first() == Some(&"confirm") {
+        privacy::forget(
+            command.state,
+            command.sender_id(),
+            Some(command.message.sender.login.as_str()),
+        )
+        .await;
+        command.reply_with("privacy.forgotten").await;
+    } else {
+        command.reply_with("privacy.confirm").await;
+    }
+}
+
+async fn ping_command(command: BuiltinCommand<'_>) {
+    let text = ping_reply(command.lang, command.message, command.received_at);
+
+    command.reply(text).await;
+}
+
+async fn lurk_command(command: BuiltinCommand<'_>) {
+    let count = {
+        let mut lurkers = command.state.lurkers.lock().await;
+
+        lurkers.insert(
+            command.message.sender.id.clone(),
+            command.message.sender.name.clone(),
+        );
+        lurkers.len()
+    };
+
+    overlay::push(
+        &command.state.overlay,
+        OverlayEvent::LurkersUpdated { count },
+    );
+    command
+        .reply(i18n::render(
+            command.lang,
+            "lurk.start",
+            &[("name", &command.sender_name())],
+        ))
+        .await;
+}
+
+async fn unlurk_command(command: BuiltinCommand<'_>) {
+    let key = if end_lurk(command.state, command.message).await {
+        "lurk.welcome_back"
+    } else {
+        "lurk.not_lurking"
+    };
+
+    command
+        .reply(i18n::render(
+            command.lang,
+            key,
+            &[("name", &command.sender_name())],
+        ))
+        .await;
+}
+
+async fn note_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let [user, text] = *command.args() else {
+        command.reply_with("notes.usage").await;
+        return;
+    };
+    let reply = match notes::resolve_user(command.state, user).await {
+        Ok((user_id, login)) => {
+            command
+                .state
+                .notes
+                .add(&user_id, &login, command.sender_name(), text)
+                .await;
+            i18n::render(lang, "notes.saved", &[("name", &user)])
+        }
+        Err(e) => {
+            tracing::warn!("Unable to find the user of the note: {e}");
+            i18n::render(lang, "notes.unknown_user", &[("name", &user)])
+        }
+    };
+
+    command.reply(reply).await;
+}
+
+async fn notes_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let Some(user) = command.args().first() else {
+        command.reply_with("notes.usage").await;
+        return;
+    };
+    let notes = match notes::resolve_user(command.state, user).await {
+        Ok((user_id, login)) => command.state.notes.for_user(&user_id, Some(&login)).await,
+        Err(e) => {
+            tracing::warn!("Unable to find the user of the notes: {e}");
+            Vec::new()
+        }
+    };
+    let reply = if notes.is_empty() {
+        i18n::render(lang, "notes.none", &[("name", &user)])
+    } else {
+        let latest = notes
+            .iter()
+            .rev()
+            .take(NOTES_IN_REPLY)
+            .map(|note| {
+                format!(
+                    "{} ({}, {})",
+                    note.text,
+                    note.author,
+                    note.created_at.format("%d.%m.%Y")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        i18n::render(
+            lang,
+            "notes.list",
+            &[("name", &user), ("count", &notes.len()), ("notes", &latest)],
+        )
+    };
+
+    command.reply(reply).await;
+}
+
+async fn skip_alert_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let reply = match alert_id(command.args()) {
+        Ok(id) => alert_queue::skip(command.state, id).await,
+        Err(()) => None,
+    }
+    .map_or_else(
+        || i18n::render(lang, "alert.not_found", &[]),
+        |alert| i18n::render(lang, "alert.skipped", &[("id", &alert.id)]),
+    );
+
+    command.reply(reply).await;
+}
+
+async fn replay_alert_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let reply = match alert_id(command.args()) {
+        Ok(id) => alert_queue::replay(command.state, id).await,
+        Err(()) => None,
+    }
+    .map_or_else(
+        || i18n::render(lang, "alert.not_found", &[]),
+        |alert| i18n::render(lang, "alert.replayed", &[("id", &alert.id)]),
+    );
+
+    command.reply(reply).await;
+}
+
+async fn next_command(command: BuiltinCommand<'_>) {
+    let reply = match raid_train::next_stop().await {
+        Ok(stop) => i18n::render(
+            command.lang,
+            "raid_train.next",
+            &[("name", &stop.user_name), ("login", &stop.login)],
+        ),
+        Err(e) => raid_train_reply(command.lang, &e),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn raid_next_command(command: BuiltinCommand<'_>) {
+    // the raid is announced to the whole chat
+    if let Err(e) = raid_train::raid_next(command.state).await {
+        command.reply(raid_train_reply(command.lang, &e)).await;
+    }
+}
+
+async fn credit_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let cooldowns = &mut command.cooldowns.credit;
+
+    cooldowns.set_period(config::get_credit_cooldown());
+
+    let sender = &command.message.sender;
+    let submitted = cooldowns.try_use(sender.id.as_str()).map(|()| {
+        credit_messages::submit(
+            sender.id.as_str(),
+            sender.name.as_str(),
+            &command.words[1..].join(" "),
+        )
+    });
+    let reply = match submitted {
+        Err(left) => i18n::render(
+            lang,
+            "credit_message.cooldown",
+            &[("seconds", &(left.as_secs() + 1))],
+        ),
+        Ok(Ok(())) => i18n::render(lang, "credit_message.queued", &[]),
+        Ok(Err(SubmitError::Empty)) => i18n::render(lang, "credit_message.empty", &[]),
+        Ok(Err(SubmitError::TooLong { max })) => {
+            i18n::render(lang, "credit_message.too_long", &[("max", &max)])
+        }
+        Ok(Err(SubmitError::Blocked)) => i18n::render(lang, "credit_message.blocked", &[]),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn shoutout_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let Some(target) = command.args().first() else {
+        command.reply_with("shoutout.usage").await;
+        return;
+    };
+    // the shoutout itself is posted to the whole chat
+    let reply = match shoutouts::shoutout(command.state, target).await {
+        Ok(()) => None,
+        Err(ShoutoutError::NotFound) => Some(i18n::render(
+            lang,
+            "shoutout.not_found",
+            &[("login", &target)],
+        )),
+        Err(ShoutoutError::Helix(e)) => {
+            tracing::warn!("Unable to shout out {target}: {e}");
+            Some(i18n::render(lang, "shoutout.failed", &[]))
+        }
+    };
+
+    if let Some(reply) = reply {
+        command.reply(reply).await;
+    }
+}
+
+async fn kv_command(command: BuiltinCommand<'_>) {
+    let lang = command.lang;
+    let reply = match *command.args() {
+        ["get", key] => match kv::get(key) {
+            Some(value) => i18n::render(lang, "kv.value", &[("key", &key), ("value", &value)]),
+            None => i18n::render(lang, "kv.missing", &[("key", &key)]),
+        },
+        ["set", rest] => {
+            let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let value = value.trim();
+
+            match kv::set(key, value) {
+                Ok(()) => i18n::render(lang, "kv.value", &[("key", &key), ("value", &value)]),
+                Err(KvError::Save(e)) => {
+                    tracing::error!("Unable to store {key}: {e}");
+                    i18n::render(lang, "kv.failed", &[])
+                }
+                Err(e) => i18n::render(lang, "kv.invalid", &[("reason", &e)]),
+            }
+        }
+        _ => i18n::render(lang, "kv.usage", &[]),
+    };
+
+    command.reply(reply).await;
+}
+
+async fn commands_command(command: BuiltinCommand<'_>) {
+    let page = command
+        .args()
+        .first()
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1);
+    let reply = commands_reply(command.state, command.lang, command.message, page).await;
+
+    command.reply(reply).await;
+}
+
+async fn help_command(command: BuiltinCommand<'_>) {
+    let name = command.args().first().copied().unwrap_or("!help");
+    let reply = help_reply(command.state, command.lang, name);
+
+    command.reply(reply).await;
+}
+
+/// `!addcom` and `!editcom`
+async fn add_command(command: BuiltinCommand<'_>) {
+    let [action, name, text] = *command.words else {
+        command.reply_with("customcom.usage").await;
+        return;
+    };
+    let name = custom_commands::command_name(name);
+    let (result, done) = if command.state.commands.is_registered(&name) {
+        (Err(CustomCommandError::Exists), "")
+    } else if action == "!addcom" {
+        (custom_commands::add(&name, text), "customcom.added")
+    } else {
+        (custom_commands::edit(&name, text), "customcom.edited")
+    };
+    let reply = custom_command_reply(command.lang, &name, done, result.map(drop));
+
+    command.reply(reply).await;
+}
+
+async fn delete_command(command: BuiltinCommand<'_>) {
+    let Some(name) = command.args().first() else {
+        command.reply_with("customcom.usage").await;
+        return;
+    };
+    let name = custom_commands::command_name(name);
+    let result = custom_commands::remove(&name);
+    let reply = custom_command_reply(command.lang, &name, "customcom.removed", result);
+
+    command.reply(reply).await;
+}
+
+/// Reply of the custom command, a viewer on cooldown is ignored like with the other bots
+async fn run_custom_command(
+    client: &ChatClient,
+    state: &BotState,
+    message: &PrivmsgMessage,
+    words: &[&str],
+) {
+    let Some(command) = words.first().and_then(|name| custom_commands::get(name)) else {
+        return;
+    };
+
+    if state
+        .custom_cooldowns
+        .try_use(&command, message.sender.id.as_str())
+        .await
+        .is_ok()
+    {
+        let reply = command.render(&message.sender.name, &words[1..].join(" "));
+
+        send_reply(client, state, message, reply).await;
+    }
+}
+
+/// Flag first-time chatters, new accounts and suspected evasion for the moderators
+async fn log_message(
+    state: &BotState,
+    message: &PrivmsgMessage,
+    user_id: Arc<str>,
+    user_name: Arc<str>,
+    copypasta: bool,
+    language: Option<&'static str>,
+    language_rule: bool,
+) {
+    let account_age_days = state
+        .users
+        .account_age_days(message.sender.id.as_str())
+        .await;
+    let new_account = account_age_days.is_some_and(|days| days < config::get_new_account_days());
+    let suspected_evasion = if new_account {
+        state
+            .evasion
+            .check(message.sender.id.as_str(), message.message_text.as_str())
+            .await
+    } else {
+        None
+    };
+
+    if let Some(ref sanctioned) = suspected_evasion {
+        tracing::warn!(
+            "{} may be evading the timeout of {}",
+            redact::Name(&user_name),
+            redact::Name(sanctioned)
+        );
+        metrics::increment("suspected_evasion");
+    }
+
+    let style = state
+        .badges
+        .style(
+            message.name_color.as_ref().map(ToString::to_string),
+            message
+                .badges
+                .iter()
+                .map(|badge| (badge.name.as_str(), badge.version.as_str())),
+        )
+        .await;
+
+    state
+        .users
+        .remember_style(message.sender.id.as_str(), style.clone())
+        .await;
+
+    let entry = ChatEntry {
+        timestamp: Local::now(),
+        user_id,
+        user_name,
+        style,
+        text: message.message_text.clone(),
+        first_message: message
+            .source
+            .tags
+            .0
+            .get("first-msg")
+            .and_then(Option::as_deref)
+            == Some("1"),
+        highlighted: message
+            .source
+            .tags
+            .0
+            .get("msg-id")
+            .and_then(Option::as_deref)
+            == Some(HIGHLIGHTED_MESSAGE_ID),
+        account_age_days,
+        new_account,
+        copypasta,
+        suspected_evasion,
+        language,
+        language_rule,
+    };
+
+    state.modlog.record(entry.clone()).await;
+    overlay::push(&state.overlay, OverlayEvent::ChatMessage(entry));
+
+    for link in state.unfurler.find_links(message.message_text.as_str()) {
+        let state = state.clone();
+
+        // do not hold the chat back while the page is fetched
+        tokio::spawn(async move {
+            match state.unfurler.preview(link.as_str()).await {
+                Ok(Some(preview)) => {
+                    overlay::push(&state.overlay, OverlayEvent::LinkPreview(preview));
+                }
+                Ok(None) => tracing::debug!("{link} has no preview"),
+                Err(e) => tracing::debug!("Unable to unfurl {link}: {e}"),
+            }
+        });
+    }
+}
+
+type ChatClient = TwitchIRCClient<ChatTransport, RefreshingLoginCredentials<ChatTokenStorage>>;
+
+/// Subscription announced in the chat, counted only if EventSub is not available
+async fn subscribed_in_chat(state: &BotState, user_id: &str, user_name: &str) {
+    if state.health.status(eventsub::HEALTH_COMPONENT) != Some(Status::Degraded) {
+        return;
+    }
+
+    tracing::info!(
+        "Got subscriber name from chat: {} {user_id}",
+        redact::Name(user_name)
+    );
+    state.users.remember(user_id, user_name).await;
+    events::publish(
+        &state.bus,
+        BotEvent::Subscribe {
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+        },
+    );
+    state
+        .notifier
+        .notify(NotificationKind::Subscribe, "New subscriber", user_name)
+        .await;
+}
+
+/// Send the held messages, returns whether all of them are sent or dropped as stale
+async fn flush_outbox(sender: &ChatClient, channel: &str, max_age: Duration) -> bool {
+    let mut pending = outbox::take(max_age).into_iter();
+    let mut sent = 0;
+
+    while let Some(message) = pending.next() {
+        if let Err(e) = sender.say(channel.to_string(), message.text.clone()).await {
+            tracing::debug!("chat is still unavailable: {e}");
+            outbox::restore(std::iter::once(message).chain(pending).collect());
+            return false;
+        }
+
+        sent += 1;
+    }
+
+    if sent > 0 {
+        tracing::info!("{sent} held chat messages are sent");
+        metrics::increment("outbox_flushed");
+    }
+
+    true
+}
+
+/// Join the channel, the retries go on in the background after `HEWPME_JOIN_ATTEMPTS`
+async fn join_channel(client: &ChatClient, state: &BotState, channel: String) {
+    let attempts = config::get_join_attempts();
+    let mut delay = JOIN_RETRY_DELAY;
+
+    state.health.set(
+        HEALTH_COMPONENT,
+        Status::Starting,
+        Some(format!("joining #{channel}")),
+    );
+    client
+        .join(channel.clone())
+        .expect("Invalid channel name in TWITCH_CHANNEL");
+
+    for attempt in 1..=attempts {
+        if confirm_join(client, state, &channel, attempt).await {
+            return;
+        }
+
+        if attempt < attempts {
+            rejoin(client, &channel, &mut delay).await;
+        }
+    }
+
+    tracing::error!("unable to join #{channel} after {attempts} attempts, still retrying");
+    state.health.set(
+        HEALTH_COMPONENT,
+        Status::Down,
+        Some(format!("unable to join #{channel}")),
+    );
+
+    let client = client.clone();
+    let state = state.clone();
+
+    tokio::spawn(async move {
+        for attempt in attempts + 1.. {
+            rejoin(&client, &channel, &mut delay).await;
+
+            if confirm_join(&client, &state, &channel, attempt).await {
+                break;
+            }
+        }
+    });
+}
+
+/// Wait for Twitch to confirm the join, the health is updated on success
+async fn confirm_join(client: &ChatClient, state: &BotState, channel: &str, attempt: u32) -> bool {
+    if wait_for_join(client, channel).await {
+        tracing::info!("joined #{channel} (attempt {attempt})");
+        state.health.set(
+            HEALTH_COMPONENT,
+            Status::Ok,
+            Some(format!("joined #{channel}")),
+        );
+        return true;
+    }
+
+    tracing::warn!("join to #{channel} is not confirmed (attempt {attempt})");
+
+    false
+}
+
+/// Join again after the delay, which is doubled for the next time
+async fn rejoin(client: &ChatClient, channel: &str, delay: &mut Duration) {
+    // the channel stays wanted in the meantime, the client may join it on its own
+    tokio::time::sleep(*delay).await;
+    *delay = (*delay * 2).min(JOIN_RETRY_MAX_DELAY);
+
+    if let (_, true) = client.get_channel_status(channel.to_string()).await {
+        return;
+    }
+
+    client.part(channel.to_string());
+    client
+        .join(channel.to_string())
+        .expect("Invalid channel name in TWITCH_CHANNEL");
+}
+
+async fn wait_for_join(client: &ChatClient, channel: &str) -> bool {
+    let deadline = tokio::time::Instant::now() + config::get_join_timeout();
+
+    while tokio::time::Instant::now() < deadline {
+        if let (_, true) = client.get_channel_status(channel.to_string()).await {
+            return true;
+        }
+
+        tokio::time::sleep(JOIN_POLL_INTERVAL).await;
     }
+
+    false
 }
 
-pub async fn run_twitch_irc_client(chatters_list: ChattersList) {
+/// Join the channel chat, handle its messages and send the ones posted on the state,
+/// runs until the connection is closed
+///
+/// # Panics
+///
+/// Will panic if the chat token has not been acquired
+pub async fn run_twitch_irc_client(state: BotState, mut chat_outbox: ChatOutboxReceiver) {
     let storage = ChatTokenStorage {};
-    let credentials = RefreshingLoginCredentials::init(
+    // the login of a configured bot account is not looked up on Helix
+    let credentials = RefreshingLoginCredentials::init_with_username(
+        config::get_bot_account(),
         config::get_client_id(),
         config::get_client_secret(),
         storage,
     );
     let config = ClientConfig::new_simple(credentials);
-    let (mut incoming_messages, client) = TwitchIRCClient::<
-        SecureTCPTransport,
-        RefreshingLoginCredentials<ChatTokenStorage>,
-    >::new(config);
+    let (mut incoming_messages, client) = ChatClient::new(config);
 
     let responder = client.clone();
+    let handler_state = state.clone();
     // first thing you should do: start consuming incoming messages,
     // otherwise they will back up.
     let join_handle = tokio::spawn(async move {
+        let state = handler_state;
+        let chat_bot_enabled = config::is_feature_enabled(Feature::ChatBot);
+        let moderation_enabled = config::is_feature_enabled(Feature::Moderation);
+        let mut cooldowns = BuiltinCooldowns::new();
+
         while let Some(message) = incoming_messages.recv().await {
+            // Twitch asks to reconnect before a restart of its chat servers
+            if let Reconnect(_) = message {
+                outage::record_failure(Source::Chat);
+            }
+
+            if let ClearChat(ref clear) = message {
+                if let ClearChatAction::UserBanned {
+                    ref user_login,
+                    ref user_id,
+                }
+                | ClearChatAction::UserTimedOut {
+                    ref user_login,
+                    ref user_id,
+                    ..
+                } = clear.action
+                {
+                    let messages = state.modlog.entries_of(user_id).await;
+
+                    state
+                        .evasion
+                        .sanctioned(
+                            user_id,
+                            state.names.intern(user_login),
+                            messages.iter().map(|entry| entry.text.as_str()),
+                        )
+                        .await;
+                }
+            }
+
             if let Privmsg(ref user_msg) = message {
-                chatters_list
-                    .lock()
+                let received_at = Instant::now();
+                let user_id = state.names.intern(user_msg.sender.id.as_str());
+                let user_name = state.names.intern(user_msg.sender.name.as_str());
+                state
+                    .users
+                    .remember(user_msg.sender.id.as_str(), user_msg.sender.name.as_str())
+                    .await;
+                state.activity.record_message(&user_id).await;
+                state
+                    .streaks
+                    .record_presence(user_msg.sender.id.as_str(), user_msg.sender.name.as_str())
+                    .await;
+
+                // checked before publishing so that the custom commands are ignored as well
+                let ignored = user_msg.message_text.starts_with('!')
+                    && moderation_enabled
+                    && !is_moderator(user_msg)
+                    && match state
+                        .command_guard
+                        .check(user_msg.sender.id.as_str(), user_msg.sender.name.as_str())
+                        .await
+                    {
+                        CommandVerdict::Allowed => false,
+                        CommandVerdict::Ignored => true,
+                        CommandVerdict::Flagged(alert) => {
+                            moderation::alert_command_flood(&state, &alert).await;
+                            true
+                        }
+                    };
+
+                events::publish(
+                    &state.bus,
+                    BotEvent::ChatMessage {
+                        user_id: user_msg.sender.id.clone(),
+                        user_name: user_msg.sender.name.clone(),
+                        text: user_msg.message_text.clone(),
+                        is_moderator: is_moderator(user_msg),
+                        is_broadcaster: is_broadcaster(user_msg),
+                    },
+                );
+
+                let copypasta = moderation_enabled
+                    && state
+                        .copypasta
+                        .check(&user_id, user_msg.message_text.as_str())
+                        .await;
+
+                if copypasta && state.instance.is_leader() && !is_moderator(user_msg) {
+                    copypasta::act(
+                        &state,
+                        user_msg.sender.id.as_str(),
+                        user_msg.sender.name.as_str(),
+                        user_msg.message_id.as_str(),
+                    )
+                    .await;
+                }
+
+                let language = chat_language::detect(user_msg.message_text.as_str());
+                let language_rule = if moderation_enabled {
+                    language.and_then(chat_language::rule_for)
+                } else {
+                    None
+                };
+
+                if let Some(language) = language {
+                    state.stats.record_language(language).await;
+                }
+
+                if let Some(rule) = language_rule {
+                    if state.instance.is_leader() && !is_moderator(user_msg) {
+                        chat_language::act(
+                            &state,
+                            user_msg.sender.id.as_str(),
+                            user_msg.sender.name.as_str(),
+                            user_msg.message_id.as_str(),
+                            rule,
+                        )
+                        .await;
+                    }
+                }
+
+                log_message(
+                    &state,
+                    user_msg,
+                    user_id,
+                    user_name,
+                    copypasta,
+                    language,
+                    language_rule.is_some(),
+                )
+                .await;
+
+                let lang = state.languages.for_user(user_msg.sender.id.as_str()).await;
+
+                if let Some(results) = state
+                    .poll
+                    .vote(user_msg.sender.id.as_str(), user_msg.message_text.as_str())
                     .await
-                    .insert(user_msg.sender.name.clone());
-
-                match user_msg.message_text.split(' ').collect::<Vec<_>>()[..] {
-                    ["!game", ..] => {
-                        let coin_flip = rand::random::<bool>();
-
-                        if coin_flip {
-                            // ban user
-                            crate::eventsub::ban_user(user_msg.sender.id.as_str(), "Ты проиграл!")
-                                .await;
-                        } else {
-                            responder
-                                .say_in_reply_to(user_msg, "В этот раз тебе повезло!".to_string())
-                                .await
-                                .unwrap();
+                {
+                    overlay::push(&state.overlay, OverlayEvent::PollUpdated { results });
+                }
+
+                if !matches!(user_msg.message_text.trim(), "!lurk" | "!unlurk")
+                    && end_lurk(&state, user_msg).await
+                {
+                    send_reply(
+                        &responder,
+                        &state,
+                        user_msg,
+                        i18n::render(
+                            lang,
+                            "lurk.welcome_back",
+                            &[("name", &user_msg.sender.name)],
+                        ),
+                    )
+                    .await;
+                }
+
+                let category = state.stream.category().await;
+                let mut buffer = [""; COMMAND_WORDS];
+                let words = command_words(user_msg.message_text.as_str(), &mut buffer);
+
+                match *words {
+                    // the leader handles the commands
+                    _ if !state.instance.is_leader() || !chat_bot_enabled => (),
+                    _ if ignored => {
+                        tracing::debug!(
+                            "ignoring the commands of {}",
+                            redact::Name(&user_msg.sender.name)
+                        )
+                    }
+                    [name, ..] if !state.commands.is_enabled(name, category.as_deref()) => (),
+                    // the commands of the moderators are not answered for the other viewers
+                    [name, ..]
+                        if state
+                            .commands
+                            .info(name)
+                            .is_some_and(|info| !is_allowed(info.permission, user_msg)) => {}
+                    [name, ..] => match state.commands.builtin(name) {
+                        Some(run) => {
+                            run(BuiltinCommand {
+                                state: &state,
+                                client: &responder,
+                                message: user_msg,
+                                words,
+                                lang,
+                                received_at,
+                                cooldowns: &mut cooldowns,
+                            })
+                            .await;
                         }
+                        // registered commands are handled on the event bus
+                        None if state.commands.is_registered(name) => (),
+                        None => run_custom_command(&responder, &state, user_msg, words).await,
+                    },
+                    _ => (),
+                }
+            }
+
+            if let UserNotice(ref notice) = message {
+                match notice.event {
+                    UserNoticeEvent::Raid { viewer_count, .. } => {
+                        raids::landed(
+                            &state,
+                            notice.sender.id.as_str(),
+                            notice.sender.login.as_str(),
+                            notice.sender.name.as_str(),
+                            viewer_count,
+                        )
+                        .await;
+                    }
+                    UserNoticeEvent::SubOrResub {
+                        cumulative_months, ..
+                    } => {
+                        state
+                            .subscriptions
+                            .record(&notice.sender.id, Some(cumulative_months))
+                            .await;
+                        subscribed_in_chat(&state, &notice.sender.id, &notice.sender.name).await;
+                    }
+                    UserNoticeEvent::SubGift { ref recipient, .. } => {
+                        subscribed_in_chat(&state, &recipient.id, &recipient.name).await;
                     }
-                    ["!ban", ..] => responder
-                        .say_in_reply_to(user_msg, "Сейчас выдам бан!".to_string())
-                        .await
-                        .unwrap(),
                     _ => (),
                 }
             }
 
-            tracing::trace!("Received message: {:?}", message);
+            tracing::trace!(
+                "Received message: {}",
+                redact::Raw(&format_args!("{message:?}"))
+            );
+        }
+    });
+
+    let channel = config::get_channel_name();
+
+    // `join` only validates the channel name, the actual join is confirmed by Twitch later
+    tokio::time::sleep(config::get_join_delay()).await;
+    join_channel(&client, &state, channel.clone()).await;
+
+    let sender = client.clone();
+    let emotes = state.emotes.clone();
+    let instance = state.instance.clone();
+    let chat_bot_enabled = config::is_feature_enabled(Feature::ChatBot);
+    tokio::spawn(async move {
+        let max_age = config::get_outbox_max_age();
+        let mut retry = tokio::time::interval(OUTBOX_RETRY_INTERVAL);
+        // the messages held by the previous run are sent as well
+        let mut held = !outbox::is_empty();
+
+        loop {
+            let message = tokio::select! {
+                message = chat_outbox.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = retry.tick() => {
+                    if held && chat_bot_enabled && instance.is_leader() {
+                        held = !flush_outbox(&sender, &channel, max_age).await;
+                    }
+
+                    continue;
+                }
+            };
+
+            if !chat_bot_enabled {
+                tracing::debug!(
+                    "chat bot is disabled, message is dropped: {}",
+                    redact::Raw(&message)
+                );
+                continue;
+            }
+
+            if !instance.is_leader() {
+                tracing::debug!(
+                    "following the leader, message is dropped: {}",
+                    redact::Raw(&message)
+                );
+                continue;
+            }
+
+            let message = emotes.expand(&kv::expand(&message)).await;
+
+            // the held messages go first to keep the order
+            if held {
+                held = !flush_outbox(&sender, &channel, max_age).await;
+            }
+
+            if held {
+                outbox::hold(message);
+                continue;
+            }
+
+            if let Err(e) = sender.say(channel.clone(), message.clone()).await {
+                tracing::warn!("Unable to send message to chat, holding it: {e}");
+                outbox::hold(message);
+                held = true;
+            }
         }
     });
+    // the viewers are credited even if the chat bot is disabled
+    tokio::spawn(presence::run_presence_polling(state.clone()));
+    tokio::spawn(lapses::run_weekly_report(state.clone()));
+    tokio::spawn(automation::run_automation(state.clone()));
+    tokio::spawn(text_files::run_text_files(state.clone()));
+    tokio::spawn(mentions::run_mentions(state.clone()));
 
-    // join a channel
-    // This function only returns an error if the passed channel login name is malformed,
-    // so in this simple case where the channel name is hardcoded we can ignore the potential
-    // error with `unwrap`.
-    let channel = env::var("TWITCH_CHANNEL").unwrap();
-    client.join(channel).unwrap();
+    if config::is_feature_enabled(Feature::Overlays) {
+        tokio::spawn(alert_queue::run_alert_queue(state.clone()));
+    }
+
+    if chat_bot_enabled {
+        tokio::spawn(scheduler::run_timers(state.clone()));
+        tokio::spawn(idle::run_idle_prompts(state.clone()));
+        tokio::spawn(emotes::run_emote_watch(state.clone()));
+        tokio::spawn(birthdays::run_announcements(state.clone()));
+    }
+
+    if config::is_feature_enabled(Feature::Moderation) {
+        tokio::spawn(celebration::run_celebrations(state.clone()));
+        tokio::spawn(protection::run_protection(state));
+    }
 
     // keep the tokio executor alive.
     // If you return instead of waiting the background task will exit.
@@ -0,0 +1,148 @@
+//! Language of the chat messages.
+//!
+//! The language of a message is detected with whatlang, the messages too short to
+//! tell are left undetected. The detected languages are counted in the session stats
+//! on `/api/stats`, and `language_rules.json` maps a language to what is done to the
+//! messages in it, e.g.
+//!
+//! ```json
+//! {
+//!   "deu": { "action": "flag" },
+//!   "spa": { "action": "timeout", "timeout_sec": 60 }
+//! }
+//! ```
+//!
+//! The languages are ISO 639-3 codes. The rules are read again once the file changes.
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::helper::BotState;
+use crate::moderation::{self, Sanction};
+use crate::storage::JsonStore;
+use crate::{config, metrics, redact};
+
+const RULES_FILE_NAME: &str = "language_rules.json";
+/// Letters needed for a reliable guess, greetings and emotes are too short
+const MIN_DETECTION_LETTERS: usize = 20;
+
+type LanguageRules = Arc<BTreeMap<String, LanguageRule>>;
+
+/// Rules with the modification time of the file they were read from
+static RULES: Mutex<Option<(Option<SystemTime>, LanguageRules)>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageAction {
+    /// Only flag the message in the mod log
+    Flag,
+    Delete,
+    /// Time out the sender for `timeout_sec`
+    Timeout,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct LanguageRule {
+    pub action: LanguageAction,
+    #[serde(default = "default_timeout_sec")]
+    pub timeout_sec: u32,
+}
+
+fn default_timeout_sec() -> u32 {
+    60
+}
+
+/// ISO 639-3 code of the language of the message, `None` if it cannot be told
+#[must_use]
+pub fn detect(text: &str) -> Option<&'static str> {
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_DETECTION_LETTERS {
+        return None;
+    }
+
+    whatlang::detect(text)
+        .filter(whatlang::Info::is_reliable)
+        .map(|info| info.lang().code())
+}
+
+/// Rule of `language_rules.json` for the language
+///
+/// # Panics
+///
+/// Will panic if the rules lock is poisoned
+#[must_use]
+pub fn rule_for(language: &str) -> Option<LanguageRule> {
+    rules().get(language).copied()
+}
+
+/// Rules of the file, read again only if the file has changed since
+fn rules() -> LanguageRules {
+    let modified = fs::metadata(config::get_app_directory_path().join(RULES_FILE_NAME))
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let mut cached = RULES.lock().unwrap();
+
+    match *cached {
+        Some((read_at, ref rules)) if read_at == modified => Arc::clone(rules),
+        _ => {
+            let rules = Arc::new(
+                JsonStore::<BTreeMap<String, LanguageRule>>::open(RULES_FILE_NAME).clone(),
+            );
+
+            *cached = Some((modified, Arc::clone(&rules)));
+            rules
+        }
+    }
+}
+
+/// Apply the rule of the language to the message
+pub async fn act(
+    state: &BotState,
+    user_id: &str,
+    user_name: &str,
+    message_id: &str,
+    rule: LanguageRule,
+) {
+    tracing::info!(
+        "language rule for {}: {:?}",
+        redact::Name(user_name),
+        rule.action
+    );
+    metrics::increment("language_rule");
+
+    match rule.action {
+        LanguageAction::Flag => (),
+        LanguageAction::Delete => moderation::delete_message(message_id).await,
+        LanguageAction::Timeout => {
+            moderation::sanction(
+                state,
+                user_id,
+                "Chat language",
+                Sanction::Timeout {
+                    duration_sec: rule.timeout_sec,
+                },
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_is_detected_in_long_enough_messages() {
+        assert_eq!(
+            detect("Привет всем, как сегодня проходит стрим? Что будем играть?"),
+            Some("rus")
+        );
+        assert_eq!(
+            detect("Hello everyone, how is the stream going today? What are we playing?"),
+            Some("eng")
+        );
+        assert_eq!(detect("hi Kappa"), None);
+    }
+}
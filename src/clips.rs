@@ -0,0 +1,227 @@
+//! Clips created during the session.
+//!
+//! `!clip` clips the live stream, the clips the viewers make on Twitch are found
+//! through Helix Get Clips. Both are listed on `/api/clips` and embedded on `/clips`.
+//! During a co-stream `!clip <co-host>` clips the channel of the co-host who granted
+//! the bot a token on the admin page.
+//!
+//! Requires the following permissions:
+//! - clips:edit (of the co-hosts as well)
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use twitch_api::helix::clips::{Clip, GetClipsRequest};
+use twitch_api::helix::HelixClient;
+use twitch_api::types::{ClipIdRef, Timestamp};
+use twitch_oauth2::{TwitchToken, UserToken};
+
+use crate::eventsub::get_eventsub_token;
+use crate::metrics;
+use crate::transport::{create_api_client, helix_url};
+use crate::utils::delegated_token;
+
+const CREATE_CLIP_PATH: &str = "clips";
+const CLIP_URL_PREFIX: &str = "https://clips.twitch.tv/";
+/// Twitch needs a few seconds to process a new clip before Get Clips returns it
+const CLIP_PROCESSING_DELAY: Duration = Duration::from_secs(15);
+/// Clips of the viewers are fetched at most this often
+const CLIPS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const GET_CLIPS_PAGE_SIZE: usize = 100;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionClip {
+    pub id: String,
+    pub url: String,
+    /// Player URL, Twitch requires the `parent` query parameter to be appended
+    pub embed_url: String,
+    pub title: String,
+    pub creator_name: String,
+    pub thumbnail_url: String,
+    pub view_count: i64,
+    pub duration: f64,
+    pub created_at: String,
+}
+
+impl From<Clip> for SessionClip {
+    fn from(clip: Clip) -> Self {
+        SessionClip {
+            id: clip.id,
+            url: clip.url,
+            embed_url: clip.embed_url,
+            title: clip.title,
+            creator_name: clip.creator_name.to_string(),
+            thumbnail_url: clip.thumbnail_url,
+            view_count: clip.view_count,
+            duration: clip.duration,
+            created_at: clip.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreatedClips {
+    data: Vec<CreatedClip>,
+}
+
+#[derive(Deserialize)]
+struct CreatedClip {
+    id: String,
+}
+
+#[derive(Default)]
+pub struct ClipCollection {
+    /// Newest first
+    clips: Mutex<Vec<SessionClip>>,
+    refreshed: Mutex<Option<Instant>>,
+}
+
+pub type SafeClipCollection = Arc<ClipCollection>;
+
+impl ClipCollection {
+    /// Clips created since `session_start`, newest first
+    pub async fn list(&self, session_start: DateTime<Local>) -> Vec<SessionClip> {
+        let stale = {
+            let mut refreshed = self.refreshed.lock().await;
+            let stale = refreshed.is_none_or(|at| at.elapsed() >= CLIPS_REFRESH_INTERVAL);
+
+            if stale {
+                *refreshed = Some(Instant::now());
+            }
+
+            stale
+        };
+
+        if stale {
+            self.add(fetch_session_clips(session_start).await).await;
+        }
+
+        self.clips.lock().await.clone()
+    }
+
+    async fn add(&self, new_clips: Vec<SessionClip>) {
+        let mut clips = self.clips.lock().await;
+
+        for clip in new_clips {
+            match clips.iter_mut().find(|known| known.id == clip.id) {
+                Some(known) => *known = clip,
+                None => clips.push(clip),
+            }
+        }
+
+        // RFC 3339 timestamps in UTC sort chronologically
+        clips.sort_unstable_by(|a, b| b.created_at.cmp(&a.created_at));
+    }
+}
+
+pub fn create_clip_collection() -> SafeClipCollection {
+    Arc::new(ClipCollection::default())
+}
+
+/// Clip the live stream, of the co-host `channel` if it is set, returns the URL of the
+/// new clip
+///
+/// The clip is added to the collection once Twitch has processed it.
+pub async fn create(clips: &SafeClipCollection, channel: Option<&str>) -> Option<String> {
+    let token = match channel {
+        Some(login) => delegated_token(login).await,
+        None => get_eventsub_token().await,
+    };
+    let Some(token) = token else {
+        tracing::warn!("Unable to create a clip without the token of the channel");
+        return None;
+    };
+    let clip_id = match metrics::timed("helix_create_clip", request_clip(&token)).await {
+        Ok(Some(clip)) => clip.id,
+        Ok(None) => {
+            tracing::warn!("Twitch created no clip");
+            return None;
+        }
+        Err(e) => {
+            tracing::warn!("Unable to create a clip: {e}");
+            return None;
+        }
+    };
+
+    metrics::increment("clips_created");
+
+    let url = format!("{CLIP_URL_PREFIX}{clip_id}");
+    let clips = Arc::clone(clips);
+
+    tokio::spawn(async move {
+        tokio::time::sleep(CLIP_PROCESSING_DELAY).await;
+        clips.add(fetch_clip(&clip_id).await).await;
+    });
+
+    Some(url)
+}
+
+/// The Helix client sends Create Clip as GET while the endpoint only accepts POST
+async fn request_clip(
+    token: &UserToken,
+) -> Result<Option<CreatedClip>, Box<dyn std::error::Error + Send + Sync>> {
+    let body = reqwest::Client::new()
+        .post(helix_url(CREATE_CLIP_PATH))
+        .query(&[("broadcaster_id", token.user_id.as_str())])
+        .bearer_auth(token.access_token.secret())
+        .header("Client-Id", token.client_id().as_str())
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    Ok(serde_json::from_slice::<CreatedClips>(&body)?
+        .data
+        .into_iter()
+        .next())
+}
+
+async fn fetch_clip(clip_id: &str) -> Vec<SessionClip> {
+    let Some(token) = get_eventsub_token().await else {
+        return Vec::new();
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let ids: [&ClipIdRef; 1] = [clip_id.into()];
+
+    match metrics::timed(
+        "helix_get_clips",
+        client.req_get(GetClipsRequest::clip_ids(&ids[..]), &token),
+    )
+    .await
+    {
+        Ok(response) => response.data.into_iter().map(SessionClip::from).collect(),
+        Err(e) => {
+            tracing::warn!("Unable to get clip {clip_id}: {e}");
+            Vec::new()
+        }
+    }
+}
+
+async fn fetch_session_clips(session_start: DateTime<Local>) -> Vec<SessionClip> {
+    let Some(token) = get_eventsub_token().await else {
+        tracing::debug!("no EventSub token yet, the clips are not fetched");
+        return Vec::new();
+    };
+    let started_at = session_start
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let Ok(started_at) = Timestamp::new(started_at) else {
+        return Vec::new();
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let mut request =
+        GetClipsRequest::broadcaster_id(token.user_id.clone()).first(GET_CLIPS_PAGE_SIZE);
+
+    request.started_at(&started_at);
+
+    match metrics::timed("helix_get_clips", client.req_get(request, &token)).await {
+        Ok(response) => response.data.into_iter().map(SessionClip::from).collect(),
+        Err(e) => {
+            tracing::warn!("Unable to get the clips of the session: {e}");
+            Vec::new()
+        }
+    }
+}
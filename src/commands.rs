@@ -0,0 +1,349 @@
+//! Registry of the chat commands added with [`crate::Bot::register_command`].
+//!
+//! Commands can be put into groups that are enabled only in some stream
+//! categories, the groups are defined in code or in `command_groups.json`:
+//!
+//! ```json
+//! [{ "name": "minecraft", "categories": ["Minecraft"], "commands": ["!build", "!seed"] }]
+//! ```
+//!
+//! A command outside of any group is always enabled, built-in commands included.
+//! The built-in commands are registered here as well and the chat client runs
+//! them through the registry.
+//!
+//! The registry also describes every command for `!commands`, `!help` and the
+//! `/commands` page, the custom commands of [`crate::custom_commands`] included.
+//!
+//! Destructive commands, `HEWPME_CONFIRM_COMMANDS` or the ones added with
+//! [`crate::Bot::confirm_command`], run only after the user answers with
+//! `!confirm <code>` and the code the bot posted within `HEWPME_CONFIRM_TIMEOUT_SEC`.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::bot::{ChatCommand, Context};
+use crate::chat::BuiltinCommand;
+use crate::config;
+use crate::custom_commands::{self, CustomCommand};
+use crate::storage::JsonStore;
+
+const COMMAND_GROUPS_FILE_NAME: &str = "command_groups.json";
+/// Command confirming a destructive command
+pub const CONFIRM_COMMAND: &str = "!confirm";
+
+pub type CommandHandler = Arc<dyn Fn(Context, ChatCommand) -> BoxFuture<'static, ()> + Send + Sync>;
+/// Built-in command run by the chat client on the message itself
+pub(crate) type BuiltinHandler = for<'a> fn(BuiltinCommand<'a>) -> BoxFuture<'a, ()>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommandGroup {
+    pub name: String,
+    /// Stream categories the commands are enabled in, e.g. `Minecraft`
+    pub categories: Vec<String>,
+    pub commands: Vec<String>,
+}
+
+impl CommandGroup {
+    fn enabled_in(&self, category: Option<&str>) -> bool {
+        category.is_some_and(|category| {
+            self.categories
+                .iter()
+                .any(|enabled| enabled.eq_ignore_ascii_case(category))
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    #[default]
+    Everyone,
+    Moderator,
+    Broadcaster,
+}
+
+impl Permission {
+    /// Whether a chatter with the given roles may use a command of this permission
+    #[must_use]
+    pub fn allows(self, is_moderator: bool, is_broadcaster: bool) -> bool {
+        match self {
+            Permission::Everyone => true,
+            Permission::Moderator => is_moderator || is_broadcaster,
+            Permission::Broadcaster => is_broadcaster,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandInfo {
+    pub name: String,
+    pub description: String,
+    pub permission: Permission,
+    /// Time in seconds before the same viewer can use the command again
+    pub cooldown_sec: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct CommandRegistry {
+    handlers: HashMap<String, CommandHandler>,
+    builtins: HashMap<String, BuiltinHandler>,
+    infos: BTreeMap<String, CommandInfo>,
+    groups: Vec<CommandGroup>,
+    /// Commands run only after `!confirm`
+    confirmed: HashSet<String>,
+}
+
+pub type SafeCommandRegistry = Arc<CommandRegistry>;
+
+impl CommandRegistry {
+    pub fn register(&mut self, name: &str, handler: CommandHandler) {
+        self.handlers.insert(name.to_string(), handler);
+        self.infos
+            .entry(name.to_string())
+            .or_insert_with(|| CommandInfo {
+                name: name.to_string(),
+                description: String::new(),
+                permission: Permission::Everyone,
+                cooldown_sec: None,
+            });
+    }
+
+    pub(crate) fn register_builtin(&mut self, name: &str, handler: BuiltinHandler) {
+        self.builtins.insert(name.to_string(), handler);
+    }
+
+    /// Add or replace the description of the command
+    pub fn describe(&mut self, info: CommandInfo) {
+        self.infos.insert(info.name.clone(), info);
+    }
+
+    #[must_use]
+    pub fn info(&self, name: &str) -> Option<CommandInfo> {
+        self.infos
+            .get(name)
+            .cloned()
+            .or_else(|| custom_commands::get(name).as_ref().map(CustomCommand::info))
+    }
+
+    /// Whether the name is taken by a registered or a built-in command
+    #[must_use]
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.infos.contains_key(name)
+    }
+
+    /// Commands enabled in `category` sorted by name
+    #[must_use]
+    pub fn list(&self, category: Option<&str>) -> Vec<CommandInfo> {
+        let mut infos: Vec<CommandInfo> = self
+            .infos
+            .values()
+            .cloned()
+            .chain(
+                custom_commands::infos()
+                    .into_iter()
+                    .filter(|info| !self.infos.contains_key(&info.name)),
+            )
+            .filter(|info| self.is_enabled(&info.name, category))
+            .collect();
+
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    pub fn add_group(&mut self, group: CommandGroup) {
+        self.groups.push(group);
+    }
+
+    /// Add the groups defined in `command_groups.json`
+    pub fn load_groups(&mut self) {
+        self.groups
+            .extend(JsonStore::<Vec<CommandGroup>>::open(COMMAND_GROUPS_FILE_NAME).to_vec());
+    }
+
+    /// Run the command only after the user confirms it with `!confirm <code>`
+    pub fn require_confirmation(&mut self, name: &str) {
+        self.confirmed.insert(name.to_string());
+    }
+
+    #[must_use]
+    pub fn needs_confirmation(&self, name: &str) -> bool {
+        self.confirmed.contains(name)
+            || config::get_confirm_commands()
+                .iter()
+                .any(|command| command == name)
+    }
+
+    #[must_use]
+    pub fn handler(&self, name: &str) -> Option<&CommandHandler> {
+        self.handlers.get(name)
+    }
+
+    #[must_use]
+    pub(crate) fn builtin(&self, name: &str) -> Option<BuiltinHandler> {
+        self.builtins.get(name).copied()
+    }
+
+    /// Whether the command can be used while streaming in `category`
+    #[must_use]
+    pub fn is_enabled(&self, name: &str, category: Option<&str>) -> bool {
+        let mut groups = self
+            .groups
+            .iter()
+            .filter(|group| group.commands.iter().any(|command| command == name))
+            .peekable();
+
+        groups.peek().is_none() || groups.any(|group| group.enabled_in(category))
+    }
+}
+
+/// Command waiting for the `!confirm` of the user who sent it
+struct PendingCommand {
+    code: String,
+    name: String,
+    command: ChatCommand,
+    expires_at: Instant,
+}
+
+/// Destructive commands waiting for confirmation, one per user
+pub struct CommandConfirmations {
+    timeout: Duration,
+    pending: Mutex<HashMap<String, PendingCommand>>,
+}
+
+pub type SafeCommandConfirmations = Arc<CommandConfirmations>;
+
+impl CommandConfirmations {
+    #[must_use]
+    pub fn new(timeout: Duration) -> Self {
+        CommandConfirmations {
+            timeout,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Hold the command until it is confirmed, returns the code to confirm it with.
+    /// A previous command of the user waiting for confirmation is dropped.
+    pub async fn request(&self, name: &str, command: ChatCommand) -> String {
+        let code = format!("{:04}", rand::thread_rng().gen_range(0..10_000));
+        let now = Instant::now();
+        let mut pending = self.pending.lock().await;
+
+        pending.retain(|_, waiting| waiting.expires_at > now);
+        pending.insert(
+            command.user_id.clone(),
+            PendingCommand {
+                code: code.clone(),
+                name: name.to_string(),
+                command,
+                expires_at: now + self.timeout,
+            },
+        );
+
+        code
+    }
+
+    /// Command of the user confirmed with the code, `None` if the code is wrong or late
+    pub async fn confirm(&self, user_id: &str, code: &str) -> Option<(String, ChatCommand)> {
+        let mut pending = self.pending.lock().await;
+        let waiting = pending.remove(user_id)?;
+
+        if waiting.expires_at <= Instant::now() {
+            return None;
+        }
+
+        if waiting.code != code {
+            // a typo keeps the command waiting
+            pending.insert(user_id.to_string(), waiting);
+            return None;
+        }
+
+        Some((waiting.name, waiting.command))
+    }
+}
+
+pub fn create_command_confirmations() -> SafeCommandConfirmations {
+    Arc::new(CommandConfirmations::new(config::get_confirm_timeout()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(user_id: &str) -> ChatCommand {
+        ChatCommand {
+            user_id: user_id.to_string(),
+            user_name: user_id.to_string(),
+            args: vec![String::from("all")],
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_code_of_the_user_confirms_the_command() {
+        let confirmations = CommandConfirmations::new(Duration::from_secs(30));
+        let code = confirmations.request("!nuke", command("1")).await;
+
+        assert!(confirmations.confirm("2", &code).await.is_none());
+        assert!(confirmations.confirm("1", "wrong").await.is_none());
+
+        let (name, confirmed) = confirmations.confirm("1", &code).await.unwrap();
+
+        assert_eq!(name, "!nuke");
+        assert_eq!(confirmed.args, ["all"]);
+        assert!(confirmations.confirm("1", &code).await.is_none());
+    }
+
+    #[test]
+    fn builtin_ban_is_confirmed_with_the_default_config() {
+        let mut commands = CommandRegistry::default();
+
+        crate::chat::register_builtin_handlers(&mut commands);
+
+        assert!(commands.handler("!ban").is_some());
+        assert!(commands.needs_confirmation("!ban"));
+    }
+
+    #[test]
+    fn described_builtins_run_through_the_registry() {
+        let mut commands = CommandRegistry::default();
+
+        crate::chat::register_builtin_handlers(&mut commands);
+
+        for info in crate::chat::builtin_commands(crate::i18n::Lang::En) {
+            let on_event_bus =
+                commands.handler(&info.name).is_some() || info.name == CONFIRM_COMMAND;
+
+            assert!(
+                on_event_bus != commands.builtin(&info.name).is_some(),
+                "{}",
+                info.name
+            );
+        }
+    }
+
+    #[test]
+    fn moderators_may_not_use_broadcaster_commands() {
+        assert!(Permission::Everyone.allows(false, false));
+        assert!(Permission::Moderator.allows(true, false));
+        assert!(Permission::Moderator.allows(false, true));
+        assert!(!Permission::Broadcaster.allows(true, false));
+        assert!(Permission::Broadcaster.allows(false, true));
+    }
+
+    #[tokio::test]
+    async fn late_confirmation_is_rejected() {
+        let confirmations = CommandConfirmations::new(Duration::ZERO);
+        let code = confirmations.request("!ban", command("1")).await;
+
+        assert!(confirmations.confirm("1", &code).await.is_none());
+    }
+}
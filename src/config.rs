@@ -1,50 +1,1278 @@
+//! Settings of the bot.
+//!
+//! Every setting is an environment variable, e.g. `HEWPME_PORT`. The main ones can be
+//! written to `config.toml` in the app directory instead, the variables override it:
+//!
+//! ```toml
+//! channel = "streamer"
+//! bot_account = "streamer_bot"
+//! client_id = "..."
+//! client_secret = "..."
+//! port = 12345
+//! redirect_url = "http://localhost:3000/auth/twitch/callback"
+//!
+//! [features]
+//! moderation = false
+//! ```
+//!
+//! [`validate`] reports what is missing or malformed before the bot starts.
 const APP_NAME: &str = "hewpme";
 
 use std::path::PathBuf;
-use std::{env, fs};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::{env, fmt, fs, io};
 
+use chrono_tz::Tz;
 use directories::BaseDirs;
+use serde::Deserialize;
+
+use crate::safe_mode;
 
 pub const REDIRECT_URL: &str = "http://localhost:3000/auth/twitch/callback";
 pub const CHAT_CONFIG_FILE_NAME: &str = "chat.json";
 pub const EVENTSUB_CONFIG_FILE_NAME: &str = "eventsub.json";
+pub const DELEGATED_CONFIG_FILE_NAME: &str = "delegated.json";
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+const DEFAULT_SERVER_PORT: u16 = 12345;
+const DEFAULT_SLOW_THRESHOLD_MS: u64 = 500;
+const DEFAULT_EVENTSUB_SILENCE_MIN: u64 = 3;
+const DEFAULT_EVENTSUB_RECONNECT_ATTEMPTS: u32 = 10;
+const DEFAULT_EVENTSUB_RECONNECT_DELAY_MS: u64 = 1000;
+const DEFAULT_EVENTSUB_RECONNECT_MAX_DELAY_SEC: u64 = 60;
+const DEFAULT_OUTAGE_FAILURES: usize = 5;
+const DEFAULT_OUTAGE_WINDOW_SEC: u64 = 2 * 60;
+const DEFAULT_OUTAGE_RETRY_SEC: u64 = 60;
+const DEFAULT_OUTBOX_MAX_AGE_SEC: u64 = 5 * 60;
+const DEFAULT_VANISH_COOLDOWN_SEC: u64 = 60;
+const DEFAULT_CLIP_COOLDOWN_SEC: u64 = 30;
+const DEFAULT_INFO_COOLDOWN_SEC: u64 = 30;
+const DEFAULT_OVERLAY_TOKEN_TTL_HOURS: u64 = 24;
+const DEFAULT_CREDIT_COOLDOWN_SEC: u64 = 60;
+const DEFAULT_STARTUP_TOKEN_TIMEOUT_SEC: u64 = 5 * 60;
+const DEFAULT_STARTUP_IDENTITY_TIMEOUT_SEC: u64 = 30;
+const DEFAULT_JOIN_TIMEOUT_SEC: u64 = 10;
+const DEFAULT_JOIN_ATTEMPTS: u32 = 3;
+const DEFAULT_IDLE_CHAT_MIN: u64 = 10;
+const DEFAULT_IDLE_COMMAND_GRACE_SEC: u64 = 5 * 60;
+const DEFAULT_FOLLOW_WAVE_COUNT: usize = 20;
+const DEFAULT_FOLLOW_WAVE_WINDOW_SEC: u64 = 60;
+const DEFAULT_RAID_MESSAGES_COUNT: usize = 100;
+const DEFAULT_RAID_WINDOW_SEC: u64 = 30;
+const DEFAULT_PROTECTION_COOLDOWN_SEC: u64 = 10 * 60;
+const DEFAULT_CELEBRATION_GIFTS: u64 = 100;
+const DEFAULT_CELEBRATION_RAID_VIEWERS: u64 = 500;
+const DEFAULT_CELEBRATION_SEC: u64 = 60;
+const DEFAULT_NEW_ACCOUNT_DAYS: i64 = 7;
+const DEFAULT_COPYPASTA_SIMILARITY: f64 = 0.8;
+const DEFAULT_CONFIRM_TIMEOUT_SEC: u64 = 30;
+const DEFAULT_COPYPASTA_USERS: usize = 3;
+const DEFAULT_COPYPASTA_WINDOW_SEC: u64 = 60;
+const DEFAULT_EVASION_SIMILARITY: f64 = 0.6;
+const DEFAULT_EVASION_WINDOW_MIN: u64 = 60;
+const DEFAULT_WHEEL_COST: u64 = 100;
+const DEFAULT_BREAK_SLOW_MODE_SEC: u64 = 30;
+const DEFAULT_SAFE_MODE_CRASHES: usize = 3;
+const DEFAULT_SAFE_MODE_WINDOW_MIN: u64 = 10;
+const DEFAULT_COPYPASTA_MIN_LENGTH: usize = 20;
+const DEFAULT_COPYPASTA_TIMEOUT_SEC: u32 = 60;
+const DEFAULT_COMMAND_FLOOD_MAX: usize = 8;
+const DEFAULT_COMMAND_FLOOD_WINDOW_SEC: u64 = 10;
+const DEFAULT_COMMAND_FLOOD_IGNORE_MIN: u64 = 10;
+const DEFAULT_EMOTE_POLL_MIN: u64 = 10;
+const DEFAULT_PRESENCE_POLL_MIN: u64 = 0;
+const DEFAULT_SUB_LAPSE_MIN_MONTHS: u64 = 6;
+const DEFAULT_CREDITS_ROLL_MIN: u64 = 0;
+const DEFAULT_SESSION_RESUME_MIN: u64 = 10;
+const DEFAULT_CREDIT_MESSAGE_MAX_LEN: usize = 80;
+const DEFAULT_MODLOG_CAPACITY: usize = 500;
+const DEFAULT_ACTIVITY_MAX_MINUTES: usize = 12 * 60;
+const DEFAULT_STORE_WARN_PERCENT: usize = 90;
+const DEFAULT_UNFURL_TIMEOUT_MS: u64 = 3000;
+const DEFAULT_IMAGE_CACHE_TTL_SEC: u64 = 24 * 60 * 60;
+
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+static FILE_CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings of `config.toml`, the environment variables override them
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// `TWITCH_CHANNEL`
+    pub channel: Option<String>,
+    /// `HEWPME_BOT_ACCOUNT`
+    pub bot_account: Option<String>,
+    /// `TWITCH_CLIENT_ID`
+    pub client_id: Option<String>,
+    /// `TWITCH_CLIENT_SECRET`
+    pub client_secret: Option<String>,
+    /// `HEWPME_PORT`
+    pub port: Option<u16>,
+    /// `HEWPME_REDIRECT_URL`
+    pub redirect_url: Option<String>,
+    #[serde(default)]
+    pub features: FeatureSwitches,
+}
+
+/// `[features]` table of `config.toml`, the missing ones are enabled
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FeatureSwitches {
+    pub chat_bot: Option<bool>,
+    pub eventsub: Option<bool>,
+    pub web_server: Option<bool>,
+    pub overlays: Option<bool>,
+    pub moderation: Option<bool>,
+}
+
+impl Config {
+    /// Value of the file for the environment variable
+    fn value_of(&self, name: &str) -> Option<String> {
+        let features = &self.features;
+        let feature = |enabled: Option<bool>| enabled.map(|enabled| enabled.to_string());
+
+        match name {
+            "TWITCH_CHANNEL" => self.channel.clone(),
+            "HEWPME_BOT_ACCOUNT" => self.bot_account.clone(),
+            "TWITCH_CLIENT_ID" => self.client_id.clone(),
+            "TWITCH_CLIENT_SECRET" => self.client_secret.clone(),
+            "HEWPME_PORT" => self.port.map(|port| port.to_string()),
+            "HEWPME_REDIRECT_URL" => self.redirect_url.clone(),
+            "HEWPME_FEATURE_CHAT_BOT" => feature(features.chat_bot),
+            "HEWPME_FEATURE_EVENTSUB" => feature(features.eventsub),
+            "HEWPME_FEATURE_WEB_SERVER" => feature(features.web_server),
+            "HEWPME_FEATURE_OVERLAYS" => feature(features.overlays),
+            "HEWPME_FEATURE_MODERATION" => feature(features.moderation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// Required setting set neither in the file nor in the environment
+    Missing {
+        key: &'static str,
+        variable: &'static str,
+    },
+    Invalid {
+        variable: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "unable to read {}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "invalid {}: {e}", path.display()),
+            ConfigError::Missing { key, variable } => write!(
+                f,
+                "{key} is not configured, set it in {CONFIG_FILE_NAME} or with {variable}"
+            ),
+            ConfigError::Invalid { variable, value } => {
+                write!(f, "invalid value {value:?} of {variable}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// What the follower counter of the credits and the overlays shows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FollowerCountMode {
+    /// New followers of the current session
+    #[default]
+    Session,
+    /// All followers of the channel, fetched on start and incremented live
+    Total,
+}
+
+impl FromStr for FollowerCountMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "session" => Ok(FollowerCountMode::Session),
+            "total" => Ok(FollowerCountMode::Total),
+            _ => Err(format!("unknown follower count mode {s}")),
+        }
+    }
+}
+
+/// Subsystem that can be turned off, e.g. to only collect the credits without
+/// answering in chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Commands, replies and announcements, the chat is still read for the credits
+    ChatBot,
+    /// Follows, subscriptions and the stream status
+    EventSub,
+    /// Credits, overlays and the admin pages
+    WebServer,
+    /// Alert, chat and highlights overlays
+    Overlays,
+    /// Raid protection, copy-pasta detection and the command flood guard
+    Moderation,
+}
+
+impl Feature {
+    const ALL: [Feature; 5] = [
+        Feature::ChatBot,
+        Feature::EventSub,
+        Feature::WebServer,
+        Feature::Overlays,
+        Feature::Moderation,
+    ];
+
+    fn env_name(self) -> &'static str {
+        match self {
+            Feature::ChatBot => "HEWPME_FEATURE_CHAT_BOT",
+            Feature::EventSub => "HEWPME_FEATURE_EVENTSUB",
+            Feature::WebServer => "HEWPME_FEATURE_WEB_SERVER",
+            Feature::Overlays => "HEWPME_FEATURE_OVERLAYS",
+            Feature::Moderation => "HEWPME_FEATURE_MODERATION",
+        }
+    }
+}
+
+/// What is done to the messages of a copy-pasta wave
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CopypastaAction {
+    /// Only flag the message in the mod log
+    #[default]
+    Flag,
+    Delete,
+    /// Time out the sender for `HEWPME_COPYPASTA_TIMEOUT_SEC`
+    Timeout,
+    Ban,
+}
+
+impl FromStr for CopypastaAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flag" => Ok(CopypastaAction::Flag),
+            "delete" => Ok(CopypastaAction::Delete),
+            "timeout" => Ok(CopypastaAction::Timeout),
+            "ban" => Ok(CopypastaAction::Ban),
+            _ => Err(format!("unknown copy-pasta action {s}")),
+        }
+    }
+}
 
+/// Select the config profile, e.g. `test`, instead of `HEWPME_PROFILE`
+///
+/// # Panics
+///
+/// Will panic if the profile is already selected
+pub fn set_profile(name: &str) {
+    PROFILE
+        .set(Some(name.to_string()))
+        .expect("Config profile is already selected");
+}
+
+/// Name of the selected config profile, `None` for the default one.
+///
+/// A profile has its own token files and every setting can be overridden
+/// for it with the `<NAME>_<PROFILE>` variable, e.g. `HEWPME_PORT_TEST`.
+#[must_use]
+pub fn get_profile() -> Option<&'static str> {
+    PROFILE
+        .get_or_init(|| env::var("HEWPME_PROFILE").ok())
+        .as_deref()
+}
+
+/// Directory of the tokens, the database and the JSON stores, `HEWPME_APP_DIR`
+/// overrides the one in the user config directory
+///
 /// # Panics
 ///
 /// Will panic if application directory cannot be created
 #[must_use]
 pub fn get_app_directory_path() -> PathBuf {
-    let app_dir = BaseDirs::new().unwrap().config_dir().join(APP_NAME);
+    // the directory of config.toml itself cannot come from it
+    let app_dir = env::var_os("HEWPME_APP_DIR").map_or_else(
+        || BaseDirs::new().unwrap().config_dir().join(APP_NAME),
+        PathBuf::from,
+    );
 
     if !app_dir.exists() {
-        fs::create_dir(&app_dir).expect("Unable to create bot config directory");
+        fs::create_dir_all(&app_dir).expect("Unable to create bot config directory");
     }
 
     app_dir
 }
 
+/// `config.toml` of the selected profile, e.g. `config.test.toml`
+#[must_use]
+pub fn get_config_file() -> PathBuf {
+    get_app_directory_path().join(profile_file_name(CONFIG_FILE_NAME))
+}
+
+/// Read `config.toml`, a missing file is an empty config
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be read or parsed
+pub fn load_file_config() -> Result<Config, ConfigError> {
+    let path = get_config_file();
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(ConfigError::Read(path, e)),
+    };
+
+    toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))
+}
+
+/// Check that the config file is valid, the required settings are present and the
+/// main ones are well-formed
+///
+/// # Errors
+///
+/// Will return `Err` describing the first problem found
+pub fn validate() -> Result<(), ConfigError> {
+    let file = load_file_config()?;
+    let lookup =
+        |variable: &str| get_env_without_file(variable).or_else(|| file.value_of(variable));
+
+    for (key, variable) in [
+        ("channel", "TWITCH_CHANNEL"),
+        ("client_id", "TWITCH_CLIENT_ID"),
+        ("client_secret", "TWITCH_CLIENT_SECRET"),
+    ] {
+        if lookup(variable).is_none_or(|value| value.trim().is_empty()) {
+            return Err(ConfigError::Missing { key, variable });
+        }
+    }
+
+    let invalid = |variable: &'static str, value: String| ConfigError::Invalid { variable, value };
+
+    if let Some(port) = lookup("HEWPME_PORT") {
+        port.parse::<u16>()
+            .map_err(|_| invalid("HEWPME_PORT", port))?;
+    }
+
+    if let Some(url) = lookup("HEWPME_REDIRECT_URL") {
+        reqwest::Url::parse(&url).map_err(|_| invalid("HEWPME_REDIRECT_URL", url))?;
+    }
+
+    // the channel would never be joined
+    if let Some(attempts) = lookup("HEWPME_JOIN_ATTEMPTS") {
+        attempts
+            .parse::<u32>()
+            .ok()
+            .filter(|&attempts| attempts > 0)
+            .ok_or_else(|| invalid("HEWPME_JOIN_ATTEMPTS", attempts))?;
+    }
+
+    for feature in Feature::ALL {
+        if let Some(enabled) = lookup(feature.env_name()) {
+            enabled
+                .parse::<bool>()
+                .map_err(|_| invalid(feature.env_name(), enabled))?;
+        }
+    }
+
+    Ok(())
+}
+
 #[must_use]
 pub fn get_eventsub_config_file() -> PathBuf {
-    get_app_directory_path().join(EVENTSUB_CONFIG_FILE_NAME)
+    get_app_directory_path().join(profile_file_name(EVENTSUB_CONFIG_FILE_NAME))
 }
 
 #[must_use]
 pub fn get_chat_config_file() -> PathBuf {
-    get_app_directory_path().join(CHAT_CONFIG_FILE_NAME)
+    get_app_directory_path().join(profile_file_name(CHAT_CONFIG_FILE_NAME))
+}
+
+/// Tokens the co-hosts granted the bot for their own channels
+#[must_use]
+pub fn get_delegated_config_file() -> PathBuf {
+    get_app_directory_path().join(profile_file_name(DELEGATED_CONFIG_FILE_NAME))
+}
+
+/// Redirect URL of the token authorization, it has to reach the callback server
+/// on port 3000 and be registered for the Twitch application
+#[must_use]
+pub fn get_redirect_url() -> String {
+    get_env("HEWPME_REDIRECT_URL").unwrap_or_else(|| REDIRECT_URL.to_string())
+}
+
+/// Redirect URL of the co-host authorization, the main one by default
+#[must_use]
+pub fn get_delegation_redirect_url() -> String {
+    get_env("HEWPME_DELEGATION_REDIRECT_URL").unwrap_or_else(get_redirect_url)
+}
+
+/// `chat.json` becomes `chat.test.json` for the `test` profile
+fn profile_file_name(file_name: &str) -> String {
+    match (get_profile(), file_name.rsplit_once('.')) {
+        (Some(profile), Some((stem, extension))) => format!("{stem}.{profile}.{extension}"),
+        _ => file_name.to_string(),
+    }
 }
 
 /// # Panics
 ///
-/// Will panic `TWITCH_CLIENT_ID` environment variable is not set
+/// Will panic if the channel is not configured, see [`validate`]
+#[must_use]
+pub fn get_channel_name() -> String {
+    get_env("TWITCH_CHANNEL").expect("Please specify Twitch channel name to connect to")
+}
+
+/// Login the chat token has to belong to, any account is accepted if not set
+#[must_use]
+pub fn get_bot_account() -> Option<String> {
+    get_env("HEWPME_BOT_ACCOUNT")
+}
+
+#[must_use]
+pub fn get_server_port() -> u16 {
+    get_env_or("HEWPME_PORT", DEFAULT_SERVER_PORT)
+}
+
+/// EventSub WebSocket to connect to instead of Twitch, e.g. the Twitch CLI mock server
+#[must_use]
+pub fn get_eventsub_url() -> Option<String> {
+    get_env("HEWPME_EVENTSUB_URL")
+}
+
+/// Chat server to connect to over plain TCP instead of Twitch, e.g. `127.0.0.1:6667`
+/// of a local IRC server
+#[must_use]
+pub fn get_irc_addr() -> Option<String> {
+    get_env("HEWPME_IRC_ADDR")
+}
+
+/// # Panics
+///
+/// Will panic if the client ID is not configured, see [`validate`]
 #[must_use]
 pub fn get_client_id() -> String {
-    env::var("TWITCH_CLIENT_ID").unwrap()
+    get_env("TWITCH_CLIENT_ID").expect("Please specify the Twitch application client ID")
 }
 
 /// # Panics
 ///
-/// Will panic `TWITCH_CLIENT_SECRET` environment variable is not set
+/// Will panic if the client secret is not configured, see [`validate`]
 #[must_use]
 pub fn get_client_secret() -> String {
-    env::var("TWITCH_CLIENT_SECRET").unwrap()
+    get_env("TWITCH_CLIENT_SECRET").expect("Please specify the Twitch application client secret")
+}
+
+/// Silence on the EventSub connection of a live stream after which the session is rebuilt
+#[must_use]
+pub fn get_eventsub_silence_limit() -> Duration {
+    Duration::from_secs(
+        get_env_or("HEWPME_EVENTSUB_SILENCE_MIN", DEFAULT_EVENTSUB_SILENCE_MIN) * 60,
+    )
+}
+
+/// Failed EventSub reconnection attempts in a row before the client gives up, `0` for no limit
+#[must_use]
+pub fn get_eventsub_reconnect_attempts() -> u32 {
+    get_env_or(
+        "HEWPME_EVENTSUB_RECONNECT_ATTEMPTS",
+        DEFAULT_EVENTSUB_RECONNECT_ATTEMPTS,
+    )
+}
+
+/// Delay before the first reconnection attempt, doubled on every next one
+#[must_use]
+pub fn get_eventsub_reconnect_delay() -> Duration {
+    Duration::from_millis(get_env_or(
+        "HEWPME_EVENTSUB_RECONNECT_DELAY_MS",
+        DEFAULT_EVENTSUB_RECONNECT_DELAY_MS,
+    ))
+}
+
+#[must_use]
+pub fn get_eventsub_reconnect_max_delay() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_EVENTSUB_RECONNECT_MAX_DELAY_SEC",
+        DEFAULT_EVENTSUB_RECONNECT_MAX_DELAY_SEC,
+    ))
+}
+
+/// Failures of a Twitch source within the window starting an outage
+#[must_use]
+pub fn get_outage_failures() -> usize {
+    get_env_or("HEWPME_OUTAGE_FAILURES", DEFAULT_OUTAGE_FAILURES)
+}
+
+#[must_use]
+pub fn get_outage_window() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_OUTAGE_WINDOW_SEC",
+        DEFAULT_OUTAGE_WINDOW_SEC,
+    ))
+}
+
+/// Interval of the retries and the probes during a Twitch outage
+#[must_use]
+pub fn get_outage_retry_delay() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_OUTAGE_RETRY_SEC",
+        DEFAULT_OUTAGE_RETRY_SEC,
+    ))
+}
+
+/// Chat messages held while the chat is down are dropped after this age
+#[must_use]
+pub fn get_outbox_max_age() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_OUTBOX_MAX_AGE_SEC",
+        DEFAULT_OUTBOX_MAX_AGE_SEC,
+    ))
+}
+
+/// Sections running longer than this threshold are reported with a warning
+#[must_use]
+pub fn get_slow_threshold_ms() -> u64 {
+    get_env_or("HEWPME_SLOW_THRESHOLD_MS", DEFAULT_SLOW_THRESHOLD_MS)
+}
+
+/// Recent crashes that make the bot start in safe mode, `0` turns it off
+#[must_use]
+pub fn get_safe_mode_crashes() -> usize {
+    get_env_or("HEWPME_SAFE_MODE_CRASHES", DEFAULT_SAFE_MODE_CRASHES)
+}
+
+/// How recent the crashes counted for safe mode are
+#[must_use]
+pub fn get_safe_mode_window() -> Duration {
+    Duration::from_secs(
+        get_env_or("HEWPME_SAFE_MODE_WINDOW_MIN", DEFAULT_SAFE_MODE_WINDOW_MIN) * 60,
+    )
+}
+
+/// Follow the bot already running for the channel instead of stopping
+#[must_use]
+pub fn get_follower_mode_enabled() -> bool {
+    get_env_or("HEWPME_FOLLOWER", false)
+}
+
+/// Every feature is enabled unless it is turned off with e.g. `HEWPME_FEATURE_CHAT_BOT=false`
+#[must_use]
+pub fn is_feature_enabled(feature: Feature) -> bool {
+    // safe mode keeps the collectors and the web server only
+    if safe_mode::is_active() && matches!(feature, Feature::ChatBot | Feature::Moderation) {
+        return false;
+    }
+
+    get_env_or(feature.env_name(), true)
+}
+
+/// Mask the user names on the admin page and in the logs from the start,
+/// the mode is toggled at runtime on the admin page
+#[must_use]
+pub fn get_streamer_privacy_enabled() -> bool {
+    get_env_or("HEWPME_STREAMER_PRIVACY", false)
+}
+
+/// Require a signed token to open the overlay WebSocket
+#[must_use]
+pub fn get_overlay_auth() -> bool {
+    get_env_or("HEWPME_OVERLAY_AUTH", false)
+}
+
+/// How long the token of the generated overlay URLs is valid
+#[must_use]
+pub fn get_overlay_token_ttl() -> Duration {
+    Duration::from_secs(
+        get_env_or(
+            "HEWPME_OVERLAY_TOKEN_TTL_HOURS",
+            DEFAULT_OVERLAY_TOKEN_TTL_HOURS,
+        ) * 60
+            * 60,
+    )
+}
+
+/// Secret of the admin routes, a generated one is kept in the app directory without it
+#[must_use]
+pub fn get_admin_token() -> Option<String> {
+    get_env("HEWPME_ADMIN_TOKEN")
+}
+
+/// Serve the highlighted chat messages on `/highlights`
+#[must_use]
+pub fn get_highlights_page_enabled() -> bool {
+    get_env_or("HEWPME_HIGHLIGHTS_PAGE", false)
+}
+
+#[must_use]
+pub fn get_toasts_enabled() -> bool {
+    get_env_or("HEWPME_TOASTS", false)
+}
+
+#[must_use]
+pub fn get_toast_on_follow() -> bool {
+    get_env_or("HEWPME_TOAST_FOLLOWS", true)
+}
+
+#[must_use]
+pub fn get_toast_on_subscribe() -> bool {
+    get_env_or("HEWPME_TOAST_SUBSCRIPTIONS", true)
+}
+
+#[must_use]
+pub fn get_toast_on_raid() -> bool {
+    get_env_or("HEWPME_TOAST_RAIDS", true)
+}
+
+/// Toasts for the chat messages with a watch word are opt-in
+#[must_use]
+pub fn get_toast_on_mention() -> bool {
+    get_env_or("HEWPME_TOAST_MENTIONS", false)
+}
+
+/// Comma-separated words or phrases the streamer wants to see mentioned in the chat,
+/// e.g. their name or the terms of the game
+#[must_use]
+pub fn get_watch_words() -> Vec<String> {
+    get_env_list("HEWPME_WATCH_WORDS")
+}
+
+/// Comma-separated list of scenes in which toasts are suppressed
+#[must_use]
+pub fn get_toast_dnd_scenes() -> Vec<String> {
+    get_env_list("HEWPME_TOAST_DND_SCENES")
+}
+
+/// Whether the bot welcomes the raiders in the chat
+#[must_use]
+pub fn get_raid_greeting() -> bool {
+    get_env_or("HEWPME_RAID_GREETING", true)
+}
+
+/// Whether the raiders get a shoutout in the chat and a Twitch shoutout
+#[must_use]
+pub fn get_raid_shoutout() -> bool {
+    get_env_or("HEWPME_RAID_SHOUTOUT", false)
+}
+
+/// Characters of a viewer message in the credits at most
+#[must_use]
+pub fn get_credit_message_max_len() -> usize {
+    get_env_or(
+        "HEWPME_CREDIT_MESSAGE_MAX_LEN",
+        DEFAULT_CREDIT_MESSAGE_MAX_LEN,
+    )
+}
+
+/// Words a viewer message in the credits must not have, compared ignoring the case
+#[must_use]
+pub fn get_credit_message_blocked_words() -> Vec<String> {
+    get_env_list("HEWPME_CREDIT_MESSAGE_BLOCKED_WORDS")
+}
+
+/// Channels of the raid train in its order, the own one included
+#[must_use]
+pub fn get_raid_train() -> Vec<String> {
+    get_env_list("HEWPME_RAID_TRAIN")
+}
+
+/// Whether the next channel of the raid train is raided when the stream goes offline
+#[must_use]
+pub fn get_raid_train_auto() -> bool {
+    get_env_or("HEWPME_RAID_TRAIN_AUTO", false)
+}
+
+/// Shoutout posted for the raiders instead of the default one,
+/// `{name}`, `{url}` and `{viewers}` are replaced
+#[must_use]
+pub fn get_raid_shoutout_message() -> Option<String> {
+    get_env("HEWPME_RAID_SHOUTOUT_MESSAGE")
+}
+
+/// Warning posted when an ad break starts instead of the default one,
+/// `{seconds}` is replaced
+#[must_use]
+pub fn get_ad_break_message() -> Option<String> {
+    get_env("HEWPME_AD_BREAK_MESSAGE")
+}
+
+/// Per-user cooldown of the `!vanish` command
+#[must_use]
+pub fn get_vanish_cooldown() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_VANISH_COOLDOWN_SEC",
+        DEFAULT_VANISH_COOLDOWN_SEC,
+    ))
+}
+
+/// Channel-wide cooldown of the `!clip` command
+#[must_use]
+pub fn get_clip_cooldown() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_CLIP_COOLDOWN_SEC",
+        DEFAULT_CLIP_COOLDOWN_SEC,
+    ))
+}
+
+/// Channel-wide cooldown of each of the `!uptime`, `!title` and `!game` commands
+#[must_use]
+pub fn get_info_cooldown() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_INFO_COOLDOWN_SEC",
+        DEFAULT_INFO_COOLDOWN_SEC,
+    ))
+}
+
+/// Per-user cooldown of the `!credit` command, each use rewrites the credit messages
+#[must_use]
+pub fn get_credit_cooldown() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_CREDIT_COOLDOWN_SEC",
+        DEFAULT_CREDIT_COOLDOWN_SEC,
+    ))
+}
+
+/// Number of missed streams in a row which do not break a watch streak
+#[must_use]
+pub fn get_streak_grace() -> u32 {
+    get_env_or("HEWPME_STREAK_GRACE", 0)
+}
+
+/// Language of the messages addressed to the whole channel
+#[must_use]
+pub fn get_channel_language() -> String {
+    get_env("HEWPME_LANGUAGE").unwrap_or_else(|| String::from("ru"))
+}
+
+/// Delay before joining the channel after the chat client is started
+#[must_use]
+pub fn get_join_delay() -> Duration {
+    Duration::from_millis(get_env_or("HEWPME_JOIN_DELAY_MS", 0))
+}
+
+/// How long the startup waits for the tokens, including the browser authorization
+#[must_use]
+pub fn get_startup_token_timeout() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_STARTUP_TOKEN_TIMEOUT_SEC",
+        DEFAULT_STARTUP_TOKEN_TIMEOUT_SEC,
+    ))
+}
+
+/// How long the startup waits for the channel user ID
+#[must_use]
+pub fn get_startup_identity_timeout() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_STARTUP_IDENTITY_TIMEOUT_SEC",
+        DEFAULT_STARTUP_IDENTITY_TIMEOUT_SEC,
+    ))
+}
+
+/// How long to wait for Twitch to confirm the channel join
+#[must_use]
+pub fn get_join_timeout() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_JOIN_TIMEOUT_SEC",
+        DEFAULT_JOIN_TIMEOUT_SEC,
+    ))
+}
+
+/// Unconfirmed joins before the chat is reported down, the bot keeps retrying after them
+#[must_use]
+pub fn get_join_attempts() -> u32 {
+    get_env_or("HEWPME_JOIN_ATTEMPTS", DEFAULT_JOIN_ATTEMPTS).max(1)
+}
+
+#[must_use]
+pub fn get_image_cache_ttl() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_IMAGE_CACHE_TTL_SEC",
+        DEFAULT_IMAGE_CACHE_TTL_SEC,
+    ))
+}
+
+/// Hosts allowed in the image cache in addition to the Twitch and 7TV CDNs
+#[must_use]
+pub fn get_image_cache_hosts() -> Vec<String> {
+    get_env_list("HEWPME_IMAGE_CACHE_HOSTS")
+}
+
+/// Silence in chat after which an engagement prompt is posted
+#[must_use]
+pub fn get_idle_chat_period() -> Duration {
+    Duration::from_secs(get_env_or("HEWPME_IDLE_CHAT_MIN", DEFAULT_IDLE_CHAT_MIN) * 60)
+}
+
+/// Extra time without prompts after the streamer used a command
+#[must_use]
+pub fn get_idle_command_grace() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_IDLE_COMMAND_GRACE_SEC",
+        DEFAULT_IDLE_COMMAND_GRACE_SEC,
+    ))
+}
+
+/// Follows within `HEWPME_FOLLOW_WAVE_WINDOW_SEC` that enable the followers-only chat
+#[must_use]
+pub fn get_follow_wave_count() -> usize {
+    get_env_or("HEWPME_FOLLOW_WAVE_COUNT", DEFAULT_FOLLOW_WAVE_COUNT)
+}
+
+#[must_use]
+pub fn get_follow_wave_window() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_FOLLOW_WAVE_WINDOW_SEC",
+        DEFAULT_FOLLOW_WAVE_WINDOW_SEC,
+    ))
+}
+
+/// Messages within `HEWPME_RAID_WINDOW_SEC` after a raid that enable the subscribers-only chat
+#[must_use]
+pub fn get_raid_messages_count() -> usize {
+    get_env_or("HEWPME_RAID_MESSAGES_COUNT", DEFAULT_RAID_MESSAGES_COUNT)
+}
+
+#[must_use]
+pub fn get_raid_window() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_RAID_WINDOW_SEC",
+        DEFAULT_RAID_WINDOW_SEC,
+    ))
+}
+
+/// How long the automatic chat restrictions stay enabled
+#[must_use]
+pub fn get_protection_cooldown() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_PROTECTION_COOLDOWN_SEC",
+        DEFAULT_PROTECTION_COOLDOWN_SEC,
+    ))
+}
+
+/// Whether big gift bombs and raids turn on the emote-only chat for a while
+#[must_use]
+pub fn get_celebration_enabled() -> bool {
+    get_env_or("HEWPME_CELEBRATION", false)
+}
+
+/// Subscriptions gifted at once that start the celebration
+#[must_use]
+pub fn get_celebration_gifts() -> u64 {
+    get_env_or("HEWPME_CELEBRATION_GIFTS", DEFAULT_CELEBRATION_GIFTS)
+}
+
+/// Raiding viewers that start the celebration
+#[must_use]
+pub fn get_celebration_raid_viewers() -> u64 {
+    get_env_or(
+        "HEWPME_CELEBRATION_RAID_VIEWERS",
+        DEFAULT_CELEBRATION_RAID_VIEWERS,
+    )
+}
+
+/// How long the emote-only chat of the celebration lasts
+#[must_use]
+pub fn get_celebration_duration() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_CELEBRATION_SEC",
+        DEFAULT_CELEBRATION_SEC,
+    ))
+}
+
+/// Share of the common trigrams that makes two messages the same copy-pasta, 0 to 1
+#[must_use]
+pub fn get_copypasta_similarity() -> f64 {
+    get_env_or("HEWPME_COPYPASTA_SIMILARITY", DEFAULT_COPYPASTA_SIMILARITY).clamp(0.0, 1.0)
+}
+
+/// Other viewers who must have sent a similar message to start a wave
+#[must_use]
+pub fn get_copypasta_users() -> usize {
+    get_env_or("HEWPME_COPYPASTA_USERS", DEFAULT_COPYPASTA_USERS).max(1)
+}
+
+#[must_use]
+pub fn get_copypasta_window() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_COPYPASTA_WINDOW_SEC",
+        DEFAULT_COPYPASTA_WINDOW_SEC,
+    ))
+}
+
+/// Shorter messages, e.g. emote spam, are not checked
+#[must_use]
+pub fn get_copypasta_min_length() -> usize {
+    get_env_or("HEWPME_COPYPASTA_MIN_LENGTH", DEFAULT_COPYPASTA_MIN_LENGTH)
+}
+
+/// Share of the common trigrams that makes a message of a new account resemble the ones
+/// of a timed out or banned user, 0 to 1, the lower the more sensitive
+#[must_use]
+pub fn get_evasion_similarity() -> f64 {
+    get_env_or("HEWPME_EVASION_SIMILARITY", DEFAULT_EVASION_SIMILARITY).clamp(0.0, 1.0)
+}
+
+/// How long the messages of a timed out or banned user are compared, 0 turns it off
+#[must_use]
+pub fn get_evasion_window() -> Duration {
+    Duration::from_secs(get_env_or("HEWPME_EVASION_WINDOW_MIN", DEFAULT_EVASION_WINDOW_MIN) * 60)
+}
+
+/// `flag`, `delete`, `timeout` or `ban`, see [`CopypastaAction`]
+#[must_use]
+pub fn get_copypasta_action() -> CopypastaAction {
+    get_env_or("HEWPME_COPYPASTA_ACTION", CopypastaAction::default())
+}
+
+#[must_use]
+pub fn get_copypasta_timeout_sec() -> u32 {
+    get_env_or(
+        "HEWPME_COPYPASTA_TIMEOUT_SEC",
+        DEFAULT_COPYPASTA_TIMEOUT_SEC,
+    )
+}
+
+/// Whisper the rules and the appeal link to the users timed out or banned by the bot
+#[must_use]
+pub fn get_appeal_whisper_enabled() -> bool {
+    get_env_or("HEWPME_APPEAL_WHISPER", false)
+}
+
+/// Whether the appeal message is whispered to the permanently banned users as well
+#[must_use]
+pub fn get_appeal_whisper_on_ban() -> bool {
+    get_env_or("HEWPME_APPEAL_WHISPER_ON_BAN", true)
+}
+
+/// Rules or appeal form linked in the appeal message, the channel about page by default
+#[must_use]
+pub fn get_appeal_url() -> Option<String> {
+    get_env("HEWPME_APPEAL_URL")
+}
+
+/// Appeal message of the timeouts with the `{channel}`, `{reason}`, `{seconds}` and
+/// `{url}` placeholders, the translated default one is used if it is not set
+#[must_use]
+pub fn get_appeal_timeout_message() -> Option<String> {
+    get_env("HEWPME_APPEAL_TIMEOUT_MESSAGE")
+}
+
+/// Appeal message of the bans, see [`get_appeal_timeout_message`]
+#[must_use]
+pub fn get_appeal_ban_message() -> Option<String> {
+    get_env("HEWPME_APPEAL_BAN_MESSAGE")
+}
+
+/// Commands a viewer may send within `HEWPME_COMMAND_FLOOD_WINDOW_SEC`
+/// before their commands are ignored
+#[must_use]
+pub fn get_command_flood_max() -> usize {
+    get_env_or("HEWPME_COMMAND_FLOOD_MAX", DEFAULT_COMMAND_FLOOD_MAX).max(1)
+}
+
+#[must_use]
+pub fn get_command_flood_window() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_COMMAND_FLOOD_WINDOW_SEC",
+        DEFAULT_COMMAND_FLOOD_WINDOW_SEC,
+    ))
+}
+
+/// How long the commands of a flooding viewer are ignored
+#[must_use]
+pub fn get_command_flood_ignore() -> Duration {
+    Duration::from_secs(
+        get_env_or(
+            "HEWPME_COMMAND_FLOOD_IGNORE_MIN",
+            DEFAULT_COMMAND_FLOOD_IGNORE_MIN,
+        ) * 60,
+    )
+}
+
+/// Accounts younger than this are flagged in the mod log
+#[must_use]
+pub fn get_new_account_days() -> i64 {
+    get_env_or("HEWPME_NEW_ACCOUNT_DAYS", DEFAULT_NEW_ACCOUNT_DAYS)
+}
+
+/// `session` or `total`, see [`FollowerCountMode`]
+#[must_use]
+pub fn get_follower_count_mode() -> FollowerCountMode {
+    get_env_or("HEWPME_FOLLOWER_COUNT", FollowerCountMode::default())
+}
+
+/// How long the credits are rolled after the stream goes offline, `None` if they are not
+#[must_use]
+pub fn get_credits_roll_duration() -> Option<Duration> {
+    let minutes = get_env_or("HEWPME_CREDITS_ROLL_MIN", DEFAULT_CREDITS_ROLL_MIN);
+
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+/// The stream back online within this time continues the session, e.g. after a dropped
+/// connection of the broadcaster
+#[must_use]
+pub fn get_session_resume_window() -> Duration {
+    Duration::from_secs(get_env_or("HEWPME_SESSION_RESUME_MIN", DEFAULT_SESSION_RESUME_MIN) * 60)
+}
+
+/// How often the channel emotes are checked for newly unlocked ones
+#[must_use]
+pub fn get_emote_poll_interval() -> Duration {
+    Duration::from_secs(get_env_or("HEWPME_EMOTE_POLL_MIN", DEFAULT_EMOTE_POLL_MIN) * 60)
+}
+
+/// How often the connected viewers are polled, `None` if they are not
+#[must_use]
+pub fn get_presence_poll_interval() -> Option<Duration> {
+    match get_env_or("HEWPME_PRESENCE_POLL_MIN", DEFAULT_PRESENCE_POLL_MIN) {
+        0 => None,
+        minutes => Some(Duration::from_secs(minutes * 60)),
+    }
+}
+
+/// Months after which an ended subscription is reported
+#[must_use]
+pub fn get_sub_lapse_min_months() -> u64 {
+    get_env_or("HEWPME_SUB_LAPSE_MIN_MONTHS", DEFAULT_SUB_LAPSE_MIN_MONTHS)
+}
+
+/// Tell the broadcaster about the lapsed long-standing subscriptions
+#[must_use]
+pub fn get_sub_lapse_notify() -> bool {
+    get_env_or("HEWPME_SUB_LAPSE_NOTIFY", false)
+}
+
+/// Discord webhook the broadcaster notifications are posted to
+#[must_use]
+pub fn get_discord_webhook_url() -> Option<String> {
+    get_env("HEWPME_DISCORD_WEBHOOK_URL")
+}
+
+/// Messages kept in the mod log, older ones are spilled to disk
+#[must_use]
+pub fn get_modlog_capacity() -> usize {
+    get_env_or("HEWPME_MODLOG_CAPACITY", DEFAULT_MODLOG_CAPACITY).max(1)
+}
+
+/// Minutes of the chat activity kept in memory, older ones are spilled to disk
+#[must_use]
+pub fn get_activity_max_minutes() -> usize {
+    get_env_or("HEWPME_ACTIVITY_MAX_MINUTES", DEFAULT_ACTIVITY_MAX_MINUTES).max(1)
+}
+
+/// Fill level of a bounded store, in percent of its capacity, that is reported with a warning
+#[must_use]
+pub fn get_store_warn_percent() -> usize {
+    get_env_or("HEWPME_STORE_WARN_PERCENT", DEFAULT_STORE_WARN_PERCENT)
+}
+
+/// Time zone of the timer windows, e.g. `Europe/Moscow`, the system one if not set
+#[must_use]
+pub fn get_timezone() -> Option<Tz> {
+    let name = get_env("HEWPME_TIMEZONE")?;
+
+    name.parse()
+        .map_err(|e| tracing::warn!("Invalid HEWPME_TIMEZONE {name}: {e}"))
+        .ok()
+}
+
+/// Value of the variable, the one set at runtime takes precedence over the
+/// profile specific one
+fn get_env(name: &str) -> Option<String> {
+    get_env_without_file(name).or_else(|| {
+        FILE_CONFIG
+            .get_or_init(|| load_file_config().unwrap_or_default())
+            .value_of(name)
+    })
+}
+
+fn get_env_without_file(name: &str) -> Option<String> {
+    crate::settings::override_of(name)
+        .or_else(|| {
+            get_profile()
+                .and_then(|profile| env::var(format!("{name}_{}", profile.to_uppercase())).ok())
+        })
+        .or_else(|| env::var(name).ok())
+}
+
+/// Scenes of the broadcasting software that start a break
+#[must_use]
+pub fn get_break_scenes() -> Vec<String> {
+    let scenes = get_env_list("HEWPME_BREAK_SCENES");
+
+    if scenes.is_empty() {
+        vec![String::from("BRB"), String::from("Break")]
+    } else {
+        scenes
+    }
+}
+
+/// Message posted when a break starts instead of the default one
+#[must_use]
+pub fn get_break_message() -> Option<String> {
+    get_env("HEWPME_BREAK_MESSAGE")
+}
+
+/// Message posted when a break ends instead of the default one
+#[must_use]
+pub fn get_break_end_message() -> Option<String> {
+    get_env("HEWPME_BREAK_END_MESSAGE")
+}
+
+/// Slow mode wait during a break, `0` to leave the chat as it is
+#[must_use]
+pub fn get_break_slow_mode_sec() -> u64 {
+    get_env_or("HEWPME_BREAK_SLOW_MODE_SEC", DEFAULT_BREAK_SLOW_MODE_SEC)
+}
+
+/// Directory of the text files for OBS, they are not written if not set
+#[must_use]
+pub fn get_text_files_dir() -> Option<PathBuf> {
+    get_env("HEWPME_TEXT_FILES_DIR").map(PathBuf::from)
+}
+
+/// Address of the bot web server for the viewers, e.g. to link the `/commands` page
+#[must_use]
+pub fn get_public_url() -> Option<String> {
+    get_env("HEWPME_PUBLIC_URL")
+}
+
+/// Title of the channel point reward spinning the prize wheel
+#[must_use]
+pub fn get_wheel_reward() -> Option<String> {
+    get_env("HEWPME_WHEEL_REWARD")
+}
+
+/// Loyalty points a `!spin` costs
+#[must_use]
+pub fn get_wheel_cost() -> u64 {
+    get_env_or("HEWPME_WHEEL_COST", DEFAULT_WHEEL_COST)
+}
+
+/// Directory of a credits theme, its templates and partials replace the default ones
+#[must_use]
+pub fn get_credits_theme_dir() -> Option<PathBuf> {
+    get_env("HEWPME_CREDITS_THEME_DIR").map(PathBuf::from)
+}
+
+/// Directory the settings are copied to on every change, e.g. a git repository
+#[must_use]
+pub fn get_export_dir() -> Option<PathBuf> {
+    get_env("HEWPME_EXPORT_DIR").map(PathBuf::from)
+}
+
+/// Commands run only after `!confirm <code>`, e.g. the mass moderation ones
+#[must_use]
+pub fn get_confirm_commands() -> Vec<String> {
+    let commands = get_env_list("HEWPME_CONFIRM_COMMANDS");
+
+    if commands.is_empty() {
+        vec![String::from("!ban")]
+    } else {
+        commands
+    }
+}
+
+/// How long a command waits for `!confirm <code>`
+#[must_use]
+pub fn get_confirm_timeout() -> Duration {
+    Duration::from_secs(get_env_or(
+        "HEWPME_CONFIRM_TIMEOUT_SEC",
+        DEFAULT_CONFIRM_TIMEOUT_SEC,
+    ))
+}
+
+/// Hosts of the links previewed in the chat overlay, no link is fetched if empty
+#[must_use]
+pub fn get_unfurl_hosts() -> Vec<String> {
+    get_env_list("HEWPME_UNFURL_HOSTS")
+}
+
+#[must_use]
+pub fn get_unfurl_timeout() -> Duration {
+    Duration::from_millis(get_env_or(
+        "HEWPME_UNFURL_TIMEOUT_MS",
+        DEFAULT_UNFURL_TIMEOUT_MS,
+    ))
+}
+
+/// Numbers of unique chatters of the session that are celebrated
+#[must_use]
+pub fn get_chatter_milestones() -> Vec<usize> {
+    get_env_numbers("HEWPME_CHATTER_MILESTONES", &[100])
+}
+
+/// Numbers of new followers of the session that are celebrated
+#[must_use]
+pub fn get_follower_milestones() -> Vec<usize> {
+    get_env_numbers("HEWPME_FOLLOWER_MILESTONES", &[10, 50, 100])
+}
+
+fn get_env_numbers(name: &str, default: &[usize]) -> Vec<usize> {
+    let numbers: Vec<usize> = get_env_list(name)
+        .iter()
+        .filter_map(|item| item.parse().ok())
+        .collect();
+
+    if numbers.is_empty() {
+        default.to_vec()
+    } else {
+        numbers
+    }
+}
+
+fn get_env_list(name: &str) -> Vec<String> {
+    get_env(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn get_env_or<T: FromStr>(name: &str, default: T) -> T {
+    get_env(name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_settings_are_named_after_the_variables() {
+        let config: Config = toml::from_str(
+            r#"
+            channel = "streamer"
+            port = 8080
+
+            [features]
+            moderation = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.value_of("TWITCH_CHANNEL").as_deref(),
+            Some("streamer")
+        );
+        assert_eq!(config.value_of("HEWPME_PORT").as_deref(), Some("8080"));
+        assert_eq!(
+            config.value_of(Feature::Moderation.env_name()).as_deref(),
+            Some("false")
+        );
+        assert_eq!(config.value_of(Feature::ChatBot.env_name()), None);
+        assert!(toml::from_str::<Config>("chanel = \"typo\"").is_err());
+    }
 }
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Per-key cooldown tracker, e.g. per user for a chat command
+pub struct Cooldowns {
+    period: Duration,
+    last_used: HashMap<String, Instant>,
+}
+
+impl Cooldowns {
+    pub fn new(period: Duration) -> Self {
+        Cooldowns {
+            period,
+            last_used: HashMap::new(),
+        }
+    }
+
+    /// Change the period, e.g. after the setting was changed at runtime
+    pub fn set_period(&mut self, period: Duration) {
+        self.period = period;
+    }
+
+    /// Mark `key` as used if its cooldown has passed, otherwise return the time left
+    pub fn try_use(&mut self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        if let Some(last) = self.last_used.get(key) {
+            let elapsed = now.duration_since(*last);
+
+            if elapsed < self.period {
+                return Err(self.period - elapsed);
+            }
+        }
+
+        self.last_used.insert(key.to_string(), now);
+        self.last_used
+            .retain(|_, last| now.duration_since(*last) < self.period);
+
+        Ok(())
+    }
+}
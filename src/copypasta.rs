@@ -0,0 +1,116 @@
+//! Detection of copy-pasta spam waves.
+//!
+//! Messages are compared with [`crate::similarity`]. A message as similar as
+//! `HEWPME_COPYPASTA_SIMILARITY` to the ones of `HEWPME_COPYPASTA_USERS` other viewers
+//! within `HEWPME_COPYPASTA_WINDOW_SEC` is part of a wave: it is flagged in the mod log
+//! and `HEWPME_COPYPASTA_ACTION` is applied to it.
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::config::{self, CopypastaAction};
+use crate::helper::BotState;
+use crate::moderation::Sanction;
+use crate::similarity::{jaccard, normalize, trigrams, Trigram};
+use crate::{metrics, moderation, redact};
+
+/// Messages compared with the new one, older ones are dropped regardless of the window
+const MAX_RECENT_MESSAGES: usize = 200;
+
+struct Fingerprint {
+    user_id: Arc<str>,
+    trigrams: HashSet<Trigram>,
+    received: Instant,
+}
+
+pub struct CopypastaDetector {
+    similarity: f64,
+    users: usize,
+    window: Duration,
+    min_length: usize,
+    recent: Mutex<VecDeque<Fingerprint>>,
+}
+
+pub type SafeCopypastaDetector = Arc<CopypastaDetector>;
+
+impl CopypastaDetector {
+    #[must_use]
+    pub fn from_config() -> Self {
+        CopypastaDetector {
+            similarity: config::get_copypasta_similarity(),
+            users: config::get_copypasta_users(),
+            window: config::get_copypasta_window(),
+            min_length: config::get_copypasta_min_length(),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Remember the message, returns whether it belongs to a copy-pasta wave
+    pub async fn check(&self, user_id: &Arc<str>, text: &str) -> bool {
+        let text = normalize(text);
+
+        if text.chars().count() < self.min_length {
+            return false;
+        }
+
+        let now = Instant::now();
+        let trigrams = trigrams(&text);
+        let mut recent = self.recent.lock().await;
+
+        while recent.front().is_some_and(|fingerprint| {
+            recent.len() >= MAX_RECENT_MESSAGES
+                || now.duration_since(fingerprint.received) > self.window
+        }) {
+            recent.pop_front();
+        }
+
+        let similar_users = recent
+            .iter()
+            .filter(|fingerprint| fingerprint.user_id != *user_id)
+            .filter(|fingerprint| jaccard(&fingerprint.trigrams, &trigrams) >= self.similarity)
+            .map(|fingerprint| &fingerprint.user_id)
+            .collect::<HashSet<_>>()
+            .len();
+
+        recent.push_back(Fingerprint {
+            user_id: Arc::clone(user_id),
+            trigrams,
+            received: now,
+        });
+
+        similar_users >= self.users
+    }
+}
+
+pub fn create_copypasta_detector() -> SafeCopypastaDetector {
+    Arc::new(CopypastaDetector::from_config())
+}
+
+/// Apply the configured action to a message of the wave
+pub async fn act(state: &BotState, user_id: &str, user_name: &str, message_id: &str) {
+    let action = config::get_copypasta_action();
+
+    tracing::warn!("copy-pasta from {}, {action:?}", redact::Name(user_name));
+    metrics::increment("copypasta");
+
+    match action {
+        CopypastaAction::Flag => (),
+        CopypastaAction::Delete => moderation::delete_message(message_id).await,
+        CopypastaAction::Timeout => {
+            let duration_sec = config::get_copypasta_timeout_sec();
+
+            moderation::sanction(
+                state,
+                user_id,
+                "Copy-pasta",
+                Sanction::Timeout { duration_sec },
+            )
+            .await;
+        }
+        CopypastaAction::Ban => {
+            moderation::sanction(state, user_id, "Copy-pasta", Sanction::Ban).await;
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Short messages of the viewers shown next to their names in the credits.
+//!
+//! A viewer submits one with `!credit <message>`, once per `HEWPME_CREDIT_COOLDOWN_SEC`,
+//! or with a channel point reward of the `credit_message` action, a new message replaces
+//! the previous one. Messages longer
+//! than `HEWPME_CREDIT_MESSAGE_MAX_LEN` characters or with a word of
+//! `HEWPME_CREDIT_MESSAGE_BLOCKED_WORDS` are refused, the rest wait on the admin page
+//! until a moderator approves them. The messages are kept in `credit_messages.json`
+//! until the session lists are cleared.
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::JsonStore;
+use crate::{config, metrics, redact};
+
+const CREDIT_MESSAGES_FILE_NAME: &str = "credit_messages.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Review {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl std::str::FromStr for Review {
+    type Err = ();
+
+    /// `approve` or `reject` of `/api/credits/messages/<id>/<review>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "approve" => Ok(Review::Approved),
+            "reject" => Ok(Review::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CreditMessage {
+    pub id: u64,
+    pub user_id: String,
+    pub user_name: String,
+    pub text: String,
+    pub review: Review,
+    pub submitted_at: DateTime<Utc>,
+}
+
+/// Approved message in the credits
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CreditLine {
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubmitError {
+    Empty,
+    TooLong { max: usize },
+    Blocked,
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::Empty => write!(f, "the message is empty"),
+            SubmitError::TooLong { max } => write!(f, "the message is longer than {max}"),
+            SubmitError::Blocked => write!(f, "the message has a blocked word"),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// The message without the surrounding spaces if it passes the filter
+fn check(text: &str, max_len: usize, blocked_words: &[String]) -> Result<String, SubmitError> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Err(SubmitError::Empty);
+    }
+
+    if text.chars().count() > max_len {
+        return Err(SubmitError::TooLong { max: max_len });
+    }
+
+    let blocked = text
+        .split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .any(|word| {
+            blocked_words
+                .iter()
+                .any(|blocked| blocked.to_lowercase() == word)
+        });
+
+    if blocked {
+        return Err(SubmitError::Blocked);
+    }
+
+    Ok(text.to_string())
+}
+
+/// Queue the message of the viewer for the review, it replaces their previous one
+///
+/// # Errors
+///
+/// Will return `Err` if the message is empty, too long or has a blocked word
+pub fn submit(user_id: &str, user_name: &str, text: &str) -> Result<(), SubmitError> {
+    let text = check(
+        text,
+        config::get_credit_message_max_len(),
+        &config::get_credit_message_blocked_words(),
+    )
+    .inspect_err(|e| {
+        tracing::info!(
+            "credit message of {} is refused, {e}",
+            redact::Name(user_name)
+        );
+        metrics::increment("credit_message_refused");
+    })?;
+
+    JsonStore::<Vec<CreditMessage>>::open(CREDIT_MESSAGES_FILE_NAME).update(|messages| {
+        let id = messages.iter().map(|message| message.id).max().unwrap_or(0) + 1;
+
+        messages.retain(|message| message.user_id != user_id);
+        messages.push(CreditMessage {
+            id,
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+            text,
+            review: Review::Pending,
+            submitted_at: Utc::now(),
+        });
+    });
+    tracing::info!(
+        "credit message of {} waits for review",
+        redact::Name(user_name)
+    );
+
+    Ok(())
+}
+
+/// Messages of the session, the latest first
+#[must_use]
+pub fn all() -> Vec<CreditMessage> {
+    let mut messages = JsonStore::<Vec<CreditMessage>>::open(CREDIT_MESSAGES_FILE_NAME).to_vec();
+
+    messages.reverse();
+    messages
+}
+
+/// Approve or reject the message, `None` if there is none with the ID
+pub fn review(id: u64, review: Review) -> Option<CreditMessage> {
+    JsonStore::<Vec<CreditMessage>>::open(CREDIT_MESSAGES_FILE_NAME).update(|messages| {
+        let message = messages.iter_mut().find(|message| message.id == id)?;
+
+        message.review = review;
+        Some(message.clone())
+    })
+}
+
+/// Approved messages for the credits, sorted by the name
+#[must_use]
+pub fn approved() -> Vec<CreditLine> {
+    approved_lines(&JsonStore::<Vec<CreditMessage>>::open(
+        CREDIT_MESSAGES_FILE_NAME,
+    ))
+}
+
+fn approved_lines(messages: &[CreditMessage]) -> Vec<CreditLine> {
+    let mut lines = messages
+        .iter()
+        .filter(|message| message.review == Review::Approved)
+        .map(|message| CreditLine {
+            name: message.user_name.clone(),
+            text: message.text.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    lines.sort_by_cached_key(|line| line.name.to_lowercase());
+    lines
+}
+
+/// Drop the messages, e.g. when the session lists are cleared
+pub fn clear() {
+    JsonStore::<Vec<CreditMessage>>::open(CREDIT_MESSAGES_FILE_NAME).update(Vec::clear);
+}
+
+/// Drop the message of the user, see [`crate::privacy`]
+pub fn forget(user_id: &str) {
+    JsonStore::<Vec<CreditMessage>>::open(CREDIT_MESSAGES_FILE_NAME)
+        .update(|messages| messages.retain(|message| message.user_id != user_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_and_blocked_messages_are_refused() {
+        let blocked = [String::from("Spam")];
+
+        assert_eq!(
+            check("  hi chat  ", 10, &blocked),
+            Ok(String::from("hi chat"))
+        );
+        assert_eq!(check("   ", 10, &blocked), Err(SubmitError::Empty));
+        assert_eq!(
+            check("привет всем!", 10, &blocked),
+            Err(SubmitError::TooLong { max: 10 })
+        );
+        assert_eq!(
+            check("buy SPAM, now", 20, &blocked),
+            Err(SubmitError::Blocked)
+        );
+        // only whole words are blocked
+        assert_eq!(check("spammer", 20, &blocked), Ok(String::from("spammer")));
+    }
+
+    #[test]
+    fn only_approved_messages_are_credited() {
+        let message = |id: u64, user_name: &str, review: Review| CreditMessage {
+            id,
+            user_id: id.to_string(),
+            user_name: user_name.to_string(),
+            text: format!("gg from {user_name}"),
+            review,
+            submitted_at: Utc::now(),
+        };
+
+        assert_eq!(
+            approved_lines(&[
+                message(1, "zed", Review::Approved),
+                message(2, "Pending", Review::Pending),
+                message(3, "amy", Review::Approved),
+                message(4, "Troll", Review::Rejected),
+            ]),
+            [
+                CreditLine {
+                    name: String::from("amy"),
+                    text: String::from("gg from amy"),
+                },
+                CreditLine {
+                    name: String::from("zed"),
+                    text: String::from("gg from zed"),
+                },
+            ]
+        );
+    }
+}
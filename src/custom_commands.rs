@@ -0,0 +1,326 @@
+//! Commands answering with a saved response, kept in `custom_commands.json`.
+//!
+//! The moderators manage them in chat, the options go before the response:
+//!
+//! ```text
+//! !addcom !discord -cd=30 Join us at https://discord.gg/example
+//! !editcom !discord -ul=mod {user}, the invite is in the panels
+//! !delcom !discord
+//! ```
+//!
+//! `-cd` is the per-viewer cooldown in seconds, `-ul=mod` limits the command to the
+//! moderators. The commands imported from another bot are stored in the same file.
+//! `{user}` in the response is the name of the viewer, `{args}` the rest of the
+//! message and `{kv:<key>}` a value of [`crate::kv`].
+//!
+//! Only a first word starting with `!` is looked up, the commands are read from the
+//! file once and again after a change in chat. The built-in commands are not moved
+//! here, they keep their arms in [`crate::chat`] and take precedence.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use std::{fmt, io};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::commands::{CommandInfo, Permission};
+use crate::cooldown::Cooldowns;
+use crate::storage::JsonStore;
+use crate::{i18n, kv};
+
+pub(crate) const FILE_NAME: &str = "custom_commands.json";
+const MAX_RESPONSE_LEN: usize = 400;
+
+/// Commands of the file, `None` until read or after a change
+static CACHE: RwLock<Option<Arc<BTreeMap<String, CustomCommand>>>> = RwLock::new(None);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomCommand {
+    pub name: String,
+    pub response: String,
+    pub permission: Permission,
+    /// Time in seconds before the same viewer can use the command again
+    pub cooldown_sec: Option<u64>,
+}
+
+impl CustomCommand {
+    /// Response to the viewer, `args` is the rest of the message
+    #[must_use]
+    pub fn render(&self, user_name: &str, args: &str) -> String {
+        kv::expand(&i18n::fill(
+            &self.response,
+            &[("user", &user_name), ("args", &args)],
+        ))
+    }
+
+    pub(crate) fn info(&self) -> CommandInfo {
+        CommandInfo {
+            name: self.name.clone(),
+            description: self.response.clone(),
+            permission: self.permission,
+            cooldown_sec: self.cooldown_sec,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CustomCommandError {
+    /// The name is taken by another custom or built-in command
+    Exists,
+    Missing,
+    InvalidOption(String),
+    EmptyResponse,
+    ResponseTooLong,
+    Save(io::Error),
+}
+
+impl fmt::Display for CustomCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomCommandError::Exists => write!(f, "command already exists"),
+            CustomCommandError::Missing => write!(f, "command does not exist"),
+            CustomCommandError::InvalidOption(option) => write!(f, "invalid option {option}"),
+            CustomCommandError::EmptyResponse => write!(f, "response is empty"),
+            CustomCommandError::ResponseTooLong => {
+                write!(f, "response is longer than {MAX_RESPONSE_LEN}")
+            }
+            CustomCommandError::Save(e) => write!(f, "unable to save the commands: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CustomCommandError {}
+
+/// Options of `!addcom`/`!editcom` given before the response
+#[derive(Debug, Default, PartialEq)]
+struct CommandOptions {
+    permission: Option<Permission>,
+    cooldown_sec: Option<u64>,
+}
+
+/// Split the leading `-cd=` and `-ul=` options from the response
+fn parse_options(text: &str) -> Result<(CommandOptions, &str), CustomCommandError> {
+    let mut options = CommandOptions::default();
+    let mut rest = text.trim();
+
+    while rest.starts_with('-') {
+        let (option, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let invalid = || CustomCommandError::InvalidOption(option.to_string());
+
+        match option.split_once('=') {
+            Some(("-cd", seconds)) => {
+                options.cooldown_sec = Some(seconds.parse().map_err(|_| invalid())?);
+            }
+            Some(("-ul", "mod" | "moderator")) => options.permission = Some(Permission::Moderator),
+            Some(("-ul", "everyone")) => options.permission = Some(Permission::Everyone),
+            _ => return Err(invalid()),
+        }
+
+        rest = tail.trim_start();
+    }
+
+    Ok((options, rest))
+}
+
+fn check_response(response: &str) -> Result<(), CustomCommandError> {
+    if response.is_empty() {
+        Err(CustomCommandError::EmptyResponse)
+    } else if response.chars().count() > MAX_RESPONSE_LEN {
+        Err(CustomCommandError::ResponseTooLong)
+    } else {
+        Ok(())
+    }
+}
+
+/// Chat command name as typed in chat, `discord` and `!Discord` are both `!discord`
+#[must_use]
+pub fn command_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+
+    if name.starts_with('!') {
+        name
+    } else {
+        format!("!{name}")
+    }
+}
+
+/// Command of a chat word, `None` for the words not starting with `!`
+#[must_use]
+pub fn get(name: &str) -> Option<CustomCommand> {
+    if !name.starts_with('!') {
+        return None;
+    }
+
+    cached().get(&command_name(name)).cloned()
+}
+
+/// Description of every custom command sorted by name
+#[must_use]
+pub fn infos() -> Vec<CommandInfo> {
+    cached().values().map(CustomCommand::info).collect()
+}
+
+/// # Panics
+///
+/// Will panic if the cache lock is poisoned
+fn cached() -> Arc<BTreeMap<String, CustomCommand>> {
+    if let Some(commands) = CACHE.read().unwrap().as_ref() {
+        return Arc::clone(commands);
+    }
+
+    let commands = Arc::new(open().clone());
+
+    *CACHE.write().unwrap() = Some(Arc::clone(&commands));
+
+    commands
+}
+
+/// Read the commands from the file again on the next use
+///
+/// # Panics
+///
+/// Will panic if the cache lock is poisoned
+pub(crate) fn invalidate() {
+    *CACHE.write().unwrap() = None;
+}
+
+/// Add the command from the text of `!addcom`, options first
+///
+/// # Errors
+///
+/// Will return `Err` if the command exists, the text is not valid or the commands
+/// cannot be saved
+pub fn add(name: &str, text: &str) -> Result<CustomCommand, CustomCommandError> {
+    let (options, response) = parse_options(text)?;
+
+    check_response(response)?;
+
+    let command = CustomCommand {
+        name: command_name(name),
+        response: response.to_string(),
+        permission: options.permission.unwrap_or_default(),
+        cooldown_sec: options.cooldown_sec,
+    };
+
+    open()
+        .try_update(|commands| {
+            if commands.contains_key(&command.name) {
+                return Err(CustomCommandError::Exists);
+            }
+
+            commands.insert(command.name.clone(), command.clone());
+
+            Ok(command)
+        })
+        .map_err(CustomCommandError::Save)
+        .inspect(|_| invalidate())?
+}
+
+/// Change the command from the text of `!editcom`, the options not given are kept and
+/// so is the response if only options are given
+///
+/// # Errors
+///
+/// Will return `Err` if the command does not exist, the text is not valid or the
+/// commands cannot be saved
+pub fn edit(name: &str, text: &str) -> Result<CustomCommand, CustomCommandError> {
+    let (options, response) = parse_options(text)?;
+
+    if !response.is_empty() {
+        check_response(response)?;
+    }
+
+    open()
+        .try_update(|commands| {
+            let command = commands
+                .get_mut(&command_name(name))
+                .ok_or(CustomCommandError::Missing)?;
+
+            if !response.is_empty() {
+                command.response = response.to_string();
+            }
+
+            command.permission = options.permission.unwrap_or(command.permission);
+            command.cooldown_sec = options.cooldown_sec.or(command.cooldown_sec);
+
+            Ok(command.clone())
+        })
+        .map_err(CustomCommandError::Save)
+        .inspect(|_| invalidate())?
+}
+
+/// # Errors
+///
+/// Will return `Err` if the command does not exist or the commands cannot be saved
+pub fn remove(name: &str) -> Result<(), CustomCommandError> {
+    open()
+        .try_update(|commands| commands.remove(&command_name(name)).is_some())
+        .map_err(CustomCommandError::Save)
+        .inspect(|_| invalidate())?
+        .then_some(())
+        .ok_or(CustomCommandError::Missing)
+}
+
+/// Per-viewer cooldowns of the custom commands
+pub struct CustomCommandCooldowns {
+    cooldowns: Mutex<HashMap<String, Cooldowns>>,
+}
+
+pub type SafeCustomCommandCooldowns = Arc<CustomCommandCooldowns>;
+
+impl CustomCommandCooldowns {
+    /// Mark the command as used by the viewer, returns the time left if it is cooling down
+    pub async fn try_use(&self, command: &CustomCommand, user_id: &str) -> Result<(), Duration> {
+        let Some(seconds) = command.cooldown_sec.filter(|&seconds| seconds > 0) else {
+            return Ok(());
+        };
+        let period = Duration::from_secs(seconds);
+        let mut cooldowns = self.cooldowns.lock().await;
+        let cooldowns = cooldowns
+            .entry(command.name.clone())
+            .or_insert_with(|| Cooldowns::new(period));
+
+        // the cooldown could have been changed with `!editcom`
+        cooldowns.set_period(period);
+        cooldowns.try_use(user_id)
+    }
+}
+
+pub fn create_custom_command_cooldowns() -> SafeCustomCommandCooldowns {
+    Arc::new(CustomCommandCooldowns {
+        cooldowns: Mutex::new(HashMap::new()),
+    })
+}
+
+pub(crate) fn open() -> JsonStore<BTreeMap<String, CustomCommand>> {
+    JsonStore::open(FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_go_before_the_response() {
+        let (options, response) = parse_options("-cd=30 -ul=mod Join {args}").unwrap();
+
+        assert_eq!(
+            options,
+            CommandOptions {
+                permission: Some(Permission::Moderator),
+                cooldown_sec: Some(30),
+            }
+        );
+        assert_eq!(response, "Join {args}");
+        assert_eq!(parse_options("-1 is a number").ok(), None);
+        assert_eq!(parse_options("Hi -cd=5").unwrap().1, "Hi -cd=5");
+        assert_eq!(command_name("Discord"), "!discord");
+    }
+
+    #[test]
+    fn plain_words_are_not_commands() {
+        assert_eq!(get("discord"), None);
+        assert_eq!(get(""), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! Channel emotes in the outgoing messages.
+//!
+//! Templates reference emotes as `{emote:Name}` or `{emote:Name|fallback}`,
+//! an emote the channel does not have is replaced with the fallback text.
+//!
+//! The emotes are polled every `HEWPME_EMOTE_POLL_MIN` and on `channel.update`,
+//! newly unlocked ones are announced in chat and on the overlay.
+//! While the emotes are unknown the fallbacks are used, a failed fetch is retried
+//! after `FETCH_RETRY_DELAY`.
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::{Mutex, Notify};
+use twitch_api::helix::chat::ChannelEmote;
+use twitch_api::helix::HelixClient;
+use twitch_api::types::SubscriptionTier;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::i18n;
+use crate::overlay::{self, OverlayEvent};
+use crate::transport::create_api_client;
+use crate::{config, metrics};
+
+const EMOTE_PREFIX: &str = "{emote:";
+const FETCH_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Emote added to the channel during the session
+#[derive(Serialize, Debug, Clone)]
+pub struct UnlockedEmote {
+    pub name: String,
+    pub image: String,
+    /// Subscription tier the emote is available from, `None` for follower and bits emotes
+    pub tier: Option<u8>,
+}
+
+impl From<ChannelEmote> for UnlockedEmote {
+    fn from(emote: ChannelEmote) -> Self {
+        let tier = match emote.tier {
+            Some(SubscriptionTier::Tier1) => Some(1),
+            Some(SubscriptionTier::Tier2) => Some(2),
+            Some(SubscriptionTier::Tier3) => Some(3),
+            _ => None,
+        };
+
+        UnlockedEmote {
+            name: emote.name,
+            image: emote.images.url_2x,
+            tier,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EmoteSet {
+    /// Emote names, fetched on first use and refreshed by the emote watch
+    names: Mutex<Option<Arc<HashSet<String>>>>,
+    /// Time of the last failed fetch of `expand`
+    failed_at: std::sync::Mutex<Option<Instant>>,
+    fetching: AtomicBool,
+    refresh: Notify,
+}
+
+pub type SafeEmoteSet = Arc<EmoteSet>;
+
+impl EmoteSet {
+    /// Replace the emote references in `text`
+    pub async fn expand(&self, text: &str) -> String {
+        if !text.contains(EMOTE_PREFIX) {
+            return text.to_string();
+        }
+
+        let names = match self.names.lock().await.clone() {
+            Some(names) => Some(names),
+            None => self.fetch_names().await,
+        };
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find(EMOTE_PREFIX) {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let reference = &rest[start + EMOTE_PREFIX.len()..start + end];
+            let (name, fallback) = reference.split_once('|').unwrap_or((reference, ""));
+
+            result.push_str(&rest[..start]);
+
+            if names.as_ref().is_some_and(|names| names.contains(name)) {
+                result.push_str(name);
+            } else {
+                result.push_str(fallback);
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        result.push_str(rest);
+
+        result
+    }
+
+    /// Fetch the emote names without holding the lock,
+    /// `None` while another fetch runs or a failed one is not retried yet
+    async fn fetch_names(&self) -> Option<Arc<HashSet<String>>> {
+        let failed_at = *self.failed_at.lock().unwrap();
+
+        if failed_at.is_some_and(|failed_at| failed_at.elapsed() < FETCH_RETRY_DELAY)
+            || self.fetching.swap(true, Ordering::AcqRel)
+        {
+            return None;
+        }
+
+        let fetched = fetch_channel_emotes().await;
+
+        self.fetching.store(false, Ordering::Release);
+
+        let Some(emotes) = fetched else {
+            *self.failed_at.lock().unwrap() = Some(Instant::now());
+            return None;
+        };
+        let mut guard = self.names.lock().await;
+
+        // the emote watch may have been faster
+        Some(Arc::clone(guard.get_or_insert_with(|| {
+            Arc::new(emotes.into_iter().map(|emote| emote.name).collect())
+        })))
+    }
+
+    /// Poll the emotes before the next interval, e.g. when the channel is updated
+    pub fn request_refresh(&self) {
+        self.refresh.notify_one();
+    }
+
+    /// Fetch the emotes and return the ones unknown so far,
+    /// nothing is returned on the first successful fetch
+    pub async fn poll(&self) -> Vec<UnlockedEmote> {
+        let Some(emotes) = fetch_channel_emotes().await else {
+            return Vec::new();
+        };
+        let mut guard = self.names.lock().await;
+        let unlocked = match guard.as_ref() {
+            Some(names) => emotes
+                .iter()
+                .filter(|emote| !names.contains(&emote.name))
+                .cloned()
+                .map(UnlockedEmote::from)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        *guard = Some(Arc::new(
+            emotes.into_iter().map(|emote| emote.name).collect(),
+        ));
+
+        unlocked
+    }
+}
+
+/// Announce the emotes unlocked during the session
+pub async fn run_emote_watch(state: BotState) {
+    let interval = config::get_emote_poll_interval();
+
+    loop {
+        let unlocked = state.emotes.poll().await;
+
+        if !unlocked.is_empty() {
+            let names = unlocked
+                .iter()
+                .map(|emote| emote.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let text = i18n::render(
+                state.languages.channel(),
+                "emotes.unlocked",
+                &[("emotes", &names)],
+            );
+
+            tracing::info!("new channel emotes: {names}");
+            state.say(text.as_str());
+            overlay::push(
+                &state.overlay,
+                OverlayEvent::EmotesUnlocked {
+                    emotes: unlocked,
+                    text,
+                },
+            );
+        }
+
+        tokio::select! {
+            () = tokio::time::sleep(interval) => (),
+            () = state.emotes.refresh.notified() => (),
+        }
+    }
+}
+
+pub fn create_emote_set() -> SafeEmoteSet {
+    Arc::new(EmoteSet::default())
+}
+
+/// `None` if the emotes are not available, they are requested again next time
+async fn fetch_channel_emotes() -> Option<Vec<ChannelEmote>> {
+    let token = get_eventsub_token().await?;
+    let client = HelixClient::with_client(create_api_client());
+    let channel = config::get_channel_name();
+
+    match metrics::timed(
+        "helix_get_channel_emotes",
+        client.get_channel_emotes_from_login(channel.as_str(), &token),
+    )
+    .await
+    {
+        Ok(emotes) => Some(emotes.unwrap_or_default()),
+        Err(e) => {
+            tracing::warn!("Unable to get channel emotes: {e}");
+            None
+        }
+    }
+}
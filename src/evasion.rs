@@ -0,0 +1,149 @@
+//! Suspected timeout evasion with new accounts.
+//!
+//! The latest messages of the users timed out or banned in the chat are kept for
+//! `HEWPME_EVASION_WINDOW_MIN`. A message of an account younger than
+//! `HEWPME_NEW_ACCOUNT_DAYS` as similar as `HEWPME_EVASION_SIMILARITY` to one of them is
+//! flagged in the mod log with the name of the sanctioned user, the moderators decide
+//! what to do with it.
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::Mutex;
+
+use crate::config;
+use crate::similarity::{jaccard, normalize, trigrams, Trigram};
+
+/// Latest messages of a sanctioned user compared with the ones of the new accounts
+const MESSAGES_PER_USER: usize = 10;
+/// Shorter messages, e.g. greetings, resemble too many others
+const MIN_MESSAGE_LENGTH: usize = 10;
+
+struct SanctionedUser {
+    user_id: String,
+    user_name: Arc<str>,
+    messages: Vec<HashSet<Trigram>>,
+    sanctioned_at: Instant,
+}
+
+#[derive(Default)]
+pub struct EvasionDetector {
+    /// Oldest first
+    sanctioned: Mutex<VecDeque<SanctionedUser>>,
+}
+
+pub type SafeEvasionDetector = Arc<EvasionDetector>;
+
+impl EvasionDetector {
+    /// Remember the messages of the user timed out or banned just now
+    pub async fn sanctioned<'a>(
+        &self,
+        user_id: &str,
+        user_name: Arc<str>,
+        texts: impl DoubleEndedIterator<Item = &'a str>,
+    ) {
+        if config::get_evasion_window().is_zero() {
+            return;
+        }
+
+        let messages = texts
+            .rev()
+            .map(normalize)
+            .filter(|text| text.chars().count() >= MIN_MESSAGE_LENGTH)
+            .take(MESSAGES_PER_USER)
+            .map(|text| trigrams(&text))
+            .collect::<Vec<_>>();
+
+        if messages.is_empty() {
+            return;
+        }
+
+        let mut sanctioned = self.sanctioned.lock().await;
+
+        sanctioned.retain(|known| known.user_id != user_id);
+        sanctioned.push_back(SanctionedUser {
+            user_id: user_id.to_string(),
+            user_name,
+            messages,
+            sanctioned_at: Instant::now(),
+        });
+    }
+
+    /// Name of the recently sanctioned user whose messages the one of `user_id` resembles
+    pub async fn check(&self, user_id: &str, text: &str) -> Option<Arc<str>> {
+        let text = normalize(text);
+
+        if text.chars().count() < MIN_MESSAGE_LENGTH {
+            return None;
+        }
+
+        let window = config::get_evasion_window();
+        let similarity = config::get_evasion_similarity();
+        let trigrams = trigrams(&text);
+        let mut sanctioned = self.sanctioned.lock().await;
+
+        while sanctioned
+            .front()
+            .is_some_and(|known| known.sanctioned_at.elapsed() > window)
+        {
+            sanctioned.pop_front();
+        }
+
+        sanctioned
+            .iter()
+            .rev()
+            .filter(|known| known.user_id != user_id)
+            .find(|known| {
+                known
+                    .messages
+                    .iter()
+                    .any(|message| jaccard(message, &trigrams) >= similarity)
+            })
+            .map(|known| Arc::clone(&known.user_name))
+    }
+
+    pub async fn forget(&self, user_id: &str) {
+        self.sanctioned
+            .lock()
+            .await
+            .retain(|known| known.user_id != user_id);
+    }
+}
+
+pub fn create_evasion_detector() -> SafeEvasionDetector {
+    Arc::new(EvasionDetector::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn similar_message_of_another_account_is_suspected() {
+        let detector = EvasionDetector::default();
+        let texts = ["hi", "this streamer is a total fraud, unfollow now"];
+
+        detector
+            .sanctioned("1", Arc::from("Troll"), texts.into_iter())
+            .await;
+
+        assert_eq!(
+            detector
+                .check("2", "This streamer is a TOTAL fraud!! unfollow now")
+                .await
+                .as_deref(),
+            Some("Troll")
+        );
+        assert_eq!(
+            detector
+                .check("1", "this streamer is a total fraud, unfollow now")
+                .await,
+            None
+        );
+        assert_eq!(detector.check("2", "hi").await, None);
+        assert_eq!(
+            detector.check("2", "what game is this, looks great").await,
+            None
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Bus of the channel events shared by the chat and EventSub clients.
+//!
+//! The clients publish what happens on the channel, the other modules, the web server
+//! and the embedding code subscribe to it independently. The session lists of the state,
+//! e.g. the chatters and the followers, are kept by a subscriber as well, see
+//! [`crate::sessions::run_session_lists`]. Handlers registered with
+//! [`crate::Bot::on_event`] receive every event of the requested kind,
+//! [`crate::BotState::subscribe_events`] gives all of them.
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Enough for a burst of chat messages, the session lists miss the skipped events
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ChatMessage,
+    Follow,
+    Subscribe,
+    Raid,
+    CategoryChanged,
+    StreamOnline,
+    StreamOffline,
+    Resubscribe,
+    GiftSubscriptions,
+    Cheer,
+    AdBreak,
+}
+
+#[derive(Debug, Clone)]
+pub enum BotEvent {
+    ChatMessage {
+        user_id: String,
+        user_name: String,
+        text: String,
+        /// The user is a moderator or the broadcaster
+        is_moderator: bool,
+        is_broadcaster: bool,
+    },
+    Follow {
+        user_id: String,
+        user_name: String,
+    },
+    Subscribe {
+        user_id: String,
+        user_name: String,
+    },
+    Raid {
+        user_id: String,
+        user_name: String,
+        viewers: u64,
+    },
+    /// The stream category is set or changed, e.g. on start
+    CategoryChanged {
+        category: String,
+    },
+    /// The stream went live
+    StreamOnline,
+    StreamOffline,
+    Resubscribe {
+        user_id: String,
+        user_name: String,
+        /// Subscription months in total
+        months: u64,
+        /// Months in a row, `None` if the user does not share it
+        streak: Option<u64>,
+    },
+    /// Anonymous gifts come without the gifter
+    GiftSubscriptions {
+        user_id: Option<String>,
+        user_name: Option<String>,
+        count: u64,
+    },
+    /// Anonymous cheers come without the user
+    Cheer {
+        user_id: Option<String>,
+        user_name: Option<String>,
+        bits: u64,
+    },
+    AdBreak {
+        seconds: u64,
+    },
+}
+
+impl BotEvent {
+    #[must_use]
+    pub fn kind(&self) -> EventKind {
+        match self {
+            BotEvent::ChatMessage { .. } => EventKind::ChatMessage,
+            BotEvent::Follow { .. } => EventKind::Follow,
+            BotEvent::Subscribe { .. } => EventKind::Subscribe,
+            BotEvent::Raid { .. } => EventKind::Raid,
+            BotEvent::CategoryChanged { .. } => EventKind::CategoryChanged,
+            BotEvent::StreamOnline => EventKind::StreamOnline,
+            BotEvent::StreamOffline => EventKind::StreamOffline,
+            BotEvent::Resubscribe { .. } => EventKind::Resubscribe,
+            BotEvent::GiftSubscriptions { .. } => EventKind::GiftSubscriptions,
+            BotEvent::Cheer { .. } => EventKind::Cheer,
+            BotEvent::AdBreak { .. } => EventKind::AdBreak,
+        }
+    }
+}
+
+pub type EventBus = broadcast::Sender<BotEvent>;
+
+pub fn create_event_bus() -> EventBus {
+    broadcast::channel(EVENT_BUS_CAPACITY).0
+}
+
+/// Publish the event to the registered handlers, it is fine to have none
+pub fn publish(bus: &EventBus, event: BotEvent) {
+    if bus.send(event).is_err() {
+        tracing::trace!("no event handler is registered");
+    }
+}
@@ -1,96 +1,343 @@
+//! Token, channel identity and start of the EventSub client of [`crate::websocket`].
 use core::str::FromStr;
-use std::env;
 
+use twitch_api::helix::streams::GetStreamsRequest;
 use twitch_api::helix::HelixClient;
-use twitch_api::types::UserId;
+use twitch_api::types::{UserId, UserIdRef};
 use twitch_oauth2::{Scope, UserToken};
 use url::Url;
 
-use crate::helper::SafeTwitchEventList;
-use crate::utils::{CreateContext, Token, Wrapper};
-use crate::{config, websocket};
+use crate::config::FollowerCountMode;
+use crate::events::{self, BotEvent};
+use crate::health::Status;
+use crate::helper::BotState;
+use crate::transport::{
+    create_api_client, ApiClient, HttpApi, TungsteniteTransport, TwitchApiClient,
+};
+use crate::{config, metrics, rewards, utils, websocket};
 
 const TEST_WEBSOCKET_URL: &str = "ws://127.0.0.1:8080/ws";
+pub(crate) const HEALTH_COMPONENT: &str = "eventsub";
 
-// moderator:read:followers channel:read:subscriptions
-pub(crate) async fn run_eventsub_client(event_list: SafeTwitchEventList) {
-    let config_file = config::get_eventsub_config_file();
-    let token = match Token::from_file(config_file.clone()) {
-        Err(_) => {
-            let scopes = [
-                Scope::ModeratorReadFollowers,
-                Scope::ModeratorManageBannedUsers,
-                Scope::ChannelReadSubscriptions,
-            ];
-            let token_create_ctx = CreateContext::new(&scopes, false, config::REDIRECT_URL);
-            let token_handler = Wrapper::new(token_create_ctx).await;
-            let token: Token = token_handler.get_user_token().into();
-
-            token
-                .save(config_file)
-                .expect("Unable to save EventSub token");
-
-            token
-        }
-        Ok(token) => token,
-    };
+/// Load the EventSub token or request a new one with the broadcaster permissions:
+/// moderator:read:followers moderator:read:chatters channel:read:subscriptions clips:edit
+/// user:manage:whispers moderator:manage:shoutouts bits:read channel:read:ads
+/// channel:manage:raids
+///
+/// # Errors
+///
+/// Will return `Err` if the token cannot be saved or validated
+pub async fn acquire_eventsub_token() -> Result<UserToken, String> {
+    let scopes = [
+        Scope::ModeratorReadFollowers,
+        Scope::ModeratorReadChatters,
+        Scope::ModeratorManageBannedUsers,
+        Scope::ModeratorManageChatSettings,
+        Scope::ChannelReadSubscriptions,
+        Scope::ChannelManageRedemptions,
+        Scope::ClipsEdit,
+        Scope::UserManageWhispers,
+        Scope::ModeratorManageShoutouts,
+        Scope::BitsRead,
+        Scope::ChannelReadAds,
+        Scope::ChannelManageRaids,
+        Scope::ModeratorManageAnnouncements,
+    ];
 
-    let token = token.into_user_token().await;
-    let client = HelixClient::<reqwest::Client>::new();
-    let channel_name =
-        env::var("TWITCH_CHANNEL").expect("Please specify Twitch channel name to connect to");
+    utils::token_manager(config::get_eventsub_config_file())
+        .load_or_authorize(&create_api_client(), &scopes, utils::redirect_url())
+        .await
+        .map_err(|e| format!("unable to get EventSub token: {e}"))
+}
 
-    let connection_url = if cfg!(feature = "debug") {
+/// Connect to EventSub and handle the channel events of `user_id`, runs until the
+/// client gives up reconnecting
+///
+/// # Panics
+///
+/// Will panic if `HEWPME_EVENTSUB_URL` is not a valid URL
+pub async fn run_eventsub_client(state: BotState, token: UserToken, user_id: UserId) {
+    let client = HelixClient::with_client(create_api_client());
+    let connection_url = if let Some(url) = config::get_eventsub_url() {
+        Url::from_str(url.as_str()).expect("Invalid EventSub URL in HEWPME_EVENTSUB_URL")
+    } else if cfg!(feature = "debug") {
         Url::from_str(TEST_WEBSOCKET_URL).unwrap()
     } else {
         Url::from_str(twitch_api::TWITCH_EVENTSUB_WEBSOCKET_URL.as_str()).unwrap()
     };
-    let user_id: UserId = if cfg!(feature = "debug") {
-        From::from("123456")
-    } else {
-        get_user_id(&client, &token, &channel_name).await
-    };
 
-    let ws = websocket::WSlient::new(None, token, client, user_id, connection_url, event_list);
+    load_stream_info(&client, &token, &user_id, &state).await;
+    rewards::sync(&client, &token, &user_id, &state).await;
+
+    let health = std::sync::Arc::clone(&state.health);
+
+    let ws = websocket::WSlient::new(
+        None,
+        token,
+        client,
+        TungsteniteTransport::default(),
+        user_id,
+        connection_url,
+        state,
+    );
 
-    ws.run()
+    health.set(HEALTH_COMPONENT, Status::Ok, None::<String>);
+
+    if let Err(e) = ws.run().await {
+        tracing::error!("EventSub client finished its execution: {e}");
+        // the chat notices stand in for the subscriptions like without the token
+        health.set(
+            HEALTH_COMPONENT,
+            Status::Degraded,
+            Some(format!(
+                "EventSub stopped, the subscriptions are taken from the chat: {e}"
+            )),
+        );
+    }
+}
+
+/// Fetch the current category, the live status and the follower count,
+/// the events only report their changes
+async fn load_stream_info<H: HttpApi>(
+    client: &HelixClient<'static, ApiClient<H>>,
+    token: &UserToken,
+    user_id: &UserId,
+    state: &BotState,
+) {
+    // `channel.update` is only sent on changes, so start with the current category
+    match metrics::timed(
+        "helix_get_channel_information",
+        client.get_channel_from_id(user_id, token),
+    )
+    .await
+    {
+        Ok(Some(channel)) => {
+            let category = channel.game_name.to_string();
+
+            if state.stream.set_category(category.clone()).await {
+                events::publish(&state.bus, BotEvent::CategoryChanged { category });
+            }
+        }
+        Ok(None) => tracing::warn!("Channel {user_id} is not found"),
+        Err(e) => tracing::warn!("Unable to get the stream category: {e}"),
+    }
+
+    // same for `stream.online`, the watchdog needs to know whether the stream is already live
+    let ids: &[&UserIdRef] = &[user_id.as_ref()];
+    match metrics::timed(
+        "helix_get_streams",
+        client.req_get(GetStreamsRequest::user_ids(ids), token),
+    )
+    .await
+    {
+        // the stream start actions are not run again when the bot restarts mid-stream
+        Ok(response) => {
+            state.stream.set_live(!response.data.is_empty());
+        }
+        Err(e) => tracing::warn!("Unable to get the stream status: {e}"),
+    }
+
+    if config::get_follower_count_mode() == FollowerCountMode::Total {
+        match metrics::timed(
+            "helix_get_channel_followers",
+            client.get_total_channel_followers(user_id, token),
+        )
         .await
-        .expect("Websocket client finished its execution");
+        {
+            Ok(total) => {
+                state
+                    .stream
+                    .set_followers_total(u64::try_from(total).unwrap_or_default())
+                    .await;
+            }
+            Err(e) => tracing::warn!("Unable to get the follower count: {e}"),
+        }
+    }
 }
 
-async fn get_user_id<'a, C: 'a>(
+async fn get_user_id<'a, C>(
     client: &'a HelixClient<'a, C>,
     token: &UserToken,
     user_name: &str,
 ) -> UserId
 where
-    C: twitch_api::HttpClient,
+    C: twitch_api::HttpClient + 'a,
+{
+    lookup_user_id(client, token, user_name)
+        .await
+        .unwrap_or_else(|e| panic!("{e}"))
+}
+
+async fn lookup_user_id<'a, C>(
+    client: &'a HelixClient<'a, C>,
+    token: &UserToken,
+    user_name: &str,
+) -> Result<UserId, String>
+where
+    C: twitch_api::HttpClient + 'a,
 {
-    match client.get_user_from_login(user_name, token).await {
-        Ok(user_id) => user_id.unwrap().id,
-        Err(e) => panic!("Unable to get User ID from Twitch: {e}"),
+    match metrics::timed(
+        "helix_get_user",
+        client.get_user_from_login(user_name, token),
+    )
+    .await
+    {
+        Ok(Some(user)) => Ok(user.id),
+        Ok(None) => Err(format!("User {user_name} is not found on Twitch")),
+        Err(e) => Err(format!("Unable to get User ID from Twitch: {e}")),
     }
 }
 
-// TODO: Add token passing
-pub async fn ban_user(user_id: &str, reason: &str) {
-    let client = HelixClient::<reqwest::Client>::new();
-    let config_file = config::get_eventsub_config_file();
-    let token = Token::from_file(config_file.clone()).expect("Unable to get token from file");
-    let duration_sec = 30;
-    let token = token.into_user_token().await;
-
-    if let Err(e) = client
-        .ban_user(
-            user_id,
-            reason,
-            duration_sec,
-            token.user_id.clone(),
-            token.user_id.clone(),
-            &token,
-        )
+/// Load the token saved by the EventSub client, it carries the broadcaster permissions
+///
+/// Returns `None` if the EventSub client has not saved its token yet
+pub async fn get_eventsub_token() -> Option<UserToken> {
+    utils::token_manager(config::get_eventsub_config_file())
+        .load(&create_api_client())
         .await
-    {
-        tracing::warn!("Unable to ban user {user_id}: {e}");
+        .expect("Unable to get EventSub token")
+}
+
+/// Resolve the channel identity on startup, the debug build uses a fixed test ID
+///
+/// # Errors
+///
+/// Will return `Err` if the channel cannot be looked up
+pub async fn resolve_channel_user_id(token: &UserToken) -> Result<UserId, String> {
+    if cfg!(feature = "debug") {
+        return Ok(From::from("123456"));
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+
+    lookup_user_id(&client, token, &config::get_channel_name()).await
+}
+
+/// Resolve the user ID of the channel the bot works in
+pub async fn get_channel_user_id(
+    client: &HelixClient<'static, TwitchApiClient>,
+    token: &UserToken,
+) -> UserId {
+    let channel_name = config::get_channel_name();
+
+    get_user_id(client, token, &channel_name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use serde_json::json;
+    use twitch_oauth2::ClientSecret;
+
+    use super::*;
+    use crate::commands::CommandRegistry;
+    use crate::helper::create_bot_state;
+    use crate::instance::SafeInstance;
+    use crate::storage::create_memory_storage;
+    use crate::transport::mock::MockHttp;
+
+    fn token() -> UserToken {
+        UserToken::from_existing_unchecked(
+            "access",
+            None,
+            "client-id",
+            ClientSecret::new(String::from("client-secret")),
+            "hewpme_test".into(),
+            "1337".into(),
+            None,
+            Some(Duration::from_secs(3600)),
+        )
+    }
+
+    fn twitch_helix(live: bool) -> Arc<MockHttp> {
+        Arc::new(MockHttp::new(move |request| {
+            let body = match request.uri().path() {
+                "/helix/users" => json!({"data": [{
+                    "id": "1337",
+                    "login": "cool_user",
+                    "display_name": "Cool_User",
+                    "type": "",
+                    "broadcaster_type": "affiliate",
+                    "description": "",
+                    "profile_image_url": "https://example.com/avatar.png",
+                    "offline_image_url": "",
+                    "view_count": 0,
+                    "created_at": "2016-12-14T20:32:28Z"
+                }]}),
+                "/helix/channels" => json!({"data": [{
+                    "broadcaster_id": "1337",
+                    "broadcaster_login": "cool_user",
+                    "broadcaster_name": "Cool_User",
+                    "broadcaster_language": "en",
+                    "game_id": "509658",
+                    "game_name": "Just Chatting",
+                    "title": "Hello",
+                    "delay": 0,
+                    "tags": [],
+                    "content_classification_labels": [],
+                    "is_branded_content": false
+                }]}),
+                "/helix/streams" if live => json!({"data": [{
+                    "id": "40952121085",
+                    "user_id": "1337",
+                    "user_login": "cool_user",
+                    "user_name": "Cool_User",
+                    "game_id": "509658",
+                    "game_name": "Just Chatting",
+                    "type": "live",
+                    "title": "Hello",
+                    "tags": [],
+                    "viewer_count": 42,
+                    "started_at": "2021-03-10T15:04:21Z",
+                    "language": "en",
+                    "thumbnail_url": "https://example.com/{width}x{height}.jpg",
+                    "tag_ids": [],
+                    "is_mature": false
+                }], "pagination": {}}),
+                "/helix/streams" => json!({"data": [], "pagination": {}}),
+                _ => {
+                    return (
+                        404,
+                        String::from(r#"{"status": 404, "message": "not found"}"#),
+                    )
+                }
+            };
+
+            (200, body.to_string())
+        }))
+    }
+
+    #[tokio::test]
+    async fn channel_user_id_is_resolved_by_login() {
+        let http = twitch_helix(false);
+        let client = HelixClient::with_client(ApiClient::new(Arc::clone(&http)));
+        let user_id = get_user_id(&client, &token(), "cool_user").await;
+        let requests = http.requests();
+
+        assert_eq!(user_id.as_str(), "1337");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/helix/users");
+    }
+
+    #[tokio::test]
+    async fn stream_info_is_loaded_on_start() {
+        for live in [true, false] {
+            let client = HelixClient::with_client(ApiClient::new(twitch_helix(live)));
+            let (state, _outbox) = create_bot_state(
+                CommandRegistry::default(),
+                SafeInstance::default(),
+                create_memory_storage(),
+            );
+
+            load_stream_info(&client, &token(), &UserId::from("1337"), &state).await;
+
+            assert_eq!(
+                state.stream.category().await.as_deref(),
+                Some("Just Chatting")
+            );
+            assert_eq!(state.stream.is_live(), live);
+        }
     }
 }
@@ -0,0 +1,96 @@
+//! Status of the bot components reported on `/healthz`.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    Starting,
+    /// Working with reduced functionality
+    Degraded,
+    Down,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ComponentHealth {
+    pub status: Status,
+    pub detail: Option<String>,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+    pub status: Status,
+    pub components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+#[derive(Default)]
+pub struct Health {
+    components: Mutex<BTreeMap<&'static str, ComponentHealth>>,
+}
+
+pub type SafeHealth = Arc<Health>;
+
+impl Health {
+    /// # Panics
+    ///
+    /// Will panic if the health lock is poisoned
+    pub fn set<T: Into<String>>(&self, component: &'static str, status: Status, detail: Option<T>) {
+        let detail = detail.map(Into::into);
+        let mut guard = self.components.lock().unwrap();
+
+        if let Some(current) = guard.get(component) {
+            if current.status == status && current.detail == detail {
+                return;
+            }
+        }
+
+        tracing::info!(
+            "{component} is {status:?}: {}",
+            detail.as_deref().unwrap_or("-")
+        );
+        guard.insert(
+            component,
+            ComponentHealth {
+                status,
+                detail,
+                since: Utc::now(),
+            },
+        );
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the health lock is poisoned
+    #[must_use]
+    pub fn status(&self, component: &str) -> Option<Status> {
+        self.components
+            .lock()
+            .unwrap()
+            .get(component)
+            .map(|component| component.status)
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the health lock is poisoned
+    #[must_use]
+    pub fn report(&self) -> HealthReport {
+        let components = self.components.lock().unwrap().clone();
+        let status = components
+            .values()
+            .map(|component| component.status)
+            .max()
+            .unwrap_or(Status::Starting);
+
+        HealthReport { status, components }
+    }
+}
+
+pub fn create_health() -> SafeHealth {
+    Arc::new(Health::default())
+}
@@ -1,43 +1,477 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
-use tokio::sync::{Mutex, MutexGuard};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, Mutex, MutexGuard};
 
-#[derive(Default)]
-pub struct TwitchEventList {
-    followers_list: Mutex<HashSet<String>>,
-    subscribers_list: Mutex<HashSet<String>>,
+use crate::activity::{create_activity_tracker, SafeActivityTracker};
+use crate::alert_queue::{create_alert_queue, SafeAlertQueue};
+use crate::automation::{create_automation, SafeAutomation};
+use crate::badges::{create_badge_images, SafeBadgeImages};
+use crate::birthdays::{create_birthday_book, SafeBirthdayBook};
+use crate::breaks::{create_break_tracker, SafeBreakTracker};
+use crate::clips::{create_clip_collection, SafeClipCollection};
+use crate::commands::{
+    create_command_confirmations, CommandRegistry, SafeCommandConfirmations, SafeCommandRegistry,
+};
+use crate::copypasta::{create_copypasta_detector, SafeCopypastaDetector};
+use crate::custom_commands::{create_custom_command_cooldowns, SafeCustomCommandCooldowns};
+use crate::emotes::{create_emote_set, SafeEmoteSet};
+use crate::evasion::{create_evasion_detector, SafeEvasionDetector};
+use crate::events::{create_event_bus, BotEvent, EventBus};
+use crate::health::{create_health, SafeHealth};
+use crate::i18n::{create_language_preferences, SafeLanguagePreferences};
+use crate::instance::SafeInstance;
+use crate::lapses::{create_subscription_book, SafeSubscriptionBook};
+use crate::mentions::{create_mention_log, SafeMentionLog};
+use crate::moderation::{create_command_rate_guard, SafeCommandRateGuard};
+use crate::modlog::{create_modlog, SafeModLog};
+use crate::names::{create_name_interner, NameInterner, SafeNameInterner};
+use crate::notes::{create_user_notes, SafeUserNotes};
+use crate::notifications::{create_notifier, SafeNotifier};
+use crate::overlay::{create_overlay_bus, OverlayBus};
+use crate::poll::{create_poll_manager, SafePollManager};
+use crate::rewards::{create_managed_rewards, SafeManagedRewards};
+use crate::scheduler::{create_timer_starts, TimerStarts};
+use crate::settings::{create_settings, SafeSettings};
+use crate::stats::{create_session_stats, SafeSessionStats};
+use crate::storage::{SafeStorage, Storage, UserList};
+use crate::streaks::{create_streak_tracker, SafeStreakTracker};
+use crate::stream::{create_stream_info, SafeStreamInfo};
+use crate::unfurl::{create_unfurler, SafeUnfurler};
+use crate::users::{create_user_directory, SafeUserDirectory};
+
+/// Users of the session by user ID, with the name they were last seen with,
+/// so that a renamed user is credited once under the new name
+pub type SessionUsers = HashMap<String, String>;
+
+/// Bits cheered by a user during the session
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CheerTally {
+    user_name: String,
+    bits: u64,
+}
+
+/// Line of the bits leaderboard of the credits
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheerEntry {
+    pub name: String,
+    pub bits: u64,
+}
+
+/// Resubscription announced during the session
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResubEntry {
+    pub name: String,
+    /// Total months subscribed
+    pub months: u64,
+    /// Months subscribed in a row, `None` if the user does not share it
+    pub streak: Option<u64>,
+}
+
+/// Line of the gifted subscriptions leaderboard of the credits
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GiftEntry {
+    pub name: String,
+    /// Subscriptions gifted during the session
+    pub gifts: u64,
+}
+
+/// Viewers credited by a channel point reward, listed under the title of the action
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RewardCredits {
+    pub title: String,
+    /// Names sorted case-insensitively
+    pub names: Vec<String>,
 }
 
-impl TwitchEventList {
-    pub async fn add_follower<T: Into<String>>(&self, follower: T) {
+/// Users of the session kept from the events of the chat and EventSub clients,
+/// see [`crate::sessions::run_session_lists`], the reward credits are added by the
+/// reward actions
+pub struct SessionLists {
+    /// Chatters, followers and subscribers are written through to survive a restart
+    storage: SafeStorage,
+    /// Chatters by user ID, the interned name they were last seen with
+    chatters: Mutex<HashMap<Arc<str>, Arc<str>>>,
+    followers_list: Mutex<SessionUsers>,
+    subscribers_list: Mutex<SessionUsers>,
+    raiders_list: Mutex<SessionUsers>,
+    cheers: Mutex<HashMap<String, CheerTally>>,
+    resubscribers: Mutex<HashMap<String, ResubEntry>>,
+    gifters: Mutex<HashMap<String, GiftEntry>>,
+    /// Users of the reward credit lists by list title
+    reward_credits: Mutex<BTreeMap<String, SessionUsers>>,
+}
+
+impl SessionLists {
+    /// Lists with the chatters, followers and subscribers of the session kept by the storage
+    fn new(storage: SafeStorage, names: &NameInterner) -> Self {
+        let chatters = restore(&*storage, UserList::Chatters)
+            .iter()
+            .map(|(id, name)| (names.intern(id), names.intern(name)))
+            .collect();
+
+        SessionLists {
+            chatters: Mutex::new(chatters),
+            followers_list: Mutex::new(restore(&*storage, UserList::Followers)),
+            subscribers_list: Mutex::new(restore(&*storage, UserList::Subscribers)),
+            storage,
+            raiders_list: Mutex::default(),
+            cheers: Mutex::default(),
+            resubscribers: Mutex::default(),
+            gifters: Mutex::default(),
+            reward_credits: Mutex::default(),
+        }
+    }
+
+    /// Returns the number of chatters if the chatter is new
+    pub async fn add_chatter(&self, user_id: Arc<str>, user_name: Arc<str>) -> Option<usize> {
+        let mut chatters = self.chatters.lock().await;
+        let previous = chatters.insert(Arc::clone(&user_id), Arc::clone(&user_name));
+
+        // only a new chatter or a new name is written to the storage
+        if previous.as_ref() != Some(&user_name) {
+            persist(&*self.storage, UserList::Chatters, &user_id, &user_name);
+        }
+
+        previous.is_none().then(|| chatters.len())
+    }
+
+    /// Returns the number of followers if the follower is new
+    pub async fn add_follower<T: Into<String>>(&self, user_id: &str, follower: T) -> Option<usize> {
+        let follower = follower.into();
         let mut guard = self.followers_list.lock().await;
 
-        guard.insert(follower.into());
+        persist(&*self.storage, UserList::Followers, user_id, &follower);
+        guard
+            .insert(user_id.to_string(), follower)
+            .is_none()
+            .then(|| guard.len())
     }
 
-    pub async fn add_subscriber<T: Into<String>>(&self, subscriber: T) {
+    pub async fn add_subscriber<T: Into<String>>(&self, user_id: &str, subscriber: T) {
+        let subscriber = subscriber.into();
         let mut guard = self.subscribers_list.lock().await;
 
-        guard.insert(subscriber.into());
+        persist(&*self.storage, UserList::Subscribers, user_id, &subscriber);
+        guard.insert(user_id.to_string(), subscriber);
+    }
+
+    pub async fn add_raider<T: Into<String>>(&self, user_id: &str, raider: T) {
+        let mut guard = self.raiders_list.lock().await;
+
+        guard.insert(user_id.to_string(), raider.into());
+    }
+
+    /// Add the bits to the tally of the user, the latest name is kept
+    pub async fn add_cheer<T: Into<String>>(&self, user_id: &str, cheerer: T, bits: u64) {
+        let mut guard = self.cheers.lock().await;
+        let tally = guard
+            .entry(user_id.to_string())
+            .or_insert_with(|| CheerTally {
+                user_name: String::new(),
+                bits: 0,
+            });
+
+        tally.user_name = cheerer.into();
+        tally.bits += bits;
+    }
+
+    /// Users who cheered the most bits, the most first
+    pub async fn top_cheerers(&self, count: usize) -> Vec<CheerEntry> {
+        let mut entries: Vec<CheerEntry> = self
+            .cheers
+            .lock()
+            .await
+            .values()
+            .map(|tally| CheerEntry {
+                name: tally.user_name.clone(),
+                bits: tally.bits,
+            })
+            .collect();
+
+        entries.sort_by(|a, b| b.bits.cmp(&a.bits).then_with(|| a.name.cmp(&b.name)));
+        entries.truncate(count);
+        entries
+    }
+
+    pub async fn clear_cheers(&self) {
+        self.cheers.lock().await.clear();
+    }
+
+    /// Remember the latest resubscription of the user
+    pub async fn add_resubscriber<T: Into<String>>(
+        &self,
+        user_id: &str,
+        resubscriber: T,
+        months: u64,
+        streak: Option<u64>,
+    ) {
+        self.resubscribers.lock().await.insert(
+            user_id.to_string(),
+            ResubEntry {
+                name: resubscriber.into(),
+                months,
+                streak,
+            },
+        );
+    }
+
+    /// Resubscribers of the session, the longest subscribed first
+    pub async fn resubscribers(&self) -> Vec<ResubEntry> {
+        let mut entries: Vec<ResubEntry> =
+            self.resubscribers.lock().await.values().cloned().collect();
+
+        entries.sort_by(|a, b| b.months.cmp(&a.months).then_with(|| a.name.cmp(&b.name)));
+        entries
+    }
+
+    /// Add the gifted subscriptions to the tally of the user, the latest name is kept
+    pub async fn add_gift<T: Into<String>>(&self, user_id: &str, gifter: T, gifts: u64) {
+        let mut guard = self.gifters.lock().await;
+        let entry = guard
+            .entry(user_id.to_string())
+            .or_insert_with(|| GiftEntry {
+                name: String::new(),
+                gifts: 0,
+            });
+
+        entry.name = gifter.into();
+        entry.gifts += gifts;
+    }
+
+    /// Users who gifted the most subscriptions, the most first
+    pub async fn top_gifters(&self, count: usize) -> Vec<GiftEntry> {
+        let mut entries: Vec<GiftEntry> = self.gifters.lock().await.values().cloned().collect();
+
+        entries.sort_by(|a, b| b.gifts.cmp(&a.gifts).then_with(|| a.name.cmp(&b.name)));
+        entries.truncate(count);
+        entries
+    }
+
+    pub async fn clear_subscription_tallies(&self) {
+        self.resubscribers.lock().await.clear();
+        self.gifters.lock().await.clear();
+    }
+
+    /// Add the user to the credit list of a channel point reward
+    pub async fn add_reward_credit<T: Into<String>>(&self, title: &str, user_id: &str, name: T) {
+        self.reward_credits
+            .lock()
+            .await
+            .entry(title.to_string())
+            .or_default()
+            .insert(user_id.to_string(), name.into());
+    }
+
+    /// Credit lists of the channel point rewards sorted by title
+    pub async fn reward_credits(&self) -> Vec<RewardCredits> {
+        self.reward_credits
+            .lock()
+            .await
+            .iter()
+            .map(|(title, users)| {
+                let mut names: Vec<String> = users.values().cloned().collect();
+
+                names.sort_by_cached_key(|name| name.to_lowercase());
+
+                RewardCredits {
+                    title: title.clone(),
+                    names,
+                }
+            })
+            .collect()
+    }
+
+    pub async fn clear_reward_credits(&self) {
+        self.reward_credits.lock().await.clear();
+    }
+
+    pub async fn get_chatters(&self) -> MutexGuard<'_, HashMap<Arc<str>, Arc<str>>> {
+        self.chatters.lock().await
     }
 
-    pub async fn get_followers(&self) -> MutexGuard<HashSet<String>> {
+    pub async fn get_followers(&self) -> MutexGuard<'_, SessionUsers> {
         self.followers_list.lock().await
     }
 
-    pub async fn get_subscribers(&self) -> MutexGuard<HashSet<String>> {
+    pub async fn get_subscribers(&self) -> MutexGuard<'_, SessionUsers> {
         self.subscribers_list.lock().await
     }
+
+    pub async fn get_raiders(&self) -> MutexGuard<'_, SessionUsers> {
+        self.raiders_list.lock().await
+    }
+
+    pub async fn forget(&self, user_id: &str) {
+        self.chatters.lock().await.remove(user_id);
+        self.followers_list.lock().await.remove(user_id);
+        self.subscribers_list.lock().await.remove(user_id);
+        self.raiders_list.lock().await.remove(user_id);
+        self.cheers.lock().await.remove(user_id);
+        self.resubscribers.lock().await.remove(user_id);
+        self.gifters.lock().await.remove(user_id);
+
+        let mut reward_credits = self.reward_credits.lock().await;
+
+        for users in reward_credits.values_mut() {
+            users.remove(user_id);
+        }
+
+        reward_credits.retain(|_, users| !users.is_empty());
+    }
 }
 
-pub type ChattersList = Arc<Mutex<HashSet<String>>>;
-pub type SafeTwitchEventList = Arc<TwitchEventList>;
+/// Chatters who announced with `!lurk` that they are watching silently
+pub type LurkersList = Arc<Mutex<SessionUsers>>;
+/// Viewers connected to the chat during the session, typing or not
+pub type PresenceList = Arc<Mutex<SessionUsers>>;
+pub type SafeSessionLists = Arc<SessionLists>;
 
-pub fn create_new_chatters_list() -> ChattersList {
-    Arc::new(Mutex::new(HashSet::new()))
+pub fn create_new_lurkers_list() -> LurkersList {
+    Arc::new(Mutex::new(HashMap::new()))
 }
 
-pub fn create_new_twitch_event_list() -> SafeTwitchEventList {
-    Arc::new(TwitchEventList::default())
+pub fn create_new_presence_list() -> PresenceList {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+pub fn create_session_lists(storage: &SafeStorage, names: &NameInterner) -> SafeSessionLists {
+    Arc::new(SessionLists::new(Arc::clone(storage), names))
+}
+
+/// Users of the stored list, none if it cannot be read
+fn restore(storage: &dyn Storage, list: UserList) -> SessionUsers {
+    storage.users(list).unwrap_or_else(|e| {
+        tracing::error!("Unable to restore the {list:?} list: {e}");
+        SessionUsers::new()
+    })
+}
+
+/// Write the user of a session list to the storage, the list in memory stays in use
+/// if it fails, a follower instance leaves the writes to the leader
+pub(crate) fn persist(storage: &dyn Storage, list: UserList, user_id: &str, user_name: &str) {
+    if !crate::storage::is_writable() {
+        return;
+    }
+
+    if let Err(e) = storage.add_user(list, user_id, user_name) {
+        tracing::error!("Unable to save the user to the {list:?} list: {e}");
+    }
+}
+
+/// Messages to be sent to the channel chat by the IRC client
+pub type ChatOutbox = mpsc::UnboundedSender<String>;
+pub type ChatOutboxReceiver = mpsc::UnboundedReceiver<String>;
+
+/// State shared between the chat client, the EventSub client and the web server
+#[derive(Clone)]
+pub struct BotState {
+    pub(crate) names: SafeNameInterner,
+    pub(crate) lurkers: LurkersList,
+    pub(crate) presence: PresenceList,
+    pub(crate) session: SafeSessionLists,
+    pub(crate) activity: SafeActivityTracker,
+    pub(crate) streaks: SafeStreakTracker,
+    pub(crate) birthdays: SafeBirthdayBook,
+    pub(crate) users: SafeUserDirectory,
+    pub(crate) poll: SafePollManager,
+    pub(crate) modlog: SafeModLog,
+    pub(crate) notes: SafeUserNotes,
+    pub(crate) stats: SafeSessionStats,
+    pub(crate) subscriptions: SafeSubscriptionBook,
+    pub(crate) unfurler: SafeUnfurler,
+    pub(crate) emotes: SafeEmoteSet,
+    pub(crate) badges: SafeBadgeImages,
+    pub(crate) languages: SafeLanguagePreferences,
+    pub(crate) notifier: SafeNotifier,
+    pub(crate) breaks: SafeBreakTracker,
+    pub(crate) command_guard: SafeCommandRateGuard,
+    pub(crate) copypasta: SafeCopypastaDetector,
+    pub(crate) evasion: SafeEvasionDetector,
+    pub(crate) mentions: SafeMentionLog,
+    pub(crate) clips: SafeClipCollection,
+    pub(crate) overlay: OverlayBus,
+    pub(crate) alert_queue: SafeAlertQueue,
+    pub(crate) rewards: SafeManagedRewards,
+    pub(crate) automation: SafeAutomation,
+    pub(crate) bus: EventBus,
+    pub(crate) commands: SafeCommandRegistry,
+    pub(crate) confirmations: SafeCommandConfirmations,
+    pub(crate) custom_cooldowns: SafeCustomCommandCooldowns,
+    pub(crate) stream: SafeStreamInfo,
+    pub(crate) health: SafeHealth,
+    pub(crate) settings: SafeSettings,
+    pub(crate) timer_starts: TimerStarts,
+    pub(crate) instance: SafeInstance,
+    pub(crate) storage: SafeStorage,
+    pub(crate) chat_outbox: ChatOutbox,
+}
+
+impl BotState {
+    /// Post a message to the channel chat
+    pub fn say<T: Into<String>>(&self, message: T) {
+        if self.chat_outbox.send(message.into()).is_err() {
+            tracing::warn!("chat client is not running, message is dropped");
+        }
+    }
+
+    /// Receive every event published on the bus from now on, see [`crate::events`]
+    #[must_use]
+    pub fn subscribe_events(&self) -> broadcast::Receiver<BotEvent> {
+        self.bus.subscribe()
+    }
+}
+
+/// State with the session lists restored from the storage
+pub fn create_bot_state(
+    commands: CommandRegistry,
+    instance: SafeInstance,
+    storage: SafeStorage,
+) -> (BotState, ChatOutboxReceiver) {
+    let (chat_outbox, chat_outbox_receiver) = mpsc::unbounded_channel();
+    let names = create_name_interner();
+    let state = BotState {
+        session: create_session_lists(&storage, &names),
+        names,
+        lurkers: create_new_lurkers_list(),
+        presence: create_new_presence_list(),
+        activity: create_activity_tracker(),
+        streaks: create_streak_tracker(),
+        birthdays: create_birthday_book(),
+        users: create_user_directory(),
+        poll: create_poll_manager(),
+        modlog: create_modlog(),
+        notes: create_user_notes(),
+        stats: create_session_stats(),
+        subscriptions: create_subscription_book(),
+        unfurler: create_unfurler(),
+        emotes: create_emote_set(),
+        badges: create_badge_images(),
+        languages: create_language_preferences(),
+        notifier: create_notifier(),
+        breaks: create_break_tracker(),
+        command_guard: create_command_rate_guard(),
+        copypasta: create_copypasta_detector(),
+        evasion: create_evasion_detector(),
+        mentions: create_mention_log(),
+        clips: create_clip_collection(),
+        overlay: create_overlay_bus(),
+        alert_queue: create_alert_queue(),
+        rewards: create_managed_rewards(),
+        automation: create_automation(),
+        bus: create_event_bus(),
+        commands: Arc::new(commands),
+        confirmations: create_command_confirmations(),
+        custom_cooldowns: create_custom_command_cooldowns(),
+        stream: create_stream_info(),
+        health: create_health(),
+        settings: create_settings(),
+        timer_starts: create_timer_starts(),
+        instance,
+        storage,
+        chat_outbox,
+    };
+
+    (state, chat_outbox_receiver)
 }
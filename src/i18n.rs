@@ -0,0 +1,850 @@
+//! Translations of the bot responses.
+//!
+//! Replies to a viewer use the language chosen with `!lang`, messages addressed
+//! to the whole channel use the channel language from `HEWPME_LANGUAGE`.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config;
+use crate::storage::JsonStore;
+
+const LANGUAGES_FILE_NAME: &str = "languages.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    Ru,
+    En,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::Ru, Lang::En];
+
+    #[must_use]
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Ru => "ru",
+            Lang::En => "en",
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lang::ALL
+            .into_iter()
+            .find(|lang| lang.code().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// Message key, Russian and English templates
+const MESSAGES: &[(&str, &str, &str)] = &[
+    (
+        "coinflip.lucky",
+        "В этот раз тебе повезло!",
+        "You got lucky this time!",
+    ),
+    (
+        "vanish.cooldown",
+        "Исчезнуть снова можно через {seconds} сек.",
+        "You can vanish again in {seconds} s.",
+    ),
+    (
+        "clip.created",
+        "Клип: {url}",
+        "Clip: {url}",
+    ),
+    (
+        "clip.failed",
+        "Не получилось создать клип, стрим идёт?",
+        "Unable to create a clip, is the stream live?",
+    ),
+    (
+        "clip.not_delegated",
+        "{channel} не дал боту доступ к своему каналу",
+        "{channel} has not granted the bot access to their channel",
+    ),
+    (
+        "info.uptime",
+        "Стрим идёт {hours} ч. {minutes} мин.",
+        "Live for {hours} h {minutes} min",
+    ),
+    (
+        "info.offline",
+        "Сейчас стрима нет",
+        "The stream is offline now",
+    ),
+    (
+        "info.title",
+        "Название стрима: {title}",
+        "Stream title: {title}",
+    ),
+    (
+        "info.category",
+        "Категория: {category}",
+        "Category: {category}",
+    ),
+    (
+        "info.failed",
+        "Не получилось узнать у Twitch, попробуй позже",
+        "Unable to ask Twitch, try again later",
+    ),
+    (
+        "clip.cooldown",
+        "Следующий клип можно через {seconds} сек.",
+        "The next clip can be made in {seconds} s.",
+    ),
+    (
+        "appeal.timeout",
+        "Вы получили тайм-аут на {seconds} сек. в чате {channel}: {reason}. Правила и апелляция: {url}",
+        "You were timed out for {seconds} s in the chat of {channel}: {reason}. Rules and appeals: {url}",
+    ),
+    (
+        "appeal.ban",
+        "Вы забанены в чате {channel}: {reason}. Правила и апелляция: {url}",
+        "You were banned in the chat of {channel}: {reason}. Rules and appeals: {url}",
+    ),
+    (
+        "lapse.notice",
+        "Подписка {name} закончилась после {months} мес. (уровень {tier})",
+        "The subscription of {name} ended after {months} months (tier {tier})",
+    ),
+    (
+        "lapse.report",
+        "Закончившихся подписок за неделю: {count}, всего {months} мес.",
+        "Subscriptions ended this week: {count}, {months} months in total",
+    ),
+    (
+        "raid.welcome",
+        "{name} врывается с рейдом на {viewers} зрителей, спасибо! Загляните к ним: {url}",
+        "{name} is raiding with {viewers} viewers, thank you! Check them out: {url}",
+    ),
+    (
+        "shoutout.message",
+        "Загляните к {name}: {url}",
+        "Go check out {name}: {url}",
+    ),
+    (
+        "shoutout.not_found",
+        "Нет такого канала: {login}",
+        "There is no channel {login}",
+    ),
+    (
+        "shoutout.failed",
+        "Twitch не принял шаутаут, попробуй позже",
+        "Twitch refused the shoutout, try again later",
+    ),
+    (
+        "shoutout.usage",
+        "Кому шаутаут? !so <канал>",
+        "Whom to shout out? !so <channel>",
+    ),
+    (
+        "raid.shoutout",
+        "Загляните к {name}: {url}",
+        "Go check out {name}: {url}",
+    ),
+    (
+        "ad_break.warning",
+        "Реклама на {seconds} сек., не уходите!",
+        "Ads for {seconds} s, don't go anywhere!",
+    ),
+    (
+        "raid_train.next",
+        "Следующая остановка рейд-трейна: {name}, twitch.tv/{login}",
+        "Next stop of the raid train: {name}, twitch.tv/{login}",
+    ),
+    (
+        "raid_train.departed",
+        "Рейд-трейн едет дальше к {name}: twitch.tv/{login}",
+        "The raid train goes on to {name}: twitch.tv/{login}",
+    ),
+    (
+        "raid_train.none",
+        "Рейд-трейна нет",
+        "There is no raid train",
+    ),
+    (
+        "raid_train.no_live",
+        "Никто из рейд-трейна сейчас не в эфире",
+        "Nobody of the raid train is live now",
+    ),
+    (
+        "raid_train.failed",
+        "Не получилось узнать, кто следующий",
+        "Unable to find out who is next",
+    ),
+    (
+        "credit_message.queued",
+        "Сообщение попадёт в титры после проверки модератором",
+        "The message gets to the credits after a moderator reviews it",
+    ),
+    (
+        "credit_message.empty",
+        "Напиши сообщение для титров: !credit <сообщение>",
+        "Write a message for the credits: !credit <message>",
+    ),
+    (
+        "credit_message.too_long",
+        "Сообщение для титров не длиннее {max} символов",
+        "A message for the credits is {max} characters at most",
+    ),
+    (
+        "credit_message.blocked",
+        "Такое сообщение в титры не попадёт",
+        "Such a message does not get to the credits",
+    ),
+    (
+        "credit_message.cooldown",
+        "Изменить сообщение для титров можно через {seconds} сек.",
+        "You can change the message for the credits in {seconds} s.",
+    ),
+    (
+        "streak.status",
+        "Стримов подряд: {current}, рекорд: {longest}",
+        "Streams in a row: {current}, record: {longest}",
+    ),
+    (
+        "birthday.saved",
+        "Запомнил: {day}.{month}",
+        "Saved: {month}-{day}",
+    ),
+    (
+        "birthday.usage",
+        "Укажи дату в формате ММ-ДД, например !birthday 04-25",
+        "Use the MM-DD format, e.g. !birthday 04-25",
+    ),
+    (
+        "birthday.announce",
+        "С днём рождения, {name}!",
+        "Happy birthday, {name}!",
+    ),
+    (
+        "anniversary.announce",
+        "{name} с нами уже {years} {years_word}!",
+        "{name} has been following for {years} {years_word}!",
+    ),
+    ("ban.warning", "Сейчас выдам бан!", "A ban is coming!"),
+    (
+        "confirm.required",
+        "{name}, подтверди {command} в течение {seconds} с: !confirm {code}",
+        "{name}, confirm {command} within {seconds} s: !confirm {code}",
+    ),
+    (
+        "confirm.unknown",
+        "Нечего подтверждать или код неверный",
+        "Nothing to confirm or the code is wrong",
+    ),
+    (
+        "poll.started",
+        "Голосование: {question} Варианты: {options}. Пишите номер варианта в чат!",
+        "Poll: {question} Options: {options}. Type the option number in chat!",
+    ),
+    (
+        "poll.usage",
+        "Формат: !poll вопрос | вариант 1 | вариант 2",
+        "Usage: !poll question | option 1 | option 2",
+    ),
+    (
+        "poll.running",
+        "Голосование уже идёт, завершите его через !endpoll",
+        "A poll is already running, finish it with !endpoll",
+    ),
+    (
+        "poll.ended",
+        "Голосование «{question}» завершено, победил вариант: {winner} ({votes} из {total})",
+        "Poll \"{question}\" is over, the winner is: {winner} ({votes} of {total})",
+    ),
+    (
+        "poll.no_votes",
+        "Голосование «{question}» завершено, но никто не проголосовал",
+        "Poll \"{question}\" is over, but nobody voted",
+    ),
+    (
+        "poll.not_running",
+        "Сейчас нет активного голосования",
+        "There is no poll running",
+    ),
+    (
+        "protection.follow_wave",
+        "Волна фолловов! Чат только для фолловеров на {minutes} мин., модераторы, проверьте",
+        "Follow wave detected! Followers-only chat for {minutes} min, moderators please check",
+    ),
+    (
+        "protection.raid",
+        "Подозрительный рейд! Чат только для подписчиков на {minutes} мин., модераторы, проверьте",
+        "Suspicious raid! Subscribers-only chat for {minutes} min, moderators please check",
+    ),
+    (
+        "break.started",
+        "Небольшой перерыв, скоро вернёмся!",
+        "Short break, be right back!",
+    ),
+    (
+        "break.ended",
+        "Мы вернулись!",
+        "We are back!",
+    ),
+    (
+        "celebration.gifts",
+        "{name} дарит {count} подписок! Чат только для эмоутов на {seconds} сек., празднуем!",
+        "{name} gifts {count} subscriptions! Emote-only chat for {seconds} s, let's celebrate!",
+    ),
+    (
+        "celebration.anonymous_gifts",
+        "Аноним дарит {count} подписок! Чат только для эмоутов на {seconds} сек., празднуем!",
+        "An anonymous gifter gifts {count} subscriptions! Emote-only chat for {seconds} s, let's celebrate!",
+    ),
+    (
+        "celebration.raid",
+        "Рейд от {name} на {viewers} зрителей! Чат только для эмоутов на {seconds} сек., празднуем!",
+        "Raid of {viewers} viewers from {name}! Emote-only chat for {seconds} s, let's celebrate!",
+    ),
+    (
+        "celebration.ended",
+        "Праздник окончен, чат снова открыт",
+        "The celebration is over, the chat is open again",
+    ),
+    (
+        "protection.reverted",
+        "Ограничения чата сняты",
+        "Chat restrictions are lifted",
+    ),
+    (
+        "moderation.command_flood",
+        "{name} шлёт команды слишком часто ({commands} за {seconds} сек.), игнорирую {minutes} мин.",
+        "{name} sends commands too fast ({commands} in {seconds} s), ignoring for {minutes} min",
+    ),
+    (
+        "followmode.on",
+        "Чат только для фолловеров",
+        "Followers-only chat is on",
+    ),
+    (
+        "followmode.off",
+        "Чат снова открыт для всех",
+        "Chat is open for everyone again",
+    ),
+    (
+        "followmode.usage",
+        "Формат: !followmode on|off",
+        "Usage: !followmode on|off",
+    ),
+    (
+        "kv.value",
+        "{key} = {value}",
+        "{key} = {value}",
+    ),
+    (
+        "kv.missing",
+        "{key} не задан",
+        "{key} is not set",
+    ),
+    (
+        "kv.invalid",
+        "Не сохранено: {reason}",
+        "Not stored: {reason}",
+    ),
+    (
+        "kv.failed",
+        "Не удалось сохранить значение",
+        "Unable to store the value",
+    ),
+    (
+        "ping.reply",
+        "Понг! Twitch → бот: {chat} мс, обработка: {processing} мс, ответ в чат: {reply}, Twitch API: {helix}",
+        "Pong! Twitch → bot: {chat} ms, processing: {processing} ms, chat reply: {reply}, Twitch API: {helix}",
+    ),
+    (
+        "ping.unknown",
+        "нет данных",
+        "no data",
+    ),
+    (
+        "kv.usage",
+        "Использование: !kv get <ключ> или !kv set <ключ> <значение>",
+        "Usage: !kv get <key> or !kv set <key> <value>",
+    ),
+    (
+        "wheel.result",
+        "{name} крутит колесо и выигрывает: {prize}!",
+        "{name} spins the wheel and wins: {prize}!",
+    ),
+    (
+        "wheel.no_prizes",
+        "На колесе пока нет призов",
+        "The wheel has no prizes yet",
+    ),
+    (
+        "wheel.not_enough_points",
+        "Вращение стоит {cost} баллов, у тебя {balance}",
+        "A spin costs {cost} points, you have {balance}",
+    ),
+    (
+        "wheel.failed",
+        "Не удалось крутить колесо",
+        "Unable to spin the wheel",
+    ),
+    (
+        "alert.follow",
+        "{name} теперь с нами!",
+        "{name} just followed!",
+    ),
+    (
+        "alert.subscribe",
+        "{name} оформил(а) подписку!",
+        "{name} just subscribed!",
+    ),
+    (
+        "alert.raid",
+        "{name} рейдит с {viewers} зрителями!",
+        "{name} is raiding with {viewers} viewers!",
+    ),
+    (
+        "alert.skipped",
+        "Алерт #{id} пропущен",
+        "Alert #{id} is skipped",
+    ),
+    (
+        "alert.replayed",
+        "Алерт #{id} показан снова",
+        "Alert #{id} is replayed",
+    ),
+    (
+        "alert.not_found",
+        "Такого алерта нет",
+        "There is no such alert",
+    ),
+    (
+        "set.done",
+        "Настройка {key} изменена на {value}",
+        "{key} is set to {value}",
+    ),
+    (
+        "set.invalid",
+        "Неверное значение {key}: {reason}",
+        "Invalid value of {key}: {reason}",
+    ),
+    (
+        "set.usage",
+        "Формат: !set greeting|vanish_cooldown_sec|clip_cooldown_sec|timer<N> <значение>",
+        "Usage: !set greeting|vanish_cooldown_sec|clip_cooldown_sec|timer<N> <value>",
+    ),
+    (
+        "set.failed",
+        "Не удалось сохранить настройку",
+        "Unable to save the setting",
+    ),
+    (
+        "followmode.failed",
+        "Не удалось изменить настройки чата",
+        "Unable to change the chat settings",
+    ),
+    (
+        "lurk.start",
+        "{name} уходит в тень, приятного просмотра!",
+        "{name} is lurking now, enjoy the stream!",
+    ),
+    (
+        "lurk.welcome_back",
+        "С возвращением, {name}! Спасибо, что оставался с нами",
+        "Welcome back, {name}! Thanks for staying with us",
+    ),
+    (
+        "lurk.not_lurking",
+        "{name}, ты и так в чате",
+        "{name}, you are not lurking",
+    ),
+    (
+        "emotes.unlocked",
+        "Новые смайлы на канале: {emotes}",
+        "New channel emotes unlocked: {emotes}",
+    ),
+    (
+        "milestone.chatter",
+        "{name} — уже {count}-й зритель в чате сегодня! Ура!",
+        "{name} is chatter number {count} today! Hooray!",
+    ),
+    (
+        "milestone.follower",
+        "{name} — {count}-й новый фолловер за стрим! Спасибо!",
+        "{name} is follower number {count} this stream! Thank you!",
+    ),
+    (
+        "notes.saved",
+        "Заметка о {name} сохранена",
+        "Note about {name} is saved",
+    ),
+    (
+        "notes.unknown_user",
+        "Пользователь {name} не найден",
+        "User {name} is not found",
+    ),
+    (
+        "customcom.added",
+        "Команда {name} добавлена",
+        "Command {name} is added",
+    ),
+    (
+        "customcom.edited",
+        "Команда {name} изменена",
+        "Command {name} is changed",
+    ),
+    (
+        "customcom.removed",
+        "Команда {name} удалена",
+        "Command {name} is removed",
+    ),
+    (
+        "customcom.invalid",
+        "Команда {name} не сохранена: {reason}",
+        "Command {name} is not saved: {reason}",
+    ),
+    (
+        "customcom.failed",
+        "Не удалось сохранить команду {name}",
+        "Unable to save the command {name}",
+    ),
+    (
+        "customcom.usage",
+        "Формат: !addcom !имя ответ, !editcom !имя ответ, !delcom !имя",
+        "Usage: !addcom !name response, !editcom !name response, !delcom !name",
+    ),
+    (
+        "notes.usage",
+        "Формат: !note пользователь текст, !notes пользователь",
+        "Usage: !note user text, !notes user",
+    ),
+    (
+        "notes.none",
+        "О {name} заметок нет",
+        "There are no notes about {name}",
+    ),
+    (
+        "notes.list",
+        "Заметки о {name} ({count}): {notes}",
+        "Notes about {name} ({count}): {notes}",
+    ),
+    (
+        "commands.list",
+        "Команды ({page}/{pages}): {commands}",
+        "Commands ({page}/{pages}): {commands}",
+    ),
+    ("commands.more", "Все команды: {url}", "All commands: {url}"),
+    (
+        "help.command",
+        "{name}: {description}",
+        "{name}: {description}",
+    ),
+    (
+        "help.moderator",
+        "только для модераторов",
+        "moderators only",
+    ),
+    (
+        "help.broadcaster",
+        "только для стримера",
+        "broadcaster only",
+    ),
+    (
+        "help.cooldown",
+        "раз в {seconds} сек.",
+        "once per {seconds} s",
+    ),
+    (
+        "help.unknown",
+        "Не знаю команду {name}, список: !commands",
+        "Unknown command {name}, see !commands",
+    ),
+    (
+        "command.coinflip",
+        "Подбросить монетку: проиграешь — таймаут",
+        "Flip a coin, lose and get a timeout",
+    ),
+    (
+        "command.vanish",
+        "Очистить свои сообщения из чата",
+        "Remove your messages from chat",
+    ),
+    (
+        "command.uptime",
+        "Сколько идёт стрим",
+        "How long the stream is live",
+    ),
+    (
+        "command.title",
+        "Название стрима",
+        "Title of the stream",
+    ),
+    (
+        "command.game",
+        "Категория стрима",
+        "Category of the stream",
+    ),
+    (
+        "command.clip",
+        "Сделать клип последних секунд стрима",
+        "Clip the last seconds of the stream",
+    ),
+    (
+        "command.streak",
+        "Сколько стримов подряд ты смотришь",
+        "How many streams in a row you watched",
+    ),
+    (
+        "command.birthday",
+        "Запомнить день рождения: !birthday ММ-ДД",
+        "Save your birthday: !birthday MM-DD",
+    ),
+    (
+        "command.lang",
+        "Выбрать язык ответов бота",
+        "Choose the language of the bot replies",
+    ),
+    (
+        "command.lurk",
+        "Смотреть стрим молча",
+        "Watch the stream silently",
+    ),
+    (
+        "command.unlurk",
+        "Вернуться в чат после !lurk",
+        "Come back to the chat after !lurk",
+    ),
+    (
+        "command.poll",
+        "Начать голосование: !poll вопрос | вариант | вариант",
+        "Start a poll: !poll question | option | option",
+    ),
+    (
+        "command.endpoll",
+        "Завершить голосование",
+        "Finish the poll",
+    ),
+    (
+        "command.followmode",
+        "Чат только для фолловеров: !followmode on|off",
+        "Followers-only chat: !followmode on|off",
+    ),
+    (
+        "command.note",
+        "Оставить заметку о пользователе",
+        "Add a note about a user",
+    ),
+    (
+        "command.set",
+        "Изменить настройку бота",
+        "Change a bot setting",
+    ),
+    (
+        "command.addcom",
+        "Добавить команду: !addcom !имя [-cd=секунды] [-ul=mod] ответ",
+        "Add a command: !addcom !name [-cd=seconds] [-ul=mod] response",
+    ),
+    (
+        "command.editcom",
+        "Изменить команду: !editcom !имя [-cd=секунды] [-ul=mod|everyone] [ответ]",
+        "Change a command: !editcom !name [-cd=seconds] [-ul=mod|everyone] [response]",
+    ),
+    (
+        "command.delcom",
+        "Удалить команду: !delcom !имя",
+        "Remove a command: !delcom !name",
+    ),
+    (
+        "command.confirm",
+        "Подтвердить опасную команду: !confirm <код>",
+        "Confirm a destructive command: !confirm <code>",
+    ),
+    (
+        "command.ping",
+        "Задержка бота и Twitch",
+        "Latency of the bot and Twitch",
+    ),
+    (
+        "command.kv",
+        "Общие значения: !kv get <ключ>, !kv set <ключ> <значение>",
+        "Shared values: !kv get <key>, !kv set <key> <value>",
+    ),
+    (
+        "command.spin",
+        "Крутить колесо призов за баллы",
+        "Spin the prize wheel for points",
+    ),
+    (
+        "command.next",
+        "Следующий канал рейд-трейна",
+        "Next channel of the raid train",
+    ),
+    (
+        "command.credit",
+        "Сообщение рядом с твоим именем в титрах",
+        "Message next to your name in the credits",
+    ),
+    (
+        "command.so",
+        "Шаутаут каналу: !so <канал>",
+        "Shout out a channel: !so <channel>",
+    ),
+    (
+        "command.raidnext",
+        "Рейд на следующий канал рейд-трейна",
+        "Raid the next channel of the raid train",
+    ),
+    (
+        "command.skipalert",
+        "Пропустить алерт, последний или #номер",
+        "Skip the latest alert or #id",
+    ),
+    (
+        "command.replayalert",
+        "Повторить алерт, последний или #номер",
+        "Replay the latest alert or #id",
+    ),
+    (
+        "command.notes",
+        "Заметки о пользователе",
+        "Notes about a user",
+    ),
+    ("command.ban", "Пригрозить баном", "Threaten with a ban"),
+    ("command.commands", "Список команд", "List of commands"),
+    (
+        "command.help",
+        "Описание команды: !help команда",
+        "Describe a command: !help command",
+    ),
+    (
+        "command.forgetme",
+        "Удалить все мои данные: !forgetme confirm",
+        "Delete all my data: !forgetme confirm",
+    ),
+    (
+        "privacy.confirm",
+        "Это удалит твои серии, день рождения, заметки и сообщения. Подтверди: !forgetme confirm",
+        "This deletes your streaks, birthday, notes and messages. Confirm with !forgetme confirm",
+    ),
+    (
+        "privacy.forgotten",
+        "Готово, я всё забыл",
+        "Done, your data is deleted",
+    ),
+    (
+        "lang.set",
+        "Теперь я отвечаю тебе на русском",
+        "I will reply to you in English now",
+    ),
+    (
+        "lang.usage",
+        "Доступные языки: {languages}",
+        "Available languages: {languages}",
+    ),
+];
+
+/// Render the message `key` in `lang`, `{name}` placeholders are replaced with `args`
+///
+/// # Panics
+///
+/// Will panic if there is no message with the given key
+#[must_use]
+pub fn render(lang: Lang, key: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    let (_, ru, en) = MESSAGES
+        .iter()
+        .find(|(message_key, _, _)| *message_key == key)
+        .unwrap_or_else(|| panic!("Unknown message key {key}"));
+    let template = match lang {
+        Lang::Ru => ru,
+        Lang::En => en,
+    };
+
+    fill(template, args)
+}
+
+/// Replace the `{name}` placeholders of the template with `args`
+#[must_use]
+pub fn fill(template: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    let mut text = template.to_string();
+
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+
+    text
+}
+
+#[must_use]
+pub fn years_word(lang: Lang, years: i32) -> &'static str {
+    match lang {
+        Lang::Ru => match (years % 10, years % 100) {
+            (1, rem) if rem != 11 => "год",
+            (2..=4, rem) if !(12..=14).contains(&rem) => "года",
+            _ => "лет",
+        },
+        Lang::En if years == 1 => "year",
+        Lang::En => "years",
+    }
+}
+
+/// Language of the messages addressed to the whole channel
+#[must_use]
+pub fn channel_language() -> Lang {
+    config::get_channel_language().parse().unwrap_or(Lang::Ru)
+}
+
+pub struct LanguagePreferences {
+    store: Mutex<JsonStore<HashMap<String, Lang>>>,
+}
+
+pub type SafeLanguagePreferences = Arc<LanguagePreferences>;
+
+impl LanguagePreferences {
+    fn open() -> Self {
+        LanguagePreferences {
+            store: Mutex::new(JsonStore::open(LANGUAGES_FILE_NAME)),
+        }
+    }
+
+    /// Language of messages addressed to the whole channel
+    #[must_use]
+    pub fn channel(&self) -> Lang {
+        channel_language()
+    }
+
+    pub async fn for_user(&self, user_id: &str) -> Lang {
+        self.store
+            .lock()
+            .await
+            .get(user_id)
+            .copied()
+            .unwrap_or_else(channel_language)
+    }
+
+    /// Language chosen by the user with `!lang`
+    pub async fn preference(&self, user_id: &str) -> Option<Lang> {
+        self.store.lock().await.get(user_id).copied()
+    }
+
+    pub async fn forget(&self, user_id: &str) -> bool {
+        self.store
+            .lock()
+            .await
+            .update(|languages| languages.remove(user_id).is_some())
+    }
+
+    pub async fn set_for_user(&self, user_id: &str, lang: Lang) {
+        self.store.lock().await.update(|languages| {
+            languages.insert(user_id.to_string(), lang);
+        });
+    }
+}
+
+pub fn create_language_preferences() -> SafeLanguagePreferences {
+    Arc::new(LanguagePreferences::open())
+}
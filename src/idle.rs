@@ -0,0 +1,61 @@
+//! Engagement prompts posted when the chat has been silent for a while during the stream.
+//!
+//! Prompts are configured in `prompts.json` as a list of strings, one of them
+//! is picked at random.
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+use crate::config;
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::storage::JsonStore;
+
+const PROMPTS_FILE_NAME: &str = "prompts.json";
+
+/// Post a random prompt every time the chat is silent for `HEWPME_IDLE_CHAT_MIN` while
+/// the stream is live
+pub async fn run_idle_prompts(state: BotState) {
+    let prompts = JsonStore::<Vec<String>>::open(PROMPTS_FILE_NAME).to_vec();
+
+    if prompts.is_empty() {
+        tracing::debug!("no engagement prompts configured");
+        return;
+    }
+
+    let channel = config::get_channel_name();
+    let mut events = state.bus.subscribe();
+    let mut deadline = Instant::now() + config::get_idle_chat_period();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(BotEvent::ChatMessage { user_name, text, .. }) => {
+                    // the streamer is driving the chat with commands, do not interrupt
+                    let streamer = user_name.eq_ignore_ascii_case(&channel);
+                    let grace = if streamer && text.starts_with('!') {
+                        config::get_idle_command_grace()
+                    } else {
+                        Duration::ZERO
+                    };
+
+                    deadline = Instant::now() + config::get_idle_chat_period() + grace;
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            () = tokio::time::sleep_until(deadline) => {
+                // nobody is there to answer an offline channel
+                if !state.stream.is_live() {
+                    tracing::debug!("stream is offline, no engagement prompt");
+                } else if let Some(prompt) = prompts.choose(&mut rand::thread_rng()) {
+                    state.say(prompt.as_str());
+                }
+
+                deadline = Instant::now() + config::get_idle_chat_period();
+            }
+        }
+    }
+}
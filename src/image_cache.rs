@@ -0,0 +1,236 @@
+//! On-disk cache for images from the Twitch and 7TV CDNs.
+//!
+//! Overlays load images through `/cache/img?url=...`, so that refreshing an
+//! overlay does not hit the CDN every time and stale copies are served while
+//! the CDN is unavailable. Only images from the allowed hosts are fetched, also
+//! after a redirect, and an image larger than 5 MiB is refused.
+use std::io;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{config, metrics};
+
+const CACHE_DIR_NAME: &str = "image-cache";
+const DEFAULT_ALLOWED_HOSTS: [&str; 3] = ["static-cdn.jtvnw.net", "cdn.7tv.app", "7tv.io"];
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_IMAGE_SIZE: usize = 5 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct CachedImage {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntryMeta {
+    url: String,
+    content_type: String,
+    fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl,
+    HostNotAllowed(String),
+    Fetch(reqwest::Error),
+    TooLarge,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidUrl => write!(f, "invalid image URL"),
+            Self::HostNotAllowed(host) => write!(f, "host {host} is not allowed"),
+            Self::Fetch(e) => write!(f, "unable to fetch image: {e}"),
+            Self::TooLarge => write!(f, "image is larger than {MAX_IMAGE_SIZE} bytes"),
+        }
+    }
+}
+
+/// Get the image from the cache, downloading it if the cached copy is missing or expired
+pub async fn get(url: &str) -> Result<CachedImage, Error> {
+    let url = check_url(url)?;
+    let (data_path, meta_path) = entry_paths(url.as_str());
+    let cached = {
+        let (data_path, meta_path) = (data_path.clone(), meta_path.clone());
+
+        tokio::task::spawn_blocking(move || read_entry(&data_path, &meta_path))
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .filter(|(meta, _)| meta.url == url.as_str())
+    };
+
+    if let Some((ref meta, ref image)) = cached {
+        let age = Utc::now() - meta.fetched_at;
+
+        if age.to_std().unwrap_or_default() < config::get_image_cache_ttl() {
+            return Ok(image.clone());
+        }
+    }
+
+    match metrics::timed("image_cache_fetch", fetch(&url)).await {
+        Ok(image) => {
+            let stored = image.clone();
+            let stored_url = url.to_string();
+
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = write_entry(&data_path, &meta_path, &stored_url, &stored) {
+                    tracing::warn!("Unable to store {stored_url} in the image cache: {e}");
+                }
+            });
+
+            Ok(image)
+        }
+        Err(e) => match cached {
+            Some((_, image)) => {
+                tracing::warn!("Serving stale copy of {url}: {e}");
+                Ok(image)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// The URL if it is an HTTPS one to an allowed host
+fn check_url(url: &str) -> Result<Url, Error> {
+    let url = Url::parse(url).map_err(|_| Error::InvalidUrl)?;
+    let host = url.host_str().ok_or(Error::InvalidUrl)?;
+
+    if url.scheme() != "https" {
+        return Err(Error::InvalidUrl);
+    }
+
+    if !is_allowed_host(host) {
+        return Err(Error::HostNotAllowed(host.to_string()));
+    }
+
+    Ok(url)
+}
+
+fn is_allowed_host(host: &str) -> bool {
+    DEFAULT_ALLOWED_HOSTS
+        .iter()
+        .map(|allowed| (*allowed).to_string())
+        .chain(config::get_image_cache_hosts())
+        .any(|allowed| host == allowed || host.ends_with(&format!(".{allowed}")))
+}
+
+/// Files of the URL named by its SHA-256, stable across the builds of the bot
+fn entry_paths(url: &str) -> (PathBuf, PathBuf) {
+    let dir = config::get_app_directory_path().join(CACHE_DIR_NAME);
+    let name = digest::digest(&digest::SHA256, url.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    (
+        dir.join(format!("{name}.bin")),
+        dir.join(format!("{name}.json")),
+    )
+}
+
+fn read_entry(
+    data_path: &PathBuf,
+    meta_path: &PathBuf,
+) -> io::Result<(CacheEntryMeta, CachedImage)> {
+    let meta: CacheEntryMeta = serde_json::from_slice(&std::fs::read(meta_path)?)?;
+    let bytes = std::fs::read(data_path)?;
+    let image = CachedImage {
+        content_type: meta.content_type.clone(),
+        bytes,
+    };
+
+    Ok((meta, image))
+}
+
+fn write_entry(
+    data_path: &PathBuf,
+    meta_path: &PathBuf,
+    url: &str,
+    image: &CachedImage,
+) -> io::Result<()> {
+    if let Some(dir) = data_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let meta = CacheEntryMeta {
+        url: url.to_string(),
+        content_type: image.content_type.clone(),
+        fetched_at: Utc::now(),
+    };
+
+    std::fs::write(data_path, &image.bytes)?;
+    std::fs::write(meta_path, serde_json::to_vec(&meta)?)
+}
+
+/// Client following the redirects only to the allowed hosts
+fn client() -> reqwest::Result<&'static reqwest::Client> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let redirect = reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if let Err(e) = check_url(attempt.url().as_str()) {
+            attempt.error(e.to_string())
+        } else {
+            attempt.follow()
+        }
+    });
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(redirect)
+        .build()?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+async fn fetch(url: &Url) -> Result<CachedImage, Error> {
+    let mut response = client()
+        .map_err(Error::Fetch)?
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(Error::Fetch)?;
+
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_IMAGE_SIZE as u64)
+    {
+        return Err(Error::TooLarge);
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = response.chunk().await.map_err(Error::Fetch)? {
+        bytes.extend_from_slice(&chunk);
+
+        if bytes.len() > MAX_IMAGE_SIZE {
+            return Err(Error::TooLarge);
+        }
+    }
+
+    Ok(CachedImage {
+        content_type,
+        bytes,
+    })
+}
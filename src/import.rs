@@ -0,0 +1,551 @@
+//! Import of the viewer data exported from other chat bots.
+//!
+//! StreamElements and Nightbot exports are read as JSON, CSV files of any of the bots
+//! (e.g. Streamlabs Chatbot) are recognized by their column names. Points are stored in
+//! `points.json` by login name, commands in `custom_commands.json` and quotes in
+//! `quotes.json`, existing entries with the same name are replaced.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Formatter;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::Permission;
+use crate::custom_commands::{self, command_name, CustomCommand};
+use crate::storage::JsonStore;
+
+pub(crate) const POINTS_FILE_NAME: &str = "points.json";
+const QUOTES_FILE_NAME: &str = "quotes.json";
+/// StreamElements access level of the moderators
+const STREAMELEMENTS_MODERATOR_LEVEL: u32 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    StreamElements,
+    Streamlabs,
+    Nightbot,
+}
+
+impl FromStr for ImportSource {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "streamelements" => Ok(ImportSource::StreamElements),
+            "streamlabs" => Ok(ImportSource::Streamlabs),
+            "nightbot" => Ok(ImportSource::Nightbot),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub text: String,
+    pub author: Option<String>,
+    /// Date as written by the other bot
+    pub added: Option<String>,
+}
+
+/// Number of the imported entries of every kind
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub points: usize,
+    pub commands: usize,
+    pub quotes: usize,
+}
+
+impl core::fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} point balances, {} commands, {} quotes",
+            self.points, self.commands, self.quotes
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Imported {
+    points: Vec<(String, u64)>,
+    commands: Vec<CustomCommand>,
+    quotes: Vec<Quote>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StreamElementsExport {
+    Points { users: Vec<StreamElementsPoints> },
+    Commands(Vec<StreamElementsCommand>),
+    Quotes(Vec<StreamElementsQuote>),
+}
+
+#[derive(Deserialize)]
+struct StreamElementsPoints {
+    username: String,
+    points: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamElementsCommand {
+    command: String,
+    reply: String,
+    #[serde(default)]
+    access_level: u32,
+    #[serde(default)]
+    cooldown: StreamElementsCooldown,
+}
+
+#[derive(Deserialize, Default)]
+struct StreamElementsCooldown {
+    #[serde(default)]
+    user: u64,
+    #[serde(default)]
+    global: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamElementsQuote {
+    message: String,
+    #[serde(default, alias = "user")]
+    username: Option<String>,
+    #[serde(default)]
+    created_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NightbotExport {
+    Wrapped { commands: Vec<NightbotCommand> },
+    Commands(Vec<NightbotCommand>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NightbotCommand {
+    name: String,
+    message: String,
+    #[serde(default)]
+    cool_down: u64,
+    #[serde(default)]
+    user_level: String,
+}
+
+/// Import the points, commands and quotes found in the exported `file`
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be read, is not in a known format
+/// or the imported data cannot be saved
+pub fn import_bot_data(source: ImportSource, file: &Path) -> io::Result<ImportSummary> {
+    let content = std::fs::read_to_string(file)?;
+    let is_csv = file
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let imported = if is_csv {
+        parse_csv(&content)?
+    } else {
+        parse_json(source, &content)?
+    };
+
+    if imported.points.is_empty() && imported.commands.is_empty() && imported.quotes.is_empty() {
+        return Err(invalid_data("no points, commands or quotes found"));
+    }
+
+    store(imported)
+}
+
+fn parse_json(source: ImportSource, content: &str) -> io::Result<Imported> {
+    let mut imported = Imported::default();
+
+    match source {
+        ImportSource::StreamElements => match serde_json::from_str(content)? {
+            StreamElementsExport::Points { users } => {
+                imported.points = users
+                    .into_iter()
+                    .map(|user| (user.username, user.points.max(0).unsigned_abs()))
+                    .collect();
+            }
+            StreamElementsExport::Commands(commands) => {
+                imported.commands = commands
+                    .into_iter()
+                    .map(|command| CustomCommand {
+                        name: command.command,
+                        response: command.reply,
+                        permission: if command.access_level >= STREAMELEMENTS_MODERATOR_LEVEL {
+                            Permission::Moderator
+                        } else {
+                            Permission::Everyone
+                        },
+                        cooldown_sec: cooldown(command.cooldown.user.max(command.cooldown.global)),
+                    })
+                    .collect();
+            }
+            StreamElementsExport::Quotes(quotes) => {
+                imported.quotes = quotes
+                    .into_iter()
+                    .map(|quote| Quote {
+                        text: quote.message,
+                        author: quote.username,
+                        added: quote.created_at,
+                    })
+                    .collect();
+            }
+        },
+        ImportSource::Nightbot => {
+            let (NightbotExport::Wrapped { commands } | NightbotExport::Commands(commands)) =
+                serde_json::from_str(content)?;
+
+            imported.commands = commands
+                .into_iter()
+                .map(|command| CustomCommand {
+                    name: command.name,
+                    response: command.message,
+                    permission: permission(&command.user_level),
+                    cooldown_sec: cooldown(command.cool_down),
+                })
+                .collect();
+        }
+        ImportSource::Streamlabs => {
+            return Err(invalid_data(
+                "Streamlabs data is imported from the CSV exports",
+            ));
+        }
+    }
+
+    Ok(imported)
+}
+
+/// Read the rows by the column names, a file holds either points, commands or quotes
+fn parse_csv(content: &str) -> io::Result<Imported> {
+    let mut lines = content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .filter(|line| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .map(split_csv_line)
+        .ok_or_else(|| invalid_data("the CSV file is empty"))?;
+    let columns = header
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| (name.trim().to_lowercase(), idx))
+        .collect::<HashMap<_, _>>();
+    let column = |names: &[&str]| names.iter().find_map(|name| columns.get(*name).copied());
+    let user = column(&["username", "name", "user", "viewer"]);
+    let points = column(&["points", "currency"]);
+    let command = column(&["command", "trigger"]);
+    let response = column(&["response", "reply", "message"]);
+    let level = column(&["permission", "userlevel", "accesslevel"]);
+    let command_cooldown = column(&["cooldown", "user cooldown"]);
+    let quote = column(&["quote", "text"]).or(response);
+    let added = column(&["date", "createdat", "added"]);
+    let mut imported = Imported::default();
+
+    for row in lines.map(split_csv_line) {
+        let field = |idx: Option<usize>| {
+            idx.and_then(|idx| row.get(idx))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        if let (Some(user), Some(points)) = (field(user), points) {
+            let points = field(Some(points))
+                .and_then(|points| points.parse::<f64>().ok())
+                .ok_or_else(|| invalid_data(format!("invalid points of {user}")))?;
+
+            // points are whole numbers in the exports, the cast only drops a fraction
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            imported
+                .points
+                .push((user.to_string(), points.max(0.0) as u64));
+        } else if let (Some(name), Some(text)) = (field(command), field(response)) {
+            imported.commands.push(CustomCommand {
+                name: name.to_string(),
+                response: text.to_string(),
+                permission: field(level).map_or(Permission::Everyone, permission),
+                cooldown_sec: field(command_cooldown)
+                    .and_then(|value| value.parse().ok())
+                    .and_then(cooldown),
+            });
+        } else if let Some(text) = field(quote) {
+            imported.quotes.push(Quote {
+                text: text.to_string(),
+                author: field(user).map(ToString::to_string),
+                added: field(added).map(ToString::to_string),
+            });
+        }
+    }
+
+    Ok(imported)
+}
+
+/// Fields of a CSV line, quoted fields may contain commas and doubled quotes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    fields.push(field);
+    fields
+}
+
+/// Moderator-only if the level of the other bot is above the regular viewers
+fn permission(level: &str) -> Permission {
+    let level = level.to_lowercase();
+
+    if let Ok(level) = level.parse::<u32>() {
+        return if level >= STREAMELEMENTS_MODERATOR_LEVEL {
+            Permission::Moderator
+        } else {
+            Permission::Everyone
+        };
+    }
+
+    if ["mod", "owner", "streamer", "broadcaster", "admin", "editor"]
+        .iter()
+        .any(|privileged| level.contains(privileged))
+    {
+        Permission::Moderator
+    } else {
+        Permission::Everyone
+    }
+}
+
+fn cooldown(seconds: u64) -> Option<u64> {
+    (seconds > 0).then_some(seconds)
+}
+
+fn store(imported: Imported) -> io::Result<ImportSummary> {
+    let summary = ImportSummary {
+        points: imported.points.len(),
+        commands: imported.commands.len(),
+        quotes: imported.quotes.len(),
+    };
+
+    if !imported.points.is_empty() {
+        let mut points = JsonStore::<BTreeMap<String, u64>>::open(POINTS_FILE_NAME);
+
+        points.try_update(|points| {
+            points.extend(
+                imported
+                    .points
+                    .into_iter()
+                    .map(|(user, balance)| (user.to_lowercase(), balance)),
+            );
+        })?;
+    }
+
+    if !imported.commands.is_empty() {
+        let mut commands = custom_commands::open();
+
+        commands.try_update(|commands| {
+            for mut command in imported.commands {
+                command.name = command_name(&command.name);
+                commands.insert(command.name.clone(), command);
+            }
+        })?;
+        custom_commands::invalidate();
+    }
+
+    if !imported.quotes.is_empty() {
+        let mut quotes = JsonStore::<Vec<Quote>>::open(QUOTES_FILE_NAME);
+
+        quotes.try_update(|quotes| {
+            for quote in imported.quotes {
+                if !quotes.iter().any(|known| known.text == quote.text) {
+                    quotes.push(quote);
+                }
+            }
+        })?;
+    }
+
+    Ok(summary)
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Export of the other bot from `tests/fixtures/import`
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_str!(concat!("../tests/fixtures/import/", $name))
+        };
+    }
+
+    fn command(
+        name: &str,
+        response: &str,
+        permission: Permission,
+        cooldown_sec: Option<u64>,
+    ) -> CustomCommand {
+        CustomCommand {
+            name: name.to_string(),
+            response: response.to_string(),
+            permission,
+            cooldown_sec,
+        }
+    }
+
+    #[test]
+    fn streamelements_exports_are_recognized_by_their_shape() {
+        let points = parse_json(
+            ImportSource::StreamElements,
+            fixture!("streamelements_points.json"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            points.points,
+            vec![
+                (String::from("viewer_one"), 1500),
+                (String::from("Viewer_Two"), 0)
+            ]
+        );
+
+        let commands = parse_json(
+            ImportSource::StreamElements,
+            fixture!("streamelements_commands.json"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            commands.commands,
+            vec![
+                command(
+                    "discord",
+                    "Join us at https://discord.gg/example",
+                    Permission::Everyone,
+                    Some(15)
+                ),
+                command("reset", "Counter is reset", Permission::Moderator, None),
+            ]
+        );
+
+        let quotes = parse_json(
+            ImportSource::StreamElements,
+            fixture!("streamelements_quotes.json"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            quotes.quotes,
+            vec![
+                Quote {
+                    text: String::from("That was not a bug, it was a feature"),
+                    author: Some(String::from("streamer")),
+                    added: Some(String::from("2023-05-01T18:00:00.000Z")),
+                },
+                Quote {
+                    text: String::from("GG"),
+                    author: None,
+                    added: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nightbot_commands_keep_the_user_level_and_cooldown() {
+        let imported =
+            parse_json(ImportSource::Nightbot, fixture!("nightbot_commands.json")).unwrap();
+
+        assert_eq!(
+            imported.commands,
+            vec![
+                command(
+                    "!socials",
+                    "Follow on Twitter",
+                    Permission::Everyone,
+                    Some(30)
+                ),
+                command(
+                    "!title",
+                    "$(twitch $(channel) \"{{title}}\")",
+                    Permission::Moderator,
+                    None
+                ),
+            ]
+        );
+        assert!(imported.points.is_empty() && imported.quotes.is_empty());
+    }
+
+    #[test]
+    fn csv_exports_are_read_by_the_column_names() {
+        let points = parse_csv(fixture!("streamlabs_points.csv")).unwrap();
+
+        assert_eq!(
+            points.points,
+            vec![
+                (String::from("viewer_one"), 1500),
+                (String::from("viewer, two"), 20)
+            ]
+        );
+
+        let commands = parse_csv(fixture!("streamlabs_commands.csv")).unwrap();
+
+        assert_eq!(
+            commands.commands,
+            vec![
+                command("!hug", "$user hugs \"$target\"", Permission::Everyone, None),
+                command("!so", "Go follow $target", Permission::Moderator, Some(5)),
+            ]
+        );
+
+        let quotes = parse_csv(fixture!("streamlabs_quotes.csv")).unwrap();
+
+        assert_eq!(
+            quotes.quotes,
+            vec![Quote {
+                text: String::from("Chat, please behave"),
+                author: None,
+                added: Some(String::from("01.05.2023")),
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_exports_are_rejected() {
+        let errors = [
+            parse_json(ImportSource::StreamElements, fixture!("malformed.json")).unwrap_err(),
+            parse_json(
+                ImportSource::Nightbot,
+                fixture!("streamelements_points.json"),
+            )
+            .unwrap_err(),
+            parse_json(
+                ImportSource::Streamlabs,
+                fixture!("streamelements_points.json"),
+            )
+            .unwrap_err(),
+            parse_csv(fixture!("malformed_points.csv")).unwrap_err(),
+            parse_csv("\n\n").unwrap_err(),
+        ];
+
+        for error in errors {
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{error}");
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Guard against several bots running for the same channel.
+//!
+//! The first instance locks `<app dir>/<channel>.lock`, another one started for the
+//! channel stops with an error. With `HEWPME_FOLLOWER` it runs as a follower instead:
+//! it collects the chat and EventSub data, but does not reply, moderate, write the
+//! stored data or serve the web pages, and takes over once the leader stops.
+use std::fmt::Formatter;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use crate::{config, storage};
+
+const TAKEOVER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum InstanceError {
+    AlreadyRunning { channel: String, pid: Option<u32> },
+    Io(io::Error),
+}
+
+impl core::fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstanceError::AlreadyRunning { channel, pid } => {
+                write!(
+                    f,
+                    "another bot is already running for the {channel} channel"
+                )?;
+
+                if let Some(pid) = pid {
+                    write!(f, " (pid {pid})")?;
+                }
+
+                write!(
+                    f,
+                    ", stop it or set HEWPME_FOLLOWER=true to run this one as a follower"
+                )
+            }
+            InstanceError::Io(e) => write!(f, "unable to lock the bot instance: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
+impl From<io::Error> for InstanceError {
+    fn from(value: io::Error) -> Self {
+        InstanceError::Io(value)
+    }
+}
+
+/// Role of this bot among the ones running for the channel
+pub struct Instance {
+    leader: AtomicBool,
+    promoted: Notify,
+    lock_path: Option<PathBuf>,
+    /// Locked file, kept open while the instance is the leader
+    lock: std::sync::Mutex<Option<File>>,
+}
+
+pub type SafeInstance = Arc<Instance>;
+
+impl Default for Instance {
+    /// Leader without a lock, e.g. for the tests
+    fn default() -> Self {
+        Instance {
+            leader: AtomicBool::new(true),
+            promoted: Notify::new(),
+            lock_path: None,
+            lock: std::sync::Mutex::new(None),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Instance {
+    /// Follower without a lock
+    pub(crate) fn follower() -> SafeInstance {
+        Arc::new(Instance {
+            leader: AtomicBool::new(false),
+            ..Instance::default()
+        })
+    }
+}
+
+impl Instance {
+    /// Whether the bot may talk to the chat and act on the channel
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::Relaxed)
+    }
+
+    /// Wait until the instance is the leader
+    pub async fn wait_leader(&self) {
+        let promoted = self.promoted.notified();
+
+        if !self.is_leader() {
+            promoted.await;
+        }
+    }
+
+    fn try_lock(&self) -> Result<bool, InstanceError> {
+        let Some(ref path) = self.lock_path else {
+            return Ok(true);
+        };
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        match file.try_lock() {
+            Ok(()) => {
+                file.set_len(0)?;
+                write!(file, "{}", std::process::id())?;
+                *self.lock.lock().unwrap() = Some(file);
+
+                Ok(true)
+            }
+            Err(TryLockError::WouldBlock) => Ok(false),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+/// Lock the channel, the instance is a follower if another bot holds the lock
+/// and the follower mode is enabled
+///
+/// # Errors
+///
+/// Will return `Err` if another bot runs for the channel and the follower mode is disabled,
+/// or the lock file cannot be opened
+pub fn acquire() -> Result<SafeInstance, InstanceError> {
+    let channel = config::get_channel_name();
+    let lock_path = config::get_app_directory_path().join(format!("{channel}.lock"));
+    let instance = Instance {
+        leader: AtomicBool::new(false),
+        lock_path: Some(lock_path.clone()),
+        ..Instance::default()
+    };
+
+    if instance.try_lock()? {
+        instance.leader.store(true, Ordering::Relaxed);
+    } else if config::get_follower_mode_enabled() {
+        tracing::warn!("another bot runs for the {channel} channel, following it");
+        storage::set_writable(false);
+    } else {
+        let pid = fs::read_to_string(&lock_path)
+            .ok()
+            .and_then(|pid| pid.trim().parse().ok());
+
+        return Err(InstanceError::AlreadyRunning { channel, pid });
+    }
+
+    Ok(Arc::new(instance))
+}
+
+/// Take over the channel once the leader stops
+pub async fn run_takeover(instance: SafeInstance) {
+    while !instance.is_leader() {
+        tokio::time::sleep(TAKEOVER_CHECK_INTERVAL).await;
+
+        match instance.try_lock() {
+            Ok(true) => {
+                tracing::info!("the leader has stopped, taking over the channel");
+                storage::set_writable(true);
+                instance.leader.store(true, Ordering::Relaxed);
+                instance.promoted.notify_waiters();
+            }
+            Ok(false) => (),
+            Err(e) => tracing::warn!("Unable to check the instance lock: {e}"),
+        }
+    }
+}
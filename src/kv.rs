@@ -0,0 +1,169 @@
+//! Small persistent key-value store shared by the plugins, the automation pipelines
+//! and the templates, e.g. the current run number.
+//!
+//! The values are strings kept in `kv.json`. The moderators use `!kv set <key> <value>`
+//! and `!kv get <key>`, the web server serves them on `/api/kv`. The chat messages and
+//! the automation actions read a value as `{kv:<key>}`, the credits templates as
+//! `{ kv.<key> }`.
+use std::collections::BTreeMap;
+use std::{fmt, io};
+
+use crate::storage::JsonStore;
+
+const FILE_NAME: &str = "kv.json";
+const REFERENCE_PREFIX: &str = "{kv:";
+const MAX_KEY_LEN: usize = 64;
+const MAX_VALUE_LEN: usize = 500;
+
+#[derive(Debug)]
+pub enum KvError {
+    /// Keys are up to 64 letters, digits, `_`, `-` and `.`
+    InvalidKey,
+    ValueTooLong,
+    /// The value to increment is not an integer
+    NotANumber,
+    Save(io::Error),
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::InvalidKey => write!(f, "invalid key"),
+            KvError::ValueTooLong => write!(f, "value is longer than {MAX_VALUE_LEN}"),
+            KvError::NotANumber => write!(f, "value is not a number"),
+            KvError::Save(e) => write!(f, "unable to save the store: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KvError {}
+
+#[must_use]
+pub fn get(key: &str) -> Option<String> {
+    open().get(key).cloned()
+}
+
+/// All the stored values by key
+#[must_use]
+pub fn all() -> BTreeMap<String, String> {
+    open().clone()
+}
+
+/// Store the value under the key
+///
+/// # Errors
+///
+/// Will return `Err` if the key or the value is not valid or the store cannot be saved
+pub fn set(key: &str, value: &str) -> Result<(), KvError> {
+    check_key(key)?;
+
+    if value.chars().count() > MAX_VALUE_LEN {
+        return Err(KvError::ValueTooLong);
+    }
+
+    open()
+        .try_update(|values| values.insert(key.to_string(), value.to_string()))
+        .map_err(KvError::Save)?;
+
+    Ok(())
+}
+
+/// Add `by` to the number stored under the key, a missing one starts from 0
+///
+/// # Errors
+///
+/// Will return `Err` if the key is not valid, the value is not an integer or the store
+/// cannot be saved
+pub fn increment(key: &str, by: i64) -> Result<i64, KvError> {
+    check_key(key)?;
+
+    open()
+        .try_update(|values| {
+            let value = match values.get(key) {
+                Some(value) => value.parse::<i64>().map_err(|_| KvError::NotANumber)?,
+                None => 0,
+            }
+            .saturating_add(by);
+
+            values.insert(key.to_string(), value.to_string());
+
+            Ok(value)
+        })
+        .map_err(KvError::Save)?
+}
+
+/// Remove the key, returns whether it was stored
+///
+/// # Errors
+///
+/// Will return `Err` if the store cannot be saved
+pub fn remove(key: &str) -> io::Result<bool> {
+    open().try_update(|values| values.remove(key).is_some())
+}
+
+/// Replace the `{kv:<key>}` references in `text` with the values, the missing ones with
+/// nothing
+#[must_use]
+pub fn expand(text: &str) -> String {
+    if !text.contains(REFERENCE_PREFIX) {
+        return text.to_string();
+    }
+
+    expand_with(text, &open())
+}
+
+fn expand_with(text: &str, values: &BTreeMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(REFERENCE_PREFIX) {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let key = &rest[start + REFERENCE_PREFIX.len()..start + end];
+
+        result.push_str(&rest[..start]);
+        result.push_str(values.get(key).map_or("", String::as_str));
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn check_key(key: &str) -> Result<(), KvError> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(KvError::InvalidKey)
+    }
+}
+
+fn open() -> JsonStore<BTreeMap<String, String>> {
+    JsonStore::open(FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_are_replaced_with_the_values() {
+        let values = BTreeMap::from([(String::from("run"), String::from("42"))]);
+
+        assert_eq!(
+            expand_with("Run #{kv:run}, best {kv:best}!", &values),
+            "Run #42, best !"
+        );
+        assert_eq!(expand_with("broken {kv:run", &values), "broken {kv:run");
+        assert!(check_key("run.number-2").is_ok());
+        assert!(check_key("two words").is_err());
+        assert!(check_key("").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Long-standing subscribers whose subscription ended.
+//!
+//! The months of the subscribers are learned from the resub notices in the chat and
+//! kept in `subscribers.json`. A `channel.subscription.end` of a subscriber of at least
+//! `HEWPME_SUB_LAPSE_MIN_MONTHS` is recorded in `lapses.json`, with
+//! `HEWPME_SUB_LAPSE_NOTIFY` the broadcaster is also told on the admin page and the
+//! Discord webhook, which gets the weekly report of the stats as well.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::helper::BotState;
+use crate::notifications::{self, NotificationKind};
+use crate::stats::WeeklyReport;
+use crate::storage::JsonStore;
+use crate::{config, i18n, redact};
+
+const SUBSCRIBERS_FILE_NAME: &str = "subscribers.json";
+const LAPSES_FILE_NAME: &str = "lapses.json";
+const REPORT_PERIOD_DAYS: i64 = 7;
+/// How often the weekly report is checked to be due
+const REPORT_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Lapses older than this are dropped from the store
+const LAPSES_KEPT_DAYS: i64 = 90;
+const DAYS_PER_MONTH: i64 = 30;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SubscriberRecord {
+    /// Cumulative months announced in the chat
+    months: u64,
+    /// When the bot first saw the subscription
+    since: DateTime<Utc>,
+}
+
+impl SubscriberRecord {
+    /// The announced months or the ones counted by the bot, whichever is more
+    fn months(&self, now: DateTime<Utc>) -> u64 {
+        let counted = (now - self.since).num_days() / DAYS_PER_MONTH;
+
+        self.months.max(u64::try_from(counted).unwrap_or_default())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lapse {
+    pub user_id: String,
+    pub user_name: String,
+    pub months: u64,
+    /// 1, 2 or 3
+    pub tier: u8,
+    pub is_gift: bool,
+    pub ended_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct LapseLog {
+    lapses: Vec<Lapse>,
+    last_report: Option<DateTime<Utc>>,
+}
+
+pub struct SubscriptionBook {
+    subscribers: Mutex<JsonStore<BTreeMap<String, SubscriberRecord>>>,
+    lapses: Mutex<JsonStore<LapseLog>>,
+}
+
+pub type SafeSubscriptionBook = Arc<SubscriptionBook>;
+
+impl SubscriptionBook {
+    /// Remember the subscription, `months` as announced in the chat if known
+    pub async fn record(&self, user_id: &str, months: Option<u64>) {
+        self.subscribers.lock().await.update(|subscribers| {
+            let record =
+                subscribers
+                    .entry(user_id.to_string())
+                    .or_insert_with(|| SubscriberRecord {
+                        months: 0,
+                        since: Utc::now(),
+                    });
+
+            if let Some(months) = months {
+                record.months = record.months.max(months);
+            }
+        });
+    }
+
+    /// Forget the ended subscription, returns the lapse if it was a long-standing one
+    pub async fn end(
+        &self,
+        user_id: &str,
+        user_name: &str,
+        tier: u8,
+        is_gift: bool,
+    ) -> Option<Lapse> {
+        let now = Utc::now();
+        let record = self
+            .subscribers
+            .lock()
+            .await
+            .update(|subscribers| subscribers.remove(user_id))?;
+        let months = record.months(now);
+
+        if months < config::get_sub_lapse_min_months() {
+            return None;
+        }
+
+        let lapse = Lapse {
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+            months,
+            tier,
+            is_gift,
+            ended_at: now,
+        };
+
+        self.lapses.lock().await.update(|log| {
+            log.lapses
+                .retain(|known| (now - known.ended_at).num_days() < LAPSES_KEPT_DAYS);
+            log.lapses.push(lapse.clone());
+        });
+
+        Some(lapse)
+    }
+
+    /// Recorded lapses, newest first
+    pub async fn lapses(&self) -> Vec<Lapse> {
+        let mut lapses = self.lapses.lock().await.lapses.clone();
+
+        lapses.reverse();
+        lapses
+    }
+
+    /// The report of the past week if a week has passed since the last one
+    async fn take_due_report(&self, now: DateTime<Utc>) -> Option<WeeklyReport> {
+        let mut log = self.lapses.lock().await;
+        let Some(last_report) = log.last_report else {
+            // the first report covers a full week from now on
+            log.update(|log| log.last_report = Some(now));
+            return None;
+        };
+
+        if (now - last_report).num_days() < REPORT_PERIOD_DAYS {
+            return None;
+        }
+
+        let report = WeeklyReport::new(last_report, now, &log.lapses);
+
+        log.update(|log| log.last_report = Some(now));
+
+        Some(report)
+    }
+
+    pub async fn forget(&self, user_id: &str) {
+        self.subscribers
+            .lock()
+            .await
+            .update(|subscribers| subscribers.remove(user_id));
+        self.lapses
+            .lock()
+            .await
+            .update(|log| log.lapses.retain(|lapse| lapse.user_id != user_id));
+    }
+}
+
+pub fn create_subscription_book() -> SafeSubscriptionBook {
+    Arc::new(SubscriptionBook {
+        subscribers: Mutex::new(JsonStore::open(SUBSCRIBERS_FILE_NAME)),
+        lapses: Mutex::new(JsonStore::open(LAPSES_FILE_NAME)),
+    })
+}
+
+/// Record the ended subscription and tell the broadcaster about a long-standing one
+pub async fn subscription_ended(
+    state: &BotState,
+    user_id: &str,
+    user_name: &str,
+    tier: u8,
+    is_gift: bool,
+) {
+    let Some(lapse) = state
+        .subscriptions
+        .end(user_id, user_name, tier, is_gift)
+        .await
+    else {
+        return;
+    };
+
+    tracing::info!(
+        "subscription of {} ended after {} months",
+        redact::Name(user_name),
+        lapse.months
+    );
+
+    if !config::get_sub_lapse_notify() {
+        return;
+    }
+
+    let text = i18n::render(
+        state.languages.channel(),
+        "lapse.notice",
+        &[
+            ("name", &user_name),
+            ("months", &lapse.months),
+            ("tier", &tier),
+        ],
+    );
+
+    state
+        .notifier
+        .notify(NotificationKind::Alert, "Subscription ended", &text)
+        .await;
+    notifications::post_webhook(&text).await;
+}
+
+/// Post the weekly report of the lapsed subscriptions to the Discord webhook
+pub async fn run_weekly_report(state: BotState) {
+    if !config::get_sub_lapse_notify() {
+        return;
+    }
+
+    loop {
+        if let Some(report) = state.subscriptions.take_due_report(Utc::now()).await {
+            notifications::post_webhook(&report.text(state.languages.channel())).await;
+        }
+
+        tokio::time::sleep(REPORT_CHECK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn months_are_the_announced_or_the_counted_ones() {
+        let now = Utc::now();
+        let record = SubscriberRecord {
+            months: 14,
+            since: now - TimeDelta::days(65),
+        };
+
+        assert_eq!(record.months(now), 14);
+        assert_eq!(
+            SubscriberRecord {
+                months: 0,
+                ..record
+            }
+            .months(now),
+            2
+        );
+    }
+}
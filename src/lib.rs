@@ -0,0 +1,97 @@
+//! Twitch chat bot with the EventSub client, the overlays and the web server.
+//!
+//! [`Bot`] runs all of them, the binary is a thin wrapper around it. The clients can
+//! also be embedded one by one: [`chat`] runs the IRC client, [`eventsub`] and
+//! [`websocket`] the EventSub listener, [`server`] the web server, all on the state
+//! of [`Bot::start`]. [`utils::token`] keeps the user tokens they need, [`events`]
+//! carries what happens on the channel between them.
+
+// the web server routes are a deeply nested warp filter type
+#![recursion_limit = "256"]
+
+pub use crate::admin_auth::secret as admin_secret;
+pub use crate::bot::{Bot, ChatCommand, Context};
+pub use crate::browser_sources::{browser_sources, BrowserSource};
+pub use crate::commands::Permission;
+pub use crate::events::{BotEvent, EventKind};
+pub use crate::helper::{BotState, ChatOutboxReceiver};
+pub use crate::import::{import_bot_data, ImportSource, ImportSummary};
+pub use crate::instance::InstanceError;
+pub use crate::sessions::import_session;
+pub use crate::storage::{export_settings, import_settings};
+
+mod activity;
+mod ad_breaks;
+mod admin_auth;
+mod alert_queue;
+mod automation;
+mod badges;
+mod birthdays;
+mod bot;
+mod breaks;
+mod browser_sources;
+mod celebration;
+mod channel_info;
+pub mod chat;
+mod chat_language;
+mod clips;
+mod commands;
+pub mod config;
+mod cooldown;
+mod copypasta;
+mod credit_messages;
+mod custom_commands;
+mod emotes;
+mod evasion;
+pub mod events;
+pub mod eventsub;
+mod health;
+mod helper;
+mod i18n;
+mod idle;
+mod image_cache;
+mod import;
+mod instance;
+pub mod kv;
+mod lapses;
+mod limits;
+mod logs;
+mod mentions;
+mod metrics;
+mod milestones;
+mod moderation;
+mod modlog;
+mod names;
+mod notes;
+mod notifications;
+mod outage;
+mod outbox;
+mod overlay;
+mod overlay_auth;
+mod poll;
+mod presence;
+mod privacy;
+mod protection;
+mod raid_train;
+mod raids;
+mod redact;
+mod rewards;
+mod safe_mode;
+mod scheduler;
+pub mod server;
+mod sessions;
+mod settings;
+mod shoutouts;
+mod similarity;
+mod startup;
+mod stats;
+mod storage;
+mod streaks;
+mod stream;
+mod text_files;
+pub mod transport;
+mod unfurl;
+mod users;
+pub mod utils;
+pub mod websocket;
+mod wheel;
@@ -0,0 +1,142 @@
+//! Caps of the in-memory stores that grow with the stream, e.g. during a 24h subathon.
+//!
+//! Entries pushed out of a full store are appended as JSON lines to
+//! `<app dir>/spill/<store>.jsonl`. The fill level of every store is exported on
+//! `/metrics` and a warning is logged once it reaches `HEWPME_STORE_WARN_PERCENT`.
+//! A follower instance drops the entries, the leader spills the same ones.
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+use crate::{config, metrics, storage};
+
+const SPILL_DIR_NAME: &str = "spill";
+/// Share of the capacity evicted at once, so that the spill file is not touched on every entry
+const EVICTION_DIVISOR: usize = 10;
+
+pub struct StoreLimit {
+    name: &'static str,
+    capacity: usize,
+    warn_at: usize,
+    warned: AtomicBool,
+}
+
+impl StoreLimit {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        let warn_at = capacity * config::get_store_warn_percent() / 100;
+
+        metrics::set_store_usage(name, 0, capacity);
+
+        StoreLimit {
+            name,
+            capacity,
+            warn_at,
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Number of the oldest entries to evict before one more is added, zero while there is room
+    #[must_use]
+    pub fn overflow(&self, len: usize) -> usize {
+        if len < self.capacity {
+            0
+        } else {
+            (len + 1 - self.capacity).max(self.capacity / EVICTION_DIVISOR)
+        }
+    }
+
+    /// Report the current number of entries
+    pub fn report(&self, len: usize) {
+        metrics::set_store_usage(self.name, len, self.capacity);
+
+        if len >= self.warn_at {
+            if !self.warned.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    "{} store holds {len} of {} entries, older ones are spilled to disk",
+                    self.name,
+                    self.capacity
+                );
+            }
+        } else {
+            self.warned.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Append the evicted entries to the spill file of the store
+    pub fn spill<T: Serialize>(&self, entries: impl IntoIterator<Item = T>) {
+        if !storage::is_writable() {
+            return;
+        }
+
+        if let Err(e) = self.write_spill(entries) {
+            tracing::error!("Unable to spill the {} store: {e}", self.name);
+        }
+    }
+
+    /// Remove the spilled entries matching `forget`, returns how many were removed
+    pub fn purge(&self, forget: impl Fn(&serde_json::Value) -> bool) -> usize {
+        if !storage::is_writable() {
+            return 0;
+        }
+
+        match self.rewrite_spill(forget) {
+            Ok(removed) => removed,
+            Err(e) => {
+                tracing::error!("Unable to purge the {} spill file: {e}", self.name);
+                0
+            }
+        }
+    }
+
+    fn rewrite_spill(&self, forget: impl Fn(&serde_json::Value) -> bool) -> io::Result<usize> {
+        let path = spill_directory_path().join(format!("{}.jsonl", self.name));
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut kept = String::with_capacity(content.len());
+        let mut removed = 0;
+
+        for line in content.lines() {
+            if serde_json::from_str(line).is_ok_and(|entry| forget(&entry)) {
+                removed += 1;
+            } else {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        if removed > 0 {
+            fs::write(&path, kept)?;
+        }
+
+        Ok(removed)
+    }
+
+    fn write_spill<T: Serialize>(&self, entries: impl IntoIterator<Item = T>) -> io::Result<()> {
+        let dir = spill_directory_path();
+
+        fs::create_dir_all(&dir)?;
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(format!("{}.jsonl", self.name)))?;
+        let mut writer = io::BufWriter::new(file);
+
+        for entry in entries {
+            serde_json::to_writer(&mut writer, &entry)?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()
+    }
+}
+
+fn spill_directory_path() -> PathBuf {
+    config::get_app_directory_path().join(SPILL_DIR_NAME)
+}
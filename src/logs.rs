@@ -0,0 +1,165 @@
+//! Recent log records of the bot for the `/admin/logs` page.
+//!
+//! The tracing layer keeps the latest records and passes the new ones to the
+//! `/ws/logs` WebSocket, a connection gets the kept records first. Both are filtered
+//! by the `level` query, e.g. `?level=warn` for the warnings and the errors.
+//! The layer keeps the records of `info` and above and skips the rest.
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+const KEPT_RECORDS: usize = 500;
+const LOG_BUS_CAPACITY: usize = 128;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl TryFrom<&Level> for LogLevel {
+    type Error = ();
+
+    /// Fails for the levels below `info`
+    fn try_from(level: &Level) -> Result<Self, ()> {
+        match *level {
+            Level::INFO => Ok(LogLevel::Info),
+            Level::WARN => Ok(LogLevel::Warn),
+            Level::ERROR => Ok(LogLevel::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LogRecord {
+    pub time: DateTime<Utc>,
+    pub level: LogLevel,
+    /// Module the record comes from
+    pub target: String,
+    /// Message followed by the other fields as `name=value`
+    pub message: String,
+}
+
+struct LogBuffer {
+    sender: broadcast::Sender<LogRecord>,
+    /// Latest records, oldest first
+    recent: Mutex<VecDeque<LogRecord>>,
+}
+
+fn buffer() -> &'static LogBuffer {
+    static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+
+    BUFFER.get_or_init(|| LogBuffer {
+        sender: broadcast::channel(LOG_BUS_CAPACITY).0,
+        recent: Mutex::new(VecDeque::with_capacity(KEPT_RECORDS)),
+    })
+}
+
+/// Tracing layer keeping the records for the admin page
+#[must_use]
+pub fn layer() -> LogLayer {
+    LogLayer
+}
+
+pub struct LogLayer;
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Ok(level) = LogLevel::try_from(event.metadata().level()) else {
+            return;
+        };
+        let mut visitor = MessageVisitor::default();
+
+        event.record(&mut visitor);
+        keep(LogRecord {
+            time: Utc::now(),
+            level,
+            target: event.metadata().target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // writing to a string does not fail, the message goes before the other fields
+        if field.name() == "message" {
+            let fields = std::mem::take(&mut self.message);
+            let _ = write!(self.message, "{value:?}{fields}");
+        } else {
+            let _ = write!(self.message, " {}={value:?}", field.name());
+        }
+    }
+}
+
+fn keep(record: LogRecord) {
+    let buffer = buffer();
+    let mut recent = buffer.recent.lock().unwrap();
+
+    if recent.len() == KEPT_RECORDS {
+        recent.pop_front();
+    }
+
+    recent.push_back(record.clone());
+    // sent under the lock so that a new connection does not get a record twice
+    let _ = buffer.sender.send(record);
+}
+
+/// Kept records of `level` and above, oldest first, and the receiver of the next ones
+///
+/// # Panics
+///
+/// Will panic if the records lock is poisoned
+pub fn subscribe(level: LogLevel) -> (Vec<LogRecord>, broadcast::Receiver<LogRecord>) {
+    let buffer = buffer();
+    let recent = buffer.recent.lock().unwrap();
+    let records = recent
+        .iter()
+        .filter(|record| record.level >= level)
+        .cloned()
+        .collect();
+
+    (records, buffer.sender.subscribe())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_are_filtered_by_level() {
+        for (level, message) in [(LogLevel::Info, "started"), (LogLevel::Warn, "lagged")] {
+            keep(LogRecord {
+                time: Utc::now(),
+                level,
+                target: String::from("hewpme::tests"),
+                message: message.to_string(),
+            });
+        }
+
+        let (records, _) = subscribe(LogLevel::Warn);
+
+        assert!(records.iter().all(|record| record.level >= LogLevel::Warn));
+        assert!(records.iter().any(|record| record.message == "lagged"));
+        assert!(LogLevel::Error > LogLevel::Info);
+        assert_eq!(LogLevel::try_from(&Level::WARN), Ok(LogLevel::Warn));
+        assert!(LogLevel::try_from(&Level::DEBUG).is_err());
+    }
+}
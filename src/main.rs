@@ -1,43 +1,150 @@
-use helper::create_new_chatters_list;
-
-use crate::chat::run_twitch_irc_client;
-use crate::eventsub::run_eventsub_client;
-use crate::helper::create_new_twitch_event_list;
-
-mod chat;
-pub mod config;
-mod eventsub;
-mod helper;
-mod server;
-mod utils;
-mod websocket;
-
-fn main() {
-    let chatters_list = create_new_chatters_list();
-    let events_list = create_new_twitch_event_list();
-    let events_list2 = events_list.clone();
-    let client_list = chatters_list.clone();
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap();
-    tracing_subscriber::fmt::init();
-
-    let webserver_handle = rt.spawn(async move {
-        server::run_server(chatters_list, events_list).await;
-    });
-    let eventsub_client_handler = rt.spawn(async move {
-        run_eventsub_client(events_list2).await;
-    });
-    let twitch_client_handler = rt.spawn(async move {
-        run_twitch_irc_client(client_list).await;
-    });
-
-    for handle in [
-        eventsub_client_handler,
-        twitch_client_handler,
-        webserver_handle,
-    ] {
-        rt.block_on(handle).unwrap();
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+
+use hewpme::{config, ImportSource};
+
+const USAGE: &str = "Usage: hewpme [run] [--profile <name>]
+       hewpme config export|import <dir> [--profile <name>]
+       hewpme import --from streamelements|streamlabs|nightbot <file> [--profile <name>]
+       hewpme session import <file> [--profile <name>]
+       hewpme overlay-urls [--profile <name>]
+       hewpme admin-url [--profile <name>]";
+
+enum Command {
+    Config { action: String, dir: String },
+    Import { source: ImportSource, file: String },
+    SessionImport { file: String },
+    OverlayUrls,
+    AdminUrl,
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("run") => {
+            args.next();
+            None
+        }
+        Some("config") => {
+            args.next();
+            match (args.next(), args.next()) {
+                (Some(action), Some(dir)) => Some(Command::Config { action, dir }),
+                _ => return usage(),
+            }
+        }
+        Some("import") => {
+            args.next();
+            match (args.next().as_deref(), args.next(), args.next()) {
+                (Some("--from"), Some(source), Some(file)) => match source.parse() {
+                    Ok(source) => Some(Command::Import { source, file }),
+                    Err(()) => return usage(),
+                },
+                _ => return usage(),
+            }
+        }
+        Some("session") => {
+            args.next();
+            match (args.next().as_deref(), args.next()) {
+                (Some("import"), Some(file)) => Some(Command::SessionImport { file }),
+                _ => return usage(),
+            }
+        }
+        Some("overlay-urls") => {
+            args.next();
+            Some(Command::OverlayUrls)
+        }
+        Some("admin-url") => {
+            args.next();
+            Some(Command::AdminUrl)
+        }
+        _ => None,
+    };
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--profile", Some(profile)) => config::set_profile(profile.as_str()),
+            _ => return usage(),
+        }
     }
+
+    match command {
+        None => {
+            if let Err(e) = config::validate() {
+                eprintln!("{e}");
+                return ExitCode::FAILURE;
+            }
+
+            hewpme::Bot::new().run();
+
+            ExitCode::SUCCESS
+        }
+        Some(Command::Config { action, dir }) => config_command(&action, &dir),
+        Some(Command::OverlayUrls) => {
+            for source in hewpme::browser_sources() {
+                println!(
+                    "{:<20} {}x{}\t{}",
+                    source.name, source.width, source.height, source.url
+                );
+            }
+
+            ExitCode::SUCCESS
+        }
+        Some(Command::AdminUrl) => {
+            println!(
+                "http://localhost:{}/admin?key={}",
+                config::get_server_port(),
+                hewpme::admin_secret()
+            );
+
+            ExitCode::SUCCESS
+        }
+        Some(Command::Import { source, file }) => {
+            match hewpme::import_bot_data(source, Path::new(&file)) {
+                Ok(summary) => {
+                    println!("imported: {summary}");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Unable to import {file}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some(Command::SessionImport { file }) => match hewpme::import_session(Path::new(&file)) {
+            Ok(id) => {
+                println!("imported session {id}");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Unable to import {file}: {e}");
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+fn config_command(action: &str, dir: &str) -> ExitCode {
+    let result = match action {
+        "export" => hewpme::export_settings(Path::new(dir)),
+        "import" => hewpme::import_settings(Path::new(dir)),
+        _ => return usage(),
+    };
+
+    match result {
+        Ok(files) => {
+            println!("{action}ed: {}", files.join(", "));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Unable to {action} settings: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+
+    ExitCode::FAILURE
 }
@@ -0,0 +1,146 @@
+//! Chat messages mentioning the watch words of the streamer, e.g. their name or the
+//! terms of the game.
+//!
+//! The words of `HEWPME_WATCH_WORDS` are matched as whole words ignoring the case,
+//! messages of the streamer are not. The recent mentions are listed on the private
+//! `/mentions` page, which needs the admin secret, rather than on an overlay. With
+//! `HEWPME_TOAST_MENTIONS` they are also shown as desktop toasts.
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::notifications::NotificationKind;
+use crate::{config, metrics};
+
+/// Mentions kept for the page, the oldest ones are dropped
+const MAX_MENTIONS: usize = 100;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Mention {
+    pub user_name: String,
+    pub text: String,
+    /// Watch words found in the message
+    pub words: Vec<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct MentionLog {
+    mentions: Mutex<VecDeque<Mention>>,
+}
+
+pub type SafeMentionLog = Arc<MentionLog>;
+
+impl MentionLog {
+    pub async fn add(&self, mention: Mention) {
+        let mut mentions = self.mentions.lock().await;
+
+        if mentions.len() == MAX_MENTIONS {
+            mentions.pop_front();
+        }
+
+        mentions.push_back(mention);
+    }
+
+    /// Mentions of the session, the latest first
+    pub async fn recent(&self) -> Vec<Mention> {
+        self.mentions.lock().await.iter().rev().cloned().collect()
+    }
+}
+
+pub fn create_mention_log() -> SafeMentionLog {
+    Arc::new(MentionLog::default())
+}
+
+/// Watch words the text has as whole words, a word may be a phrase
+fn find_words(text: &str, watch_words: &[String]) -> Vec<String> {
+    let text = text.to_lowercase();
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+
+    watch_words
+        .iter()
+        .filter(|word| {
+            let word = word.to_lowercase();
+
+            !word.is_empty()
+                && text.match_indices(&word).any(|(start, _)| {
+                    is_boundary(text[..start].chars().next_back())
+                        && is_boundary(text[start + word.len()..].chars().next())
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Log the chat messages mentioning `HEWPME_WATCH_WORDS`
+pub async fn run_mentions(state: BotState) {
+    let watch_words = config::get_watch_words();
+
+    if watch_words.is_empty() {
+        tracing::debug!("no watch words configured");
+        return;
+    }
+
+    let channel = config::get_channel_name();
+    let mut events = state.bus.subscribe();
+
+    loop {
+        match events.recv().await {
+            Ok(BotEvent::ChatMessage {
+                user_name, text, ..
+            }) if !user_name.eq_ignore_ascii_case(&channel) => {
+                let words = find_words(&text, &watch_words);
+
+                if words.is_empty() {
+                    continue;
+                }
+
+                metrics::increment("mention");
+                state
+                    .notifier
+                    .notify(
+                        NotificationKind::Mention,
+                        "Chat mention",
+                        &format!("{user_name}: {text}"),
+                    )
+                    .await;
+                state
+                    .mentions
+                    .add(Mention {
+                        user_name,
+                        text,
+                        words,
+                        received_at: Utc::now(),
+                    })
+                    .await;
+            }
+            Ok(_) => (),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("mentions lagged, {skipped} events skipped");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_words_are_matched_as_whole_words() {
+        let watch_words = ["Streamer", "boss fight", "ой"].map(String::from);
+
+        assert_eq!(
+            find_words("hey STREAMER, the boss fight is next", &watch_words),
+            ["Streamer", "boss fight"]
+        );
+        assert_eq!(find_words("Ой, всё", &watch_words), ["ой"]);
+        assert!(find_words("streamers and bossfights", &watch_words).is_empty());
+    }
+}
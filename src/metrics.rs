@@ -0,0 +1,281 @@
+//! Latency histograms for the slow paths of the bot, counters of notable incidents
+//! and the fill level of the bounded stores.
+//!
+//! Every timed section is recorded in a process-wide registry, so that the web
+//! server can expose the collected data in the Prometheus text format on `/metrics`
+//! and as a JSON summary on `/api/debug/timings`.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::Instrument;
+
+use crate::config;
+
+/// Histogram bucket upper bounds in milliseconds
+const BUCKETS_MS: [f64; 12] = [
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+#[derive(Default, Clone)]
+struct Histogram {
+    /// Number of observations per bucket, the last one is `+Inf`
+    buckets: [u64; BUCKETS_MS.len() + 1],
+    count: u64,
+    sum_ms: f64,
+    max_ms: f64,
+    /// Time and duration of the latest observation
+    latest: Option<(Instant, Duration)>,
+}
+
+impl Histogram {
+    fn observe(&mut self, value_ms: f64) {
+        let idx = BUCKETS_MS
+            .iter()
+            .position(|bound| value_ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+
+        self.buckets[idx] += 1;
+        self.count += 1;
+        self.sum_ms += value_ms;
+        self.max_ms = self.max_ms.max(value_ms);
+    }
+
+    /// Estimate the given quantile by the upper bound of the bucket it falls in
+    fn quantile(&self, q: f64) -> f64 {
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = (self.count as f64 * q).ceil() as u64;
+        let mut seen = 0;
+
+        for (idx, count) in self.buckets.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank.max(1) {
+                return BUCKETS_MS.get(idx).copied().unwrap_or(self.max_ms);
+            }
+        }
+
+        self.max_ms
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimingSummary {
+    pub name: &'static str,
+    pub count: u64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+fn registry() -> &'static Mutex<BTreeMap<&'static str, Histogram>> {
+    static REGISTRY: OnceLock<Mutex<BTreeMap<&'static str, Histogram>>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+fn counters() -> &'static Mutex<BTreeMap<&'static str, u64>> {
+    static COUNTERS: OnceLock<Mutex<BTreeMap<&'static str, u64>>> = OnceLock::new();
+
+    COUNTERS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+fn stores() -> &'static Mutex<BTreeMap<&'static str, (usize, usize)>> {
+    static STORES: OnceLock<Mutex<BTreeMap<&'static str, (usize, usize)>>> = OnceLock::new();
+
+    STORES.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Record the number of entries held by a bounded store and its capacity
+///
+/// # Panics
+///
+/// Will panic if the stores lock is poisoned
+pub fn set_store_usage(name: &'static str, entries: usize, capacity: usize) {
+    stores().lock().unwrap().insert(name, (entries, capacity));
+}
+
+/// Count one more occurrence of the `name` event
+///
+/// # Panics
+///
+/// Will panic if the counters lock is poisoned
+pub fn increment(name: &'static str) {
+    *counters().lock().unwrap().entry(name).or_default() += 1;
+}
+
+/// Record a single observation of the `name` section
+///
+/// # Panics
+///
+/// Will panic if the registry lock is poisoned
+pub fn observe(name: &'static str, elapsed: Duration) {
+    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+
+    let mut registry = registry().lock().unwrap();
+    let histogram = registry.entry(name).or_default();
+
+    histogram.observe(elapsed_ms);
+    histogram.latest = Some((Instant::now(), elapsed));
+    drop(registry);
+
+    if elapsed.as_millis() >= u128::from(config::get_slow_threshold_ms()) {
+        tracing::warn!("slow path: {name} took {elapsed_ms:.1} ms");
+    }
+}
+
+/// Run `fut` inside a tracing span named after the section and record how long it took
+pub async fn timed<F: Future>(name: &'static str, fut: F) -> F::Output {
+    let span = tracing::debug_span!("timed", section = name);
+    let start = Instant::now();
+    let output = fut.instrument(span).await;
+
+    observe(name, start.elapsed());
+
+    output
+}
+
+/// Synchronous counterpart of [`timed`]
+pub fn timed_sync<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    let _span = tracing::debug_span!("timed", section = name).entered();
+    let start = Instant::now();
+    let output = f();
+
+    observe(name, start.elapsed());
+
+    output
+}
+
+/// Name and duration of the latest observed section starting with `prefix`,
+/// e.g. `helix_` for the latest Helix call
+///
+/// # Panics
+///
+/// Will panic if the registry lock is poisoned
+#[must_use]
+pub fn latest(prefix: &str) -> Option<(&'static str, Duration)> {
+    registry()
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .filter_map(|(name, histogram)| Some((*name, histogram.latest?)))
+        .max_by_key(|(_, (at, _))| *at)
+        .map(|(name, (_, elapsed))| (name, elapsed))
+}
+
+/// # Panics
+///
+/// Will panic if the registry lock is poisoned
+#[must_use]
+pub fn timings() -> Vec<TimingSummary> {
+    let guard = registry().lock().unwrap();
+
+    guard
+        .iter()
+        .map(|(name, histogram)| {
+            #[allow(clippy::cast_precision_loss)]
+            let mean_ms = if histogram.count > 0 {
+                histogram.sum_ms / histogram.count as f64
+            } else {
+                0.0
+            };
+
+            TimingSummary {
+                name,
+                count: histogram.count,
+                mean_ms,
+                max_ms: histogram.max_ms,
+                p50_ms: histogram.quantile(0.5),
+                p95_ms: histogram.quantile(0.95),
+            }
+        })
+        .collect()
+}
+
+/// Render all histograms, counters and store gauges in the Prometheus text exposition format
+///
+/// # Panics
+///
+/// Will panic if one of the registry locks is poisoned
+#[must_use]
+pub fn render_prometheus() -> String {
+    let guard = registry().lock().unwrap();
+    let mut out = String::new();
+
+    if !guard.is_empty() {
+        out.push_str(
+            "# HELP hewpme_section_duration_seconds Time spent in instrumented sections\n",
+        );
+        out.push_str("# TYPE hewpme_section_duration_seconds histogram\n");
+    }
+
+    for (name, histogram) in guard.iter() {
+        let mut cumulative = 0;
+
+        for (idx, count) in histogram.buckets.iter().enumerate() {
+            cumulative += count;
+            let le = BUCKETS_MS.get(idx).map_or_else(
+                || String::from("+Inf"),
+                |bound| (bound / 1000.0).to_string(),
+            );
+
+            let _ = writeln!(
+                out,
+                "hewpme_section_duration_seconds_bucket{{section=\"{name}\",le=\"{le}\"}} {cumulative}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "hewpme_section_duration_seconds_sum{{section=\"{name}\"}} {}",
+            histogram.sum_ms / 1000.0
+        );
+        let _ = writeln!(
+            out,
+            "hewpme_section_duration_seconds_count{{section=\"{name}\"}} {}",
+            histogram.count
+        );
+    }
+
+    let counters = counters().lock().unwrap();
+
+    if !counters.is_empty() {
+        out.push_str("# HELP hewpme_events_total Number of notable incidents by kind\n");
+        out.push_str("# TYPE hewpme_events_total counter\n");
+    }
+
+    for (name, count) in counters.iter() {
+        let _ = writeln!(out, "hewpme_events_total{{event=\"{name}\"}} {count}");
+    }
+
+    let stores = stores().lock().unwrap();
+
+    if !stores.is_empty() {
+        out.push_str("# HELP hewpme_store_entries Entries held in memory by a bounded store\n");
+        out.push_str("# TYPE hewpme_store_entries gauge\n");
+
+        for (name, (entries, _)) in stores.iter() {
+            let _ = writeln!(out, "hewpme_store_entries{{store=\"{name}\"}} {entries}");
+        }
+
+        out.push_str(
+            "# HELP hewpme_store_capacity Entries a bounded store keeps before spilling\n",
+        );
+        out.push_str("# TYPE hewpme_store_capacity gauge\n");
+
+        for (name, (_, capacity)) in stores.iter() {
+            let _ = writeln!(out, "hewpme_store_capacity{{store=\"{name}\"}} {capacity}");
+        }
+    }
+
+    out
+}
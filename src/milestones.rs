@@ -0,0 +1,60 @@
+//! Celebration of the Nth unique chatter or follower of the session.
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::helper::BotState;
+use crate::i18n;
+use crate::overlay::{self, OverlayEvent};
+use crate::redact;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MilestoneKind {
+    Chatter,
+    Follower,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Milestone {
+    pub kind: MilestoneKind,
+    pub count: usize,
+    pub user_name: String,
+    pub reached_at: DateTime<Local>,
+}
+
+/// Celebrate if `user_name` is the `count`-th chatter or follower of the session
+pub async fn check(state: &BotState, kind: MilestoneKind, count: usize, user_name: &str) {
+    let milestones = match kind {
+        MilestoneKind::Chatter => config::get_chatter_milestones(),
+        MilestoneKind::Follower => config::get_follower_milestones(),
+    };
+
+    if !milestones.contains(&count) {
+        return;
+    }
+
+    let key = match kind {
+        MilestoneKind::Chatter => "milestone.chatter",
+        MilestoneKind::Follower => "milestone.follower",
+    };
+    let text = i18n::render(
+        state.languages.channel(),
+        key,
+        &[("name", &user_name), ("count", &count)],
+    );
+    let milestone = Milestone {
+        kind,
+        count,
+        user_name: user_name.to_string(),
+        reached_at: Local::now(),
+    };
+
+    tracing::info!(
+        "milestone reached: {kind:?} {count} by {}",
+        redact::Name(user_name)
+    );
+    state.stats.record_milestone(milestone.clone()).await;
+    state.say(text.as_str());
+    overlay::push(&state.overlay, OverlayEvent::Milestone { milestone, text });
+}
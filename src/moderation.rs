@@ -0,0 +1,503 @@
+/// Requires the following permissions:
+/// - moderator:manage:banned_users
+/// - moderator:manage:chat_messages
+/// - moderator:manage:chat_settings
+/// - moderator:manage:announcements
+/// - user:manage:whispers (the appeal messages)
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use twitch_api::helix::chat::{
+    AnnouncementColor, ChatSettings, UpdateChatSettingsBody, UpdateChatSettingsRequest,
+};
+use twitch_api::helix::HelixClient;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::i18n;
+use crate::notifications::NotificationKind;
+use crate::transport::create_api_client;
+use crate::{config, metrics, redact, safe_mode};
+
+/// Command flood alerts kept for the admin page
+const MAX_COMMAND_FLOOD_ALERTS: usize = 50;
+
+const CHANNEL_URL_PREFIX: &str = "https://twitch.tv/";
+
+/// Punishment of a rule breaker, the user is whispered the rules and the appeal link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanction {
+    Timeout { duration_sec: u32 },
+    Ban,
+}
+
+/// No moderation action is taken in safe mode, whatever path asked for it
+fn is_blocked(action: &str) -> bool {
+    let blocked = safe_mode::is_active();
+
+    if blocked {
+        tracing::warn!("{action} is skipped in safe mode");
+    }
+
+    blocked
+}
+
+// TODO: Add token passing
+/// Returns whether the user is timed out
+pub async fn timeout_user(user_id: &str, reason: &str, duration_sec: u32) -> bool {
+    ban_user(user_id, reason, Some(duration_sec)).await
+}
+
+/// Apply the sanction and whisper the user the appeal message if it is enabled
+pub async fn sanction(state: &BotState, user_id: &str, reason: &str, sanction: Sanction) {
+    let duration_sec = match sanction {
+        Sanction::Timeout { duration_sec } => Some(duration_sec),
+        Sanction::Ban => None,
+    };
+
+    if ban_user(user_id, reason, duration_sec).await {
+        whisper_appeal(state, user_id, reason, sanction).await;
+    }
+}
+
+/// Time out the user for `duration_sec` or ban them, returns whether it succeeded
+async fn ban_user(user_id: &str, reason: &str, duration_sec: Option<u32>) -> bool {
+    if is_blocked("ban") {
+        return false;
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to ban user {user_id} without the EventSub token");
+        return false;
+    };
+
+    match metrics::timed(
+        "helix_ban_user",
+        client.ban_user(
+            user_id,
+            reason,
+            duration_sec,
+            token.user_id.clone(),
+            token.user_id.clone(),
+            &token,
+        ),
+    )
+    .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Unable to ban user {user_id} for {duration_sec:?} s: {e}");
+            false
+        }
+    }
+}
+
+async fn whisper_appeal(state: &BotState, user_id: &str, reason: &str, sanction: Sanction) {
+    if !config::get_appeal_whisper_enabled()
+        || (sanction == Sanction::Ban && !config::get_appeal_whisper_on_ban())
+    {
+        return;
+    }
+
+    let channel = config::get_channel_name();
+    let url =
+        config::get_appeal_url().unwrap_or_else(|| format!("{CHANNEL_URL_PREFIX}{channel}/about"));
+    let (key, template, duration_sec) = match sanction {
+        Sanction::Timeout { duration_sec } => (
+            "appeal.timeout",
+            config::get_appeal_timeout_message(),
+            duration_sec,
+        ),
+        Sanction::Ban => ("appeal.ban", config::get_appeal_ban_message(), 0),
+    };
+    let args: [(&str, &(dyn std::fmt::Display + Sync)); 4] = [
+        ("channel", &channel),
+        ("reason", &reason),
+        ("seconds", &duration_sec),
+        ("url", &url),
+    ];
+    let text = match template {
+        Some(template) => i18n::fill(&template, &args),
+        None => i18n::render(state.languages.for_user(user_id).await, key, &args),
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        return;
+    };
+
+    match metrics::timed(
+        "helix_send_whisper",
+        client.send_whisper(token.user_id.clone(), user_id, text.as_str(), &token),
+    )
+    .await
+    {
+        Ok(_) => metrics::increment("appeal_whisper"),
+        Err(e) => tracing::warn!("Unable to whisper the appeal message to {user_id}: {e}"),
+    }
+}
+
+pub async fn delete_message(message_id: &str) {
+    if is_blocked("message deletion") {
+        return;
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to delete a message without the EventSub token");
+        return;
+    };
+
+    if let Err(e) = metrics::timed(
+        "helix_delete_chat_message",
+        client.delete_chat_message(
+            token.user_id.clone(),
+            token.user_id.clone(),
+            message_id,
+            &token,
+        ),
+    )
+    .await
+    {
+        tracing::warn!("Unable to delete message {message_id}: {e}");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChatMode {
+    FollowersOnly,
+    SubscribersOnly,
+}
+
+impl ChatMode {
+    /// Whether the mode is turned on in the chat settings
+    #[must_use]
+    pub fn is_on(self, settings: &ChatSettings) -> bool {
+        match self {
+            ChatMode::FollowersOnly => settings.follower_mode,
+            ChatMode::SubscribersOnly => settings.subscriber_mode,
+        }
+    }
+}
+
+/// Current settings of the chat, `None` if they cannot be read
+pub async fn chat_settings() -> Option<ChatSettings> {
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to read chat settings without the EventSub token");
+        return None;
+    };
+
+    match metrics::timed(
+        "helix_get_chat_settings",
+        client.get_chat_settings(&token.user_id, Some(&*token.user_id), &token),
+    )
+    .await
+    {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            tracing::warn!("Unable to read the chat settings: {e}");
+            None
+        }
+    }
+}
+
+/// Turn the chat mode on or off, returns whether the settings were updated
+pub async fn set_chat_mode(mode: ChatMode, enabled: bool) -> bool {
+    if is_blocked("chat mode change") {
+        return false;
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to change chat settings without the EventSub token");
+        return false;
+    };
+    let request = UpdateChatSettingsRequest::new(token.user_id.clone(), token.user_id.clone());
+    let mut body = UpdateChatSettingsBody::default();
+
+    match mode {
+        ChatMode::FollowersOnly => body.follower_mode = Some(enabled),
+        ChatMode::SubscribersOnly => body.subscriber_mode = Some(enabled),
+    }
+
+    match metrics::timed(
+        "helix_update_chat_settings",
+        client.req_patch(request, body, &token),
+    )
+    .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Unable to set {mode:?} to {enabled}: {e}");
+            false
+        }
+    }
+}
+
+/// Turn the slow mode on with the wait between the messages or off with `None`,
+/// returns whether the settings were updated
+pub async fn set_slow_mode(wait_sec: Option<u64>) -> bool {
+    if is_blocked("slow mode change") {
+        return false;
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to change chat settings without the EventSub token");
+        return false;
+    };
+    let request = UpdateChatSettingsRequest::new(token.user_id.clone(), token.user_id.clone());
+    let mut body = UpdateChatSettingsBody::default();
+
+    body.slow_mode = Some(wait_sec.is_some());
+    body.slow_mode_wait_time = wait_sec;
+
+    match metrics::timed(
+        "helix_update_chat_settings",
+        client.req_patch(request, body, &token),
+    )
+    .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Unable to set the slow mode to {wait_sec:?}: {e}");
+            false
+        }
+    }
+}
+
+/// Turn the emote-only chat on or off, returns whether the settings were updated
+pub async fn set_emote_mode(enabled: bool) -> bool {
+    if is_blocked("emote-only mode change") {
+        return false;
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to change chat settings without the EventSub token");
+        return false;
+    };
+    let request = UpdateChatSettingsRequest::new(token.user_id.clone(), token.user_id.clone());
+    let mut body = UpdateChatSettingsBody::default();
+
+    body.emote_mode = Some(enabled);
+
+    match metrics::timed(
+        "helix_update_chat_settings",
+        client.req_patch(request, body, &token),
+    )
+    .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Unable to set the emote-only mode to {enabled}: {e}");
+            false
+        }
+    }
+}
+
+/// Highlight the message in the chat as an announcement, returns whether it was sent
+pub async fn send_announcement(message: &str, color: AnnouncementColor) -> bool {
+    let client = HelixClient::with_client(create_api_client());
+    let Some(token) = get_eventsub_token().await else {
+        tracing::warn!("Unable to send an announcement without the EventSub token");
+        return false;
+    };
+
+    match metrics::timed(
+        "helix_send_chat_announcement",
+        client.send_chat_announcement(
+            token.user_id.clone(),
+            token.user_id.clone(),
+            message,
+            color,
+            &token,
+        ),
+    )
+    .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Unable to send the announcement: {e}");
+            false
+        }
+    }
+}
+
+/// Thresholds of the command flood detection
+#[derive(Debug, Clone, Copy)]
+pub struct CommandRateLimits {
+    /// Commands a user may send within `window`
+    pub max_commands: usize,
+    pub window: Duration,
+    /// How long the commands of a flooding user are silently dropped
+    pub ignore_for: Duration,
+}
+
+impl CommandRateLimits {
+    #[must_use]
+    pub fn from_config() -> Self {
+        CommandRateLimits {
+            max_commands: config::get_command_flood_max(),
+            window: config::get_command_flood_window(),
+            ignore_for: config::get_command_flood_ignore(),
+        }
+    }
+}
+
+/// User caught sending commands at a bot-like rate
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandFloodAlert {
+    pub user_id: String,
+    pub user_name: String,
+    /// Commands sent within the window
+    pub commands: usize,
+    pub timestamp: DateTime<Local>,
+    pub ignored_until: DateTime<Local>,
+}
+
+#[derive(Debug)]
+pub enum CommandVerdict {
+    Allowed,
+    /// The user is shadow-ignored, the command must not be handled
+    Ignored,
+    /// The user has just crossed the limits and is ignored from now on
+    Flagged(CommandFloodAlert),
+}
+
+#[derive(Default)]
+struct CommandRates {
+    recent: HashMap<String, VecDeque<Instant>>,
+    ignored: HashMap<String, Instant>,
+    alerts: VecDeque<CommandFloodAlert>,
+}
+
+/// Tracks how often the viewers use commands and shadow-ignores the flooding ones
+pub struct CommandRateGuard {
+    limits: CommandRateLimits,
+    rates: Mutex<CommandRates>,
+}
+
+pub type SafeCommandRateGuard = Arc<CommandRateGuard>;
+
+impl CommandRateGuard {
+    #[must_use]
+    pub fn new(limits: CommandRateLimits) -> Self {
+        CommandRateGuard {
+            limits,
+            rates: Mutex::new(CommandRates::default()),
+        }
+    }
+
+    /// Record a command sent by the user
+    pub async fn check(&self, user_id: &str, user_name: &str) -> CommandVerdict {
+        let now = Instant::now();
+        let window = self.limits.window;
+        let mut rates = self.rates.lock().await;
+
+        rates.ignored.retain(|_, until| *until > now);
+        rates.recent.retain(|_, sent| {
+            sent.back()
+                .is_some_and(|last| now.duration_since(*last) < window)
+        });
+
+        if rates.ignored.contains_key(user_id) {
+            return CommandVerdict::Ignored;
+        }
+
+        let sent = rates.recent.entry(user_id.to_string()).or_default();
+
+        while sent
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= window)
+        {
+            sent.pop_front();
+        }
+        sent.push_back(now);
+
+        let commands = sent.len();
+
+        if commands <= self.limits.max_commands {
+            return CommandVerdict::Allowed;
+        }
+
+        rates.recent.remove(user_id);
+        rates
+            .ignored
+            .insert(user_id.to_string(), now + self.limits.ignore_for);
+
+        let timestamp = Local::now();
+        let alert = CommandFloodAlert {
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+            commands,
+            timestamp,
+            ignored_until: timestamp
+                + chrono::Duration::from_std(self.limits.ignore_for).unwrap_or_default(),
+        };
+
+        if rates.alerts.len() == MAX_COMMAND_FLOOD_ALERTS {
+            rates.alerts.pop_front();
+        }
+        rates.alerts.push_back(alert.clone());
+
+        CommandVerdict::Flagged(alert)
+    }
+
+    /// Whether the commands of the user are currently dropped
+    pub async fn is_ignored(&self, user_id: &str) -> bool {
+        self.rates
+            .lock()
+            .await
+            .ignored
+            .get(user_id)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    pub async fn forget(&self, user_id: &str) {
+        let mut rates = self.rates.lock().await;
+
+        rates.recent.remove(user_id);
+        rates.alerts.retain(|alert| alert.user_id != user_id);
+    }
+
+    /// Recent alerts, oldest first
+    pub async fn alerts(&self) -> Vec<CommandFloodAlert> {
+        self.rates.lock().await.alerts.iter().cloned().collect()
+    }
+}
+
+pub fn create_command_rate_guard() -> SafeCommandRateGuard {
+    Arc::new(CommandRateGuard::new(CommandRateLimits::from_config()))
+}
+
+/// Let the broadcaster know that a user is flooding commands, the chat is not told
+pub async fn alert_command_flood(state: &BotState, alert: &CommandFloodAlert) {
+    let limits = state.command_guard.limits;
+    let text = i18n::render(
+        state.languages.channel(),
+        "moderation.command_flood",
+        &[
+            ("name", &alert.user_name),
+            ("commands", &alert.commands),
+            ("seconds", &limits.window.as_secs()),
+            ("minutes", &limits.ignore_for.as_secs().div_ceil(60)),
+        ],
+    );
+
+    tracing::warn!(
+        "command flood by {}: {} commands",
+        redact::Name(&alert.user_name),
+        alert.commands
+    );
+    metrics::increment("command_flood");
+    state
+        .notifier
+        .notify(NotificationKind::Alert, "Command flood", text.as_str())
+        .await;
+}
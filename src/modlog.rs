@@ -0,0 +1,125 @@
+//! Recent chat messages with the markers that help moderators spot trolls.
+//!
+//! First-time chatters and accounts younger than `HEWPME_NEW_ACCOUNT_DAYS` are
+//! flagged as well as copy-pasta spam, suspected timeout evasion and the messages in a
+//! language with a rule. The log is served to the admin page on `/api/modlog`, the chat
+//! overlay gets it on `/api/chat/recent` with its token and the new messages pushed.
+//! Messages beyond `HEWPME_MODLOG_CAPACITY` are spilled to disk.
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::badges::ChatStyle;
+use crate::config;
+use crate::limits::StoreLimit;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatEntry {
+    pub timestamp: DateTime<Local>,
+    pub user_id: Arc<str>,
+    pub user_name: Arc<str>,
+    /// Name color and badges the user has in the chat
+    #[serde(flatten)]
+    pub style: ChatStyle,
+    pub text: String,
+    /// The first message of the user in the channel ever
+    pub first_message: bool,
+    /// Sent with the "Highlight My Message" channel point reward
+    pub highlighted: bool,
+    /// `None` if the account creation date is not known
+    pub account_age_days: Option<i64>,
+    pub new_account: bool,
+    /// Part of a copy-pasta wave
+    pub copypasta: bool,
+    /// Name of the timed out or banned user the message of the new account resembles
+    pub suspected_evasion: Option<Arc<str>>,
+    /// ISO 639-3 code, `None` if the message is too short to tell
+    pub language: Option<&'static str>,
+    /// A rule of `language_rules.json` applies to the language
+    pub language_rule: bool,
+}
+
+impl ChatEntry {
+    #[must_use]
+    pub fn is_flagged(&self) -> bool {
+        self.first_message
+            || self.new_account
+            || self.copypasta
+            || self.suspected_evasion.is_some()
+            || self.language_rule
+    }
+}
+
+pub struct ModLog {
+    entries: Mutex<VecDeque<ChatEntry>>,
+    limit: StoreLimit,
+}
+
+impl Default for ModLog {
+    fn default() -> Self {
+        ModLog {
+            entries: Mutex::new(VecDeque::new()),
+            limit: StoreLimit::new("modlog", config::get_modlog_capacity()),
+        }
+    }
+}
+
+pub type SafeModLog = Arc<ModLog>;
+
+impl ModLog {
+    pub async fn record(&self, entry: ChatEntry) {
+        let mut entries = self.entries.lock().await;
+        let overflow = self.limit.overflow(entries.len());
+
+        if overflow > 0 {
+            self.limit.spill(entries.drain(..overflow));
+        }
+
+        entries.push_back(entry);
+        self.limit.report(entries.len());
+    }
+
+    /// Logged messages, oldest first
+    pub async fn entries(&self, flagged_only: bool) -> Vec<ChatEntry> {
+        self.entries
+            .lock()
+            .await
+            .iter()
+            .filter(|entry| !flagged_only || entry.is_flagged())
+            .cloned()
+            .collect()
+    }
+
+    /// Logged messages of the user, oldest first
+    pub async fn entries_of(&self, user_id: &str) -> Vec<ChatEntry> {
+        self.entries
+            .lock()
+            .await
+            .iter()
+            .filter(|entry| &*entry.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Delete the messages of the user, the spilled ones included
+    pub async fn forget(&self, user_id: &str) -> usize {
+        let mut entries = self.entries.lock().await;
+        let len = entries.len();
+
+        entries.retain(|entry| &*entry.user_id != user_id);
+        self.limit.report(entries.len());
+
+        let spilled = self
+            .limit
+            .purge(|entry| entry.get("user_id").and_then(|id| id.as_str()) == Some(user_id));
+
+        len - entries.len() + spilled
+    }
+}
+
+pub fn create_modlog() -> SafeModLog {
+    Arc::new(ModLog::default())
+}
@@ -0,0 +1,37 @@
+//! Interned user names and IDs shared by the per-message bookkeeping.
+//!
+//! A chatter sends many messages, so the name is allocated once per session
+//! and every later message only bumps the reference count.
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+pub struct NameInterner {
+    names: Mutex<HashSet<Arc<str>>>,
+}
+
+pub type SafeNameInterner = Arc<NameInterner>;
+
+impl NameInterner {
+    /// Shared copy of `name`, allocated only the first time it is seen
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the interner lock is poisoned
+    pub fn intern(&self, name: &str) -> Arc<str> {
+        let mut guard = self.names.lock().unwrap();
+
+        if let Some(interned) = guard.get(name) {
+            return Arc::clone(interned);
+        }
+
+        let interned: Arc<str> = Arc::from(name);
+
+        guard.insert(Arc::clone(&interned));
+        interned
+    }
+}
+
+pub fn create_name_interner() -> SafeNameInterner {
+    Arc::new(NameInterner::default())
+}
@@ -0,0 +1,173 @@
+//! Moderator notes about users kept between streams.
+//!
+//! The notes are keyed by the user ID so they survive a rename and are deleted with the
+//! rest of the user data. Notes saved by the login before are moved under the ID once
+//! the user is looked up again.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use twitch_api::helix::HelixClient;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::metrics;
+use crate::storage::JsonStore;
+use crate::transport::create_api_client;
+
+const NOTES_FILE_NAME: &str = "user_notes.json";
+/// Notes keyed by the lowercase login
+const LEGACY_NOTES_FILE_NAME: &str = "notes.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Note {
+    pub author: String,
+    pub text: String,
+    pub created_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct NoteList {
+    /// Lowercase login the user had when the latest note was added
+    login: String,
+    /// Oldest first
+    notes: Vec<Note>,
+}
+
+pub struct UserNotes {
+    /// User ID to the notes
+    store: Mutex<JsonStore<HashMap<String, NoteList>>>,
+    legacy: Mutex<JsonStore<HashMap<String, Vec<Note>>>>,
+}
+
+pub type SafeUserNotes = Arc<UserNotes>;
+
+/// `@Name` and `name` refer to the same user
+fn normalize(user: &str) -> String {
+    user.trim_start_matches('@').to_lowercase()
+}
+
+/// User ID and lowercase login of `!note` and `!notes` argument, e.g. `@Viewer`
+///
+/// # Errors
+///
+/// Will return `Err` if the user is not found
+pub async fn resolve_user(state: &BotState, user: &str) -> Result<(String, String), String> {
+    let login = normalize(user);
+
+    if let Some(user_id) = state.users.id_of(login.as_str()).await {
+        return Ok((user_id, login));
+    }
+
+    let token = get_eventsub_token()
+        .await
+        .ok_or_else(|| String::from("no EventSub token"))?;
+    let client = HelixClient::with_client(create_api_client());
+    let user = metrics::timed(
+        "helix_get_user",
+        client.get_user_from_login(login.as_str(), &token),
+    )
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("no user {login}"))?;
+
+    Ok((user.id.take(), login))
+}
+
+impl UserNotes {
+    fn open() -> Self {
+        UserNotes {
+            store: Mutex::new(JsonStore::open(NOTES_FILE_NAME)),
+            legacy: Mutex::new(JsonStore::open(LEGACY_NOTES_FILE_NAME)),
+        }
+    }
+
+    pub async fn add(&self, user_id: &str, login: &str, author: &str, text: &str) {
+        let note = Note {
+            author: author.to_string(),
+            text: text.to_string(),
+            created_at: Local::now(),
+        };
+        let legacy = self.take_legacy(login).await;
+
+        self.store.lock().await.update(|notes| {
+            let list = notes.entry(user_id.to_string()).or_default();
+
+            list.login = normalize(login);
+            list.notes.splice(0..0, legacy);
+            list.notes.push(note);
+        });
+    }
+
+    /// Notes about the user, the ones saved by `login` before are moved under the ID
+    pub async fn for_user(&self, user_id: &str, login: Option<&str>) -> Vec<Note> {
+        let legacy = match login {
+            Some(login) => self.take_legacy(login).await,
+            None => Vec::new(),
+        };
+        let mut store = self.store.lock().await;
+
+        if !legacy.is_empty() {
+            store.update(|notes| {
+                let list = notes.entry(user_id.to_string()).or_default();
+
+                if let Some(login) = login {
+                    list.login = normalize(login);
+                }
+
+                list.notes.splice(0..0, legacy);
+            });
+        }
+
+        store
+            .get(user_id)
+            .map(|list| list.notes.clone())
+            .unwrap_or_default()
+    }
+
+    /// Notes by the login of the user, the not yet moved ones included
+    pub async fn all(&self) -> HashMap<String, Vec<Note>> {
+        let mut all = (**self.legacy.lock().await).clone();
+
+        for list in self.store.lock().await.values() {
+            all.entry(list.login.clone())
+                .or_default()
+                .extend(list.notes.iter().cloned());
+        }
+
+        all
+    }
+
+    /// Delete the notes about the user, returns how many there were
+    pub async fn forget(&self, user_id: &str, login: Option<&str>) -> usize {
+        let legacy = match login {
+            Some(login) => self.take_legacy(login).await.len(),
+            None => 0,
+        };
+
+        legacy
+            + self
+                .store
+                .lock()
+                .await
+                .update(|notes| notes.remove(user_id).map_or(0, |list| list.notes.len()))
+    }
+
+    /// Remove the notes saved by the login
+    async fn take_legacy(&self, login: &str) -> Vec<Note> {
+        let login = normalize(login);
+        let mut legacy = self.legacy.lock().await;
+
+        if !legacy.contains_key(&login) {
+            return Vec::new();
+        }
+
+        legacy.update(|notes| notes.remove(&login).unwrap_or_default())
+    }
+}
+
+pub fn create_user_notes() -> SafeUserNotes {
+    Arc::new(UserNotes::open())
+}
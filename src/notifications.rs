@@ -0,0 +1,130 @@
+//! Desktop notifications for the streaming PC and the Discord webhook.
+//!
+//! Toasts are only shown when the application is built with the
+//! `desktop-notifications` feature and enabled with `HEWPME_TOASTS`.
+use std::sync::Arc;
+
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::{config, metrics};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    Follow,
+    Subscribe,
+    Raid,
+    /// Chat message with a watch word, see [`crate::mentions`]
+    Mention,
+    /// Something the moderators should look at, always shown
+    Alert,
+}
+
+pub struct Notifier {
+    enabled: bool,
+    follows: bool,
+    subscriptions: bool,
+    raids: bool,
+    mentions: bool,
+    dnd_scenes: Vec<String>,
+    current_scene: Mutex<Option<String>>,
+}
+
+pub type SafeNotifier = Arc<Notifier>;
+
+impl Notifier {
+    #[must_use]
+    pub fn from_config() -> Self {
+        Notifier {
+            enabled: config::get_toasts_enabled(),
+            follows: config::get_toast_on_follow(),
+            subscriptions: config::get_toast_on_subscribe(),
+            raids: config::get_toast_on_raid(),
+            mentions: config::get_toast_on_mention(),
+            dnd_scenes: config::get_toast_dnd_scenes(),
+            current_scene: Mutex::new(None),
+        }
+    }
+
+    /// Remember the scene currently shown by the broadcasting software
+    pub async fn set_current_scene<T: Into<String>>(&self, scene: T) {
+        *self.current_scene.lock().await = Some(scene.into());
+    }
+
+    pub async fn notify(&self, kind: NotificationKind, summary: &str, body: &str) {
+        if !self.is_allowed(kind) {
+            return;
+        }
+
+        if let Some(ref scene) = *self.current_scene.lock().await {
+            if self
+                .dnd_scenes
+                .iter()
+                .any(|dnd| dnd.eq_ignore_ascii_case(scene))
+            {
+                tracing::debug!("do not disturb in scene {scene}, skip toast: {summary}");
+                return;
+            }
+        }
+
+        show_toast(summary.to_string(), body.to_string());
+    }
+
+    fn is_allowed(&self, kind: NotificationKind) -> bool {
+        self.enabled
+            && match kind {
+                NotificationKind::Follow => self.follows,
+                NotificationKind::Subscribe => self.subscriptions,
+                NotificationKind::Raid => self.raids,
+                NotificationKind::Mention => self.mentions,
+                NotificationKind::Alert => true,
+            }
+    }
+}
+
+pub fn create_notifier() -> SafeNotifier {
+    Arc::new(Notifier::from_config())
+}
+
+/// Post the message to `HEWPME_DISCORD_WEBHOOK_URL` if it is set
+pub async fn post_webhook(text: &str) {
+    let Some(url) = config::get_discord_webhook_url() else {
+        return;
+    };
+    let request = reqwest::Client::new()
+        .post(url)
+        .json(&json!({ "content": text }))
+        .send();
+
+    match metrics::timed("discord_webhook", request).await {
+        Ok(response) => {
+            if let Err(e) = response.error_for_status() {
+                tracing::warn!("Discord webhook rejected the message: {e}");
+            }
+        }
+        Err(e) => tracing::warn!("Unable to post to the Discord webhook: {e}"),
+    }
+}
+
+#[cfg(feature = "desktop-notifications")]
+fn show_toast(summary: String, body: String) {
+    // showing a notification talks to the notification daemon synchronously
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = notify_rust::Notification::new()
+            .appname("hewpme")
+            .summary(&summary)
+            .body(&body)
+            .show()
+        {
+            tracing::warn!("Unable to show desktop notification: {e}");
+        }
+    });
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+fn show_toast(summary: String, body: String) {
+    tracing::debug!(
+        "desktop notifications are not compiled in: {summary} {}",
+        crate::redact::Raw(&body)
+    );
+}
@@ -0,0 +1,242 @@
+//! Detection of the Twitch-wide outages, e.g. a maintenance window.
+//!
+//! The Helix responses with a 5xx status or without a response at all, the EventSub
+//! reconnects and the chat reconnects are counted per source. `HEWPME_OUTAGE_FAILURES`
+//! failures of a source within `HEWPME_OUTAGE_WINDOW_SEC` start an outage: the
+//! `twitch` health component is degraded, the overlays show a banner and the EventSub
+//! client retries every `HEWPME_OUTAGE_RETRY_SEC` without giving up.
+//!
+//! During the outage Helix is probed on the same interval, the outage ends when
+//! every source is back, e.g. on the first Helix response that is not a 5xx.
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::health::Status;
+use crate::helper::BotState;
+use crate::notifications::NotificationKind;
+use crate::overlay::{self, OverlayEvent};
+use crate::transport::{helix_url, HttpApi, ReqwestApi};
+use crate::{config, metrics};
+
+pub const HEALTH_COMPONENT: &str = "twitch";
+/// Answers without a token, any status but a 5xx means Helix is up
+const PROBE_PATH: &str = "users";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Helix,
+    EventSub,
+    Chat,
+}
+
+/// Failures of the sources within the window, the outage starts and ends with
+/// the failures of a source reaching the threshold and going below it
+struct Detector {
+    threshold: usize,
+    window: Duration,
+    failures: BTreeMap<Source, VecDeque<Instant>>,
+}
+
+impl Detector {
+    fn new(threshold: usize, window: Duration) -> Self {
+        Detector {
+            threshold,
+            window,
+            failures: BTreeMap::new(),
+        }
+    }
+
+    fn failure(&mut self, source: Source, now: Instant) {
+        let failures = self.failures.entry(source).or_default();
+
+        failures.push_back(now);
+
+        while failures
+            .front()
+            .is_some_and(|&failed| now.duration_since(failed) >= self.window)
+        {
+            failures.pop_front();
+        }
+    }
+
+    fn success(&mut self, source: Source) {
+        self.failures.remove(&source);
+    }
+
+    /// Sources failing often enough for an outage
+    fn failing(&mut self, now: Instant) -> Vec<Source> {
+        for failures in self.failures.values_mut() {
+            failures.retain(|&failed| now.duration_since(failed) < self.window);
+        }
+
+        self.failures
+            .iter()
+            .filter(|(_, failures)| failures.len() >= self.threshold.max(1))
+            .map(|(&source, _)| source)
+            .collect()
+    }
+}
+
+fn detector() -> &'static Mutex<Detector> {
+    static DETECTOR: OnceLock<Mutex<Detector>> = OnceLock::new();
+
+    DETECTOR.get_or_init(|| {
+        Mutex::new(Detector::new(
+            config::get_outage_failures(),
+            config::get_outage_window(),
+        ))
+    })
+}
+
+/// Failing sources of the ongoing outage, empty if there is none
+fn outage() -> &'static watch::Sender<Vec<Source>> {
+    static OUTAGE: OnceLock<watch::Sender<Vec<Source>>> = OnceLock::new();
+
+    OUTAGE.get_or_init(|| watch::channel(Vec::new()).0)
+}
+
+fn update(record: impl FnOnce(&mut Detector, Instant)) {
+    let now = Instant::now();
+    let failing = {
+        let mut detector = detector().lock().unwrap();
+
+        record(&mut detector, now);
+        detector.failing(now)
+    };
+
+    outage().send_if_modified(|current| {
+        let modified = *current != failing;
+
+        *current = failing;
+        modified
+    });
+}
+
+/// Count a failed request or a reconnect of the source
+pub fn record_failure(source: Source) {
+    update(|detector, now| detector.failure(source, now));
+}
+
+/// The source works again, its failures are forgotten
+pub fn record_success(source: Source) {
+    update(|detector, _| detector.success(source));
+}
+
+/// Count the Helix response, only the 5xx ones are failures
+pub fn record_helix_status(status: http::StatusCode) {
+    if status.is_server_error() {
+        record_failure(Source::Helix);
+    } else {
+        record_success(Source::Helix);
+    }
+}
+
+/// Whether Twitch is considered down
+#[must_use]
+pub fn is_active() -> bool {
+    !outage().borrow().is_empty()
+}
+
+/// Report the outages on the health page and the overlays and probe Helix during them
+pub async fn run_outage_monitor(state: BotState) {
+    let mut changes = outage().subscribe();
+
+    loop {
+        let failing = changes.borrow_and_update().clone();
+
+        if failing.is_empty() {
+            if state.health.status(HEALTH_COMPONENT) == Some(Status::Degraded) {
+                tracing::info!("Twitch is back");
+                overlay::push(&state.overlay, OverlayEvent::TwitchOutage { active: false });
+            }
+
+            state
+                .health
+                .set(HEALTH_COMPONENT, Status::Ok, None::<String>);
+
+            if changes.changed().await.is_err() {
+                return;
+            }
+
+            continue;
+        }
+
+        if state.health.status(HEALTH_COMPONENT) != Some(Status::Degraded) {
+            tracing::warn!("Twitch outage detected, failing: {failing:?}");
+            metrics::increment("twitch_outage");
+            overlay::push(&state.overlay, OverlayEvent::TwitchOutage { active: true });
+            state
+                .notifier
+                .notify(
+                    NotificationKind::Alert,
+                    "Twitch outage",
+                    "Twitch is not responding, the bot retries less often until it is back",
+                )
+                .await;
+        }
+
+        state.health.set(
+            HEALTH_COMPONENT,
+            Status::Degraded,
+            Some(format!("Twitch outage, failing: {failing:?}")),
+        );
+
+        tokio::select! {
+            changed = changes.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            () = tokio::time::sleep(config::get_outage_retry_delay()) => probe().await,
+        }
+    }
+}
+
+/// Send a request to Helix, its status is counted by the transport
+async fn probe() {
+    let request = http::Request::get(helix_url(PROBE_PATH).as_str())
+        .body(Vec::new())
+        .expect("Invalid probe request");
+
+    tracing::debug!("probing Helix");
+
+    if let Err(e) = metrics::timed("outage_probe", ReqwestApi::default().send(request)).await {
+        tracing::debug!("Helix is still down: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outage_needs_the_failures_within_the_window() {
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut detector = Detector::new(3, window);
+
+        detector.failure(Source::Helix, start);
+        detector.failure(Source::Helix, start + Duration::from_secs(10));
+        detector.failure(Source::Chat, start + Duration::from_secs(20));
+        assert!(detector.failing(start + Duration::from_secs(20)).is_empty());
+
+        detector.failure(Source::Helix, start + Duration::from_secs(30));
+        assert_eq!(
+            detector.failing(start + Duration::from_secs(30)),
+            [Source::Helix]
+        );
+        // the first failure is out of the window
+        assert!(detector.failing(start + window).is_empty());
+
+        detector.failure(Source::Helix, start + window);
+        assert_eq!(detector.failing(start + window), [Source::Helix]);
+
+        detector.success(Source::Helix);
+        assert!(detector.failing(start + window).is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! Chat messages that could not be sent, e.g. while the chat connection is down.
+//!
+//! The messages are kept in `outbox.json`, so that a thank-you for a subscription
+//! survives a reconnect or a restart, and sent again in order once the chat works.
+//! Messages older than `HEWPME_OUTBOX_MAX_AGE_SEC` are dropped instead, a late reply
+//! to a command is worse than none.
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::JsonStore;
+use crate::{metrics, redact};
+
+const OUTBOX_FILE_NAME: &str = "outbox.json";
+/// Messages kept at most, the oldest ones are dropped first
+const MAX_PENDING_MESSAGES: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingMessage {
+    pub text: String,
+    queued_at: DateTime<Utc>,
+}
+
+/// Keep the message to send it once the chat works again
+pub fn hold(text: String) {
+    let saved = JsonStore::<Vec<PendingMessage>>::open(OUTBOX_FILE_NAME).try_update(|pending| {
+        pending.push(PendingMessage {
+            text,
+            queued_at: Utc::now(),
+        });
+
+        let overflow = pending.len().saturating_sub(MAX_PENDING_MESSAGES);
+
+        pending.drain(..overflow);
+        pending.len()
+    });
+
+    match saved {
+        Ok(count) => tracing::info!("chat message is held, {count} waiting"),
+        Err(e) => tracing::warn!("Unable to hold the chat message: {e}"),
+    }
+}
+
+#[must_use]
+pub fn is_empty() -> bool {
+    JsonStore::<Vec<PendingMessage>>::open(OUTBOX_FILE_NAME).is_empty()
+}
+
+/// Take the held messages to send, oldest first, the stale ones are dropped
+#[must_use]
+pub fn take(max_age: Duration) -> Vec<PendingMessage> {
+    let pending = JsonStore::<Vec<PendingMessage>>::open(OUTBOX_FILE_NAME)
+        .update(std::mem::take::<Vec<PendingMessage>>);
+
+    fresh(pending, Utc::now(), max_age)
+}
+
+/// Put the messages that still could not be sent back before the held ones
+pub fn restore(messages: Vec<PendingMessage>) {
+    let saved = JsonStore::<Vec<PendingMessage>>::open(OUTBOX_FILE_NAME).try_update(|pending| {
+        pending.splice(..0, messages);
+    });
+
+    if let Err(e) = saved {
+        tracing::warn!("Unable to hold the chat messages: {e}");
+    }
+}
+
+fn fresh(
+    pending: Vec<PendingMessage>,
+    now: DateTime<Utc>,
+    max_age: Duration,
+) -> Vec<PendingMessage> {
+    pending
+        .into_iter()
+        .filter_map(|message| {
+            let age = (now - message.queued_at).to_std().unwrap_or_default();
+
+            if age <= max_age {
+                return Some(message);
+            }
+
+            tracing::info!(
+                "held chat message is stale after {} s, dropped: {}",
+                age.as_secs(),
+                redact::Raw(&message.text)
+            );
+            metrics::increment("outbox_stale");
+            None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn stale_messages_are_dropped() {
+        let now = Utc::now();
+        let message = |text: &str, age_sec: i64| PendingMessage {
+            text: text.to_string(),
+            queued_at: now - TimeDelta::seconds(age_sec),
+        };
+        let pending = vec![
+            message("stale", 600),
+            message("thank you", 120),
+            message("welcome", 5),
+        ];
+
+        assert_eq!(
+            fresh(pending, now, Duration::from_secs(300))
+                .into_iter()
+                .map(|message| message.text)
+                .collect::<Vec<_>>(),
+            ["thank you", "welcome"]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Events pushed to the browser overlays over the `/ws/overlay` WebSocket or the
+//! `/events/stream` server-sent events.
+//!
+//! The events are numbered, the latest ones are kept so that a reconnected event
+//! stream gets the events it missed after its `Last-Event-ID`.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::alert_queue::AlertKind;
+use crate::emotes::UnlockedEmote;
+use crate::milestones::Milestone;
+use crate::modlog::ChatEntry;
+use crate::poll::PollResults;
+use crate::unfurl::LinkPreview;
+use crate::wheel::Prize;
+
+const OVERLAY_BUS_CAPACITY: usize = 64;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OverlayEvent {
+    Announcement {
+        text: String,
+    },
+    PollUpdated {
+        results: PollResults,
+    },
+    PollEnded {
+        results: PollResults,
+    },
+    LurkersUpdated {
+        count: usize,
+    },
+    /// Session or total followers depending on `HEWPME_FOLLOWER_COUNT`
+    FollowersUpdated {
+        count: u64,
+    },
+    Milestone {
+        milestone: Milestone,
+        text: String,
+    },
+    /// Credits are shown for `seconds` after the stream went offline
+    CreditsRoll {
+        seconds: u64,
+    },
+    CreditsRollEnded,
+    /// Twitch is down, the overlays show a banner until it is back
+    TwitchOutage {
+        active: bool,
+    },
+    /// Ads run for `seconds`, `automatic` if Twitch started them on schedule
+    AdBreak {
+        seconds: u64,
+        automatic: bool,
+    },
+    /// Intro animation played when the stream starts
+    Intro {
+        text: String,
+    },
+    EmotesUnlocked {
+        emotes: Vec<UnlockedEmote>,
+        text: String,
+    },
+    ChatMessage(ChatEntry),
+    LinkPreview(LinkPreview),
+    /// A replayed alert comes again with the same `id`
+    Alert {
+        id: u64,
+        kind: AlertKind,
+        text: String,
+    },
+    AlertSkipped {
+        id: u64,
+    },
+    /// The wheel stops on the prize of `index` after `duration_ms`
+    WheelSpin {
+        user_name: String,
+        prizes: Vec<Prize>,
+        index: usize,
+        duration_ms: u64,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct SequencedEvent {
+    /// Grows by one with every event of the session
+    pub id: u64,
+    pub event: OverlayEvent,
+}
+
+pub struct OverlayBroadcast {
+    sender: broadcast::Sender<SequencedEvent>,
+    /// Latest events, oldest first
+    recent: Mutex<VecDeque<SequencedEvent>>,
+}
+
+pub type OverlayBus = Arc<OverlayBroadcast>;
+
+impl OverlayBroadcast {
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Kept events following the one with `last_id`, they are lost if it is too old
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the events lock is poisoned
+    pub fn since(&self, last_id: u64) -> Vec<SequencedEvent> {
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|sequenced| sequenced.id > last_id)
+            .cloned()
+            .collect()
+    }
+}
+
+pub fn create_overlay_bus() -> OverlayBus {
+    Arc::new(OverlayBroadcast {
+        sender: broadcast::channel(OVERLAY_BUS_CAPACITY).0,
+        recent: Mutex::new(VecDeque::with_capacity(OVERLAY_BUS_CAPACITY)),
+    })
+}
+
+/// Push the event to every connected overlay, it is fine to have none
+///
+/// # Panics
+///
+/// Will panic if the events lock is poisoned
+pub fn push(bus: &OverlayBus, event: OverlayEvent) {
+    let mut recent = bus.recent.lock().unwrap();
+    let sequenced = SequencedEvent {
+        id: recent.back().map_or(1, |last| last.id + 1),
+        event,
+    };
+
+    if recent.len() == OVERLAY_BUS_CAPACITY {
+        recent.pop_front();
+    }
+
+    recent.push_back(sequenced.clone());
+
+    // sent under the lock so that the subscribers see the events in the order of the ids
+    if bus.sender.send(sequenced).is_err() {
+        tracing::trace!("no overlay is connected");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed_events_are_kept_for_the_reconnected_streams() {
+        let bus = create_overlay_bus();
+
+        for count in 0..OVERLAY_BUS_CAPACITY + 2 {
+            push(&bus, OverlayEvent::LurkersUpdated { count });
+        }
+
+        let missed = bus.since(OVERLAY_BUS_CAPACITY as u64);
+
+        assert_eq!(
+            missed
+                .iter()
+                .map(|sequenced| sequenced.id)
+                .collect::<Vec<_>>(),
+            [
+                OVERLAY_BUS_CAPACITY as u64 + 1,
+                OVERLAY_BUS_CAPACITY as u64 + 2
+            ]
+        );
+        // the first events are no longer kept
+        assert_eq!(bus.since(0).len(), OVERLAY_BUS_CAPACITY);
+    }
+}
@@ -0,0 +1,143 @@
+//! Signed tokens of the overlay WebSocket.
+//!
+//! With `HEWPME_OVERLAY_AUTH` the browser source URLs of the overlays carry a `token`
+//! valid for `HEWPME_OVERLAY_TOKEN_TTL_HOURS`, the overlay pages pass it on to
+//! `/ws/overlay` which refuses the upgrade without a valid one. A token is the name of
+//! its source and its expiry time signed with the key of `overlay_key.json`, rotating
+//! the key on `/api/overlay/rotate` revokes all the issued tokens.
+use std::io;
+use std::sync::{OnceLock, RwLock};
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::Utc;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::storage::JsonStore;
+
+const KEY_FILE_NAME: &str = "overlay_key.json";
+const KEY_LEN: usize = 32;
+
+static KEY: OnceLock<RwLock<hmac::Key>> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct StoredKey {
+    /// Base64 of the signing key
+    key: String,
+}
+
+/// Token to add to the URLs of the overlay `source`, `None` if the overlays are not protected
+///
+/// # Panics
+///
+/// Will panic if the key lock is poisoned
+#[must_use]
+pub fn issue(source: &str) -> Option<String> {
+    config::get_overlay_auth().then(|| {
+        let ttl = i64::try_from(config::get_overlay_token_ttl().as_secs()).unwrap_or(i64::MAX);
+
+        sign(
+            &key().read().unwrap(),
+            source,
+            Utc::now().timestamp().saturating_add(ttl),
+        )
+    })
+}
+
+/// Whether the overlay WebSocket may be opened with `token`
+///
+/// # Panics
+///
+/// Will panic if the key lock is poisoned
+pub(crate) fn is_authorized(token: Option<&str>) -> bool {
+    !config::get_overlay_auth()
+        || token.is_some_and(|token| verify(&key().read().unwrap(), token, Utc::now().timestamp()))
+}
+
+/// Replace the signing key, the issued tokens stop working
+///
+/// # Errors
+///
+/// Will return `Err` if the new key cannot be saved, the old one is kept then
+///
+/// # Panics
+///
+/// Will panic if the key lock is poisoned
+pub(crate) fn rotate() -> io::Result<()> {
+    let bytes: [u8; KEY_LEN] = rand::random();
+
+    JsonStore::<StoredKey>::open(KEY_FILE_NAME)
+        .try_update(|stored| stored.key = STANDARD.encode(bytes))?;
+    *key().write().unwrap() = hmac::Key::new(hmac::HMAC_SHA256, &bytes);
+    tracing::info!("overlay key rotated");
+
+    Ok(())
+}
+
+fn key() -> &'static RwLock<hmac::Key> {
+    KEY.get_or_init(|| {
+        let mut stored = JsonStore::<StoredKey>::open(KEY_FILE_NAME);
+        let bytes = match STANDARD.decode(&stored.key) {
+            Ok(bytes) if bytes.len() == KEY_LEN => bytes,
+            _ => {
+                let bytes: [u8; KEY_LEN] = rand::random();
+
+                if let Err(e) = stored.try_update(|stored| stored.key = STANDARD.encode(bytes)) {
+                    tracing::warn!(
+                        "Unable to save the overlay key, tokens end with the session: {e}"
+                    );
+                }
+
+                bytes.to_vec()
+            }
+        };
+
+        RwLock::new(hmac::Key::new(hmac::HMAC_SHA256, &bytes))
+    })
+}
+
+/// `<source>.<expiry>.<signature>`, the expiry in seconds since the epoch
+fn sign(key: &hmac::Key, source: &str, expires_at: i64) -> String {
+    let payload = format!("{source}.{expires_at}");
+    let signature = hmac::sign(key, payload.as_bytes());
+
+    format!("{payload}.{}", URL_SAFE_NO_PAD.encode(signature))
+}
+
+fn verify(key: &hmac::Key, token: &str, now: i64) -> bool {
+    let Some((payload, signature)) = token.rsplit_once('.') else {
+        return false;
+    };
+    let Some((_, expires_at)) = payload.rsplit_once('.') else {
+        return false;
+    };
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+
+    expires_at
+        .parse::<i64>()
+        .is_ok_and(|expires_at| now < expires_at)
+        && hmac::verify(key, payload.as_bytes(), &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_checked_for_expiry_and_signature() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &[7; KEY_LEN]);
+        let other = hmac::Key::new(hmac::HMAC_SHA256, &[8; KEY_LEN]);
+        let token = sign(&key, "chat", 1_000);
+
+        assert!(verify(&key, &token, 999));
+        assert!(!verify(&key, &token, 1_000));
+        assert!(!verify(&other, &token, 999));
+        assert!(!verify(&key, &token.replacen("chat", "wheel", 1), 999));
+        assert!(!verify(&key, &token.replacen("1000", "2000", 1), 999));
+        assert!(!verify(&key, "chat.1000", 999));
+    }
+}
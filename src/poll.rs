@@ -0,0 +1,149 @@
+//! Chat polls for channels without access to Twitch polls.
+//!
+//! Viewers vote by typing the number or the text of an option, the last vote of
+//! a viewer counts.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+struct Poll {
+    question: String,
+    options: Vec<String>,
+    /// User ID to the index of the chosen option
+    votes: HashMap<String, usize>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OptionResult {
+    pub option: String,
+    pub votes: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PollResults {
+    pub question: String,
+    pub options: Vec<OptionResult>,
+    pub total: usize,
+}
+
+impl PollResults {
+    /// Options with the most votes, several in case of a tie
+    #[must_use]
+    pub fn winners(&self) -> Vec<&str> {
+        let max = self.options.iter().map(|option| option.votes).max();
+
+        match max {
+            Some(max) if max > 0 => self
+                .options
+                .iter()
+                .filter(|option| option.votes == max)
+                .map(|option| option.option.as_str())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Poll {
+    fn option_index(&self, text: &str) -> Option<usize> {
+        let text = text.trim();
+
+        if let Ok(number) = text.parse::<usize>() {
+            return (1..=self.options.len())
+                .contains(&number)
+                .then(|| number - 1);
+        }
+
+        let text = text.to_lowercase();
+
+        self.options
+            .iter()
+            .position(|option| option.to_lowercase() == text)
+    }
+
+    fn results(&self) -> PollResults {
+        let mut counts = vec![0; self.options.len()];
+
+        for idx in self.votes.values() {
+            counts[*idx] += 1;
+        }
+
+        PollResults {
+            question: self.question.clone(),
+            options: self
+                .options
+                .iter()
+                .zip(counts)
+                .map(|(option, votes)| OptionResult {
+                    option: option.clone(),
+                    votes,
+                })
+                .collect(),
+            total: self.votes.len(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PollManager {
+    current: Mutex<Option<Poll>>,
+}
+
+pub type SafePollManager = Arc<PollManager>;
+
+impl PollManager {
+    /// Start a new poll, returns `None` if there is a poll already running
+    pub async fn start(&self, question: String, options: Vec<String>) -> Option<PollResults> {
+        let mut guard = self.current.lock().await;
+
+        if guard.is_some() {
+            return None;
+        }
+
+        let poll = Poll {
+            question,
+            options,
+            votes: HashMap::new(),
+        };
+        let results = poll.results();
+        *guard = Some(poll);
+
+        Some(results)
+    }
+
+    /// Count the message as a vote, returns the updated results if it was one
+    pub async fn vote(&self, user_id: &str, text: &str) -> Option<PollResults> {
+        let mut guard = self.current.lock().await;
+        let poll = guard.as_mut()?;
+        let idx = poll.option_index(text)?;
+
+        if poll.votes.insert(user_id.to_string(), idx) == Some(idx) {
+            return None;
+        }
+
+        Some(poll.results())
+    }
+
+    pub async fn end(&self) -> Option<PollResults> {
+        self.current.lock().await.take().map(|poll| poll.results())
+    }
+}
+
+pub fn create_poll_manager() -> SafePollManager {
+    Arc::new(PollManager::default())
+}
+
+/// Parse `question | option 1 | option 2 ...`
+#[must_use]
+pub fn parse_poll_definition(text: &str) -> Option<(String, Vec<String>)> {
+    let mut parts = text
+        .split('|')
+        .map(str::trim)
+        .filter(|part| !part.is_empty());
+    let question = parts.next()?.to_string();
+    let options: Vec<String> = parts.map(String::from).collect();
+
+    (options.len() >= 2).then_some((question, options))
+}
@@ -0,0 +1,81 @@
+//! Viewers present in the chat without typing, polled from Helix Get Chatters.
+//!
+//! The chat only reveals the viewers who write, the connected ones are polled every
+//! `HEWPME_PRESENCE_POLL_MIN` if it is set and credited along with the chatters.
+//!
+//! Requires the following permissions:
+//! - moderator:read:chatters
+use twitch_api::helix::chat::GetChattersRequest;
+use twitch_api::helix::HelixClient;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::transport::create_api_client;
+use crate::{config, metrics};
+
+const GET_CHATTERS_PAGE_SIZE: usize = 1000;
+
+/// Add the connected viewers to the presence list of the session
+pub async fn run_presence_polling(state: BotState) {
+    let Some(interval) = config::get_presence_poll_interval() else {
+        tracing::debug!("chatters presence polling is disabled");
+        return;
+    };
+
+    loop {
+        if let Some(chatters) = fetch_chatters().await {
+            let mut presence = state.presence.lock().await;
+            let known = presence.len();
+
+            for (user_id, user_name) in chatters {
+                state.users.remember(&user_id, &user_name).await;
+                presence.insert(user_id, user_name);
+            }
+
+            tracing::debug!(
+                "{} chatters present, {} new",
+                presence.len(),
+                presence.len() - known
+            );
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// User IDs and names of the connected viewers, `None` if they are not available
+async fn fetch_chatters() -> Option<Vec<(String, String)>> {
+    let token = get_eventsub_token().await?;
+    let client = HelixClient::with_client(create_api_client());
+    let request =
+        GetChattersRequest::new(&token.user_id, &token.user_id).first(GET_CHATTERS_PAGE_SIZE);
+    let mut chatters = Vec::new();
+    let mut response =
+        match metrics::timed("helix_get_chatters", client.req_get(request, &token)).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Unable to get chatters: {e}");
+                return None;
+            }
+        };
+
+    loop {
+        chatters.extend(
+            response
+                .data
+                .iter()
+                .map(|chatter| (chatter.user_id.to_string(), chatter.user_name.to_string())),
+        );
+
+        match metrics::timed("helix_get_chatters", response.get_next(&client, &token)).await {
+            Ok(Some(next)) => response = next,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::warn!("Unable to get the next chatters page: {e}");
+                break;
+            }
+        }
+    }
+
+    Some(chatters)
+}
@@ -0,0 +1,119 @@
+//! Export and deletion of everything the bot stores about a viewer.
+//!
+//! Viewers delete their data with `!forgetme confirm`, the moderators export or delete
+//! it on `/api/privacy/export` and `/api/privacy/forget`. Stores keyed by the login,
+//! e.g. the points, are looked up by the name the user was last seen with.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::helper::BotState;
+use crate::i18n::Lang;
+use crate::import::POINTS_FILE_NAME;
+use crate::modlog::ChatEntry;
+use crate::notes::Note;
+use crate::storage::JsonStore;
+use crate::streaks::StreakRecord;
+use crate::{credit_messages, rewards, wheel};
+
+#[derive(Serialize, Debug)]
+pub struct UserData {
+    pub user_id: String,
+    pub login: Option<String>,
+    pub streak: Option<StreakRecord>,
+    /// `MM-DD`
+    pub birthday: Option<String>,
+    pub language: Option<Lang>,
+    pub points: Option<u64>,
+    pub notes: Vec<Note>,
+    pub messages: Vec<ChatEntry>,
+}
+
+/// What was deleted for the user
+#[derive(Serialize, Debug, Default)]
+pub struct ForgetReport {
+    pub streak: bool,
+    pub birthday: bool,
+    pub language: bool,
+    pub points: bool,
+    pub notes: usize,
+    pub messages: usize,
+    pub wheel_spins: usize,
+    pub refunds: usize,
+}
+
+/// Everything stored about the user
+pub async fn export(state: &BotState, user_id: &str, login: Option<&str>) -> UserData {
+    let login = resolve_login(state, user_id, login).await;
+    let notes = state.notes.for_user(user_id, login.as_deref()).await;
+    let points = login.as_ref().and_then(|login| {
+        JsonStore::<BTreeMap<String, u64>>::open(POINTS_FILE_NAME)
+            .get(login)
+            .copied()
+    });
+
+    UserData {
+        user_id: user_id.to_string(),
+        streak: state.streaks.get_streak(user_id).await,
+        birthday: state
+            .birthdays
+            .birthday_of(user_id)
+            .await
+            .map(|(month, day)| format!("{month:02}-{day:02}")),
+        language: state.languages.preference(user_id).await,
+        points,
+        notes,
+        messages: state.modlog.entries_of(user_id).await,
+        login,
+    }
+}
+
+/// Delete everything stored about the user, the session lists included
+pub async fn forget(state: &BotState, user_id: &str, login: Option<&str>) -> ForgetReport {
+    let login = resolve_login(state, user_id, login).await;
+    let mut report = ForgetReport {
+        streak: state.streaks.forget(user_id).await.is_some(),
+        birthday: state.birthdays.forget(user_id).await,
+        language: state.languages.forget(user_id).await,
+        messages: state.modlog.forget(user_id).await,
+        wheel_spins: wheel::forget(user_id),
+        refunds: rewards::forget(user_id),
+        notes: state.notes.forget(user_id, login.as_deref()).await,
+        ..ForgetReport::default()
+    };
+
+    state.activity.forget(user_id).await;
+    state.command_guard.forget(user_id).await;
+    state.lurkers.lock().await.remove(user_id);
+    state.presence.lock().await.remove(user_id);
+    state.session.forget(user_id).await;
+    state.subscriptions.forget(user_id).await;
+    state.alert_queue.forget(user_id).await;
+    state.evasion.forget(user_id).await;
+    credit_messages::forget(user_id);
+
+    if let Err(e) = state.storage.remove_user(user_id) {
+        tracing::error!("Unable to remove user {user_id} from the stored lists: {e}");
+    }
+
+    if let Some(ref login) = login {
+        report.points = JsonStore::<BTreeMap<String, u64>>::open(POINTS_FILE_NAME)
+            .update(|points| points.remove(login).is_some());
+    }
+
+    state.users.forget(user_id).await;
+    tracing::info!("forgot user {user_id}: {report:?}");
+
+    report
+}
+
+async fn resolve_login(state: &BotState, user_id: &str, login: Option<&str>) -> Option<String> {
+    match login {
+        Some(login) => Some(login.trim_start_matches('@').to_lowercase()),
+        None => state
+            .users
+            .name_of(user_id)
+            .await
+            .map(|name| name.to_lowercase()),
+    }
+}
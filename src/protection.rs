@@ -0,0 +1,156 @@
+//! Automatic chat restrictions against follow-bot waves and hostile raids.
+//!
+//! A wave of follows turns on the followers-only chat, a flood of messages
+//! right after a raid turns on the subscribers-only chat. Both are reverted
+//! after `HEWPME_PROTECTION_COOLDOWN_SEC`, a mode already turned on by a moderator
+//! is left as it is.
+use std::collections::{HashMap, VecDeque};
+use std::future;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+use crate::config;
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::i18n;
+use crate::moderation::{self, ChatMode};
+use crate::notifications::NotificationKind;
+
+/// Number of hits within the sliding window
+struct RateWindow {
+    window: Duration,
+    hits: VecDeque<Instant>,
+}
+
+impl RateWindow {
+    fn new(window: Duration) -> Self {
+        RateWindow {
+            window,
+            hits: VecDeque::new(),
+        }
+    }
+
+    fn hit(&mut self, now: Instant) -> usize {
+        while self
+            .hits
+            .front()
+            .is_some_and(|hit| now.duration_since(*hit) > self.window)
+        {
+            self.hits.pop_front();
+        }
+
+        self.hits.push_back(now);
+        self.hits.len()
+    }
+
+    fn clear(&mut self) {
+        self.hits.clear();
+    }
+}
+
+pub async fn run_protection(state: BotState) {
+    let raid_window = config::get_raid_window();
+    let mut follows = RateWindow::new(config::get_follow_wave_window());
+    let mut raid_messages = RateWindow::new(raid_window);
+    let mut raid_started: Option<Instant> = None;
+    let mut active: HashMap<ChatMode, Instant> = HashMap::new();
+    let mut events = state.bus.subscribe();
+
+    loop {
+        let next_revert = active
+            .iter()
+            .min_by_key(|(_, until)| **until)
+            .map(|(mode, until)| (*mode, *until));
+        let revert = async {
+            match next_revert {
+                Some((mode, until)) => {
+                    tokio::time::sleep_until(until).await;
+                    mode
+                }
+                None => future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            event = events.recv() => {
+                let now = Instant::now();
+                let after_raid = raid_started
+                    .is_some_and(|started| now.duration_since(started) <= raid_window);
+                let triggered = match event {
+                    Ok(BotEvent::Follow { .. }) => {
+                        let count = follows.hit(now);
+
+                        (count >= config::get_follow_wave_count()).then_some(ChatMode::FollowersOnly)
+                    }
+                    Ok(BotEvent::Raid { .. }) => {
+                        raid_started = Some(now);
+                        raid_messages.clear();
+                        None
+                    }
+                    Ok(BotEvent::ChatMessage { .. }) if after_raid => {
+                        let count = raid_messages.hit(now);
+
+                        (count >= config::get_raid_messages_count()).then_some(ChatMode::SubscribersOnly)
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => None,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if let Some(mode) = triggered
+                    .filter(|mode| !active.contains_key(mode))
+                    .filter(|_| state.instance.is_leader())
+                {
+                    if enable(mode).await {
+                        let cooldown = config::get_protection_cooldown();
+
+                        active.insert(mode, now + cooldown);
+                        alert(&state, mode, cooldown).await;
+                    }
+                }
+            },
+            mode = revert => {
+                active.remove(&mode);
+
+                if moderation::set_chat_mode(mode, false).await {
+                    state.say(i18n::render(state.languages.channel(), "protection.reverted", &[]));
+                }
+            }
+        }
+    }
+}
+
+/// Turn the mode on, returns whether the bot turned it on and has to revert it
+async fn enable(mode: ChatMode) -> bool {
+    let Some(settings) = moderation::chat_settings().await else {
+        tracing::warn!("Unable to read the chat settings, {mode:?} is not turned on");
+        return false;
+    };
+
+    if mode.is_on(&settings) {
+        tracing::info!("{mode:?} is already on, it is left to the moderators");
+        return false;
+    }
+
+    moderation::set_chat_mode(mode, true).await
+}
+
+async fn alert(state: &BotState, mode: ChatMode, cooldown: Duration) {
+    let key = match mode {
+        ChatMode::FollowersOnly => "protection.follow_wave",
+        ChatMode::SubscribersOnly => "protection.raid",
+    };
+    let text = i18n::render(
+        state.languages.channel(),
+        key,
+        &[("minutes", &cooldown.as_secs().div_ceil(60))],
+    );
+
+    tracing::warn!("{text}");
+    state
+        .notifier
+        .notify(NotificationKind::Alert, "Chat protection", text.as_str())
+        .await;
+    state.say(text);
+}
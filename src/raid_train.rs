@@ -0,0 +1,171 @@
+//! Raid trains, the participating channels raid each other one after another.
+//!
+//! The channels of `HEWPME_RAID_TRAIN` are in the order of the train. The next stop
+//! is the first live channel after the own one, `!next` names it and `!raidnext`
+//! starts the raid. With `HEWPME_RAID_TRAIN_AUTO` the raid is started when the stream
+//! goes offline. Starting a raid requires the channel:manage:raids permission.
+use twitch_api::helix::raids::StartARaidRequest;
+use twitch_api::helix::streams::GetStreamsRequest;
+use twitch_api::helix::HelixClient;
+use twitch_api::types::UserNameRef;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::transport::create_api_client;
+use crate::{config, i18n, metrics, redact};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stop {
+    pub user_id: String,
+    pub login: String,
+    pub user_name: String,
+}
+
+#[derive(Debug)]
+pub enum RaidTrainError {
+    /// `HEWPME_RAID_TRAIN` has no channels after the own one
+    NoTrain,
+    /// None of the next channels is live
+    NoLiveStop,
+    Helix(String),
+}
+
+impl std::fmt::Display for RaidTrainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RaidTrainError::NoTrain => write!(f, "no raid train is configured"),
+            RaidTrainError::NoLiveStop => write!(f, "no channel of the raid train is live"),
+            RaidTrainError::Helix(e) => write!(f, "Helix request failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RaidTrainError {}
+
+/// Channels following the own one in the train, all of them if it is not a participant
+fn following(train: &[String], own: &str) -> Vec<String> {
+    match train
+        .iter()
+        .position(|channel| channel.eq_ignore_ascii_case(own))
+    {
+        Some(position) => train[position + 1..].to_vec(),
+        None => train.to_vec(),
+    }
+}
+
+/// The first channel of the train order among the live ones
+fn first_live(following: &[String], live: &[Stop]) -> Option<Stop> {
+    following.iter().find_map(|channel| {
+        live.iter()
+            .find(|stop| stop.login.eq_ignore_ascii_case(channel))
+            .cloned()
+    })
+}
+
+/// The next live channel of the train
+///
+/// # Errors
+///
+/// Will return `Err` if there is no train, none of the channels is live or Helix fails
+pub async fn next_stop() -> Result<Stop, RaidTrainError> {
+    let following = following(&config::get_raid_train(), &config::get_channel_name());
+
+    if following.is_empty() {
+        return Err(RaidTrainError::NoTrain);
+    }
+
+    let Some(token) = get_eventsub_token().await else {
+        return Err(RaidTrainError::Helix(String::from("no EventSub token yet")));
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let logins: Vec<&UserNameRef> = following
+        .iter()
+        .map(|login| login.as_str().into())
+        .collect();
+    let live = metrics::timed(
+        "helix_get_streams",
+        client.req_get(GetStreamsRequest::user_logins(logins.as_slice()), &token),
+    )
+    .await
+    .map_err(|e| RaidTrainError::Helix(e.to_string()))?
+    .data
+    .into_iter()
+    .map(|stream| Stop {
+        user_id: stream.user_id.to_string(),
+        login: stream.user_login.to_string(),
+        user_name: stream.user_name.to_string(),
+    })
+    .collect::<Vec<_>>();
+
+    first_live(&following, &live).ok_or(RaidTrainError::NoLiveStop)
+}
+
+/// Start the raid to the next live channel of the train
+///
+/// # Errors
+///
+/// Will return `Err` if there is no live channel to raid or the raid cannot be started
+pub async fn raid_next(state: &BotState) -> Result<Stop, RaidTrainError> {
+    let stop = next_stop().await?;
+    let Some(token) = get_eventsub_token().await else {
+        return Err(RaidTrainError::Helix(String::from("no EventSub token yet")));
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let request = StartARaidRequest::new(&token.user_id, stop.user_id.as_str());
+
+    metrics::timed(
+        "helix_start_raid",
+        client.req_post(request, Default::default(), &token),
+    )
+    .await
+    .map_err(|e| RaidTrainError::Helix(e.to_string()))?;
+
+    tracing::info!("raid train continues to {}", redact::Name(&stop.user_name));
+    metrics::increment("raid_train_raid");
+    state.say(i18n::render(
+        state.languages.channel(),
+        "raid_train.departed",
+        &[("name", &stop.user_name), ("login", &stop.login)],
+    ));
+
+    Ok(stop)
+}
+
+/// The stream went offline, the train goes on with `HEWPME_RAID_TRAIN_AUTO`
+pub async fn stream_ended(state: &BotState) {
+    if !config::get_raid_train_auto() {
+        return;
+    }
+
+    if let Err(e) = raid_next(state).await {
+        tracing::warn!("Unable to continue the raid train: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(login: &str) -> Stop {
+        Stop {
+            user_id: login.len().to_string(),
+            login: login.to_string(),
+            user_name: login.to_uppercase(),
+        }
+    }
+
+    #[test]
+    fn next_stop_is_the_first_live_channel_after_the_own_one() {
+        let train = ["first", "own", "second", "third"].map(String::from);
+        let following = following(&train, "Own");
+
+        assert_eq!(following, ["second", "third"]);
+        assert_eq!(
+            first_live(&following, &[stop("third"), stop("first")]),
+            Some(stop("third"))
+        );
+        assert_eq!(first_live(&following, &[stop("first")]), None);
+        assert_eq!(super::following(&train, "guest").len(), 4);
+        assert!(super::following(&train, "third").is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+//! Welcome of the incoming raids, the raids are kept in the session stats.
+//!
+//! A raid is reported by the chat and by EventSub, the second report is ignored.
+//! With `HEWPME_RAID_SHOUTOUT` the raider also gets a shoutout in the chat and a
+//! Twitch shoutout, see [`crate::shoutouts`].
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{self, BotEvent};
+use crate::helper::BotState;
+use crate::notifications::NotificationKind;
+use crate::shoutouts::{self, CHANNEL_URL_PREFIX};
+use crate::{config, i18n, redact};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Raid {
+    pub user_name: String,
+    pub login: String,
+    pub viewers: u64,
+    pub raided_at: DateTime<Local>,
+}
+
+/// Record the raid, notify the broadcaster and welcome the raider in chat
+pub async fn landed(state: &BotState, user_id: &str, login: &str, user_name: &str, viewers: u64) {
+    let raid = Raid {
+        user_name: user_name.to_string(),
+        login: login.to_string(),
+        viewers,
+        raided_at: Local::now(),
+    };
+
+    if !state.stats.record_raid(raid).await {
+        tracing::debug!("raid by {} is already welcomed", redact::Name(user_name));
+        return;
+    }
+
+    tracing::info!("raid by {} with {viewers} viewers", redact::Name(user_name));
+    state
+        .notifier
+        .notify(
+            NotificationKind::Raid,
+            "Incoming raid",
+            format!("{user_name} with {viewers} viewers").as_str(),
+        )
+        .await;
+    welcome(state, login, user_name, viewers);
+
+    if config::get_raid_shoutout() {
+        shoutout(state, user_id, login, user_name, viewers).await;
+    }
+
+    events::publish(
+        &state.bus,
+        BotEvent::Raid {
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+            viewers,
+        },
+    );
+}
+
+/// Thank the raider in chat with a link to their channel
+fn welcome(state: &BotState, login: &str, user_name: &str, viewers: u64) {
+    if !config::get_raid_greeting() {
+        return;
+    }
+
+    let url = format!("{CHANNEL_URL_PREFIX}{login}");
+
+    state.say(i18n::render(
+        state.languages.channel(),
+        "raid.welcome",
+        &[("name", &user_name), ("viewers", &viewers), ("url", &url)],
+    ));
+}
+
+/// Post the shoutout message and send the Twitch shoutout to the raider
+async fn shoutout(state: &BotState, user_id: &str, login: &str, user_name: &str, viewers: u64) {
+    let url = format!("{CHANNEL_URL_PREFIX}{login}");
+    let args: [(&str, &(dyn std::fmt::Display + Sync)); 3] =
+        [("name", &user_name), ("url", &url), ("viewers", &viewers)];
+    let text = match config::get_raid_shoutout_message() {
+        Some(template) => i18n::fill(&template, &args),
+        None => i18n::render(state.languages.channel(), "raid.shoutout", &args),
+    };
+
+    state.say(text);
+
+    if let Err(e) = shoutouts::send(state, user_id).await {
+        tracing::warn!("Unable to send a shoutout to {user_id}: {e}");
+    }
+}
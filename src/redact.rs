@@ -0,0 +1,91 @@
+//! Streamer privacy mode for screen-sharing the bot on stream.
+//!
+//! While the mode is on, user names are masked when they are rendered on the admin
+//! page and in the logs. The overlays and pages for the viewers keep the full names.
+//! A masked name is stable, so the entries of the same user can still be matched.
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use crate::config;
+
+fn mode() -> &'static AtomicBool {
+    static MODE: OnceLock<AtomicBool> = OnceLock::new();
+
+    MODE.get_or_init(|| AtomicBool::new(config::get_streamer_privacy_enabled()))
+}
+
+#[must_use]
+pub fn enabled() -> bool {
+    mode().load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    if mode().swap(enabled, Ordering::Relaxed) != enabled {
+        tracing::info!("streamer privacy is {}", if enabled { "on" } else { "off" });
+    }
+}
+
+/// The name as it may be shown on stream
+#[must_use]
+pub fn name(name: &str) -> String {
+    if enabled() {
+        mask(name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// First letter and a short hash of the lowercase name, e.g. `c…4f1a`
+fn mask(name: &str) -> String {
+    let name = name.to_lowercase();
+    let mut hasher = DefaultHasher::new();
+
+    name.hash(&mut hasher);
+
+    let first = name.chars().next().unwrap_or('?');
+
+    format!("{first}…{:04x}", hasher.finish() & 0xffff)
+}
+
+/// User name in a log message
+pub struct Name<'a>(pub &'a str);
+
+impl Display for Name<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if enabled() {
+            f.write_str(&mask(self.0))
+        } else {
+            f.write_str(self.0)
+        }
+    }
+}
+
+/// Raw chat or EventSub message in a log message, hidden completely since
+/// the names cannot be told apart from the rest of it
+pub struct Raw<'a, T: ?Sized>(pub &'a T);
+
+impl<T: Display + ?Sized> Display for Raw<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if enabled() {
+            f.write_str("<hidden by streamer privacy>")
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_is_stable_and_hides_the_name() {
+        assert_eq!(mask("Cool_User"), mask("cool_user"));
+        assert_ne!(mask("cool_user"), mask("cool_user2"));
+        assert!(mask("cool_user").starts_with('c'));
+        assert!(!mask("cool_user").contains("cool"));
+    }
+}
@@ -0,0 +1,816 @@
+//! Channel point rewards managed by the bot, declared in `rewards.json`:
+//!
+//! ```json
+//! {
+//!   "Hydrate": { "cost": 500, "prompt": "Make me drink", "action": { "say": "{name} says drink!" } },
+//!   "Spin the wheel": { "cost": 1000, "action": "wheel" },
+//!   "Timeout": { "cost": 5000, "user_input_required": true, "action": { "timeout": 60 } },
+//!   "Hydrated": { "cost": 100, "action": { "credits": "Hydrated" } },
+//!   "Say thanks": { "cost": 200, "user_input_required": true, "action": "credit_message" },
+//!   "Song request": { "cost": 300, "user_input_required": true }
+//! }
+//! ```
+//!
+//! On start the rewards the bot created before are updated or deleted to match the
+//! file and the missing ones are created. Helix lets an application manage only the
+//! rewards it created, the ones made on the dashboard are left untouched.
+//!
+//! A redemption of a reward with an action is fulfilled when the action succeeds and
+//! canceled, refunding the points, when it fails. Without an action the redemption
+//! stays in the queue for the moderators. `{name}` and `{input}` in the texts are the
+//! viewer and the text entered with the redemption. `timeout` times out the viewer
+//! named in the text, a single login or one `@mention`, or the redeeming one if there
+//! is no text, any other text is refunded, `credits` adds the
+//! viewer to the credits list of the given title, `credit_message` queues the text for
+//! the credits, see [`crate::credit_messages`].
+//!
+//! The moderators can fulfill or refund a redemption waiting in the queue with
+//! `POST /api/rewards/<reward_id>/redemptions/<redemption_id>/<fulfill|refund>`.
+//!
+//! The status update is retried a few times in the background, the refunds, the ones of
+//! the moderators included, are logged to `refunds.json` and served on
+//! `/api/rewards/refunds`.
+//!
+//! Requires the `channel:manage:redemptions` permission, and for `timeout` the
+//! `moderator:manage:banned_users` one.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use twitch_api::helix::points::{
+    CreateCustomRewardBody, CreateCustomRewardRequest, CustomReward, CustomRewardRedemption,
+    CustomRewardRedemptionStatus, DeleteCustomRewardRequest, GetCustomRewardRequest,
+    UpdateCustomRewardBody, UpdateCustomRewardRequest, UpdateRedemptionStatusBody,
+    UpdateRedemptionStatusInformation, UpdateRedemptionStatusRequest,
+};
+use twitch_api::helix::HelixClient;
+use twitch_api::types::{RewardId, UserId};
+use twitch_oauth2::UserToken;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::overlay::{self, OverlayEvent};
+use crate::storage::JsonStore;
+use crate::transport::{create_api_client, ApiClient, HttpApi};
+use crate::wheel::{self, SpinSource};
+use crate::{config, credit_messages, i18n, metrics, moderation, redact};
+
+const REWARDS_FILE_NAME: &str = "rewards.json";
+const REFUNDS_FILE_NAME: &str = "refunds.json";
+const MAX_LOGGED_REFUNDS: usize = 500;
+const STATUS_UPDATE_ATTEMPTS: u32 = 3;
+/// Delay before the second attempt, doubled for every next one
+const STATUS_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RewardConfig {
+    pub cost: usize,
+    #[serde(default)]
+    pub prompt: String,
+    #[serde(default)]
+    pub user_input_required: bool,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Seconds before the reward can be redeemed again by anyone
+    #[serde(default)]
+    pub global_cooldown_sec: Option<u32>,
+    /// What the bot does on a redemption, `None` to leave it to the moderators
+    #[serde(default)]
+    pub action: Option<RewardAction>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl RewardConfig {
+    fn matches(&self, reward: &CustomReward) -> bool {
+        let cooldown = &reward.global_cooldown_setting;
+
+        reward.cost == self.cost
+            && reward.prompt == self.prompt
+            && reward.is_enabled == self.enabled
+            && reward.is_user_input_required == self.user_input_required
+            && cooldown
+                .is_enabled
+                .then_some(cooldown.global_cooldown_seconds)
+                == self.global_cooldown_sec
+    }
+
+    fn create_body<'a>(&'a self, title: &'a str) -> CreateCustomRewardBody<'a> {
+        let mut body = CreateCustomRewardBody::new(title, self.cost);
+
+        body.prompt = Some(self.prompt.as_str().into());
+        body.is_enabled = Some(self.enabled);
+        body.is_user_input_required = Some(self.user_input_required);
+        body.is_global_cooldown_enabled = Some(self.global_cooldown_sec.is_some());
+        body.global_cooldown_seconds = self.global_cooldown_sec.map(|seconds| seconds as usize);
+
+        body
+    }
+
+    fn update_body(&self) -> UpdateCustomRewardBody<'_> {
+        let mut body = UpdateCustomRewardBody::default();
+
+        body.cost = Some(self.cost);
+        body.prompt = Some(self.prompt.as_str().into());
+        body.is_enabled = Some(self.enabled);
+        body.is_user_input_required = Some(self.user_input_required);
+        body.is_global_cooldown_enabled = Some(self.global_cooldown_sec.is_some());
+        body.global_cooldown_seconds = self.global_cooldown_sec.map(|seconds| seconds as usize);
+
+        body
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardAction {
+    /// Spin the prize wheel for the viewer
+    Wheel,
+    Say(String),
+    /// Show the text as an announcement on the overlay
+    Overlay(String),
+    /// Time out the viewer for the seconds
+    Timeout(u32),
+    /// Add the viewer to the credits list of the title
+    Credits(String),
+    /// Queue the text of the redemption as the viewer message in the credits
+    CreditMessage,
+}
+
+/// Status a moderator gives to a redemption from the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Fulfill,
+    /// Cancel the redemption, the viewer gets the points back
+    Refund,
+}
+
+impl std::str::FromStr for Resolution {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fulfill" => Ok(Resolution::Fulfill),
+            "refund" => Ok(Resolution::Refund),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Rewards created by the bot by title
+#[derive(Default)]
+pub struct ManagedRewards {
+    ids: Mutex<HashMap<String, RewardId>>,
+}
+
+pub type SafeManagedRewards = Arc<ManagedRewards>;
+
+impl ManagedRewards {
+    async fn is_managed(&self, reward_id: &str) -> bool {
+        self.ids
+            .lock()
+            .await
+            .values()
+            .any(|id| id.as_str() == reward_id)
+    }
+}
+
+/// Redemption canceled because its action failed or by a moderator
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Refund {
+    pub redemption_id: String,
+    pub reward: String,
+    pub user_id: String,
+    pub user_name: String,
+    /// Why the action failed, or that a moderator refunded it
+    pub reason: String,
+    /// The points went back to the viewer
+    pub refunded: bool,
+    /// Last error of Twitch if the redemption could not be canceled
+    pub error: Option<String>,
+    pub failed_at: DateTime<Utc>,
+}
+
+pub fn create_managed_rewards() -> SafeManagedRewards {
+    Arc::new(ManagedRewards::default())
+}
+
+fn declared_rewards() -> BTreeMap<String, RewardConfig> {
+    JsonStore::<BTreeMap<String, RewardConfig>>::open(REWARDS_FILE_NAME).clone()
+}
+
+/// Whether the bot has a reward to handle, the redemptions are not subscribed to otherwise
+#[must_use]
+pub fn is_configured() -> bool {
+    config::get_wheel_reward().is_some() || !declared_rewards().is_empty()
+}
+
+/// Create, update and delete the rewards of the bot to match `rewards.json`
+pub async fn sync<H: HttpApi>(
+    client: &HelixClient<'static, ApiClient<H>>,
+    token: &UserToken,
+    broadcaster_id: &UserId,
+    state: &BotState,
+) {
+    sync_with(client, token, broadcaster_id, state, &declared_rewards()).await;
+}
+
+async fn sync_with<H: HttpApi>(
+    client: &HelixClient<'static, ApiClient<H>>,
+    token: &UserToken,
+    broadcaster_id: &UserId,
+    state: &BotState,
+    declared: &BTreeMap<String, RewardConfig>,
+) {
+    let request =
+        GetCustomRewardRequest::broadcaster_id(broadcaster_id).only_manageable_rewards(true);
+    let existing: Vec<CustomReward> =
+        match metrics::timed("helix_get_custom_reward", client.req_get(request, token)).await {
+            Ok(response) => response.data,
+            Err(e) => {
+                tracing::warn!("Unable to get the channel point rewards: {e}");
+                return;
+            }
+        };
+    let mut ids = state.rewards.ids.lock().await;
+
+    ids.clear();
+
+    // the rewards are changed by the leader, a follower only learns their IDs for a takeover
+    if !state.instance.is_leader() {
+        ids.extend(
+            existing
+                .into_iter()
+                .filter(|reward| declared.contains_key(&reward.title))
+                .map(|reward| (reward.title, reward.id)),
+        );
+        return;
+    }
+
+    for reward in &existing {
+        let Some(config) = declared.get(&reward.title) else {
+            let request = DeleteCustomRewardRequest::new(broadcaster_id, &reward.id);
+
+            match metrics::timed(
+                "helix_delete_custom_reward",
+                client.req_delete(request, token),
+            )
+            .await
+            {
+                Ok(_) => tracing::info!("channel point reward {} is deleted", reward.title),
+                Err(e) => tracing::warn!("Unable to delete reward {}: {e}", reward.title),
+            }
+
+            continue;
+        };
+
+        if !config.matches(reward) {
+            let request = UpdateCustomRewardRequest::new(broadcaster_id, &reward.id);
+
+            match metrics::timed(
+                "helix_update_custom_reward",
+                client.req_patch(request, config.update_body(), token),
+            )
+            .await
+            {
+                Ok(_) => tracing::info!("channel point reward {} is updated", reward.title),
+                Err(e) => tracing::warn!("Unable to update reward {}: {e}", reward.title),
+            }
+        }
+
+        ids.insert(reward.title.clone(), reward.id.clone());
+    }
+
+    for (title, config) in declared {
+        if ids.contains_key(title) {
+            continue;
+        }
+
+        let request = CreateCustomRewardRequest::broadcaster_id(broadcaster_id);
+
+        match metrics::timed(
+            "helix_create_custom_reward",
+            client.req_post(request, config.create_body(title), token),
+        )
+        .await
+        {
+            Ok(response) => {
+                tracing::info!("channel point reward {title} is created");
+                ids.insert(title.clone(), response.data.id);
+            }
+            // e.g. a reward with the same title was made on the dashboard
+            Err(e) => tracing::warn!("Unable to create reward {title}: {e}"),
+        }
+    }
+}
+
+/// Redemption of a channel point reward
+pub struct Redemption<'a> {
+    pub id: &'a str,
+    pub reward_id: &'a str,
+    pub title: &'a str,
+    pub user_id: &'a str,
+    pub user_login: &'a str,
+    pub user_name: &'a str,
+    pub input: &'a str,
+}
+
+/// Run the action of a reward managed by the bot and fulfill or cancel the
+/// redemption, returns `false` if the reward is not managed by the bot
+///
+/// A follower leaves every redemption to the leader and returns `true`
+pub async fn redeemed(state: &BotState, redemption: &Redemption<'_>) -> bool {
+    if !state.instance.is_leader() {
+        return true;
+    }
+
+    if !state.rewards.is_managed(redemption.reward_id).await {
+        return false;
+    }
+
+    let Some(action) = declared_rewards()
+        .remove(redemption.title)
+        .and_then(|config| config.action)
+    else {
+        return true;
+    };
+
+    match run_action(state, redemption, &action).await {
+        Ok(()) => fulfill(redemption),
+        Err(reason) => refund(redemption, reason),
+    }
+
+    true
+}
+
+/// Fulfill the redemption in the background, the retries do not hold the other events
+fn fulfill(redemption: &Redemption<'_>) {
+    let reward_id = redemption.reward_id.to_string();
+    let redemption_id = redemption.id.to_string();
+
+    tokio::spawn(async move {
+        let status = CustomRewardRedemptionStatus::Fulfilled;
+
+        if let Err(e) = set_status(&reward_id, &redemption_id, status).await {
+            tracing::warn!("Unable to fulfill redemption {redemption_id}: {e}");
+        }
+    });
+}
+
+/// Cancel the redemption in the background so that the viewer gets the points back
+/// and log it
+fn refund(redemption: &Redemption<'_>, reason: String) {
+    tracing::warn!(
+        "reward {} of {} failed, refunding: {reason}",
+        redemption.title,
+        redact::Name(redemption.user_name)
+    );
+
+    let reward_id = redemption.reward_id.to_string();
+    let mut refund = Refund {
+        redemption_id: redemption.id.to_string(),
+        reward: redemption.title.to_string(),
+        user_id: redemption.user_id.to_string(),
+        user_name: redemption.user_name.to_string(),
+        reason,
+        refunded: false,
+        error: None,
+        failed_at: Utc::now(),
+    };
+
+    tokio::spawn(async move {
+        let status = CustomRewardRedemptionStatus::Canceled;
+        let result = set_status(&reward_id, &refund.redemption_id, status).await;
+
+        match result {
+            Ok(_) => metrics::increment("reward_refunded"),
+            Err(ref e) => {
+                metrics::increment("reward_refund_failed");
+                tracing::error!("Unable to refund redemption {}: {e}", refund.redemption_id);
+            }
+        }
+
+        refund.refunded = result.is_ok();
+        refund.error = result.err();
+        log_refund(refund);
+    });
+}
+
+fn log_refund(refund: Refund) {
+    let saved = JsonStore::<Vec<Refund>>::open(REFUNDS_FILE_NAME).try_update(|refunds| {
+        refunds.push(refund);
+
+        let overflow = refunds.len().saturating_sub(MAX_LOGGED_REFUNDS);
+
+        refunds.drain(..overflow);
+    });
+
+    if let Err(e) = saved {
+        tracing::warn!("Unable to log the refund: {e}");
+    }
+}
+
+/// Logged refunds, the latest first
+#[must_use]
+pub fn refunds() -> Vec<Refund> {
+    let mut refunds = JsonStore::<Vec<Refund>>::open(REFUNDS_FILE_NAME).to_vec();
+
+    refunds.reverse();
+    refunds
+}
+
+/// Remove the user from the logged refunds, returns how many were removed
+pub fn forget(user_id: &str) -> usize {
+    JsonStore::<Vec<Refund>>::open(REFUNDS_FILE_NAME).update(|refunds| {
+        let len = refunds.len();
+
+        refunds.retain(|refund| refund.user_id != user_id);
+        len - refunds.len()
+    })
+}
+
+async fn run_action(
+    state: &BotState,
+    redemption: &Redemption<'_>,
+    action: &RewardAction,
+) -> Result<(), String> {
+    let fill = |template: &str| {
+        i18n::fill(
+            template,
+            &[
+                ("name", &redemption.user_name),
+                ("input", &redemption.input),
+            ],
+        )
+    };
+
+    match action {
+        RewardAction::Wheel => wheel::spin(
+            state,
+            redemption.user_id,
+            redemption.user_login,
+            redemption.user_name,
+            SpinSource::Reward,
+        )
+        .await
+        .map(drop)
+        .map_err(|e| e.to_string()),
+        RewardAction::Say(template) => {
+            state.say(fill(template));
+            Ok(())
+        }
+        RewardAction::Overlay(template) => {
+            overlay::push(
+                &state.overlay,
+                OverlayEvent::Announcement {
+                    text: fill(template),
+                },
+            );
+            Ok(())
+        }
+        RewardAction::Timeout(duration_sec) => timeout(redemption, *duration_sec).await,
+        RewardAction::Credits(title) => {
+            state
+                .session
+                .add_reward_credit(title, redemption.user_id, redemption.user_name)
+                .await;
+            Ok(())
+        }
+        RewardAction::CreditMessage => {
+            credit_messages::submit(redemption.user_id, redemption.user_name, redemption.input)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Time out the viewer named in the redemption text, or the redeeming one
+async fn timeout(redemption: &Redemption<'_>, duration_sec: u32) -> Result<(), String> {
+    let user_id = match timeout_target(redemption.input)? {
+        Some(login) => {
+            let token = get_eventsub_token()
+                .await
+                .ok_or_else(|| String::from("no EventSub token"))?;
+            let client = HelixClient::with_client(create_api_client());
+
+            metrics::timed("helix_get_user", client.get_user_from_login(login, &token))
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("no user {login}"))?
+                .id
+                .take()
+        }
+        None => redemption.user_id.to_string(),
+    };
+    let reason = format!("channel point reward {}", redemption.title);
+
+    if moderation::timeout_user(&user_id, &reason, duration_sec).await {
+        Ok(())
+    } else {
+        Err(format!("unable to time out {user_id}"))
+    }
+}
+
+/// Login named in the redemption text, `None` for an empty text
+///
+/// The text is either a single login or has exactly one `@mention`, anything else is
+/// rejected so a free-form sentence does not time out a random viewer.
+fn timeout_target(input: &str) -> Result<Option<&str>, String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let login = match words.as_slice() {
+        [] => return Ok(None),
+        [word] => word.trim_start_matches('@'),
+        _ => {
+            let mut mentions = words.iter().filter_map(|word| word.strip_prefix('@'));
+
+            match (mentions.next(), mentions.next()) {
+                (Some(login), None) => login,
+                _ => return Err(format!("no single viewer named in {input:?}")),
+            }
+        }
+    };
+    let login = login.trim_end_matches(|c: char| c.is_ascii_punctuation() && c != '_');
+
+    if login.is_empty() || !login.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("no viewer named in {input:?}"));
+    }
+
+    Ok(Some(login))
+}
+
+/// Fulfill or refund a redemption of a reward managed by the bot
+///
+/// # Errors
+///
+/// Will return `Err` if Twitch rejects the status, e.g. the redemption is not in the
+/// queue anymore
+pub async fn resolve(
+    reward_id: &str,
+    redemption_id: &str,
+    resolution: Resolution,
+) -> Result<(), String> {
+    let status = match resolution {
+        Resolution::Fulfill => CustomRewardRedemptionStatus::Fulfilled,
+        Resolution::Refund => CustomRewardRedemptionStatus::Canceled,
+    };
+
+    let redemption = set_status(reward_id, redemption_id, status).await?;
+
+    tracing::info!("redemption {redemption_id} is resolved: {resolution:?}");
+
+    if resolution == Resolution::Refund {
+        metrics::increment("reward_refunded");
+        log_refund(Refund {
+            redemption_id: redemption_id.to_string(),
+            reward: redemption.reward.title,
+            user_id: redemption.user_id.take(),
+            user_name: redemption.user_name.take(),
+            reason: String::from("refunded by a moderator"),
+            refunded: true,
+            error: None,
+            failed_at: Utc::now(),
+        });
+    }
+
+    Ok(())
+}
+
+async fn set_status(
+    reward_id: &str,
+    redemption_id: &str,
+    status: CustomRewardRedemptionStatus,
+) -> Result<CustomRewardRedemption, String> {
+    let Some(token) = get_eventsub_token().await else {
+        return Err(String::from("no EventSub token"));
+    };
+    let client = HelixClient::with_client(create_api_client());
+
+    update_status(
+        &client,
+        &token,
+        reward_id,
+        redemption_id,
+        status,
+        STATUS_RETRY_DELAY,
+    )
+    .await
+}
+
+/// Update the status of the redemption, retried with a growing delay on failure,
+/// returns the updated redemption
+async fn update_status<H: HttpApi>(
+    client: &HelixClient<'static, ApiClient<H>>,
+    token: &UserToken,
+    reward_id: &str,
+    redemption_id: &str,
+    status: CustomRewardRedemptionStatus,
+    retry_delay: Duration,
+) -> Result<CustomRewardRedemption, String> {
+    let mut delay = retry_delay;
+    let mut attempt = 1;
+
+    loop {
+        let request = UpdateRedemptionStatusRequest::new(&token.user_id, reward_id, redemption_id);
+        let body = UpdateRedemptionStatusBody::status(status);
+
+        match metrics::timed(
+            "helix_update_redemption_status",
+            client.req_patch(request, body, token),
+        )
+        .await
+        {
+            Ok(response) => {
+                return match response.data {
+                    UpdateRedemptionStatusInformation::Success(redemption) => Ok(redemption),
+                    _ => Err(String::from("unexpected response of Twitch")),
+                };
+            }
+            Err(e) if attempt == STATUS_UPDATE_ATTEMPTS => return Err(e.to_string()),
+            Err(e) => tracing::warn!(
+                "Unable to update redemption {redemption_id}, attempt {attempt}: {e}"
+            ),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = delay.saturating_mul(2);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use serde_json::json;
+    use twitch_oauth2::ClientSecret;
+
+    use super::*;
+    use crate::commands::CommandRegistry;
+    use crate::helper::create_bot_state;
+    use crate::instance::{Instance, SafeInstance};
+    use crate::storage::create_memory_storage;
+    use crate::transport::mock::MockHttp;
+
+    fn reward(id: &str, title: &str, cost: usize) -> serde_json::Value {
+        json!({
+            "broadcaster_id": "1337",
+            "broadcaster_login": "cool_user",
+            "broadcaster_name": "Cool_User",
+            "id": id,
+            "title": title,
+            "prompt": "",
+            "cost": cost,
+            "image": null,
+            "default_image": null,
+            "background_color": "#00E5CB",
+            "is_enabled": true,
+            "is_user_input_required": false,
+            "max_per_stream_setting": {"is_enabled": false, "max_per_stream": 0},
+            "max_per_user_per_stream_setting": {"is_enabled": false, "max_per_user_per_stream": 0},
+            "global_cooldown_setting": {"is_enabled": false, "global_cooldown_seconds": 0},
+            "is_paused": false,
+            "is_in_stock": true,
+            "should_redemptions_skip_request_queue": false,
+            "redemptions_redeemed_current_stream": null,
+            "cooldown_expires_at": null
+        })
+    }
+
+    fn token() -> UserToken {
+        UserToken::from_existing_unchecked(
+            "access",
+            None,
+            "client-id",
+            ClientSecret::new(String::from("client-secret")),
+            "cool_user".into(),
+            "1337".into(),
+            None,
+            Some(Duration::from_secs(3600)),
+        )
+    }
+
+    #[tokio::test]
+    async fn declared_rewards_are_synced() {
+        let http = Arc::new(MockHttp::new(|request| {
+            let body = match request.method().as_str() {
+                "GET" => json!({"data": [reward("1", "Old", 100), reward("2", "Hydrate", 100)]}),
+                "POST" => json!({"data": [reward("3", "Wheel", 1000)]}),
+                "PATCH" => json!({"data": [reward("2", "Hydrate", 500)]}),
+                _ => return (204, String::new()),
+            };
+
+            (200, body.to_string())
+        }));
+        let client = HelixClient::with_client(ApiClient::new(Arc::clone(&http)));
+        let token = token();
+        let (state, _outbox) = create_bot_state(
+            CommandRegistry::default(),
+            SafeInstance::default(),
+            create_memory_storage(),
+        );
+        let declared: BTreeMap<String, RewardConfig> = serde_json::from_value(json!({
+            "Hydrate": {"cost": 500, "action": {"say": "{name} says drink!"}},
+            "Wheel": {"cost": 1000, "action": "wheel"}
+        }))
+        .unwrap();
+
+        sync_with(&client, &token, &UserId::from("1337"), &state, &declared).await;
+
+        let methods: Vec<String> = http
+            .requests()
+            .into_iter()
+            .map(|request| request.method)
+            .collect();
+
+        assert_eq!(methods, ["GET", "DELETE", "PATCH", "POST"]);
+        assert!(state.rewards.is_managed("2").await);
+        assert!(state.rewards.is_managed("3").await);
+        assert!(!state.rewards.is_managed("1").await);
+    }
+
+    #[tokio::test]
+    async fn follower_only_learns_the_reward_ids() {
+        let http = Arc::new(MockHttp::new(|_| {
+            let body = json!({"data": [reward("1", "Old", 100), reward("2", "Hydrate", 100)]});
+
+            (200, body.to_string())
+        }));
+        let client = HelixClient::with_client(ApiClient::new(Arc::clone(&http)));
+        let (state, _outbox) = create_bot_state(
+            CommandRegistry::default(),
+            Instance::follower(),
+            create_memory_storage(),
+        );
+        let declared: BTreeMap<String, RewardConfig> =
+            serde_json::from_value(json!({"Hydrate": {"cost": 500}})).unwrap();
+
+        sync_with(&client, &token(), &UserId::from("1337"), &state, &declared).await;
+
+        assert_eq!(http.requests().len(), 1);
+        assert!(state.rewards.is_managed("2").await);
+        assert!(!state.rewards.is_managed("1").await);
+    }
+
+    #[test]
+    fn timeout_target_is_a_mention_or_a_single_login() {
+        assert_eq!(timeout_target("be quiet @Viewer!"), Ok(Some("Viewer")));
+        assert_eq!(timeout_target(" @Viewer "), Ok(Some("Viewer")));
+        assert_eq!(timeout_target("viewer"), Ok(Some("viewer")));
+        assert_eq!(timeout_target("  "), Ok(None));
+        assert!(timeout_target("@").is_err());
+        assert!(timeout_target("time out the streamer").is_err());
+        assert!(timeout_target("@one and @two").is_err());
+        assert!(timeout_target("not/a/login").is_err());
+    }
+
+    #[tokio::test]
+    async fn status_update_is_retried() {
+        let calls = AtomicUsize::new(0);
+        let http = Arc::new(MockHttp::new(move |_| {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                return (
+                    503,
+                    String::from(r#"{"error":"Service Unavailable","status":503,"message":""}"#),
+                );
+            }
+
+            let redemption = json!({
+                "broadcaster_id": "1337",
+                "broadcaster_login": "cool_user",
+                "broadcaster_name": "Cool_User",
+                "id": "r1",
+                "user_id": "42",
+                "user_login": "viewer",
+                "user_name": "Viewer",
+                "user_input": "",
+                "status": "CANCELED",
+                "redeemed_at": "2024-01-01T00:00:00Z",
+                "reward": {"id": "3", "title": "Wheel", "prompt": "", "cost": 1000}
+            });
+
+            (200, json!({"data": [redemption]}).to_string())
+        }));
+        let client = HelixClient::with_client(ApiClient::new(Arc::clone(&http)));
+
+        let result = update_status(
+            &client,
+            &token(),
+            "3",
+            "r1",
+            CustomRewardRedemptionStatus::Canceled,
+            Duration::ZERO,
+        )
+        .await;
+
+        assert_eq!(
+            result.map(|redemption| redemption.reward.title),
+            Ok(String::from("Wheel"))
+        );
+        assert_eq!(http.requests().len(), 3);
+    }
+}
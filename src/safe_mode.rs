@@ -0,0 +1,132 @@
+//! Safe mode after repeated crashes.
+//!
+//! The start of every run is written to `crashes.json` and cleared when the run stops,
+//! a start finding the previous run unfinished counts it as a crash. With
+//! `HEWPME_SAFE_MODE_CRASHES` crashes of the runs started within
+//! `HEWPME_SAFE_MODE_WINDOW_MIN` the bot starts in safe mode: the chat and EventSub
+//! data is collected and the web server runs, but nothing is posted to the chat and no
+//! moderation action is taken. The admin page shows a banner while it is on.
+//!
+//! A run staying up for the window clears the crashes, the next start is a normal one.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::JsonStore;
+use crate::{config, metrics};
+
+const CRASHES_FILE_NAME: &str = "crashes.json";
+/// What safe mode turns off, for the admin page
+const DISABLED: [&str; 2] = ["chat messages and commands", "moderation actions"];
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Recent crashes found on the start of the run
+static CRASHES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CrashLog {
+    /// Start of the current run, `None` once it has stopped
+    running_since: Option<DateTime<Utc>>,
+    /// Starts of the runs that crashed, oldest first
+    crashes: Vec<DateTime<Utc>>,
+}
+
+impl CrashLog {
+    /// Start a new run, returns the crashes of the runs started within the window
+    fn start(&mut self, now: DateTime<Utc>, window: TimeDelta) -> usize {
+        if let Some(started) = self.running_since.replace(now) {
+            self.crashes.push(started);
+        }
+
+        self.crashes.retain(|&started| now - started < window);
+        self.crashes.len()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SafeModeStatus {
+    pub active: bool,
+    /// Recent crashes found on the start of the run
+    pub crashes: usize,
+    pub disabled: &'static [&'static str],
+}
+
+/// Whether the chat messages and the moderation actions are turned off
+#[must_use]
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+fn window() -> TimeDelta {
+    TimeDelta::from_std(config::get_safe_mode_window()).unwrap_or_else(|_| TimeDelta::max_value())
+}
+
+/// Record the start of the run and turn safe mode on after too many recent crashes,
+/// returns whether it is on
+pub fn record_start() -> bool {
+    let max_crashes = config::get_safe_mode_crashes();
+    let crashes = JsonStore::<CrashLog>::open(CRASHES_FILE_NAME)
+        .update(|log| log.start(Utc::now(), window()));
+    let active = max_crashes != 0 && crashes >= max_crashes;
+
+    ACTIVE.store(active, Ordering::Relaxed);
+    CRASHES.store(crashes, Ordering::Relaxed);
+
+    if active {
+        metrics::increment("safe_mode_start");
+        tracing::error!(
+            "the bot crashed {crashes} times recently, starting in safe mode without {}",
+            DISABLED.join(" and ")
+        );
+    }
+
+    active
+}
+
+/// Mark the run as stopped, it is not counted as a crash on the next start
+pub fn record_stop() {
+    JsonStore::<CrashLog>::open(CRASHES_FILE_NAME).update(|log| log.running_since = None);
+}
+
+/// Forget the crashes once the run has been up for the window
+pub async fn run_stability_check() {
+    tokio::time::sleep(config::get_safe_mode_window()).await;
+
+    JsonStore::<CrashLog>::open(CRASHES_FILE_NAME).update(|log| log.crashes.clear());
+
+    if is_active() {
+        tracing::info!("the bot is stable, restart it to leave safe mode");
+    }
+}
+
+#[must_use]
+pub fn status() -> SafeModeStatus {
+    SafeModeStatus {
+        active: is_active(),
+        crashes: CRASHES.load(Ordering::Relaxed),
+        disabled: &DISABLED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unfinished_runs_within_the_window_are_crashes() {
+        let window = TimeDelta::minutes(10);
+        let now = Utc::now();
+        let mut log = CrashLog {
+            running_since: Some(now - TimeDelta::minutes(2)),
+            crashes: vec![now - TimeDelta::hours(3), now - TimeDelta::minutes(5)],
+        };
+
+        assert_eq!(log.start(now, window), 2);
+        assert_eq!(log.running_since, Some(now));
+
+        log.running_since = None;
+
+        assert_eq!(log.start(now + TimeDelta::minutes(6), window), 1);
+    }
+}
@@ -0,0 +1,215 @@
+//! Timed chat announcements configured in `timers.json`, e.g.
+//!
+//! ```json
+//! [
+//!   {
+//!     "message": "Follow the channel!",
+//!     "interval_min": 15,
+//!     "window": { "start": "18:00", "end": "23:00" },
+//!     "days": ["Fri", "Sat"]
+//!   }
+//! ]
+//! ```
+//!
+//! The window and the days are checked in the `HEWPME_TIMEZONE` time zone,
+//! a window may cross midnight.
+//!
+//! A timer with `"wait_for_start": true` and a `name` runs only after a `start_timers`
+//! action of [`crate::automation`] names it, e.g. on the stream start, and stops when
+//! the stream goes offline.
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+
+use crate::config;
+use crate::helper::BotState;
+use crate::settings::Section;
+use crate::storage::JsonStore;
+
+const TIMERS_FILE_NAME: &str = "timers.json";
+const TIMER_STARTS_CAPACITY: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct TimeWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Timer {
+    /// Name the `start_timers` action refers to the timer by
+    #[serde(default)]
+    name: Option<String>,
+    message: String,
+    interval_min: u64,
+    #[serde(default)]
+    window: Option<TimeWindow>,
+    /// Empty means every day
+    #[serde(default)]
+    days: Vec<Weekday>,
+    /// Runs only after it is started by name, until the stream goes offline
+    #[serde(default)]
+    wait_for_start: bool,
+}
+
+impl Timer {
+    fn is_active(&self, now: NaiveDateTime) -> bool {
+        let day_matches = self.days.is_empty() || self.days.contains(&now.weekday());
+        let time_matches = self.window.is_none_or(|window| window.contains(now.time()));
+
+        day_matches && time_matches
+    }
+}
+
+/// Names of the timers to start, sent by the `start_timers` action
+pub type TimerStarts = Arc<broadcast::Sender<String>>;
+
+pub fn create_timer_starts() -> TimerStarts {
+    Arc::new(broadcast::channel(TIMER_STARTS_CAPACITY).0)
+}
+
+/// Start the waiting timer of the name, it is fine if there is none
+pub fn start_timer(state: &BotState, name: &str) {
+    if state.timer_starts.send(name.to_string()).is_err() {
+        tracing::debug!("no timer waits for a start");
+    }
+}
+
+fn local_now(timezone: Option<Tz>) -> NaiveDateTime {
+    match timezone {
+        Some(timezone) => Utc::now().with_timezone(&timezone).naive_local(),
+        None => Local::now().naive_local(),
+    }
+}
+
+/// Post every configured timer message to chat on its interval,
+/// the timers restart when they are changed at runtime
+pub async fn run_timers(state: BotState) {
+    let mut changes = state.settings.subscribe();
+
+    loop {
+        // the timer tasks are aborted when the set is dropped
+        let _timers = start_timers(&state);
+
+        loop {
+            match changes.recv().await {
+                Ok(Section::Timers) | Err(broadcast::error::RecvError::Lagged(_)) => break,
+                Ok(_) => (),
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+
+        tracing::info!("timers changed, restarting them");
+    }
+}
+
+fn start_timers(state: &BotState) -> JoinSet<()> {
+    let timers = JsonStore::<Vec<Timer>>::open(TIMERS_FILE_NAME).to_vec();
+    let timezone = config::get_timezone();
+    let mut tasks = JoinSet::new();
+
+    for timer in timers {
+        if timer.interval_min == 0 {
+            tracing::warn!("timer \"{}\" has no interval, skipped", timer.message);
+            continue;
+        }
+
+        let state = state.clone();
+
+        if !timer.wait_for_start {
+            tasks.spawn(async move { run_timer(&state, &timer, timezone).await });
+            continue;
+        }
+
+        let Some(name) = timer.name.clone() else {
+            tracing::warn!(
+                "timer \"{}\" waits for a start without a name",
+                timer.message
+            );
+            continue;
+        };
+        let mut starts = state.timer_starts.subscribe();
+
+        tasks.spawn(async move {
+            loop {
+                match starts.recv().await {
+                    Ok(started) if started == name => (),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+
+                tracing::info!("timer {name} is started");
+
+                tokio::select! {
+                    () = run_timer(&state, &timer, timezone) => (),
+                    () = state.stream.wait_offline() => tracing::info!("timer {name} is stopped"),
+                }
+            }
+        });
+    }
+
+    tasks
+}
+
+async fn run_timer(state: &BotState, timer: &Timer, timezone: Option<Tz>) {
+    let period = Duration::from_secs(timer.interval_min * 60);
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+
+        if timer.is_active(local_now(timezone)) {
+            state.say(timer.message.as_str());
+        }
+    }
+}
+
+/// Check the timers sent to `/api/config/timers`
+pub(crate) fn check_timers(value: &serde_json::Value) -> Result<(), String> {
+    let timers = Vec::<Timer>::deserialize(value).map_err(|e| e.to_string())?;
+
+    match timers.iter().find(|timer| timer.interval_min == 0) {
+        Some(timer) => Err(format!("timer \"{}\" has no interval", timer.message)),
+        None => Ok(()),
+    }
+}
+
+/// The timers of `timers.json` with the interval of the `number`-th one, counted from 1,
+/// changed to `interval_min`
+pub(crate) fn timers_with_interval(
+    number: usize,
+    interval_min: u64,
+) -> Result<serde_json::Value, String> {
+    let mut timers = JsonStore::<Vec<Timer>>::open(TIMERS_FILE_NAME).to_vec();
+    let count = timers.len();
+    let timer = number
+        .checked_sub(1)
+        .and_then(|index| timers.get_mut(index))
+        .ok_or_else(|| format!("there are {count} timers"))?;
+
+    timer.interval_min = interval_min;
+    serde_json::to_value(timers).map_err(|e| e.to_string())
+}
+
+/// Replace the timers of `timers.json`, the value is checked with [`check_timers`]
+pub(crate) fn replace_timers(value: serde_json::Value) -> io::Result<()> {
+    let timers = serde_json::from_value::<Vec<Timer>>(value)?;
+
+    JsonStore::<Vec<Timer>>::open(TIMERS_FILE_NAME).try_update(|stored| *stored = timers)
+}
@@ -1,102 +1,647 @@
+//! Web server of the overlays, the admin page and the JSON API on `HEWPME_PORT`.
+use std::collections::HashMap;
 use std::convert::Infallible;
-use std::fmt::{Formatter, Write};
-use std::fs;
-use std::io::Read;
 use std::net::SocketAddr;
-use std::path::Path;
 
-use serde::Serialize;
-use serde_json::Value;
-use tinytemplate::TinyTemplate;
+use chrono::{TimeDelta, Utc};
+use futures::{SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use warp::hyper::Body;
+use warp::ws::{Message, WebSocket};
 use warp::{Filter, Reply};
 
-use crate::helper::{ChattersList, SafeTwitchEventList};
+use crate::admin_auth;
+use crate::alert_queue;
+use crate::automation;
+use crate::breaks;
+use crate::browser_sources;
+use crate::config::{self, Feature, FollowerCountMode};
+use crate::credit_messages::{self, Review};
+use crate::health::Status;
+use crate::helper::{BotState, SessionUsers};
+use crate::image_cache::{self, CachedImage};
+use crate::kv::{self, KvError};
+use crate::logs::{self, LogLevel};
+use crate::metrics;
+use crate::overlay::{OverlayBus, SequencedEvent};
+use crate::overlay_auth;
+use crate::privacy;
+use crate::redact;
+use crate::rewards::{self, Resolution};
+use crate::safe_mode;
+use crate::sessions;
+use crate::settings::{self, Section};
+use crate::stats::WeeklyReport;
+use crate::unfurl;
+use crate::utils;
+use crate::wheel;
 
-#[derive(Serialize, Debug)]
-struct Content<T>
-where
-    T: IntoIterator,
-{
-    chatters: Option<T>,
-    followers: Option<T>,
-    subscribers: Option<T>,
+mod credits;
+mod roll;
+
+use credits::{CreditEntry, CreditProfiles, CreditsLayout, TemplateContext};
+use roll::SafeCreditsRoll;
+
+const CREDITS_STREAKS_COUNT: usize = 10;
+const CREDITS_CHEERERS_COUNT: usize = 10;
+const CREDITS_GIFTERS_COUNT: usize = 10;
+
+#[derive(Deserialize, Debug)]
+struct CreditsQuery {
+    layout: Option<CreditsLayout>,
 }
 
-#[derive(Debug)]
-struct ServerError {
-    kind: String,
-    message: String,
+#[derive(Deserialize, Debug)]
+struct ImageCacheQuery {
+    url: String,
 }
 
-#[derive(Debug)]
-struct TemplateContext<T: IntoIterator + Serialize> {
-    chatters: Option<T>,
-    followers: Option<T>,
-    subscribers: Option<T>,
-}
-
-impl<T: IntoIterator + Serialize + Clone> TemplateContext<T> {
-    fn new(chatters_list: T, followers_list: T, subscriber_list: T) -> Self {
-        let c = chatters_list.clone().into_iter().count();
-        let f = followers_list.clone().into_iter().count();
-        let s = subscriber_list.clone().into_iter().count();
-
-        let chatters = if c > 0 { Some(chatters_list) } else { None };
-        let followers = if f > 0 { Some(followers_list) } else { None };
-        let subscribers = if s > 0 { Some(subscriber_list) } else { None };
-
-        TemplateContext {
-            chatters,
-            followers,
-            subscribers,
-        }
-    }
+#[derive(Deserialize, Debug)]
+struct UnfurlQuery {
+    url: String,
 }
 
-type Result<T> = std::result::Result<T, ServerError>;
+#[derive(Deserialize, Debug)]
+struct OverlayQuery {
+    token: Option<String>,
+}
 
-impl core::fmt::Display for ServerError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "kind: {}, message: {}", self.kind, self.message)
-    }
+#[derive(Deserialize, Debug)]
+struct LogsQuery {
+    #[serde(default)]
+    level: LogLevel,
 }
 
-impl From<std::io::Error> for ServerError {
-    fn from(value: std::io::Error) -> Self {
-        ServerError {
-            kind: String::from("io"),
-            message: value.to_string(),
-        }
-    }
+#[derive(Deserialize, Debug)]
+struct ModLogQuery {
+    #[serde(default)]
+    flagged: bool,
+}
+
+/// Recent chat of the chat overlay, `token` is the one of the overlay URL
+#[derive(Deserialize, Debug)]
+struct ChatHistoryQuery {
+    token: Option<String>,
+    #[serde(default)]
+    highlighted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StreamerPrivacy {
+    enabled: bool,
 }
 
-impl From<tinytemplate::error::Error> for ServerError {
-    fn from(value: tinytemplate::error::Error) -> Self {
-        ServerError {
-            kind: String::from("template"),
-            message: value.to_string(),
+/// Viewer whose data is exported or deleted, the login is looked up if missing
+#[derive(Deserialize, Debug)]
+struct PrivacyRequest {
+    user_id: String,
+    login: Option<String>,
+}
+
+/// `skip` or `replay` of `/api/alerts/queue/<id>/<action>`
+#[derive(Debug, Clone, Copy)]
+enum AlertAction {
+    Skip,
+    Replay,
+}
+
+impl std::str::FromStr for AlertAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(AlertAction::Skip),
+            "replay" => Ok(AlertAction::Replay),
+            _ => Err(()),
         }
     }
 }
 
-pub(crate) async fn run_server(chatters_list: ChattersList, event_list: SafeTwitchEventList) {
+/// Value of `/api/kv/<key>`
+#[derive(Serialize, Deserialize, Debug)]
+struct KvValue {
+    value: String,
+}
+
+/// Authorization URL to send to the co-host
+#[derive(Serialize, Debug)]
+struct DelegationStarted {
+    url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SceneChange {
+    scene: String,
+}
+
+/// Serve the pages and the API of the state, runs until the process exits
+pub async fn run_server(state: BotState) {
+    let roll = SafeCreditsRoll::default();
+
+    if let Some(duration) = config::get_credits_roll_duration() {
+        tokio::spawn(roll::run_credits_roll(
+            state.clone(),
+            roll.clone(),
+            duration,
+        ));
+    }
+
     let static_files = warp::path("static").and(warp::fs::dir("public"));
     let credits = warp::path::end()
-        .and(warp::any().map(move || chatters_list.clone()))
-        .and(warp::any().map(move || event_list.clone()))
+        .and(warp::query::<CreditsQuery>())
+        .and(with_state(state.clone()))
+        .and(warp::any().map(move || roll.clone()))
         .and_then(credit_request);
-    let routes = warp::get().and(credits.or(static_files));
-    let server_addr: SocketAddr = "0.0.0.0:12345".parse().unwrap();
+    let image_cache = warp::path!("cache" / "img")
+        .and(warp::query::<ImageCacheQuery>())
+        .and_then(image_cache_request);
+    let avatars = warp::path!("avatars" / String)
+        .and(with_state(state.clone()))
+        .and_then(avatar_request);
+    let metrics = warp::path!("metrics").map(|| {
+        warp::reply::with_header(
+            metrics::render_prometheus(),
+            "content-type",
+            "text/plain; version=0.0.4",
+        )
+    });
+    let healthz = warp::path!("healthz")
+        .and(with_state(state.clone()))
+        .map(|state: BotState| {
+            let report = state.health.report();
+            let code = if report.status == Status::Down {
+                warp::http::StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                warp::http::StatusCode::OK
+            };
+
+            warp::reply::with_status(warp::reply::json(&report), code)
+        });
+    let timings =
+        warp::path!("api" / "debug" / "timings").map(|| warp::reply::json(&metrics::timings()));
+    let weekly_report = warp::path!("api" / "stats" / "weekly")
+        .and(with_state(state.clone()))
+        .and_then(weekly_report_request);
+    let lapses = warp::path!("api" / "lapses")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(lapses_request);
+    let stats = warp::path!("api" / "stats")
+        .and(with_state(state.clone()))
+        .and_then(stats_request);
+    let activity_page = warp::path!("activity").and(warp::fs::file("public/activity.html"));
+    let activity_api = warp::path!("api" / "activity")
+        .and(with_state(state.clone()))
+        .and_then(activity_request);
+    let overlays_enabled = config::is_feature_enabled(Feature::Overlays);
+    let overlay_page = warp::path!("overlay")
+        .and(enabled(overlays_enabled))
+        .and(warp::fs::file("public/overlay.html"));
+    let wheel_page = warp::path!("wheel")
+        .and(enabled(overlays_enabled))
+        .and(warp::fs::file("public/wheel.html"));
+    let refunds = warp::path!("api" / "rewards" / "refunds")
+        .and(admin())
+        .map(|| {
+            let refunds = rewards::refunds()
+                .into_iter()
+                .map(|mut refund| {
+                    refund.user_name = redact::name(&refund.user_name);
+                    refund
+                })
+                .collect::<Vec<_>>();
+
+            warp::reply::json(&refunds)
+        });
+    let redemption_resolve = warp::post()
+        .and(warp::path!(
+            "api" / "rewards" / String / "redemptions" / String / Resolution
+        ))
+        .and(admin())
+        .and_then(redemption_resolve_request);
+    let credit_messages = warp::path!("api" / "credits" / "messages")
+        .and(admin())
+        .map(|| {
+            let messages = credit_messages::all()
+                .into_iter()
+                .map(|mut message| {
+                    message.user_name = redact::name(&message.user_name);
+                    message
+                })
+                .collect::<Vec<_>>();
+
+            warp::reply::json(&messages)
+        });
+    let credit_message_review = warp::post()
+        .and(warp::path!("api" / "credits" / "messages" / u64 / Review))
+        .and(admin())
+        .and_then(credit_message_review_request);
+    let wheel_spins = warp::path!("api" / "wheel" / "spins").map(|| {
+        let spins = wheel::spins()
+            .into_iter()
+            .map(|mut spin| {
+                spin.user_name = redact::name(&spin.user_name);
+                spin
+            })
+            .collect::<Vec<_>>();
+
+        warp::reply::json(&spins)
+    });
+    let unfurl = warp::path!("api" / "unfurl")
+        .and(warp::query::<UnfurlQuery>())
+        .and(with_state(state.clone()))
+        .and_then(unfurl_request);
+    let commands_page = warp::path!("commands").and(warp::fs::file("public/commands.html"));
+    let commands_api = warp::path!("api" / "commands")
+        .and(with_state(state.clone()))
+        .and_then(commands_request);
+    let admin_page = warp::path!("admin").and(warp::fs::file("public/admin.html"));
+    let logs_page = warp::path!("admin" / "logs")
+        .and(admin())
+        .and(warp::fs::file("public/logs.html"));
+    let logs_ws = warp::path!("ws" / "logs")
+        .and(admin())
+        .and(warp::ws())
+        .and(warp::query::<LogsQuery>())
+        .map(|ws: warp::ws::Ws, query: LogsQuery| {
+            ws.on_upgrade(move |socket| logs_session(socket, query.level))
+        });
+    let notes = warp::path!("api" / "notes")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(notes_request);
+    let alerts = warp::path!("api" / "alerts")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(alerts_request);
+    let alert_queue = warp::path!("api" / "alerts" / "queue")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(alert_queue_request);
+    let alert_action = warp::post()
+        .and(warp::path!("api" / "alerts" / "queue" / u64 / AlertAction))
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(alert_action_request);
+    let overlay_urls = warp::path!("api" / "overlay" / "urls")
+        .and(admin())
+        .map(|| warp::reply::json(&browser_sources::browser_sources()));
+    let clips_page = warp::path!("clips").and(warp::fs::file("public/clips.html"));
+    let clips_api = warp::path!("api" / "clips")
+        .and(with_state(state.clone()))
+        .and_then(clips_request);
+    let chat_page = warp::path!("chat")
+        .and(enabled(overlays_enabled))
+        .and(warp::fs::file("public/chat.html"));
+    let highlights_page = warp::path!("highlights")
+        .and(enabled(
+            overlays_enabled && config::get_highlights_page_enabled(),
+        ))
+        .and(warp::fs::file("public/highlights.html"));
+    let mentions_page = warp::path!("mentions").and(warp::fs::file("public/mentions.html"));
+    let mentions_api = warp::path!("api" / "mentions")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(mentions_request);
+    let modlog = warp::path!("api" / "modlog")
+        .and(admin())
+        .and(warp::query::<ModLogQuery>())
+        .and(with_state(state.clone()))
+        .and_then(modlog_request);
+    let chat_history = warp::path!("api" / "chat" / "recent")
+        .and(enabled(overlays_enabled))
+        .and(warp::query::<ChatHistoryQuery>())
+        .and(with_state(state.clone()))
+        .and_then(chat_history_request);
+    let overlay_ws = warp::path!("ws" / "overlay")
+        .and(enabled(overlays_enabled))
+        .and(warp::ws())
+        .and(warp::query::<OverlayQuery>())
+        .and(with_state(state.clone()))
+        .map(|ws: warp::ws::Ws, query: OverlayQuery, state: BotState| {
+            if !overlay_auth::is_authorized(query.token.as_deref()) {
+                return warp::http::StatusCode::UNAUTHORIZED.into_response();
+            }
+
+            let events = state.overlay.subscribe();
+
+            ws.on_upgrade(move |socket| overlay_session(socket, events))
+                .into_response()
+        });
+    let overlay_sse = warp::path!("events" / "stream")
+        .and(enabled(overlays_enabled))
+        .and(warp::query::<OverlayQuery>())
+        .and(warp::header::optional::<u64>("last-event-id"))
+        .and(with_state(state.clone()))
+        .map(
+            |query: OverlayQuery, last_id: Option<u64>, state: BotState| {
+                if !overlay_auth::is_authorized(query.token.as_deref()) {
+                    return warp::http::StatusCode::UNAUTHORIZED.into_response();
+                }
+
+                let events = overlay_event_stream(&state.overlay, last_id);
+
+                warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response()
+            },
+        );
+    let overlay_rotate = warp::post()
+        .and(warp::path!("api" / "overlay" / "rotate"))
+        .and(admin())
+        .map(|| match overlay_auth::rotate() {
+            Ok(()) => warp::reply::json(&browser_sources::browser_sources()).into_response(),
+            Err(e) => warp::reply::with_status(
+                e.to_string(),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response(),
+        });
+    let automation = warp::path!("api" / "automation")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(automation_request);
+    let automation_lists = warp::path!("api" / "automation" / "lists")
+        .and(admin())
+        .map(|| warp::reply::json(&automation::lists()));
+    let automation_reload = warp::post()
+        .and(warp::path!("api" / "automation" / "reload"))
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(automation_reload_request);
+    let kv_values = warp::path!("api" / "kv").map(|| warp::reply::json(&kv::all()));
+    let kv_value = warp::path!("api" / "kv" / String).map(|key: String| match kv::get(&key) {
+        Some(value) => warp::reply::json(&KvValue { value }).into_response(),
+        None => warp::http::StatusCode::NOT_FOUND.into_response(),
+    });
+    let kv_set = warp::post()
+        .and(warp::path!("api" / "kv" / String))
+        .and(admin())
+        .and(warp::body::json())
+        .map(
+            |key: String, body: KvValue| match kv::set(&key, &body.value) {
+                Ok(()) => warp::http::StatusCode::NO_CONTENT.into_response(),
+                Err(KvError::Save(e)) => warp::reply::with_status(
+                    e.to_string(),
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into_response(),
+                Err(e) => {
+                    warp::reply::with_status(e.to_string(), warp::http::StatusCode::BAD_REQUEST)
+                        .into_response()
+                }
+            },
+        );
+    let kv_remove = warp::post()
+        .and(warp::path!("api" / "kv" / String / "remove"))
+        .and(admin())
+        .map(|key: String| match kv::remove(&key) {
+            Ok(true) => warp::http::StatusCode::NO_CONTENT.into_response(),
+            Ok(false) => warp::http::StatusCode::NOT_FOUND.into_response(),
+            Err(e) => warp::reply::with_status(
+                e.to_string(),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response(),
+        });
+    let delegations = warp::path!("api" / "delegations").and(admin()).map(|| {
+        let delegations = utils::delegations()
+            .into_iter()
+            .map(|mut delegation| {
+                delegation.login = redact::name(&delegation.login);
+                delegation
+            })
+            .collect::<Vec<_>>();
+
+        warp::reply::json(&delegations)
+    });
+    let delegation_start = warp::post()
+        .and(warp::path!("api" / "delegations"))
+        .and(admin())
+        .map(|| match utils::start_delegation() {
+            Some(url) => warp::reply::json(&DelegationStarted {
+                url: url.to_string(),
+            })
+            .into_response(),
+            None => warp::reply::with_status(
+                "Another co-host is being authorized",
+                warp::http::StatusCode::CONFLICT,
+            )
+            .into_response(),
+        });
+    let delegation_remove = warp::post()
+        .and(warp::path!("api" / "delegations" / String / "remove"))
+        .and(admin())
+        .and_then(delegation_remove_request);
+    let privacy_export = warp::path!("api" / "privacy" / "export")
+        .and(admin())
+        .and(warp::query::<PrivacyRequest>())
+        .and(with_state(state.clone()))
+        .and_then(privacy_export_request);
+    let privacy_forget = warp::post()
+        .and(warp::path!("api" / "privacy" / "forget"))
+        .and(admin())
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(privacy_forget_request);
+    let sessions_list = warp::path!("api" / "sessions")
+        .and(with_state(state.clone()))
+        .map(|state: BotState| warp::reply::json(&sessions::ids(&state)));
+    let session_export = warp::path!("api" / "sessions" / String / "export")
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(session_export_request);
+    let safe_mode_status =
+        warp::path!("api" / "safe-mode").map(|| warp::reply::json(&safe_mode::status()));
+    let streamer_privacy = warp::path!("api" / "streamer-privacy").map(|| {
+        warp::reply::json(&StreamerPrivacy {
+            enabled: redact::enabled(),
+        })
+    });
+    let streamer_privacy_toggle = warp::post()
+        .and(warp::path!("api" / "streamer-privacy"))
+        .and(admin())
+        .and(warp::body::json())
+        .map(|privacy: StreamerPrivacy| {
+            redact::set_enabled(privacy.enabled);
+
+            warp::reply::json(&privacy)
+        });
+    let config_section = warp::path!("api" / "config" / Section)
+        .and(admin())
+        .and(with_state(state.clone()))
+        .and_then(config_section_request);
+    let config_patch = warp::post()
+        .and(warp::path!("api" / "config" / Section))
+        .and(admin())
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(config_patch_request);
+    let scene = warp::post()
+        .and(warp::path!("api" / "scene"))
+        .and(admin())
+        .and(warp::body::json())
+        .and(with_state(state))
+        .and_then(scene_change_request);
+    let routes = warp::get()
+        .and(
+            credits
+                .or(avatars)
+                .or(image_cache)
+                .or(metrics)
+                .or(healthz)
+                .or(timings)
+                .or(activity_page)
+                .or(activity_api)
+                .or(stats)
+                .or(weekly_report)
+                .or(lapses)
+                .or(overlay_page)
+                .or(overlay_urls)
+                .or(chat_page)
+                .or(wheel_page)
+                .or(wheel_spins)
+                .or(refunds)
+                .or(credit_messages)
+                .or(highlights_page)
+                .or(clips_page)
+                .or(clips_api)
+                .or(modlog)
+                .or(chat_history)
+                .or(mentions_page)
+                .or(mentions_api)
+                .or(admin_page)
+                .or(logs_page)
+                .or(logs_ws)
+                .or(commands_page)
+                .or(commands_api)
+                .or(notes)
+                .or(alerts)
+                .or(alert_queue)
+                .or(delegations)
+                .or(kv_values)
+                .or(kv_value)
+                .or(automation)
+                .or(automation_lists)
+                .or(streamer_privacy)
+                .or(safe_mode_status)
+                .or(sessions_list)
+                .or(session_export)
+                .or(config_section)
+                .or(privacy_export)
+                .or(unfurl)
+                .or(overlay_ws)
+                .or(overlay_sse)
+                .or(static_files),
+        )
+        .or(privacy_forget)
+        .or(streamer_privacy_toggle)
+        .or(config_patch)
+        .or(overlay_rotate)
+        .or(alert_action)
+        .or(redemption_resolve)
+        .or(credit_message_review)
+        .or(delegation_start)
+        .or(delegation_remove)
+        .or(automation_reload)
+        .or(kv_remove)
+        .or(kv_set)
+        .or(scene)
+        .recover(unauthorized);
+    let server_addr = SocketAddr::from(([0, 0, 0, 0], config::get_server_port()));
 
     warp::serve(routes).run(server_addr).await;
 }
 
+/// Reject the requests to a route turned off in the config
+fn enabled(enabled: bool) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+/// Rejection of a request without the admin secret
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Reject the requests without the admin secret in the header or the cookie of the admin page
+fn admin() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(admin_auth::HEADER)
+        .and(warp::cookie::optional::<String>(admin_auth::COOKIE))
+        .and_then(
+            |header: Option<String>, cookie: Option<String>| async move {
+                if [header, cookie]
+                    .iter()
+                    .any(|token| admin_auth::is_authorized(token.as_deref()))
+                {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized))
+                }
+            },
+        )
+        .untuple_one()
+}
+
+async fn unauthorized(
+    rejection: warp::Rejection,
+) -> std::result::Result<warp::reply::Response, warp::Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        Ok(warp::http::StatusCode::UNAUTHORIZED.into_response())
+    } else {
+        Err(rejection)
+    }
+}
+
+fn with_state(state: BotState) -> impl Filter<Extract = (BotState,), Error = Infallible> + Clone {
+    warp::any().map(move || state.clone())
+}
+
+async fn config_section_request(
+    section: Section,
+    state: BotState,
+) -> std::result::Result<impl Reply, Infallible> {
+    Ok(warp::reply::json(&state.settings.section(section).await))
+}
+
+async fn config_patch_request(
+    section: Section,
+    patch: serde_json::Value,
+    state: BotState,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match state.settings.apply(section, patch).await {
+        Ok(settings) => Ok(warp::reply::json(&settings).into_response()),
+        Err(e @ settings::Error::Save(_)) => Ok(warp::reply::with_status(
+            e.to_string(),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response()),
+        Err(e) => Ok(
+            warp::reply::with_status(e.to_string(), warp::http::StatusCode::BAD_REQUEST)
+                .into_response(),
+        ),
+    }
+}
+
 async fn credit_request(
-    chatters_list: ChattersList,
-    event_list: SafeTwitchEventList,
+    query: CreditsQuery,
+    state: BotState,
+    roll: SafeCreditsRoll,
 ) -> std::result::Result<impl Reply, Infallible> {
-    match generate_credit_page(&chatters_list, &event_list).await {
+    let layout = query.layout.unwrap_or_default();
+
+    if let Some(ref credits) = *roll.lock().await {
+        return Ok(warp::reply::html(credits.page(layout).to_string()).into_response());
+    }
+
+    match generate_credit_page(&state, layout).await {
         Ok(page) => Ok(warp::reply::html(page).into_response()),
         Err(e) => Ok(warp::http::Response::builder()
             .body(Body::from(e.to_string()))
@@ -104,60 +649,513 @@ async fn credit_request(
     }
 }
 
-fn generate_credits_text<T: IntoIterator + Serialize>(ctx: TemplateContext<T>) -> Result<String> {
-    let template = read_index_template()?;
+async fn avatar_request(
+    user_id: String,
+    state: BotState,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match state.users.avatar(&user_id).await {
+        Some(avatar) => Ok(image_response(avatar)),
+        None => Ok(warp::http::StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+async fn image_cache_request(
+    query: ImageCacheQuery,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match image_cache::get(&query.url).await {
+        Ok(image) => Ok(image_response(image)),
+        Err(e @ (image_cache::Error::Fetch(_) | image_cache::Error::TooLarge)) => Ok(
+            warp::reply::with_status(e.to_string(), warp::http::StatusCode::BAD_GATEWAY)
+                .into_response(),
+        ),
+        Err(e) => Ok(
+            warp::reply::with_status(e.to_string(), warp::http::StatusCode::FORBIDDEN)
+                .into_response(),
+        ),
+    }
+}
+
+fn image_response(image: CachedImage) -> warp::reply::Response {
+    warp::http::Response::builder()
+        .header("content-type", image.content_type)
+        .header("cache-control", "max-age=3600")
+        .body(Body::from(image.bytes))
+        .unwrap()
+}
+
+async fn activity_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    Ok(warp::reply::json(&state.activity.series().await))
+}
+
+async fn unfurl_request(
+    query: UnfurlQuery,
+    state: BotState,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match state.unfurler.preview(&query.url).await {
+        Ok(Some(preview)) => Ok(warp::reply::json(&preview).into_response()),
+        Ok(None) => Ok(warp::http::StatusCode::NO_CONTENT.into_response()),
+        Err(e @ unfurl::Error::HostNotAllowed(_)) => Ok(warp::reply::with_status(
+            e.to_string(),
+            warp::http::StatusCode::FORBIDDEN,
+        )
+        .into_response()),
+        Err(e @ unfurl::Error::InvalidUrl) => Ok(warp::reply::with_status(
+            e.to_string(),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+        .into_response()),
+        Err(e) => Ok(
+            warp::reply::with_status(e.to_string(), warp::http::StatusCode::BAD_GATEWAY)
+                .into_response(),
+        ),
+    }
+}
+
+async fn commands_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let category = state.stream.category().await;
+
+    Ok(warp::reply::json(&state.commands.list(category.as_deref())))
+}
+
+async fn notes_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let notes = state
+        .notes
+        .all()
+        .await
+        .into_iter()
+        .map(|(login, notes)| {
+            let notes = notes
+                .into_iter()
+                .map(|mut note| {
+                    note.author = redact::name(&note.author);
+                    note
+                })
+                .collect::<Vec<_>>();
 
-    add_chatters_to_index_page(ctx, template.as_str())
+            (redact::name(&login), notes)
+        })
+        .collect::<HashMap<_, _>>();
+
+    Ok(warp::reply::json(&notes))
 }
 
-fn read_index_template() -> Result<String> {
-    let file_path = Path::new("./public/index.template.html");
-    let mut file = fs::File::open(file_path)?;
-    let mut buffer = String::new();
+async fn mentions_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let mentions = state
+        .mentions
+        .recent()
+        .await
+        .into_iter()
+        .map(|mut mention| {
+            mention.user_name = redact::name(&mention.user_name);
+            mention
+        })
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::json(&mentions))
+}
 
-    file.read_to_string(&mut buffer)?;
+async fn alerts_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let alerts = state
+        .command_guard
+        .alerts()
+        .await
+        .into_iter()
+        .map(|mut alert| {
+            alert.user_name = redact::name(&alert.user_name);
+            alert
+        })
+        .collect::<Vec<_>>();
 
-    Ok(buffer)
+    Ok(warp::reply::json(&alerts))
 }
 
-fn add_chatters_to_index_page<T: IntoIterator + Serialize>(
-    ctx: TemplateContext<T>,
-    index_template: &str,
-) -> Result<String> {
-    let mut tt = TinyTemplate::new();
-    let context = Content {
-        chatters: ctx.chatters,
-        followers: ctx.followers,
-        subscribers: ctx.subscribers,
+async fn alert_queue_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let alerts = state
+        .alert_queue
+        .recent()
+        .await
+        .into_iter()
+        .map(|mut alert| {
+            alert.user_name = redact::name(&alert.user_name);
+            alert
+        })
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::json(&alerts))
+}
+
+async fn alert_action_request(
+    id: u64,
+    action: AlertAction,
+    state: BotState,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    let alert = match action {
+        AlertAction::Skip => alert_queue::skip(&state, Some(id)).await,
+        AlertAction::Replay => alert_queue::replay(&state, Some(id)).await,
     };
 
-    tt.add_template("index", index_template)?;
-    tt.add_formatter("followers", chatter_name_formatter);
-    tt.add_formatter("subscribers", chatter_name_formatter);
-    tt.add_formatter("chatters", chatter_name_formatter);
+    match alert {
+        Some(mut alert) => {
+            alert.user_name = redact::name(&alert.user_name);
+
+            Ok(warp::reply::json(&alert).into_response())
+        }
+        None => Ok(warp::http::StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+async fn redemption_resolve_request(
+    reward_id: String,
+    redemption_id: String,
+    resolution: Resolution,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match rewards::resolve(&reward_id, &redemption_id, resolution).await {
+        Ok(()) => Ok(warp::http::StatusCode::NO_CONTENT.into_response()),
+        Err(e) => {
+            Ok(warp::reply::with_status(e, warp::http::StatusCode::BAD_GATEWAY).into_response())
+        }
+    }
+}
+
+async fn credit_message_review_request(
+    id: u64,
+    review: Review,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match credit_messages::review(id, review) {
+        Some(mut message) => {
+            message.user_name = redact::name(&message.user_name);
+
+            Ok(warp::reply::json(&message).into_response())
+        }
+        None => Ok(warp::http::StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+async fn automation_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    Ok(warp::reply::json(&state.automation.pipelines().await))
+}
+
+async fn automation_reload_request(
+    state: BotState,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match state.automation.reload().await {
+        Ok(_) => Ok(warp::reply::json(&state.automation.pipelines().await).into_response()),
+        Err(e) => Ok(warp::reply::with_status(
+            e.to_string(),
+            warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response()),
+    }
+}
+
+async fn delegation_remove_request(
+    user_id: String,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    match utils::remove_delegation(&user_id).await {
+        Ok(true) => Ok(warp::http::StatusCode::NO_CONTENT.into_response()),
+        Ok(false) => Ok(warp::http::StatusCode::NOT_FOUND.into_response()),
+        Err(e) => Ok(warp::reply::with_status(
+            e.to_string(),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response()),
+    }
+}
+
+async fn clips_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let clips = state.clips.list(state.stats.session_start()).await;
+
+    Ok(warp::reply::json(&clips))
+}
+
+async fn modlog_request(
+    query: ModLogQuery,
+    state: BotState,
+) -> std::result::Result<impl Reply, Infallible> {
+    let entries = state
+        .modlog
+        .entries(query.flagged)
+        .await
+        .into_iter()
+        .map(|mut entry| {
+            // the admin page follows the streamer privacy mode
+            entry.user_name = redact::name(&entry.user_name).into();
+            entry.suspected_evasion = entry
+                .suspected_evasion
+                .map(|sanctioned| redact::name(&sanctioned).into());
+            entry
+        })
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::json(&entries))
+}
+
+/// Messages the chat overlay shows on load, the same ones it gets on the overlay WebSocket
+async fn chat_history_request(
+    query: ChatHistoryQuery,
+    state: BotState,
+) -> std::result::Result<warp::reply::Response, Infallible> {
+    if !overlay_auth::is_authorized(query.token.as_deref()) {
+        return Ok(warp::http::StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let entries = state
+        .modlog
+        .entries(false)
+        .await
+        .into_iter()
+        .filter(|entry| !query.highlighted || entry.highlighted)
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::json(&entries).into_response())
+}
+
+async fn weekly_report_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let to = Utc::now();
+    let report = WeeklyReport::new(
+        to - TimeDelta::days(7),
+        to,
+        &state.subscriptions.lapses().await,
+    );
+
+    Ok(warp::reply::json(&report))
+}
+
+async fn lapses_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    let lapses = state
+        .subscriptions
+        .lapses()
+        .await
+        .into_iter()
+        .map(|mut lapse| {
+            lapse.user_name = redact::name(&lapse.user_name);
+            lapse
+        })
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::json(&lapses))
+}
+
+async fn stats_request(state: BotState) -> std::result::Result<impl Reply, Infallible> {
+    Ok(warp::reply::json(&state.stats.snapshot().await))
+}
+
+async fn overlay_session(socket: WebSocket, mut events: broadcast::Receiver<SequencedEvent>) {
+    let (mut sink, mut incoming) = socket.split();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(SequencedEvent { event, .. }) => {
+                    let text = serde_json::to_string(&event).expect("Overlay event is serializable");
 
-    Ok(tt.render("index", &context)?)
+                    if sink.send(Message::text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("overlay connection lagged, {skipped} events skipped");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => (),
+                _ => break,
+            },
+        }
+    }
 }
 
-fn chatter_name_formatter(name: &Value, out: &mut String) -> tinytemplate::error::Result<()> {
-    if let Value::String(str) = name {
-        out.write_str(str)?;
-        out.write_char('\n')?;
+async fn logs_session(socket: WebSocket, level: LogLevel) {
+    let (mut sink, mut incoming) = socket.split();
+    let (kept, mut records) = logs::subscribe(level);
+
+    for record in kept {
+        let text = serde_json::to_string(&record).expect("Log record is serializable");
+
+        if sink.send(Message::text(text)).await.is_err() {
+            return;
+        }
     }
 
-    Ok(())
+    loop {
+        tokio::select! {
+            record = records.recv() => match record {
+                Ok(record) if record.level >= level => {
+                    let text = serde_json::to_string(&record).expect("Log record is serializable");
+
+                    if sink.send(Message::text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                // not logged, the warning would be another record to skip
+                Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => (),
+                _ => break,
+            },
+        }
+    }
 }
 
-async fn generate_credit_page(
-    chatters_list: &ChattersList,
-    event_list: &SafeTwitchEventList,
-) -> Result<String> {
-    let guard1 = chatters_list.lock().await;
-    let guard2 = event_list.get_followers().await;
-    let guard3 = event_list.get_subscribers().await;
+/// Overlay events as server-sent events, the ones missed after `last_id` come first
+fn overlay_event_stream(
+    bus: &OverlayBus,
+    last_id: Option<u64>,
+) -> impl Stream<Item = Result<warp::sse::Event, serde_json::Error>> + Send + 'static {
+    // subscribed before taking the missed events so that none falls in between
+    let events = bus.subscribe();
+    let missed = last_id
+        .map(|last_id| bus.since(last_id))
+        .unwrap_or_default();
+    let last_missed = missed.last().map_or(0, |sequenced| sequenced.id);
+    let live = futures::stream::unfold(events, move |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(sequenced) if sequenced.id <= last_missed => (),
+                Ok(sequenced) => return Some((sequenced, events)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("overlay event stream lagged, {skipped} events skipped");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    futures::stream::iter(missed).chain(live).map(|sequenced| {
+        warp::sse::Event::default()
+            .id(sequenced.id.to_string())
+            .json_data(&sequenced.event)
+    })
+}
+
+async fn privacy_export_request(
+    request: PrivacyRequest,
+    state: BotState,
+) -> std::result::Result<impl Reply, Infallible> {
+    let data = privacy::export(&state, &request.user_id, request.login.as_deref()).await;
 
-    let template_context =
-        TemplateContext::new(guard1.to_owned(), guard2.to_owned(), guard3.to_owned());
+    Ok(warp::reply::json(&data))
+}
+
+async fn privacy_forget_request(
+    request: PrivacyRequest,
+    state: BotState,
+) -> std::result::Result<impl Reply, Infallible> {
+    let report = privacy::forget(&state, &request.user_id, request.login.as_deref()).await;
+
+    Ok(warp::reply::json(&report))
+}
+
+/// `current` is the session being streamed, the other IDs are archived sessions
+async fn session_export_request(
+    id: String,
+    state: BotState,
+) -> std::result::Result<impl Reply, Infallible> {
+    let session = if id == "current" {
+        Ok(Some(sessions::current(&state).await))
+    } else {
+        sessions::archived(&state, &id)
+    };
+
+    let session = match session {
+        Ok(Some(session)) => session,
+        Ok(None) => return Ok(warp::http::StatusCode::NOT_FOUND.into_response()),
+        Err(e) => {
+            tracing::error!("Unable to read the archived session {id}: {e}");
+            return Ok(warp::http::StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+    let disposition = format!("attachment; filename=\"session_{}.json\"", session.id);
+
+    Ok(warp::reply::with_header(
+        warp::reply::json(&session),
+        "content-disposition",
+        disposition,
+    )
+    .into_response())
+}
+
+async fn scene_change_request(
+    change: SceneChange,
+    state: BotState,
+) -> std::result::Result<impl Reply, Infallible> {
+    tracing::debug!("scene changed to {}", change.scene);
+    breaks::scene_changed(&state, &change.scene).await;
+    state.notifier.set_current_scene(change.scene).await;
+
+    Ok(warp::http::StatusCode::NO_CONTENT)
+}
+
+async fn generate_credit_page(state: &BotState, layout: CreditsLayout) -> credits::Result<String> {
+    state.users.resolve().await;
+
+    let styles = state.users.styles().await;
+
+    let chatters = state.session.get_chatters().await;
+    let followers = state.session.get_followers().await;
+    let subscribers = state.session.get_subscribers().await;
+    let lurkers = state.lurkers.lock().await;
+    let presence = state.presence.lock().await;
+    // the chat knows the latest name of a viewer, the polled one may be older
+    let silent_viewers = presence
+        .iter()
+        .filter(|(id, _)| !chatters.contains_key(id.as_str()))
+        .map(|(id, name)| (id.as_str(), name.as_str()));
+    let profiles = CreditProfiles {
+        chatters: state
+            .users
+            .profiles_of(
+                chatters
+                    .keys()
+                    .map(AsRef::as_ref)
+                    .chain(presence.keys().map(String::as_str)),
+            )
+            .await,
+        followers: state.users.profiles_of(followers.keys()).await,
+        subscribers: state.users.profiles_of(subscribers.keys()).await,
+    };
+    let lurker_profiles = state.users.profiles_of(lurkers.keys()).await;
+
+    let follower_count = match config::get_follower_count_mode() {
+        FollowerCountMode::Session => None,
+        FollowerCountMode::Total => state.stream.followers_total().await,
+    };
+    let template_context = TemplateContext::new(
+        CreditEntry::from_session(
+            chatters
+                .iter()
+                .map(|(id, name)| (&**id, &**name))
+                .chain(silent_viewers),
+            &profiles.chatters,
+            &styles,
+        ),
+        CreditEntry::from_session(session_users(&followers), &profiles.followers, &styles),
+        CreditEntry::from_session(session_users(&subscribers), &profiles.subscribers, &styles),
+    )
+    .with_lurkers(CreditEntry::from_session(
+        session_users(&lurkers),
+        &lurker_profiles,
+        &styles,
+    ))
+    .with_follower_count(follower_count)
+    .with_streaks(state.streaks.longest_streaks(CREDITS_STREAKS_COUNT).await)
+    .with_cheerers(state.session.top_cheerers(CREDITS_CHEERERS_COUNT).await)
+    .with_resubscribers(state.session.resubscribers().await)
+    .with_gifters(state.session.top_gifters(CREDITS_GIFTERS_COUNT).await)
+    .with_rewards(state.session.reward_credits().await)
+    .with_messages(credit_messages::approved())
+    .with_profiles(profiles)
+    .with_kv(kv::all());
+
+    metrics::timed_sync("template_render", || {
+        credits::generate_credits_text(template_context, layout)
+    })
+}
 
-    generate_credits_text(template_context)
+fn session_users(users: &SessionUsers) -> impl Iterator<Item = (&str, &str)> {
+    users.iter().map(|(id, name)| (id.as_str(), name.as_str()))
 }
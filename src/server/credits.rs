@@ -0,0 +1,714 @@
+//! Rendering of the credits page from the session lists.
+//!
+//! Kept apart from the routes so that the templates can be rendered without a
+//! running bot, the tests compare the output with the snapshots in `tests/snapshots`.
+//!
+//! `chatters`, `followers`, `subscribers` and `lurkers` are lists of entries with the
+//! `id`, `login` and `display_name` of the user, e.g. `{ user.login }` in a `for` loop,
+//! the `login` is empty if the profile of the user could not be resolved,
+//! the `color` of the name in the chat and the image of the subscriber `badge` if known.
+//! `cheerers` lists the `name` and the `bits` of the top cheerers, the most first.
+//! `resubscribers` lists the `name`, the total `months` and the `streak` of the users
+//! who resubscribed, `gifters` the `name` and the `gifts` of the top subscription gifters.
+//! `rewards` lists the `title` and the `names` of the credit lists of the channel point
+//! rewards, `messages` the `name` and the `text` of the approved viewer messages.
+//! The list formatters, e.g. `{ value | chatters }`, print the display name.
+//! The values of the key-value store are in `kv`, e.g. `{ kv.run }`.
+//!
+//! The templates are taken from `HEWPME_CREDITS_THEME_DIR` if set, the missing ones
+//! from `public`. The partials of `partials/<name>.html` in both directories are pasted
+//! in place of `{{ include <name> }}`, a theme may replace only some of them.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Formatter, Write};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tinytemplate::TinyTemplate;
+
+use crate::badges::ChatStyle;
+use crate::config;
+use crate::credit_messages::CreditLine;
+use crate::helper::{CheerEntry, GiftEntry, ResubEntry, RewardCredits};
+use crate::streaks::StreakEntry;
+use crate::users::UserProfile;
+
+/// Directory of the templates shipped with the bot
+const DEFAULT_THEME_DIR: &str = "./public";
+const PARTIALS_DIR: &str = "partials";
+const PARTIAL_EXTENSION: &str = ".html";
+const INCLUDE_TAG_START: &str = "{{ include ";
+const INCLUDE_TAG_END: &str = " }}";
+/// Partials including each other deeper than this are considered a cycle
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+#[derive(Serialize, Debug)]
+struct Content<T>
+where
+    T: IntoIterator,
+{
+    chatters: Option<T>,
+    followers: Option<T>,
+    subscribers: Option<T>,
+    lurkers: Option<T>,
+    streaks: Option<Vec<StreakEntry>>,
+    cheerers: Option<Vec<CheerEntry>>,
+    resubscribers: Option<Vec<ResubEntry>>,
+    gifters: Option<Vec<GiftEntry>>,
+    rewards: Option<Vec<RewardCredits>>,
+    messages: Option<Vec<CreditLine>>,
+    profiles: CreditProfiles,
+    follower_count: Option<u64>,
+    kv: BTreeMap<String, String>,
+}
+
+/// Helix profiles of the credited users, e.g. to render their avatars
+#[derive(Serialize, Debug, Default)]
+pub(super) struct CreditProfiles {
+    pub chatters: Vec<UserProfile>,
+    pub followers: Vec<UserProfile>,
+    pub subscribers: Vec<UserProfile>,
+}
+
+/// Credited user of a session list
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(super) struct CreditEntry {
+    pub id: String,
+    /// `None` without the resolved profile, the display name may differ from it
+    pub login: Option<String>,
+    pub display_name: String,
+    /// Name color in the chat as `#RRGGBB`
+    pub color: Option<String>,
+    /// Image of the subscriber badge
+    pub badge: Option<String>,
+}
+
+impl CreditEntry {
+    /// Entries of the session users sorted by display name, the resolved profiles
+    /// take precedence over the names the users were seen with
+    pub(super) fn from_session<'a, I>(
+        users: I,
+        profiles: &[UserProfile],
+        styles: &HashMap<String, ChatStyle>,
+    ) -> Vec<CreditEntry>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut entries = users
+            .into_iter()
+            .map(|(id, name)| {
+                let style = styles.get(id);
+                let (login, display_name) = match profiles.iter().find(|profile| profile.id == id) {
+                    Some(profile) => (Some(profile.login.clone()), profile.display_name.clone()),
+                    None => (None, name.to_string()),
+                };
+
+                CreditEntry {
+                    id: id.to_string(),
+                    login,
+                    display_name,
+                    color: style.and_then(|style| style.color.clone()),
+                    badge: style
+                        .and_then(ChatStyle::subscriber_badge)
+                        .map(String::from),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by_cached_key(|entry| entry.display_name.to_lowercase());
+        entries
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct ServerError {
+    kind: String,
+    message: String,
+}
+
+#[derive(Debug)]
+pub(super) struct TemplateContext<T: IntoIterator + Serialize> {
+    chatters: Option<T>,
+    followers: Option<T>,
+    subscribers: Option<T>,
+    lurkers: Option<T>,
+    streaks: Option<Vec<StreakEntry>>,
+    /// Users who cheered the most bits during the session
+    cheerers: Option<Vec<CheerEntry>>,
+    /// Users who resubscribed during the session
+    resubscribers: Option<Vec<ResubEntry>>,
+    /// Users who gifted the most subscriptions during the session
+    gifters: Option<Vec<GiftEntry>>,
+    /// Viewers credited by the channel point rewards
+    rewards: Option<Vec<RewardCredits>>,
+    /// Approved messages of the viewers
+    messages: Option<Vec<CreditLine>>,
+    profiles: CreditProfiles,
+    /// Total followers of the channel, `None` when the session followers are counted
+    follower_count: Option<u64>,
+    /// Values of the key-value store, e.g. `{ kv.run }`
+    kv: BTreeMap<String, String>,
+}
+
+impl<T: IntoIterator + Serialize + Clone> TemplateContext<T> {
+    pub(super) fn new(chatters_list: T, followers_list: T, subscriber_list: T) -> Self {
+        let c = chatters_list.clone().into_iter().count();
+        let f = followers_list.clone().into_iter().count();
+        let s = subscriber_list.clone().into_iter().count();
+
+        let chatters = if c > 0 { Some(chatters_list) } else { None };
+        let followers = if f > 0 { Some(followers_list) } else { None };
+        let subscribers = if s > 0 { Some(subscriber_list) } else { None };
+
+        TemplateContext {
+            chatters,
+            followers,
+            subscribers,
+            lurkers: None,
+            streaks: None,
+            cheerers: None,
+            resubscribers: None,
+            gifters: None,
+            rewards: None,
+            messages: None,
+            profiles: CreditProfiles::default(),
+            follower_count: None,
+            kv: BTreeMap::new(),
+        }
+    }
+
+    pub(super) fn with_kv(mut self, kv: BTreeMap<String, String>) -> Self {
+        self.kv = kv;
+
+        self
+    }
+
+    pub(super) fn with_profiles(mut self, profiles: CreditProfiles) -> Self {
+        self.profiles = profiles;
+
+        self
+    }
+
+    pub(super) fn with_follower_count(mut self, follower_count: Option<u64>) -> Self {
+        self.follower_count = follower_count;
+
+        self
+    }
+
+    pub(super) fn with_lurkers(mut self, lurkers: T) -> Self {
+        let count = lurkers.clone().into_iter().count();
+
+        self.lurkers = if count > 0 { Some(lurkers) } else { None };
+
+        self
+    }
+
+    pub(super) fn with_streaks(mut self, streaks: Vec<StreakEntry>) -> Self {
+        self.streaks = if streaks.is_empty() {
+            None
+        } else {
+            Some(streaks)
+        };
+
+        self
+    }
+
+    pub(super) fn with_cheerers(mut self, cheerers: Vec<CheerEntry>) -> Self {
+        self.cheerers = if cheerers.is_empty() {
+            None
+        } else {
+            Some(cheerers)
+        };
+
+        self
+    }
+
+    pub(super) fn with_resubscribers(mut self, resubscribers: Vec<ResubEntry>) -> Self {
+        self.resubscribers = if resubscribers.is_empty() {
+            None
+        } else {
+            Some(resubscribers)
+        };
+
+        self
+    }
+
+    pub(super) fn with_gifters(mut self, gifters: Vec<GiftEntry>) -> Self {
+        self.gifters = if gifters.is_empty() {
+            None
+        } else {
+            Some(gifters)
+        };
+
+        self
+    }
+
+    pub(super) fn with_rewards(mut self, rewards: Vec<RewardCredits>) -> Self {
+        self.rewards = if rewards.is_empty() {
+            None
+        } else {
+            Some(rewards)
+        };
+
+        self
+    }
+
+    pub(super) fn with_messages(mut self, messages: Vec<CreditLine>) -> Self {
+        self.messages = if messages.is_empty() {
+            None
+        } else {
+            Some(messages)
+        };
+
+        self
+    }
+}
+
+pub(super) type Result<T> = std::result::Result<T, ServerError>;
+
+impl core::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "kind: {}, message: {}", self.kind, self.message)
+    }
+}
+
+impl From<std::io::Error> for ServerError {
+    fn from(value: std::io::Error) -> Self {
+        ServerError {
+            kind: String::from("io"),
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<tinytemplate::error::Error> for ServerError {
+    fn from(value: tinytemplate::error::Error) -> Self {
+        ServerError {
+            kind: String::from("template"),
+            message: value.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum CreditsLayout {
+    #[default]
+    List,
+    /// Avatars of followers and subscribers in a grid
+    Grid,
+}
+
+impl CreditsLayout {
+    fn template_name(self) -> &'static str {
+        match self {
+            CreditsLayout::List => "index.template.html",
+            CreditsLayout::Grid => "grid.template.html",
+        }
+    }
+}
+
+/// Directories the templates are looked up in, the first one containing a file wins
+struct Theme {
+    dirs: Vec<PathBuf>,
+}
+
+impl Theme {
+    fn from_config() -> Self {
+        Theme {
+            dirs: config::get_credits_theme_dir()
+                .into_iter()
+                .chain([PathBuf::from(DEFAULT_THEME_DIR)])
+                .collect(),
+        }
+    }
+
+    fn read_template(&self, layout: CreditsLayout) -> Result<String> {
+        let path = self
+            .dirs
+            .iter()
+            .map(|dir| dir.join(layout.template_name()))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not found", layout.template_name()),
+                )
+            })?;
+
+        read_file(&path)
+    }
+
+    /// Names and texts of the partials, the ones of the theme replace the default ones
+    fn read_partials(&self) -> Result<Vec<(String, String)>> {
+        let mut partials: Vec<(String, String)> = Vec::new();
+
+        for dir in &self.dirs {
+            let Ok(entries) = fs::read_dir(dir.join(PARTIALS_DIR)) else {
+                continue;
+            };
+
+            for entry in entries {
+                let path = entry?.path();
+                let Some(name) = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(PARTIAL_EXTENSION))
+                else {
+                    continue;
+                };
+
+                if partials.iter().all(|(known, _)| known != name) {
+                    let text = read_file(&path)?;
+                    // the include tag stands on its own line, which already ends the partial
+                    let text = text
+                        .strip_suffix('\n')
+                        .map(|text| text.strip_suffix('\r').unwrap_or(text))
+                        .unwrap_or(&text)
+                        .to_string();
+
+                    partials.push((name.to_string(), text));
+                }
+            }
+        }
+
+        Ok(partials)
+    }
+}
+
+pub(super) fn generate_credits_text<T: IntoIterator + Serialize>(
+    ctx: TemplateContext<T>,
+    layout: CreditsLayout,
+) -> Result<String> {
+    render_theme(&Theme::from_config(), ctx, layout)
+}
+
+fn render_theme<T: IntoIterator + Serialize>(
+    theme: &Theme,
+    ctx: TemplateContext<T>,
+    layout: CreditsLayout,
+) -> Result<String> {
+    let template = theme.read_template(layout)?;
+    let partials = theme.read_partials()?;
+    let template = expand_includes(&template, &partials, 0)?;
+
+    add_chatters_to_index_page(ctx, template.as_str())
+}
+
+/// Paste the partials in place of their include tags
+fn expand_includes(template: &str, partials: &[(String, String)], depth: usize) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(INCLUDE_TAG_START) {
+        let after_start = &rest[start + INCLUDE_TAG_START.len()..];
+        let end = after_start
+            .find(INCLUDE_TAG_END)
+            .ok_or_else(|| template_error(String::from("include tag is not closed")))?;
+        let name = after_start[..end].trim();
+        let (_, partial) = partials
+            .iter()
+            .find(|(known, _)| known == name)
+            .ok_or_else(|| template_error(format!("partial {name} is not found")))?;
+
+        if depth == MAX_INCLUDE_DEPTH {
+            return Err(template_error(format!("partial {name} includes itself")));
+        }
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&expand_includes(partial, partials, depth + 1)?);
+        rest = &after_start[end + INCLUDE_TAG_END.len()..];
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+fn template_error(message: String) -> ServerError {
+    ServerError {
+        kind: String::from("template"),
+        message,
+    }
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut buffer = String::new();
+
+    file.read_to_string(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+fn add_chatters_to_index_page<T: IntoIterator + Serialize>(
+    ctx: TemplateContext<T>,
+    index_template: &str,
+) -> Result<String> {
+    let mut tt = TinyTemplate::new();
+    let context = Content {
+        chatters: ctx.chatters,
+        followers: ctx.followers,
+        subscribers: ctx.subscribers,
+        lurkers: ctx.lurkers,
+        streaks: ctx.streaks,
+        cheerers: ctx.cheerers,
+        resubscribers: ctx.resubscribers,
+        gifters: ctx.gifters,
+        rewards: ctx.rewards,
+        messages: ctx.messages,
+        profiles: ctx.profiles,
+        follower_count: ctx.follower_count,
+        kv: ctx.kv,
+    };
+
+    tt.add_template("index", index_template)?;
+    tt.add_formatter("followers", chatter_name_formatter);
+    tt.add_formatter("subscribers", chatter_name_formatter);
+    tt.add_formatter("chatters", chatter_name_formatter);
+    tt.add_formatter("lurkers", chatter_name_formatter);
+
+    Ok(tt.render("index", &context)?)
+}
+
+fn chatter_name_formatter(entry: &Value, out: &mut String) -> tinytemplate::error::Result<()> {
+    let name = match entry {
+        Value::Object(entry) => entry.get("display_name").and_then(Value::as_str),
+        Value::String(name) => Some(name.as_str()),
+        _ => None,
+    };
+
+    if let Some(name) = name {
+        out.write_str(name)?;
+        out.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Rewrite the snapshots instead of comparing with them, e.g. after a template change
+    const UPDATE_SNAPSHOTS_VAR: &str = "HEWPME_UPDATE_SNAPSHOTS";
+
+    fn entries(names: &[&str]) -> Vec<CreditEntry> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| CreditEntry {
+                id: format!("{}", idx + 100),
+                login: Some(name.to_lowercase()),
+                display_name: name.to_string(),
+                color: None,
+                badge: (*name == "Mallory")
+                    .then(|| String::from("https://example.com/subscriber.png")),
+            })
+            .collect()
+    }
+
+    fn profile(id: &str, name: &str) -> UserProfile {
+        UserProfile {
+            id: id.to_string(),
+            login: name.to_lowercase(),
+            display_name: name.to_string(),
+            profile_image_url: Some(format!("https://example.com/{id}.png")),
+            created_at: String::from("2020-01-01T00:00:00Z"),
+        }
+    }
+
+    fn session() -> TemplateContext<Vec<CreditEntry>> {
+        TemplateContext::new(
+            entries(&["Alice", "Bob", "Carol"]),
+            entries(&["Dave", "Eve"]),
+            entries(&["Mallory"]),
+        )
+        .with_lurkers(entries(&["Trent"]))
+        .with_streaks(vec![
+            StreakEntry {
+                name: String::from("Alice"),
+                streak: 12,
+            },
+            StreakEntry {
+                name: String::from("Bob"),
+                streak: 3,
+            },
+        ])
+        .with_cheerers(vec![
+            CheerEntry {
+                name: String::from("Carol"),
+                bits: 500,
+            },
+            CheerEntry {
+                name: String::from("Alice"),
+                bits: 100,
+            },
+        ])
+        .with_resubscribers(vec![
+            ResubEntry {
+                name: String::from("Mallory"),
+                months: 24,
+                streak: Some(12),
+            },
+            ResubEntry {
+                name: String::from("Bob"),
+                months: 3,
+                streak: None,
+            },
+        ])
+        .with_gifters(vec![GiftEntry {
+            name: String::from("Eve"),
+            gifts: 5,
+        }])
+        .with_rewards(vec![RewardCredits {
+            title: String::from("Hydrated"),
+            names: vec![String::from("Bob"), String::from("Eve")],
+        }])
+        .with_messages(vec![CreditLine {
+            name: String::from("Carol"),
+            text: String::from("gg wp"),
+        }])
+        .with_profiles(CreditProfiles {
+            chatters: vec![profile("1", "Alice"), profile("2", "Bob")],
+            followers: vec![profile("4", "Dave"), profile("5", "Eve")],
+            subscribers: vec![profile("6", "Mallory")],
+        })
+    }
+
+    fn assert_snapshot(name: &str, rendered: &str) {
+        let path = PathBuf::from("tests/snapshots").join(format!("{name}.html"));
+
+        if env::var_os(UPDATE_SNAPSHOTS_VAR).is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, rendered).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+            panic!(
+                "{}: {e}, run with {UPDATE_SNAPSHOTS_VAR}=1 to create it",
+                path.display()
+            )
+        });
+
+        assert_eq!(
+            rendered,
+            expected,
+            "{} is outdated, run with {UPDATE_SNAPSHOTS_VAR}=1 if the change is intended",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn list_layout() {
+        let rendered = generate_credits_text(session(), CreditsLayout::List).unwrap();
+
+        assert_snapshot("credits_list", &rendered);
+    }
+
+    #[test]
+    fn grid_layout() {
+        let rendered = generate_credits_text(session(), CreditsLayout::Grid).unwrap();
+
+        assert_snapshot("credits_grid", &rendered);
+    }
+
+    #[test]
+    fn total_follower_count() {
+        let rendered = generate_credits_text(
+            session().with_follower_count(Some(1234)),
+            CreditsLayout::List,
+        )
+        .unwrap();
+
+        assert_snapshot("credits_list_total_followers", &rendered);
+    }
+
+    #[test]
+    fn empty_session() {
+        for layout in [CreditsLayout::List, CreditsLayout::Grid] {
+            let empty = TemplateContext::new(Vec::<CreditEntry>::new(), Vec::new(), Vec::new())
+                .with_lurkers(Vec::new())
+                .with_streaks(Vec::new())
+                .with_cheerers(Vec::new())
+                .with_resubscribers(Vec::new())
+                .with_gifters(Vec::new())
+                .with_rewards(Vec::new())
+                .with_messages(Vec::new());
+            let rendered = generate_credits_text(empty, layout).unwrap();
+            let name = format!("credits_{layout:?}_empty").to_lowercase();
+
+            assert_snapshot(&name, &rendered);
+        }
+    }
+
+    #[test]
+    fn entry_fields() {
+        let template = "{{ for user in chatters }}{ user.id } { user.login } { user.display_name };{{ endfor }}";
+        let rendered = add_chatters_to_index_page(session(), template).unwrap();
+
+        assert_eq!(rendered, "100 alice Alice;101 bob Bob;102 carol Carol;");
+    }
+
+    #[test]
+    fn theme_partials_replace_the_default_ones() {
+        let dir = env::temp_dir().join(format!("hewpme-theme-{}", std::process::id()));
+
+        fs::create_dir_all(dir.join(PARTIALS_DIR)).unwrap();
+        fs::write(
+            dir.join(PARTIALS_DIR).join("header.html"),
+            "<h1>Thanks!</h1>\n",
+        )
+        .unwrap();
+
+        let theme = Theme {
+            dirs: vec![dir.clone(), PathBuf::from(DEFAULT_THEME_DIR)],
+        };
+        let rendered = render_theme(&theme, session(), CreditsLayout::List).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+        assert!(rendered.contains("<h1>Thanks!</h1>\n"));
+        // the layout template and the other partials come from the default theme
+        assert!(rendered.contains("Alice"));
+        assert!(!rendered.contains("<h1>Cпасибо"));
+    }
+
+    #[test]
+    fn partials_including_each_other_are_refused() {
+        let partials = [
+            (String::from("a"), String::from("a{{ include b }}")),
+            (String::from("b"), String::from("b{{ include a }}")),
+        ];
+
+        assert_eq!(
+            expand_includes("<{{ include b }}>", &partials[..1], 0)
+                .unwrap_err()
+                .kind,
+            "template"
+        );
+        assert!(expand_includes("{{ include a }}", &partials, 0).is_err());
+    }
+
+    #[test]
+    fn renamed_user_is_credited_once() {
+        let users = [("1", "OldName"), ("2", "bob")];
+        let renamed = profile("1", "NewName");
+        let credited = CreditEntry::from_session(users, &[renamed], &HashMap::new());
+
+        assert_eq!(
+            credited
+                .iter()
+                .map(|entry| entry.display_name.as_str())
+                .collect::<Vec<_>>(),
+            ["bob", "NewName"]
+        );
+        assert_eq!(credited[0].login, None);
+        assert_eq!(credited[1].login.as_deref(), Some("newname"));
+    }
+}
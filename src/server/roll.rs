@@ -0,0 +1,103 @@
+//! Credits rolled automatically when the stream goes offline.
+//!
+//! The credits are rendered once at `stream.offline`, `/` serves them unchanged for
+//! `HEWPME_CREDITS_ROLL_MIN` while the overlay shows them. Afterwards the pages and
+//! the session, see [`crate::sessions`], is archived to `<app dir>/archive/<time>/` and
+//! the lists are cleared.
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io};
+
+use tokio::sync::Mutex;
+
+use crate::helper::BotState;
+use crate::overlay::{self, OverlayEvent};
+use crate::sessions::{self, SessionExport};
+
+use super::credits::CreditsLayout;
+use super::generate_credit_page;
+
+/// Credits pages rendered when the stream went offline
+#[derive(Clone)]
+pub(super) struct RolledCredits {
+    list: String,
+    grid: String,
+}
+
+impl RolledCredits {
+    pub(super) fn page(&self, layout: CreditsLayout) -> &str {
+        match layout {
+            CreditsLayout::List => &self.list,
+            CreditsLayout::Grid => &self.grid,
+        }
+    }
+}
+
+/// Credits being rolled, `None` when `/` renders the current session
+pub(super) type SafeCreditsRoll = Arc<Mutex<Option<RolledCredits>>>;
+
+pub(super) async fn run_credits_roll(state: BotState, roll: SafeCreditsRoll, duration: Duration) {
+    loop {
+        state.stream.wait_offline().await;
+
+        let credits = match render(&state).await {
+            Ok(credits) => credits,
+            Err(e) => {
+                tracing::error!("Unable to render the credits to roll: {e}");
+                continue;
+            }
+        };
+
+        tracing::info!("rolling the credits for {} s", duration.as_secs());
+        *roll.lock().await = Some(credits.clone());
+        overlay::push(
+            &state.overlay,
+            OverlayEvent::CreditsRoll {
+                seconds: duration.as_secs(),
+            },
+        );
+
+        tokio::time::sleep(duration).await;
+
+        *roll.lock().await = None;
+        overlay::push(&state.overlay, OverlayEvent::CreditsRollEnded);
+
+        let session = take_session(&state).await;
+
+        if let Err(e) = archive(&credits, &session) {
+            tracing::error!("Unable to archive the session: {e}");
+        }
+
+        sessions::save(&state, &session);
+    }
+}
+
+async fn render(state: &BotState) -> super::credits::Result<RolledCredits> {
+    Ok(RolledCredits {
+        list: generate_credit_page(state, CreditsLayout::List).await?,
+        grid: generate_credit_page(state, CreditsLayout::Grid).await?,
+    })
+}
+
+/// Session ending now, the lists are cleared unless the stream is back online
+async fn take_session(state: &BotState) -> SessionExport {
+    let keep = state.stream.is_live();
+
+    if keep {
+        tracing::info!("stream is back online, the session lists are kept");
+    }
+
+    sessions::capture(state, sessions::new_id(), !keep).await
+}
+
+fn archive(credits: &RolledCredits, session: &SessionExport) -> io::Result<()> {
+    let dir = sessions::archive_dir().join(&session.id);
+
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("credits.html"), &credits.list)?;
+    fs::write(dir.join("credits_grid.html"), &credits.grid)?;
+    sessions::write(&dir, session)?;
+    tracing::info!("session is archived to {}", dir.display());
+
+    Ok(())
+}
@@ -0,0 +1,541 @@
+//! Sessions in a versioned JSON format to move them between machines or to feed them
+//! to external credit generators.
+//!
+//! The session being streamed is exported on `/api/sessions/current/export` and the
+//! archived ones, see [`crate::server`], on `/api/sessions/<id>/export`, the ID is the
+//! name of the archive directory. `hewpme session import <file>` adds an exported
+//! session to the archive:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "id": "2024-05-01_22-10-00",
+//!   "channel": "streamer",
+//!   "started_at": "2024-05-01T19:00:00+02:00",
+//!   "ended_at": "2024-05-01T22:10:00+02:00",
+//!   "followers": [{ "user_id": "1234", "user_name": "Viewer" }],
+//!   "subscribers": [],
+//!   "chatters": [],
+//!   "lurkers": [],
+//!   "stats": { "milestones": [], "raids": [], "raid_viewers": 0, "chat_languages": [] }
+//! }
+//! ```
+//!
+//! The version is increased on incompatible changes, a file of a newer version is not
+//! imported.
+//!
+//! The archived sessions are also saved to the [`crate::storage::Storage`] of the bot,
+//! the archive directory is read for the sessions it does not have, e.g. the imported
+//! ones.
+//!
+//! The lists of the session being streamed are kept by [`run_session_lists`] from the
+//! events the chat and EventSub clients publish.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::config::FollowerCountMode;
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::milestones::{self, Milestone, MilestoneKind};
+use crate::overlay::{self, OverlayEvent};
+use crate::raids::Raid;
+use crate::stats::StatsSnapshot;
+use crate::{config, credit_messages};
+
+pub const SCHEMA_VERSION: u32 = 1;
+const ARCHIVE_DIR_NAME: &str = "archive";
+pub(crate) const SESSION_FILE_NAME: &str = "session.json";
+/// Format of the archive directory names, the IDs of the sessions
+const ID_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionExport {
+    pub schema_version: u32,
+    pub id: String,
+    pub channel: String,
+    /// `None` for the sessions archived before the format was introduced
+    pub started_at: Option<DateTime<Local>>,
+    pub ended_at: DateTime<Local>,
+    pub followers: Vec<SessionUser>,
+    pub subscribers: Vec<SessionUser>,
+    pub chatters: Vec<SessionUser>,
+    pub lurkers: Vec<SessionUser>,
+    pub stats: ExportedStats,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionUser {
+    pub user_id: String,
+    pub user_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ExportedStats {
+    pub milestones: Vec<Milestone>,
+    pub raids: Vec<Raid>,
+    pub raid_viewers: u64,
+    /// Chat messages per ISO 639-3 language code, the most used first
+    pub chat_languages: Vec<LanguageShare>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LanguageShare {
+    pub language: String,
+    pub messages: u64,
+}
+
+impl From<StatsSnapshot> for ExportedStats {
+    fn from(snapshot: StatsSnapshot) -> Self {
+        ExportedStats {
+            milestones: snapshot.milestones,
+            raids: snapshot.raids,
+            raid_viewers: snapshot.raid_viewers,
+            chat_languages: snapshot
+                .chat_languages
+                .into_iter()
+                .map(|count| LanguageShare {
+                    language: count.language.to_string(),
+                    messages: count.messages,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Session lists by user ID, as archived before the format was introduced
+#[derive(Deserialize)]
+struct LegacySession {
+    chatters: BTreeMap<String, String>,
+    followers: BTreeMap<String, String>,
+    subscribers: BTreeMap<String, String>,
+    lurkers: BTreeMap<String, String>,
+}
+
+impl SessionExport {
+    /// Legacy archives have no channel, it is the channel of the bot
+    fn from_legacy(id: &str, channel: String, legacy: LegacySession) -> Self {
+        let users = |users: &BTreeMap<String, String>| {
+            to_users(users.iter().map(|(id, name)| (id.as_str(), name.as_str())))
+        };
+        let ended_at = NaiveDateTime::parse_from_str(id, ID_FORMAT)
+            .ok()
+            .and_then(|time| Local.from_local_datetime(&time).earliest())
+            .unwrap_or_else(Local::now);
+
+        SessionExport {
+            schema_version: SCHEMA_VERSION,
+            id: id.to_string(),
+            channel,
+            started_at: None,
+            ended_at,
+            followers: users(&legacy.followers),
+            subscribers: users(&legacy.subscribers),
+            chatters: users(&legacy.chatters),
+            lurkers: users(&legacy.lurkers),
+            stats: ExportedStats::default(),
+        }
+    }
+}
+
+/// Users sorted by name for a stable file
+fn to_users<'a>(users: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<SessionUser> {
+    let mut users: Vec<SessionUser> = users
+        .map(|(user_id, user_name)| SessionUser {
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+        })
+        .collect();
+
+    users.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+    users
+}
+
+/// ID of a session archived now
+pub(crate) fn new_id() -> String {
+    Local::now().format(ID_FORMAT).to_string()
+}
+
+pub(crate) fn archive_dir() -> PathBuf {
+    config::get_app_directory_path().join(ARCHIVE_DIR_NAME)
+}
+
+/// The session being streamed, its ID is the time it started
+pub(crate) async fn current(state: &BotState) -> SessionExport {
+    let id = state.stats.session_start().format(ID_FORMAT).to_string();
+
+    capture(state, id, false).await
+}
+
+/// Session of the bot state, the lists are cleared with `clear`
+pub(crate) async fn capture(state: &BotState, id: String, clear: bool) -> SessionExport {
+    let stats = state.stats.snapshot().await;
+    let mut chatters = state.session.get_chatters().await;
+    let mut followers = state.session.get_followers().await;
+    let mut subscribers = state.session.get_subscribers().await;
+    let mut lurkers = state.lurkers.lock().await;
+    let mut raiders = state.session.get_raiders().await;
+    let session = SessionExport {
+        schema_version: SCHEMA_VERSION,
+        id,
+        channel: config::get_channel_name(),
+        started_at: Some(stats.session_start),
+        ended_at: Local::now(),
+        followers: to_users(followers.iter().map(|(id, name)| (&**id, &**name))),
+        subscribers: to_users(subscribers.iter().map(|(id, name)| (&**id, &**name))),
+        chatters: to_users(chatters.iter().map(|(id, name)| (&**id, &**name))),
+        lurkers: to_users(lurkers.iter().map(|(id, name)| (&**id, &**name))),
+        stats: stats.into(),
+    };
+
+    if clear {
+        chatters.clear();
+        followers.clear();
+        subscribers.clear();
+        lurkers.clear();
+        raiders.clear();
+        clear_stored_users(state);
+        clear_tallies(state).await;
+    }
+
+    session
+}
+
+fn clear_stored_users(state: &BotState) {
+    // the leader clears the lists for every instance
+    if !state.instance.is_leader() {
+        return;
+    }
+
+    if let Err(e) = state.storage.clear_users() {
+        tracing::error!("Unable to clear the stored session lists: {e}");
+    }
+}
+
+async fn clear_tallies(state: &BotState) {
+    state.session.clear_cheers().await;
+    state.session.clear_subscription_tallies().await;
+    state.session.clear_reward_credits().await;
+    credit_messages::clear();
+}
+
+/// Clear the session lists, e.g. of the chat before the stream started
+pub(crate) async fn reset(state: &BotState) {
+    let chatters = std::mem::take(&mut *state.session.get_chatters().await);
+
+    state.session.get_followers().await.clear();
+    state.session.get_subscribers().await.clear();
+    state.session.get_raiders().await.clear();
+    state.lurkers.lock().await.clear();
+    clear_stored_users(state);
+    clear_tallies(state).await;
+
+    tracing::info!(
+        "session lists are reset, {} chatters dropped",
+        chatters.len()
+    );
+}
+
+/// Keep the session lists from the events published on the bus, every instance
+/// keeps its own lists
+pub(crate) async fn run_session_lists(state: BotState, mut events: broadcast::Receiver<BotEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => record(&state, event).await,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("session lists lagged, {skipped} events skipped");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Add the user of the event to the session lists, anonymous gifts and cheers are
+/// not credited
+pub(crate) async fn record(state: &BotState, event: BotEvent) {
+    let lists = &state.session;
+
+    match event {
+        BotEvent::ChatMessage {
+            user_id, user_name, ..
+        } => {
+            let added = lists
+                .add_chatter(state.names.intern(&user_id), state.names.intern(&user_name))
+                .await;
+
+            if let Some(count) = added {
+                milestones::check(state, MilestoneKind::Chatter, count, &user_name).await;
+            }
+        }
+        BotEvent::Follow { user_id, user_name } => {
+            let Some(count) = lists.add_follower(&user_id, user_name.as_str()).await else {
+                return;
+            };
+
+            milestones::check(state, MilestoneKind::Follower, count, &user_name).await;
+
+            let shown = match config::get_follower_count_mode() {
+                FollowerCountMode::Session => u64::try_from(count).ok(),
+                FollowerCountMode::Total => state.stream.add_follower().await,
+            };
+
+            if let Some(count) = shown {
+                overlay::push(&state.overlay, OverlayEvent::FollowersUpdated { count });
+            }
+        }
+        BotEvent::Subscribe { user_id, user_name } => {
+            lists.add_subscriber(&user_id, user_name).await;
+        }
+        BotEvent::Raid {
+            user_id, user_name, ..
+        } => lists.add_raider(&user_id, user_name).await,
+        BotEvent::Resubscribe {
+            user_id,
+            user_name,
+            months,
+            streak,
+        } => {
+            lists
+                .add_resubscriber(&user_id, user_name, months, streak)
+                .await;
+        }
+        BotEvent::GiftSubscriptions {
+            user_id: Some(user_id),
+            user_name: Some(user_name),
+            count,
+        } => lists.add_gift(&user_id, user_name, count).await,
+        BotEvent::Cheer {
+            user_id: Some(user_id),
+            user_name: Some(user_name),
+            bits,
+        } => lists.add_cheer(&user_id, user_name, bits).await,
+        BotEvent::GiftSubscriptions { .. }
+        | BotEvent::Cheer { .. }
+        | BotEvent::CategoryChanged { .. }
+        | BotEvent::StreamOnline
+        | BotEvent::StreamOffline
+        | BotEvent::AdBreak { .. } => (),
+    }
+}
+
+/// The stream went online, a new session starts with empty lists unless the stream
+/// is back soon after going offline
+pub(crate) async fn stream_started(state: &BotState) {
+    if let Some(offline) = state.stream.offline_for() {
+        if offline < config::get_session_resume_window() {
+            tracing::info!(
+                "stream is back after {} s, the session continues",
+                offline.as_secs()
+            );
+            return;
+        }
+    }
+
+    reset(state).await;
+    state.streaks.start_session().await;
+}
+
+/// The stream went offline, the session is archived to generate the credits later
+/// unless the stream is back within the resume window, the lists are kept until the
+/// next stream
+pub(crate) fn stream_ended(state: &BotState) {
+    // the credits roll archives the session along with the rendered credits
+    if config::get_credits_roll_duration().is_some() {
+        return;
+    }
+
+    let state = state.clone();
+
+    tokio::spawn(async move {
+        let window = config::get_session_resume_window();
+
+        tokio::time::sleep(window).await;
+
+        // a later `stream.offline` archives the session once its own window has passed
+        let ended = !state.stream.is_live()
+            && state
+                .stream
+                .offline_for()
+                .is_some_and(|offline| offline >= window);
+
+        if ended && state.instance.is_leader() {
+            archive(&state).await;
+        }
+    });
+}
+
+/// Archive the session that has ended
+async fn archive(state: &BotState) {
+    if !has_users(state).await {
+        tracing::debug!("session has no users, it is not archived");
+        return;
+    }
+
+    let session = capture(state, new_id(), false).await;
+    let dir = archive_dir().join(&session.id);
+
+    match fs::create_dir_all(&dir).and_then(|()| write(&dir, &session)) {
+        Ok(()) => tracing::info!("session is archived to {}", dir.display()),
+        Err(e) => tracing::error!("Unable to archive the session: {e}"),
+    }
+
+    save(state, &session);
+}
+
+/// Save the archived session to the storage
+pub(crate) fn save(state: &BotState, session: &SessionExport) {
+    if let Err(e) = state.storage.save_session(session) {
+        tracing::error!(
+            "Unable to save the session {} to the storage: {e}",
+            session.id
+        );
+    }
+}
+
+/// Whether the session has users to credit
+async fn has_users(state: &BotState) -> bool {
+    !state.session.get_chatters().await.is_empty()
+        || !state.session.get_followers().await.is_empty()
+        || !state.session.get_subscribers().await.is_empty()
+        || !state.lurkers.lock().await.is_empty()
+}
+
+/// IDs of the archived sessions, the latest first
+pub(crate) fn ids(state: &BotState) -> Vec<String> {
+    let mut ids = state.storage.session_ids().unwrap_or_else(|e| {
+        tracing::error!("Unable to list the stored sessions: {e}");
+        Vec::new()
+    });
+
+    if let Ok(entries) = fs::read_dir(archive_dir()) {
+        ids.extend(
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().join(SESSION_FILE_NAME).is_file())
+                .filter_map(|entry| entry.file_name().into_string().ok()),
+        );
+    }
+
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    ids.dedup();
+    ids
+}
+
+/// Archived session, `None` if there is none with the ID
+pub(crate) fn archived(state: &BotState, id: &str) -> io::Result<Option<SessionExport>> {
+    if !is_valid_id(id) {
+        return Ok(None);
+    }
+
+    if let Some(session) = state.storage.session(id)? {
+        return Ok(Some(session));
+    }
+
+    let path = archive_dir().join(id).join(SESSION_FILE_NAME);
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(session) => Ok(Some(session)),
+        Err(_) => {
+            let legacy: LegacySession = serde_json::from_str(&content)?;
+
+            Ok(Some(SessionExport::from_legacy(
+                id,
+                config::get_channel_name(),
+                legacy,
+            )))
+        }
+    }
+}
+
+/// The ID names a directory, so it must not leave the archive
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Add the session exported to `file` to the archive, returns its ID
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be read, is not an exported session of a
+/// supported version, or a session with the same ID is already archived
+pub fn import_session(file: &Path) -> io::Result<String> {
+    let session: SessionExport = serde_json::from_str(&fs::read_to_string(file)?)?;
+
+    if session.schema_version > SCHEMA_VERSION {
+        return Err(invalid_data(format!(
+            "schema version {} is newer than the supported {SCHEMA_VERSION}",
+            session.schema_version
+        )));
+    }
+
+    if !is_valid_id(&session.id) {
+        return Err(invalid_data(format!("invalid session ID {}", session.id)));
+    }
+
+    let dir = archive_dir().join(&session.id);
+
+    if dir.join(SESSION_FILE_NAME).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("session {} is already archived", session.id),
+        ));
+    }
+
+    fs::create_dir_all(&dir)?;
+    write(&dir, &session)?;
+
+    Ok(session.id)
+}
+
+pub(crate) fn write(dir: &Path, session: &SessionExport) -> io::Result<()> {
+    fs::write(
+        dir.join(SESSION_FILE_NAME),
+        serde_json::to_vec_pretty(session)?,
+    )
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_archive_is_converted() {
+        let legacy: LegacySession = serde_json::from_str(
+            r#"{
+                "chatters": {"1": "Chatter"},
+                "followers": {"2": "Follower"},
+                "subscribers": {},
+                "lurkers": {}
+            }"#,
+        )
+        .unwrap();
+        let session =
+            SessionExport::from_legacy("2024-05-01_22-10-00", "streamer".to_string(), legacy);
+        let exported = serde_json::to_string(&session).unwrap();
+
+        assert_eq!(session.schema_version, SCHEMA_VERSION);
+        assert_eq!(session.ended_at.format(ID_FORMAT).to_string(), session.id);
+        assert_eq!(session.followers[0].user_name, "Follower");
+        assert_eq!(
+            serde_json::from_str::<SessionExport>(&exported).unwrap(),
+            session
+        );
+        assert!(!is_valid_id("../tokens"));
+    }
+}
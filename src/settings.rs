@@ -0,0 +1,535 @@
+//! Settings changed at runtime on `/api/config/<section>`, see [`crate::admin_auth`].
+//!
+//! A patch is a JSON object of the section settings, e.g.
+//! `{"copypasta_action": "timeout"}` for `moderation`, `null` brings back the value of
+//! the environment. A patch is validated as a whole before it is applied, the values
+//! take precedence over the environment variables and are kept in `config.json`. The
+//! `timers` setting replaces the timers of `timers.json`. The broadcaster changes a few
+//! of the chat settings and the timer intervals with `!set <key> <value>`.
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::{fmt, io};
+
+use chrono_tz::Tz;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::scheduler;
+use crate::storage::JsonStore;
+
+pub(crate) const CONFIG_FILE_NAME: &str = "config.json";
+
+static OVERRIDES: OnceLock<RwLock<HashMap<&'static str, String>>> = OnceLock::new();
+
+/// Overrides by section and setting name as kept in `config.json`
+type StoredSettings = BTreeMap<String, BTreeMap<String, Value>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Chat,
+    Timers,
+    Moderation,
+    Overlay,
+}
+
+impl Section {
+    fn name(self) -> &'static str {
+        match self {
+            Section::Chat => "chat",
+            Section::Timers => "timers",
+            Section::Moderation => "moderation",
+            Section::Overlay => "overlay",
+        }
+    }
+
+    fn settings(self) -> &'static [Setting] {
+        match self {
+            Section::Chat => CHAT_SETTINGS,
+            Section::Timers => TIMERS_SETTINGS,
+            Section::Moderation => MODERATION_SETTINGS,
+            Section::Overlay => OVERLAY_SETTINGS,
+        }
+    }
+}
+
+impl FromStr for Section {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chat" => Ok(Section::Chat),
+            "timers" => Ok(Section::Timers),
+            "moderation" => Ok(Section::Moderation),
+            "overlay" => Ok(Section::Overlay),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Number,
+    Flag,
+    Choice(&'static [&'static str]),
+    Numbers,
+    Names,
+    TimeZone,
+    /// Kept in its own store instead of `config.json`
+    Timers,
+}
+
+struct Setting {
+    name: &'static str,
+    /// Environment variable the setting overrides
+    env: &'static str,
+    kind: Kind,
+}
+
+const CHAT_SETTINGS: &[Setting] = &[
+    Setting {
+        name: "language",
+        env: "HEWPME_LANGUAGE",
+        kind: Kind::Choice(&["ru", "en"]),
+    },
+    Setting {
+        name: "idle_chat_min",
+        env: "HEWPME_IDLE_CHAT_MIN",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "idle_command_grace_sec",
+        env: "HEWPME_IDLE_COMMAND_GRACE_SEC",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "chatter_milestones",
+        env: "HEWPME_CHATTER_MILESTONES",
+        kind: Kind::Numbers,
+    },
+    Setting {
+        name: "follower_milestones",
+        env: "HEWPME_FOLLOWER_MILESTONES",
+        kind: Kind::Numbers,
+    },
+    Setting {
+        name: "greeting",
+        env: "HEWPME_RAID_GREETING",
+        kind: Kind::Flag,
+    },
+    Setting {
+        name: "vanish_cooldown_sec",
+        env: "HEWPME_VANISH_COOLDOWN_SEC",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "clip_cooldown_sec",
+        env: "HEWPME_CLIP_COOLDOWN_SEC",
+        kind: Kind::Number,
+    },
+];
+
+/// Chat settings the broadcaster may change with `!set`, besides `timer<N>`
+const CHAT_COMMAND_SETTINGS: &[&str] = &["greeting", "vanish_cooldown_sec", "clip_cooldown_sec"];
+/// Prefix of the `!set` key changing the interval of a timer, e.g. `timer2`
+const TIMER_KEY_PREFIX: &str = "timer";
+
+const TIMERS_SETTINGS: &[Setting] = &[
+    Setting {
+        name: "timezone",
+        env: "HEWPME_TIMEZONE",
+        kind: Kind::TimeZone,
+    },
+    Setting {
+        name: "timers",
+        env: "",
+        kind: Kind::Timers,
+    },
+];
+
+const MODERATION_SETTINGS: &[Setting] = &[
+    Setting {
+        name: "copypasta_action",
+        env: "HEWPME_COPYPASTA_ACTION",
+        kind: Kind::Choice(&["flag", "delete", "timeout", "ban"]),
+    },
+    Setting {
+        name: "copypasta_timeout_sec",
+        env: "HEWPME_COPYPASTA_TIMEOUT_SEC",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "new_account_days",
+        env: "HEWPME_NEW_ACCOUNT_DAYS",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "follow_wave_count",
+        env: "HEWPME_FOLLOW_WAVE_COUNT",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "raid_messages_count",
+        env: "HEWPME_RAID_MESSAGES_COUNT",
+        kind: Kind::Number,
+    },
+    Setting {
+        name: "protection_cooldown_sec",
+        env: "HEWPME_PROTECTION_COOLDOWN_SEC",
+        kind: Kind::Number,
+    },
+];
+
+const OVERLAY_SETTINGS: &[Setting] = &[
+    Setting {
+        name: "follower_count",
+        env: "HEWPME_FOLLOWER_COUNT",
+        kind: Kind::Choice(&["session", "total"]),
+    },
+    Setting {
+        name: "unfurl_hosts",
+        env: "HEWPME_UNFURL_HOSTS",
+        kind: Kind::Names,
+    },
+    Setting {
+        name: "unfurl_timeout_ms",
+        env: "HEWPME_UNFURL_TIMEOUT_MS",
+        kind: Kind::Number,
+    },
+];
+
+#[derive(Debug)]
+pub enum Error {
+    NotAnObject,
+    UnknownSetting(String),
+    InvalidValue { setting: String, reason: String },
+    Save(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnObject => write!(f, "the patch must be a JSON object"),
+            Self::UnknownSetting(setting) => write!(f, "unknown setting {setting}"),
+            Self::InvalidValue { setting, reason } => write!(f, "invalid {setting}: {reason}"),
+            Self::Save(e) => write!(f, "unable to save the settings: {e}"),
+        }
+    }
+}
+
+pub struct Settings {
+    store: tokio::sync::Mutex<JsonStore<StoredSettings>>,
+    changes: broadcast::Sender<Section>,
+}
+
+pub type SafeSettings = Arc<Settings>;
+
+impl Settings {
+    /// Settings of the section changed at runtime
+    pub async fn section(&self, section: Section) -> BTreeMap<String, Value> {
+        self.store
+            .lock()
+            .await
+            .get(section.name())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Validate the patch, apply it and persist the result, returns the section settings
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the patch has an unknown setting or an invalid value,
+    /// nothing is changed then, or if the settings cannot be saved
+    pub async fn apply(
+        &self,
+        section: Section,
+        patch: Value,
+    ) -> Result<BTreeMap<String, Value>, Error> {
+        let patch = validate(section, patch)?;
+        let mut store = self.store.lock().await;
+
+        for (setting, value) in &patch {
+            if matches!(setting.kind, Kind::Timers) {
+                scheduler::replace_timers(value.clone()).map_err(Error::Save)?;
+            }
+        }
+
+        let settings = store
+            .try_update(|stored| {
+                let settings = stored.entry(section.name().to_string()).or_default();
+
+                for (setting, value) in patch {
+                    match value {
+                        _ if matches!(setting.kind, Kind::Timers) => (),
+                        Value::Null => {
+                            settings.remove(setting.name);
+                        }
+                        value => {
+                            settings.insert(setting.name.to_string(), value);
+                        }
+                    }
+                }
+
+                let settings = settings.clone();
+
+                stored.retain(|_, settings| !settings.is_empty());
+                settings
+            })
+            .map_err(Error::Save)?;
+
+        drop(store);
+        load_overrides(section, &settings);
+        tracing::info!("{} settings changed: {settings:?}", section.name());
+        // the tasks reading the settings once restart on the change
+        let _ = self.changes.send(section);
+
+        Ok(settings)
+    }
+
+    /// Change a setting from the chat, `key` is one of the chat settings allowed to the
+    /// broadcaster or `timer<N>` for the interval in minutes of the N-th timer
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the key is not allowed, the value is invalid or the setting
+    /// cannot be saved
+    pub async fn set_from_chat(&self, key: &str, value: &str) -> Result<(), Error> {
+        if let Some(number) = key.strip_prefix(TIMER_KEY_PREFIX) {
+            let invalid = |reason: String| Error::InvalidValue {
+                setting: key.to_string(),
+                reason,
+            };
+            let number = number
+                .parse::<usize>()
+                .map_err(|_| Error::UnknownSetting(key.to_string()))?;
+            let interval_min = value
+                .parse::<u64>()
+                .map_err(|_| invalid(String::from("expected a number of minutes")))?;
+            let timers = scheduler::timers_with_interval(number, interval_min).map_err(invalid)?;
+
+            return self
+                .apply(Section::Timers, Value::from_iter([("timers", timers)]))
+                .await
+                .map(drop);
+        }
+
+        let setting = CHAT_SETTINGS
+            .iter()
+            .find(|setting| setting.name == key && CHAT_COMMAND_SETTINGS.contains(&key))
+            .ok_or_else(|| Error::UnknownSetting(key.to_string()))?;
+
+        self.apply(
+            Section::Chat,
+            Value::from_iter([(key, chat_value(setting.kind, value))]),
+        )
+        .await
+        .map(drop)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Section> {
+        self.changes.subscribe()
+    }
+}
+
+pub fn create_settings() -> SafeSettings {
+    let (changes, _) = broadcast::channel(16);
+
+    Arc::new(Settings {
+        store: tokio::sync::Mutex::new(JsonStore::open(CONFIG_FILE_NAME)),
+        changes,
+    })
+}
+
+/// Value of the environment variable set at runtime
+///
+/// # Panics
+///
+/// Will panic if the overrides lock is poisoned
+pub(crate) fn override_of(env: &str) -> Option<String> {
+    overrides().read().unwrap().get(env).cloned()
+}
+
+fn overrides() -> &'static RwLock<HashMap<&'static str, String>> {
+    OVERRIDES.get_or_init(|| {
+        let stored = JsonStore::<StoredSettings>::open(CONFIG_FILE_NAME);
+        let mut overrides = HashMap::new();
+
+        for section in [
+            Section::Chat,
+            Section::Timers,
+            Section::Moderation,
+            Section::Overlay,
+        ] {
+            let Some(settings) = stored.get(section.name()) else {
+                continue;
+            };
+
+            for setting in section.settings() {
+                if let Some(value) = settings.get(setting.name).and_then(env_value) {
+                    overrides.insert(setting.env, value);
+                }
+            }
+        }
+
+        RwLock::new(overrides)
+    })
+}
+
+/// Replace the overrides of the section with `settings`
+fn load_overrides(section: Section, settings: &BTreeMap<String, Value>) {
+    let mut overrides = overrides().write().unwrap();
+
+    for setting in section.settings() {
+        match settings.get(setting.name).and_then(env_value) {
+            Some(value) => overrides.insert(setting.env, value),
+            None => overrides.remove(setting.env),
+        };
+    }
+}
+
+fn validate(section: Section, patch: Value) -> Result<Vec<(&'static Setting, Value)>, Error> {
+    let Value::Object(patch) = patch else {
+        return Err(Error::NotAnObject);
+    };
+
+    patch
+        .into_iter()
+        .map(|(name, value)| {
+            let setting = section
+                .settings()
+                .iter()
+                .find(|setting| setting.name == name)
+                .ok_or_else(|| Error::UnknownSetting(name.clone()))?;
+
+            // the timers cannot be reset to the environment, they have no variable
+            if !value.is_null() || matches!(setting.kind, Kind::Timers) {
+                check(setting.kind, &value).map_err(|reason| Error::InvalidValue {
+                    setting: name,
+                    reason,
+                })?;
+            }
+
+            Ok((setting, value))
+        })
+        .collect()
+}
+
+fn check(kind: Kind, value: &Value) -> Result<(), String> {
+    match kind {
+        Kind::Number => value
+            .as_u64()
+            .map(drop)
+            .ok_or_else(|| String::from("expected a non-negative integer")),
+        Kind::Flag => value
+            .as_bool()
+            .map(drop)
+            .ok_or_else(|| String::from("expected true or false")),
+        Kind::Choice(options) => value
+            .as_str()
+            .filter(|choice| {
+                options
+                    .iter()
+                    .any(|option| option.eq_ignore_ascii_case(choice))
+            })
+            .map(drop)
+            .ok_or_else(|| format!("expected one of {}", options.join(", "))),
+        Kind::Numbers => value
+            .as_array()
+            .filter(|items| items.iter().all(Value::is_u64))
+            .map(drop)
+            .ok_or_else(|| String::from("expected a list of non-negative integers")),
+        Kind::Names => value
+            .as_array()
+            .filter(|items| items.iter().all(Value::is_string))
+            .map(drop)
+            .ok_or_else(|| String::from("expected a list of strings")),
+        Kind::TimeZone => value
+            .as_str()
+            .and_then(|name| name.parse::<Tz>().ok())
+            .map(drop)
+            .ok_or_else(|| String::from("expected an IANA time zone, e.g. Europe/Berlin")),
+        Kind::Timers => scheduler::check_timers(value),
+    }
+}
+
+/// The value typed in the chat as the setting holds it, unknown words stay strings
+/// and fail the validation
+fn chat_value(kind: Kind, text: &str) -> Value {
+    match kind {
+        Kind::Flag => match text.to_ascii_lowercase().as_str() {
+            "on" | "true" | "yes" => Value::Bool(true),
+            "off" | "false" | "no" => Value::Bool(false),
+            _ => Value::from(text),
+        },
+        Kind::Number => text
+            .parse::<u64>()
+            .map_or_else(|_| Value::from(text), Value::from),
+        _ => Value::from(text),
+    }
+}
+
+/// The setting as the environment variable would hold it, lists are comma separated
+fn env_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Array(items) => items
+            .iter()
+            .map(env_value)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        Value::Null | Value::Object(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn patch_is_validated_as_a_whole() {
+        let patch = json!({"copypasta_action": "timeout", "new_account_days": -1});
+
+        assert!(matches!(
+            validate(Section::Moderation, patch),
+            Err(Error::InvalidValue { setting, .. }) if setting == "new_account_days"
+        ));
+        assert!(matches!(
+            validate(Section::Chat, json!({"copypasta_action": "flag"})),
+            Err(Error::UnknownSetting(_))
+        ));
+        assert!(matches!(
+            validate(Section::Overlay, json!(["session"])),
+            Err(Error::NotAnObject)
+        ));
+        assert_eq!(
+            validate(
+                Section::Chat,
+                json!({"language": "EN", "chatter_milestones": [50, 100], "idle_chat_min": null})
+            )
+            .unwrap()
+            .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn settings_become_environment_values() {
+        assert_eq!(env_value(&json!([10, 50])).as_deref(), Some("10,50"));
+        assert_eq!(env_value(&json!("total")).as_deref(), Some("total"));
+        assert_eq!(env_value(&json!(30)).as_deref(), Some("30"));
+        assert_eq!(env_value(&json!({"a": 1})), None);
+    }
+
+    #[test]
+    fn chat_values_follow_the_setting_kind() {
+        assert_eq!(chat_value(Kind::Flag, "OFF"), json!(false));
+        assert_eq!(chat_value(Kind::Number, "45"), json!(45));
+        assert!(check(Kind::Number, &chat_value(Kind::Number, "-5")).is_err());
+        assert!(check(Kind::Flag, &chat_value(Kind::Flag, "maybe")).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Shoutouts of other channels, in the chat and with the Twitch shoutout.
+//!
+//! Moderators give one with `!so <user>`, the raiders get one with
+//! `HEWPME_RAID_SHOUTOUT`, see [`crate::raids`]. The Twitch shoutout requires the
+//! moderator:manage:shoutouts permission and has cooldowns of its own, a refused one
+//! does not take the chat message back.
+use twitch_api::helix::chat::SendAShoutoutRequest;
+use twitch_api::helix::HelixClient;
+
+use crate::eventsub::get_eventsub_token;
+use crate::helper::BotState;
+use crate::transport::create_api_client;
+use crate::{i18n, metrics, redact};
+
+pub const CHANNEL_URL_PREFIX: &str = "https://twitch.tv/";
+
+#[derive(Debug)]
+pub enum ShoutoutError {
+    /// No Twitch user has the login
+    NotFound,
+    Helix(String),
+}
+
+impl std::fmt::Display for ShoutoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShoutoutError::NotFound => write!(f, "the user is not found"),
+            ShoutoutError::Helix(e) => write!(f, "Helix request failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ShoutoutError {}
+
+/// Login of the `!so` argument, e.g. `@Streamer`
+fn target_login(arg: &str) -> String {
+    arg.trim_start_matches('@').to_lowercase()
+}
+
+/// Shoutout of `!so`, the chat message is posted once the user is found
+///
+/// # Errors
+///
+/// Will return `Err` if the user is not found or the Twitch shoutout fails
+pub async fn shoutout(state: &BotState, arg: &str) -> Result<(), ShoutoutError> {
+    let login = target_login(arg);
+    let Some(token) = get_eventsub_token().await else {
+        return Err(ShoutoutError::Helix(String::from("no EventSub token yet")));
+    };
+    let client = HelixClient::with_client(create_api_client());
+    let user = metrics::timed(
+        "helix_get_user",
+        client.get_user_from_login(login.as_str(), &token),
+    )
+    .await
+    .map_err(|e| ShoutoutError::Helix(e.to_string()))?
+    .ok_or(ShoutoutError::NotFound)?;
+    let url = format!("{CHANNEL_URL_PREFIX}{}", user.login);
+
+    tracing::info!("shoutout to {}", redact::Name(user.display_name.as_str()));
+    state.say(i18n::render(
+        state.languages.channel(),
+        "shoutout.message",
+        &[("name", &user.display_name), ("url", &url)],
+    ));
+
+    send(state, user.id.as_str())
+        .await
+        .map_err(ShoutoutError::Helix)
+}
+
+/// Send the Twitch shoutout to the channel of the user
+///
+/// # Errors
+///
+/// Will return `Err` if there is no EventSub token yet or Twitch refuses the shoutout
+pub async fn send(state: &BotState, user_id: &str) -> Result<(), String> {
+    // the followers would send the shoutout again
+    if !state.instance.is_leader() {
+        return Ok(());
+    }
+
+    let client = HelixClient::with_client(create_api_client());
+    let token = get_eventsub_token()
+        .await
+        .ok_or_else(|| String::from("no EventSub token"))?;
+    let request = SendAShoutoutRequest::new(&token.user_id, user_id, &token.user_id);
+
+    metrics::timed(
+        "helix_send_shoutout",
+        client.req_post(request, Default::default(), &token),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    metrics::increment("shoutout");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_is_the_login_of_the_argument() {
+        assert_eq!(target_login("@Cool_User"), "cool_user");
+        assert_eq!(target_login("streamer"), "streamer");
+    }
+}
@@ -0,0 +1,39 @@
+//! Similarity of chat messages by the character trigrams of their normalized text.
+//!
+//! Shared by the copy-pasta and the timeout evasion detection, the texts are compared
+//! as sets of trigrams so that small edits keep them similar.
+use std::collections::HashSet;
+
+pub(crate) type Trigram = (char, char, char);
+
+/// Lowercase letters and digits, words separated by a single space
+pub(crate) fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn trigrams(text: &str) -> HashSet<Trigram> {
+    let chars = text.chars().collect::<Vec<_>>();
+
+    chars
+        .windows(3)
+        .map(|window| (window[0], window[1], window[2]))
+        .collect()
+}
+
+/// Share of the trigrams the texts have in common
+// the sets are a few hundred trigrams at most
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn jaccard(a: &HashSet<Trigram>, b: &HashSet<Trigram>) -> f64 {
+    let common = a.intersection(b).count();
+    let all = a.len() + b.len() - common;
+
+    if all == 0 {
+        return 0.0;
+    }
+
+    common as f64 / all as f64
+}
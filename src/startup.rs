@@ -0,0 +1,98 @@
+//! Ordered startup of the bot: tokens, then the channel identity, then the services.
+//!
+//! The tokens are acquired one after another since both OAuth flows share the
+//! callback server, every stage is logged and limited by its own timeout. The bot
+//! starts without EventSub if its token cannot be acquired, e.g. when the streamer
+//! declines the scopes, the chat token is still required.
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use twitch_api::types::UserId;
+use twitch_oauth2::UserToken;
+
+use crate::{chat, config, eventsub};
+
+/// Everything the services need before they start
+pub(crate) struct Prepared {
+    /// `None` if EventSub is not available, the events are then taken from the chat
+    pub eventsub: Option<(UserToken, UserId)>,
+}
+
+/// Run the stages preceding the services
+///
+/// # Panics
+///
+/// Will panic if the chat token is not acquired in time
+pub(crate) async fn prepare() -> Prepared {
+    let token_timeout = config::get_startup_token_timeout();
+    let eventsub_token = try_stage(
+        "EventSub token",
+        token_timeout,
+        eventsub::acquire_eventsub_token(),
+    )
+    .await;
+
+    stage("chat token", token_timeout, chat::acquire_chat_token()).await;
+
+    let eventsub = match eventsub_token {
+        Some(token) => {
+            try_stage(
+                "channel identity",
+                config::get_startup_identity_timeout(),
+                async move {
+                    eventsub::resolve_channel_user_id(&token)
+                        .await
+                        .map(|user_id| (token, user_id))
+                },
+            )
+            .await
+        }
+        None => None,
+    };
+
+    Prepared { eventsub }
+}
+
+/// Run a single stage, logging its progress
+///
+/// # Panics
+///
+/// Will panic if the stage does not finish within `timeout`
+async fn stage<F: Future>(name: &str, timeout: Duration, fut: F) -> F::Output {
+    tracing::info!("startup: {name}...");
+    let start = Instant::now();
+
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(output) => {
+            tracing::info!("startup: {name} done in {} ms", start.elapsed().as_millis());
+            output
+        }
+        Err(_) => panic!("startup: {name} did not finish in {} s", timeout.as_secs()),
+    }
+}
+
+/// Run a stage the bot can start without, its error is logged instead of panicking
+async fn try_stage<F, T, E>(name: &str, timeout: Duration, fut: F) -> Option<T>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    tracing::info!("startup: {name}...");
+    let start = Instant::now();
+
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(Ok(output)) => {
+            tracing::info!("startup: {name} done in {} ms", start.elapsed().as_millis());
+            Some(output)
+        }
+        Ok(Err(e)) => {
+            tracing::error!("startup: {name} failed: {e}");
+            None
+        }
+        Err(_) => {
+            tracing::error!("startup: {name} did not finish in {} s", timeout.as_secs());
+            None
+        }
+    }
+}
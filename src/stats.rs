@@ -0,0 +1,232 @@
+//! Statistics of the current session served on `/api/stats` and the weekly report
+//! of the lapsed subscriptions served on `/api/stats/weekly`.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::i18n::{self, Lang};
+use crate::lapses::Lapse;
+use crate::milestones::Milestone;
+use crate::raids::Raid;
+
+/// Raids of the same channel within the window are one raid
+const DUPLICATE_RAID_WINDOW: TimeDelta = TimeDelta::minutes(2);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StatsSnapshot {
+    pub session_start: DateTime<Local>,
+    pub milestones: Vec<Milestone>,
+    pub raids: Vec<Raid>,
+    /// Viewers brought by all the raids
+    pub raid_viewers: u64,
+    /// Chat messages per detected language, the most used first
+    pub chat_languages: Vec<LanguageCount>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LanguageCount {
+    /// ISO 639-3 code
+    pub language: &'static str,
+    pub messages: u64,
+}
+
+pub struct SessionStats {
+    session_start: DateTime<Local>,
+    milestones: Mutex<Vec<Milestone>>,
+    raids: Mutex<Vec<Raid>>,
+    languages: Mutex<HashMap<&'static str, u64>>,
+}
+
+pub type SafeSessionStats = Arc<SessionStats>;
+
+impl SessionStats {
+    pub fn session_start(&self) -> DateTime<Local> {
+        self.session_start
+    }
+
+    pub async fn record_milestone(&self, milestone: Milestone) {
+        self.milestones.lock().await.push(milestone);
+    }
+
+    /// Returns whether the raid is new, the chat and EventSub report the same raid
+    pub async fn record_raid(&self, raid: Raid) -> bool {
+        let mut raids = self.raids.lock().await;
+        let duplicate = raids.iter().any(|recorded| {
+            recorded.login == raid.login
+                && (raid.raided_at - recorded.raided_at).abs() < DUPLICATE_RAID_WINDOW
+        });
+
+        if !duplicate {
+            raids.push(raid);
+        }
+
+        !duplicate
+    }
+
+    pub async fn record_language(&self, language: &'static str) {
+        *self.languages.lock().await.entry(language).or_default() += 1;
+    }
+
+    pub async fn snapshot(&self) -> StatsSnapshot {
+        let raids = self.raids.lock().await.clone();
+        let mut chat_languages = self
+            .languages
+            .lock()
+            .await
+            .iter()
+            .map(|(&language, &messages)| LanguageCount { language, messages })
+            .collect::<Vec<_>>();
+
+        chat_languages.sort_by(|a, b| {
+            b.messages
+                .cmp(&a.messages)
+                .then_with(|| a.language.cmp(b.language))
+        });
+
+        StatsSnapshot {
+            session_start: self.session_start,
+            milestones: self.milestones.lock().await.clone(),
+            raid_viewers: raids.iter().map(|raid| raid.viewers).sum(),
+            raids,
+            chat_languages,
+        }
+    }
+}
+
+pub fn create_session_stats() -> SafeSessionStats {
+    Arc::new(SessionStats {
+        session_start: Local::now(),
+        milestones: Mutex::new(Vec::new()),
+        raids: Mutex::new(Vec::new()),
+        languages: Mutex::new(HashMap::new()),
+    })
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WeeklyReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Long-standing subscriptions ended within the week, the longest first
+    pub lapses: Vec<Lapse>,
+    /// Subscription months of all the lapsed subscribers
+    pub months_lost: u64,
+}
+
+impl WeeklyReport {
+    #[must_use]
+    pub fn new(from: DateTime<Utc>, to: DateTime<Utc>, lapses: &[Lapse]) -> Self {
+        let mut lapses = lapses
+            .iter()
+            .filter(|lapse| from <= lapse.ended_at && lapse.ended_at < to)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        lapses.sort_by_key(|lapse| std::cmp::Reverse(lapse.months));
+
+        WeeklyReport {
+            from,
+            to,
+            months_lost: lapses.iter().map(|lapse| lapse.months).sum(),
+            lapses,
+        }
+    }
+
+    /// Summary for the broadcaster, one line per lapsed subscriber
+    #[must_use]
+    pub fn text(&self, lang: Lang) -> String {
+        let mut text = i18n::render(
+            lang,
+            "lapse.report",
+            &[("count", &self.lapses.len()), ("months", &self.months_lost)],
+        );
+
+        for lapse in &self.lapses {
+            text.push_str(&format!("\n- {} ({})", lapse.user_name, lapse.months));
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lapse(user_name: &str, months: u64, ended_at: DateTime<Utc>) -> Lapse {
+        Lapse {
+            user_id: user_name.to_lowercase(),
+            user_name: user_name.to_string(),
+            months,
+            tier: 1,
+            is_gift: false,
+            ended_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_languages_are_the_most_used_first() {
+        let stats = create_session_stats();
+
+        for language in ["eng", "rus", "rus", "deu"] {
+            stats.record_language(language).await;
+        }
+
+        assert_eq!(
+            stats
+                .snapshot()
+                .await
+                .chat_languages
+                .iter()
+                .map(|count| (count.language, count.messages))
+                .collect::<Vec<_>>(),
+            [("rus", 2), ("deu", 1), ("eng", 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn raid_reported_twice_is_recorded_once() {
+        let stats = create_session_stats();
+        let raid = Raid {
+            user_name: String::from("Raider"),
+            login: String::from("raider"),
+            viewers: 42,
+            raided_at: Local::now(),
+        };
+        let mut later = raid.clone();
+
+        later.raided_at += TimeDelta::hours(1);
+
+        assert!(stats.record_raid(raid.clone()).await);
+        assert!(!stats.record_raid(raid).await);
+        assert!(stats.record_raid(later).await);
+        assert_eq!(stats.snapshot().await.raid_viewers, 84);
+    }
+
+    #[test]
+    fn weekly_report_covers_the_week() {
+        let to = Utc::now();
+        let from = to - TimeDelta::days(7);
+        let lapses = [
+            lapse("Old", 30, from - TimeDelta::hours(1)),
+            lapse("Loyal", 12, from + TimeDelta::days(1)),
+            lapse("Loyalest", 24, to - TimeDelta::hours(1)),
+        ];
+        let report = WeeklyReport::new(from, to, &lapses);
+
+        assert_eq!(report.months_lost, 36);
+        assert_eq!(
+            report
+                .lapses
+                .iter()
+                .map(|lapse| lapse.user_name.as_str())
+                .collect::<Vec<_>>(),
+            ["Loyalest", "Loyal"]
+        );
+        assert!(report
+            .text(Lang::En)
+            .ends_with("- Loyalest (24)\n- Loyal (12)"));
+    }
+}
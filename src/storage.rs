@@ -0,0 +1,277 @@
+//! JSON files in the application directory used to keep data between sessions.
+//!
+//! Keys are written sorted so the files are stable under version control, the
+//! settings are also copied to `HEWPME_EXPORT_DIR` on every change.
+//!
+//! Only the leader instance writes, a follower keeps its changes in memory and reloads
+//! a store from the file before its first change after taking over, see
+//! [`crate::instance`].
+//!
+//! The session lists and the archived sessions are kept by a [`Storage`] instead, an
+//! SQLite database in `hewpme.sqlite3`, so that a restart during the stream does not
+//! lose the chatters, followers and subscribers.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{fs, ops};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config;
+use crate::helper::SessionUsers;
+use crate::sessions::SessionExport;
+
+mod sqlite;
+
+pub use sqlite::SqliteStorage;
+
+const DATABASE_FILE_NAME: &str = "hewpme.sqlite3";
+
+/// Whether this process may write the stores, a follower instance only reads them
+static WRITABLE: AtomicBool = AtomicBool::new(true);
+
+/// Allow or forbid the writes of the stored data
+pub(crate) fn set_writable(writable: bool) {
+    WRITABLE.store(writable, Ordering::Relaxed);
+}
+
+/// Whether the stored data may be written by this process
+pub(crate) fn is_writable() -> bool {
+    WRITABLE.load(Ordering::Relaxed)
+}
+
+/// Files edited by the streamer, tokens, session data and the data of the viewers,
+/// e.g. their notes, birthdays and languages, are not included
+pub const SETTINGS_FILES: [&str; 10] = [
+    "command_groups.json",
+    "config.json",
+    "custom_commands.json",
+    "kv.json",
+    "language_rules.json",
+    "prompts.json",
+    "quotes.json",
+    "rewards.json",
+    "timers.json",
+    "wheel.json",
+];
+
+/// Session list kept by a [`Storage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserList {
+    Chatters,
+    Followers,
+    Subscribers,
+}
+
+impl UserList {
+    fn as_str(self) -> &'static str {
+        match self {
+            UserList::Chatters => "chatters",
+            UserList::Followers => "followers",
+            UserList::Subscribers => "subscribers",
+        }
+    }
+}
+
+/// Persistence of the session lists and of the archived sessions
+pub trait Storage: Send + Sync {
+    /// Users of the list by user ID
+    fn users(&self, list: UserList) -> io::Result<SessionUsers>;
+
+    /// Add the user to the list, the name of a known user is updated
+    fn add_user(&self, list: UserList, user_id: &str, user_name: &str) -> io::Result<()>;
+
+    /// Remove the user from all the lists
+    fn remove_user(&self, user_id: &str) -> io::Result<()>;
+
+    /// Empty all the lists, e.g. when a new session starts
+    fn clear_users(&self) -> io::Result<()>;
+
+    /// Add the session or replace the one with the same ID
+    fn save_session(&self, session: &SessionExport) -> io::Result<()>;
+
+    /// IDs of the saved sessions, the latest first
+    fn session_ids(&self) -> io::Result<Vec<String>>;
+
+    /// Saved session, `None` if there is none with the ID
+    fn session(&self, id: &str) -> io::Result<Option<SessionExport>>;
+}
+
+pub type SafeStorage = Arc<dyn Storage>;
+
+/// The database in the application directory, kept in memory if it cannot be opened
+pub fn open_storage() -> SafeStorage {
+    let path = config::get_app_directory_path().join(DATABASE_FILE_NAME);
+
+    match SqliteStorage::open(&path) {
+        Ok(storage) => Arc::new(storage),
+        Err(e) => {
+            tracing::error!(
+                "Unable to open {}, the session lists are not persisted: {e}",
+                path.display()
+            );
+            create_memory_storage()
+        }
+    }
+}
+
+/// Storage lost on exit, e.g. for the tests
+///
+/// # Panics
+///
+/// Panics if SQLite cannot allocate the database
+pub fn create_memory_storage() -> SafeStorage {
+    Arc::new(SqliteStorage::in_memory().expect("Unable to create an in-memory database"))
+}
+
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: T,
+    /// The data may be behind the file, it was read or changed while not writable
+    stale: bool,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    /// Open `<app dir>/<file_name>`, a missing or broken file results in the default value
+    pub fn open(file_name: &str) -> Self {
+        let path = config::get_app_directory_path().join(file_name);
+
+        JsonStore {
+            data: load(&path),
+            path,
+            stale: !is_writable(),
+        }
+    }
+
+    /// Write the data to the file, nothing is written while the stores are not writable
+    pub fn save(&self) -> io::Result<()> {
+        if !is_writable() {
+            return Ok(());
+        }
+
+        let value = serde_json::to_value(&self.data)?;
+
+        write_sorted(&self.path, &value)?;
+
+        if let (Some(export_dir), Some(file_name)) = (config::get_export_dir(), self.file_name()) {
+            if SETTINGS_FILES.contains(&file_name) {
+                write_sorted(&export_dir.join(file_name), &value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply `f` to the stored data and persist the result
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        let result = self.apply(f);
+
+        if let Err(e) = self.save() {
+            tracing::error!("Unable to save {}: {e}", self.path.display());
+        }
+
+        result
+    }
+
+    /// Like [`JsonStore::update`], the save error is returned instead of logged
+    pub fn try_update<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> io::Result<R> {
+        let result = self.apply(f);
+
+        self.save()?;
+
+        Ok(result)
+    }
+
+    /// Apply `f` to the data, reloaded first if the leader may have changed the file
+    fn apply<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        if !is_writable() {
+            self.stale = true;
+        } else if self.stale {
+            self.data = load(&self.path);
+            self.stale = false;
+        }
+
+        f(&mut self.data)
+    }
+
+    fn file_name(&self) -> Option<&str> {
+        self.path.file_name()?.to_str()
+    }
+}
+
+impl<T> ops::Deref for JsonStore<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+/// Data of the file, a missing or broken file results in the default value
+fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    match fs::File::open(path) {
+        Ok(file) => serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|e| {
+            tracing::warn!("Unable to parse {}: {e}", path.display());
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// `serde_json::Value` keeps the object keys sorted
+fn write_sorted(path: &Path, value: &serde_json::Value) -> io::Result<()> {
+    // write to a temporary file first so a crash never leaves a truncated store behind
+    let tmp_path = path.with_extension("json.tmp");
+    let file = fs::File::create(&tmp_path)?;
+    let mut writer = io::BufWriter::new(file);
+
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    io::Write::write_all(&mut writer, b"\n")?;
+    io::Write::flush(&mut writer)?;
+    drop(writer);
+    fs::rename(tmp_path, path)
+}
+
+/// Copy the settings from the application directory to `dir`,
+/// returns the names of the exported files
+///
+/// # Errors
+///
+/// Will return `Err` if a settings file cannot be parsed or written
+pub fn export_settings(dir: &Path) -> io::Result<Vec<&'static str>> {
+    copy_settings(&config::get_app_directory_path(), dir)
+}
+
+/// Replace the settings in the application directory with the ones from `dir`,
+/// returns the names of the imported files
+///
+/// # Errors
+///
+/// Will return `Err` if a settings file cannot be parsed or written
+pub fn import_settings(dir: &Path) -> io::Result<Vec<&'static str>> {
+    copy_settings(dir, &config::get_app_directory_path())
+}
+
+fn copy_settings(from: &Path, to: &Path) -> io::Result<Vec<&'static str>> {
+    fs::create_dir_all(to)?;
+
+    let mut copied = Vec::new();
+
+    for file_name in SETTINGS_FILES {
+        let source = from.join(file_name);
+
+        if !source.exists() {
+            continue;
+        }
+
+        let value: serde_json::Value = serde_json::from_slice(&fs::read(&source)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{file_name}: {e}")))?;
+
+        write_sorted(&to.join(file_name), &value)?;
+        copied.push(file_name);
+    }
+
+    Ok(copied)
+}
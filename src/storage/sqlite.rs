@@ -0,0 +1,204 @@
+//! SQLite implementation of [`Storage`], a single file in the application directory.
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{Storage, UserList};
+use crate::helper::SessionUsers;
+use crate::sessions::SessionExport;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS session_users (
+        list TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        user_name TEXT NOT NULL,
+        PRIMARY KEY (list, user_id)
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        id TEXT PRIMARY KEY,
+        ended_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+";
+
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Open the database file, the tables are created if missing
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be opened or is not an SQLite database
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::with_connection(Connection::open(path).map_err(to_io)?)
+    }
+
+    /// Database kept in memory, e.g. for the tests
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if SQLite cannot allocate the database
+    pub fn in_memory() -> io::Result<Self> {
+        Self::with_connection(Connection::open_in_memory().map_err(to_io)?)
+    }
+
+    fn with_connection(connection: Connection) -> io::Result<Self> {
+        connection.execute_batch(SCHEMA).map_err(to_io)?;
+
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn run<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> io::Result<T> {
+        let connection = self.connection.lock().unwrap();
+
+        f(&connection).map_err(to_io)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn users(&self, list: UserList) -> io::Result<SessionUsers> {
+        self.run(|connection| {
+            connection
+                .prepare("SELECT user_id, user_name FROM session_users WHERE list = ?1")?
+                .query_map([list.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+    }
+
+    fn add_user(&self, list: UserList, user_id: &str, user_name: &str) -> io::Result<()> {
+        self.run(|connection| {
+            connection.execute(
+                "INSERT INTO session_users (list, user_id, user_name) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (list, user_id) DO UPDATE SET user_name = excluded.user_name",
+                params![list.as_str(), user_id, user_name],
+            )
+        })
+        .map(drop)
+    }
+
+    fn remove_user(&self, user_id: &str) -> io::Result<()> {
+        self.run(|connection| {
+            connection.execute("DELETE FROM session_users WHERE user_id = ?1", [user_id])
+        })
+        .map(drop)
+    }
+
+    fn clear_users(&self) -> io::Result<()> {
+        self.run(|connection| connection.execute("DELETE FROM session_users", []))
+            .map(drop)
+    }
+
+    fn save_session(&self, session: &SessionExport) -> io::Result<()> {
+        let data = serde_json::to_string(session)?;
+
+        self.run(|connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO sessions (id, ended_at, data) VALUES (?1, ?2, ?3)",
+                params![session.id, session.ended_at.to_rfc3339(), data],
+            )
+        })
+        .map(drop)
+    }
+
+    fn session_ids(&self) -> io::Result<Vec<String>> {
+        self.run(|connection| {
+            connection
+                .prepare("SELECT id FROM sessions ORDER BY id DESC")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+    }
+
+    fn session(&self, id: &str) -> io::Result<Option<SessionExport>> {
+        let data: Option<String> = self.run(|connection| {
+            connection
+                .query_row("SELECT data FROM sessions WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })
+                .optional()
+        })?;
+
+        data.map(|data| serde_json::from_str(&data).map_err(io::Error::from))
+            .transpose()
+    }
+}
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+    use crate::sessions::{ExportedStats, SessionUser, SCHEMA_VERSION};
+
+    #[test]
+    fn lists_and_sessions_are_persisted() {
+        let storage = SqliteStorage::in_memory().unwrap();
+
+        storage.add_user(UserList::Chatters, "1", "alice").unwrap();
+        storage.add_user(UserList::Chatters, "1", "Alice").unwrap();
+        storage.add_user(UserList::Followers, "2", "Bob").unwrap();
+        storage
+            .add_user(UserList::Subscribers, "1", "Alice")
+            .unwrap();
+
+        assert_eq!(
+            storage.users(UserList::Chatters).unwrap(),
+            SessionUsers::from([(String::from("1"), String::from("Alice"))])
+        );
+        assert_eq!(storage.users(UserList::Followers).unwrap().len(), 1);
+
+        storage.remove_user("1").unwrap();
+        assert!(storage.users(UserList::Subscribers).unwrap().is_empty());
+
+        storage.clear_users().unwrap();
+        assert!(storage.users(UserList::Followers).unwrap().is_empty());
+
+        let session = |id: &str| SessionExport {
+            schema_version: SCHEMA_VERSION,
+            id: id.to_string(),
+            channel: String::from("streamer"),
+            started_at: None,
+            ended_at: Local::now(),
+            followers: vec![SessionUser {
+                user_id: String::from("2"),
+                user_name: String::from("Bob"),
+            }],
+            subscribers: Vec::new(),
+            chatters: Vec::new(),
+            lurkers: Vec::new(),
+            stats: ExportedStats::default(),
+        };
+
+        storage
+            .save_session(&session("2024-05-01_22-10-00"))
+            .unwrap();
+        storage
+            .save_session(&session("2024-05-02_22-10-00"))
+            .unwrap();
+
+        assert_eq!(
+            storage.session_ids().unwrap(),
+            ["2024-05-02_22-10-00", "2024-05-01_22-10-00"]
+        );
+        assert_eq!(
+            storage
+                .session("2024-05-01_22-10-00")
+                .unwrap()
+                .unwrap()
+                .followers[0]
+                .user_name,
+            "Bob"
+        );
+        assert!(storage.session("missing").unwrap().is_none());
+    }
+}
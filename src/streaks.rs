@@ -0,0 +1,151 @@
+//! Consecutive streams attended by every viewer.
+//!
+//! Every stream going online begins a new session, unless it is back within
+//! `HEWPME_SESSION_RESUME_MIN`, and a viewer attends it by chatting. Up to
+//! `HEWPME_STREAK_GRACE` missed streams in a row do not break the streak.
+//! Chatting before the first stream seen by the bot counts for the last session.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config;
+use crate::storage::JsonStore;
+
+const STREAKS_FILE_NAME: &str = "streaks.json";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct StreakRecord {
+    pub name: String,
+    pub current: u32,
+    pub longest: u32,
+    last_session: u32,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct StreakData {
+    sessions: u32,
+    viewers: HashMap<String, StreakRecord>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StreakEntry {
+    pub name: String,
+    pub streak: u32,
+}
+
+pub struct StreakTracker {
+    session: AtomicU32,
+    grace: u32,
+    store: Mutex<JsonStore<StreakData>>,
+}
+
+pub type SafeStreakTracker = Arc<StreakTracker>;
+
+impl StreakTracker {
+    /// Load the stored streaks, the last session continues until the next stream
+    fn load() -> Self {
+        let store = JsonStore::<StreakData>::open(STREAKS_FILE_NAME);
+
+        StreakTracker {
+            session: AtomicU32::new(store.sessions),
+            grace: config::get_streak_grace(),
+            store: Mutex::new(store),
+        }
+    }
+
+    /// Start a new session when the stream goes online
+    pub async fn start_session(&self) {
+        let session = self.store.lock().await.update(|data| {
+            data.sessions += 1;
+            data.sessions
+        });
+
+        self.session.store(session, Ordering::Relaxed);
+        tracing::info!("streak session {session} started");
+    }
+
+    fn session(&self) -> u32 {
+        self.session.load(Ordering::Relaxed)
+    }
+
+    /// Mark the viewer as present in the current session
+    pub async fn record_presence(&self, user_id: &str, name: &str) {
+        let session = self.session();
+        let mut guard = self.store.lock().await;
+
+        if guard
+            .viewers
+            .get(user_id)
+            .is_some_and(|record| record.last_session == session && record.name == name)
+        {
+            return;
+        }
+
+        guard.update(|data| {
+            let record = data.viewers.entry(user_id.to_string()).or_default();
+
+            if record.last_session != session {
+                let missed = session.saturating_sub(record.last_session + 1);
+
+                record.current = if record.last_session > 0 && missed <= self.grace {
+                    record.current + 1
+                } else {
+                    1
+                };
+                record.longest = record.longest.max(record.current);
+                record.last_session = session;
+            }
+
+            record.name = name.to_string();
+        });
+    }
+
+    /// Current streak of the viewer, zero if the streak is already broken
+    pub async fn get_streak(&self, user_id: &str) -> Option<StreakRecord> {
+        let guard = self.store.lock().await;
+
+        guard.viewers.get(user_id).map(|record| {
+            let mut record = record.clone();
+
+            if self.session().saturating_sub(record.last_session) > self.grace + 1 {
+                record.current = 0;
+            }
+
+            record
+        })
+    }
+
+    pub async fn forget(&self, user_id: &str) -> Option<StreakRecord> {
+        self.store
+            .lock()
+            .await
+            .update(|data| data.viewers.remove(user_id))
+    }
+
+    /// Viewers of the current session with the longest ongoing streaks
+    pub async fn longest_streaks(&self, count: usize) -> Vec<StreakEntry> {
+        let session = self.session();
+        let guard = self.store.lock().await;
+        let mut entries: Vec<_> = guard
+            .viewers
+            .values()
+            .filter(|record| record.last_session == session && record.current > 1)
+            .map(|record| StreakEntry {
+                name: record.name.clone(),
+                streak: record.current,
+            })
+            .collect();
+
+        entries.sort_by(|a, b| b.streak.cmp(&a.streak).then_with(|| a.name.cmp(&b.name)));
+        entries.truncate(count);
+
+        entries
+    }
+}
+
+pub fn create_streak_tracker() -> SafeStreakTracker {
+    Arc::new(StreakTracker::load())
+}
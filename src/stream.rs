@@ -0,0 +1,94 @@
+//! Information about the stream kept up to date from the `channel.update`,
+//! `stream.online` and `stream.offline` events.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Mutex, Notify};
+
+#[derive(Default)]
+pub struct StreamInfo {
+    category: Mutex<Option<String>>,
+    live: AtomicBool,
+    followers_total: Mutex<Option<u64>>,
+    offline: Notify,
+    offline_at: std::sync::Mutex<Option<Instant>>,
+}
+
+pub type SafeStreamInfo = Arc<StreamInfo>;
+
+impl StreamInfo {
+    /// Returns whether the category is changed
+    pub async fn set_category(&self, category: String) -> bool {
+        let mut current = self.category.lock().await;
+
+        if current.as_deref() == Some(category.as_str()) {
+            return false;
+        }
+
+        tracing::info!("stream category is {category}");
+        *current = Some(category);
+
+        true
+    }
+
+    /// Current stream category, `None` until it is known
+    pub async fn category(&self) -> Option<String> {
+        self.category.lock().await.clone()
+    }
+
+    /// Returns whether the stream has just gone online or offline
+    pub fn set_live(&self, live: bool) -> bool {
+        let changed = self.live.swap(live, Ordering::Relaxed) != live;
+
+        if changed {
+            tracing::info!("stream is {}", if live { "online" } else { "offline" });
+
+            if !live {
+                *self.offline_at.lock().unwrap() = Some(Instant::now());
+                self.offline.notify_waiters();
+            }
+        }
+
+        changed
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    /// Time since the last `stream.offline`, `None` if the stream has not gone offline
+    pub fn offline_for(&self) -> Option<Duration> {
+        self.offline_at
+            .lock()
+            .unwrap()
+            .map(|offline_at| offline_at.elapsed())
+    }
+
+    /// Wait for the next `stream.offline`
+    pub async fn wait_offline(&self) {
+        self.offline.notified().await;
+    }
+
+    pub async fn set_followers_total(&self, total: u64) {
+        tracing::info!("channel has {total} followers");
+        *self.followers_total.lock().await = Some(total);
+    }
+
+    /// Total followers of the channel, `None` until it is fetched
+    pub async fn followers_total(&self) -> Option<u64> {
+        *self.followers_total.lock().await
+    }
+
+    /// Count a new follower, returns the new total if it is known
+    pub async fn add_follower(&self) -> Option<u64> {
+        let mut total = self.followers_total.lock().await;
+
+        *total = total.map(|total| total + 1);
+        *total
+    }
+}
+
+pub fn create_stream_info() -> SafeStreamInfo {
+    Arc::new(StreamInfo::default())
+}
@@ -0,0 +1,124 @@
+//! Text files for the OBS text sources reading from a file.
+//!
+//! When `HEWPME_TEXT_FILES_DIR` is set the files in it are rewritten on every follow,
+//! subscription and category change:
+//!
+//! - `follower_count.txt`, session or total followers like the overlays
+//! - `subscriber_count.txt`, subscribers of the session
+//! - `last_follower.txt`
+//! - `last_subscriber.txt`
+//! - `now_playing.txt`, the stream category
+//!
+//! Only the leader instance writes the files.
+use std::path::Path;
+use std::{fs, io};
+
+use tokio::sync::broadcast;
+
+use crate::config::{self, FollowerCountMode};
+use crate::events::BotEvent;
+use crate::helper::BotState;
+use crate::overlay::{OverlayEvent, SequencedEvent};
+
+const FOLLOWER_COUNT_FILE_NAME: &str = "follower_count.txt";
+const SUBSCRIBER_COUNT_FILE_NAME: &str = "subscriber_count.txt";
+const LAST_FOLLOWER_FILE_NAME: &str = "last_follower.txt";
+const LAST_SUBSCRIBER_FILE_NAME: &str = "last_subscriber.txt";
+const NOW_PLAYING_FILE_NAME: &str = "now_playing.txt";
+
+/// Keep the text files up to date, returns at once if they are not configured
+pub async fn run_text_files(state: BotState) {
+    let Some(dir) = config::get_text_files_dir() else {
+        return;
+    };
+
+    state.instance.wait_leader().await;
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        tracing::error!("Unable to create {}: {e}", dir.display());
+        return;
+    }
+
+    let mut events = state.bus.subscribe();
+    let mut overlay_events = state.overlay.subscribe();
+
+    write_initial(&state, &dir).await;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(BotEvent::Follow { user_name, .. }) => {
+                    write(&dir, LAST_FOLLOWER_FILE_NAME, &user_name);
+                }
+                Ok(BotEvent::Subscribe { user_id, user_name }) => {
+                    // the session lists may get the event after this task
+                    let count = {
+                        let subscribers = state.session.get_subscribers().await;
+
+                        subscribers.len() + usize::from(!subscribers.contains_key(&user_id))
+                    };
+
+                    write(&dir, LAST_SUBSCRIBER_FILE_NAME, &user_name);
+                    write(&dir, SUBSCRIBER_COUNT_FILE_NAME, &count.to_string());
+                }
+                Ok(BotEvent::CategoryChanged { category }) => {
+                    write(&dir, NOW_PLAYING_FILE_NAME, &category);
+                }
+                Ok(
+                    BotEvent::ChatMessage { .. }
+                    | BotEvent::Raid { .. }
+                    | BotEvent::StreamOnline
+                    | BotEvent::StreamOffline
+                    | BotEvent::Resubscribe { .. }
+                    | BotEvent::GiftSubscriptions { .. }
+                    | BotEvent::Cheer { .. }
+                    | BotEvent::AdBreak { .. },
+                ) => (),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("text files lagged, {skipped} events skipped");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            event = overlay_events.recv() => match event {
+                Ok(SequencedEvent { event: OverlayEvent::FollowersUpdated { count }, .. }) => {
+                    write(&dir, FOLLOWER_COUNT_FILE_NAME, &count.to_string());
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+/// Write the values known before the first event
+async fn write_initial(state: &BotState, dir: &Path) {
+    let followers = match config::get_follower_count_mode() {
+        FollowerCountMode::Session => Some(state.session.get_followers().await.len() as u64),
+        FollowerCountMode::Total => state.stream.followers_total().await,
+    };
+    let subscribers = state.session.get_subscribers().await.len();
+
+    if let Some(followers) = followers {
+        write(dir, FOLLOWER_COUNT_FILE_NAME, &followers.to_string());
+    }
+
+    write(dir, SUBSCRIBER_COUNT_FILE_NAME, &subscribers.to_string());
+
+    if let Some(category) = state.stream.category().await {
+        write(dir, NOW_PLAYING_FILE_NAME, &category);
+    }
+}
+
+fn write(dir: &Path, file_name: &str, text: &str) {
+    if let Err(e) = write_replacing(dir, file_name, text) {
+        tracing::warn!("Unable to write {file_name}: {e}");
+    }
+}
+
+/// Write next to the file and rename, so that OBS never reads a half-written one
+fn write_replacing(dir: &Path, file_name: &str, text: &str) -> io::Result<()> {
+    let temporary = dir.join(format!("{file_name}.tmp"));
+
+    fs::write(&temporary, text)?;
+    fs::rename(temporary, dir.join(file_name))
+}
@@ -0,0 +1,348 @@
+//! Network boundaries of the bot: the Twitch HTTP APIs, the EventSub websocket and
+//! the chat connection.
+//!
+//! The bot talks to Twitch through reqwest, tungstenite and `twitch_irc`, the unit tests
+//! plug in the mocks from `mock` that serve canned responses without the network. The
+//! integration tests point the bot at local servers instead: Helix follows
+//! `TWITCH_HELIX_URL`, the chat follows [`config::get_irc_addr`].
+use std::error::Error;
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite;
+use twitch_api::client::{BoxedFuture, Request, Response};
+use twitch_irc::transport::tcp::{MakeConnection, TCPTransport, TCPTransportConnectError, TLS};
+use url::Url;
+
+use crate::config;
+use crate::outage::{self, Source};
+
+pub type HttpRequest = http::Request<Vec<u8>>;
+pub type HttpResponse = http::Response<Vec<u8>>;
+
+#[derive(Debug)]
+pub struct TransportError {
+    description: String,
+}
+
+impl core::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "transport error: {}", self.description)
+    }
+}
+
+impl Error for TransportError {}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(value: reqwest::Error) -> Self {
+        TransportError {
+            description: value.to_string(),
+        }
+    }
+}
+
+impl From<http::Error> for TransportError {
+    fn from(value: http::Error) -> Self {
+        TransportError {
+            description: value.to_string(),
+        }
+    }
+}
+
+impl From<tungstenite::Error> for TransportError {
+    fn from(value: tungstenite::Error) -> Self {
+        TransportError {
+            description: value.to_string(),
+        }
+    }
+}
+
+/// Sends HTTP requests to the Twitch Helix and OAuth endpoints
+#[async_trait]
+pub trait HttpApi: Send + Sync + 'static {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError>;
+}
+
+/// Websocket connection the EventSub messages are received from
+#[async_trait]
+pub trait WsTransport: Send {
+    /// Open a new connection, the previous one is dropped
+    async fn connect(&mut self, url: &Url) -> Result<(), TransportError>;
+
+    /// Next message of the connection, `None` if there is no open connection
+    async fn recv(&mut self) -> Option<Result<tungstenite::Message, tungstenite::Error>>;
+
+    async fn close(&mut self);
+}
+
+/// [`HttpApi`] in the shape of the HTTP clients expected by `twitch_api` and `twitch_oauth2`
+pub struct ApiClient<H> {
+    api: Arc<H>,
+}
+
+impl<H> Clone for ApiClient<H> {
+    fn clone(&self) -> Self {
+        ApiClient {
+            api: Arc::clone(&self.api),
+        }
+    }
+}
+
+impl<H: HttpApi> ApiClient<H> {
+    pub fn new(api: Arc<H>) -> Self {
+        ApiClient { api }
+    }
+
+    /// The API for the requests `twitch_api` has no type for
+    pub fn api(&self) -> &H {
+        &self.api
+    }
+}
+
+impl<H: HttpApi> twitch_api::HttpClient for ApiClient<H> {
+    type Error = TransportError;
+
+    fn req(&self, request: Request) -> BoxedFuture<'_, Result<Response, TransportError>> {
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let response = self
+                .api
+                .send(http::Request::from_parts(parts, body.to_vec()))
+                .await?;
+            let (parts, body) = response.into_parts();
+
+            Ok(http::Response::from_parts(parts, body.into()))
+        })
+    }
+}
+
+impl<H: HttpApi> twitch_oauth2::client::Client for ApiClient<H> {
+    type Error = TransportError;
+
+    fn req(&self, request: HttpRequest) -> BoxedFuture<'_, Result<HttpResponse, TransportError>> {
+        Box::pin(self.api.send(request))
+    }
+}
+
+/// [`HttpApi`] backed by reqwest
+#[derive(Clone)]
+pub struct ReqwestApi {
+    client: reqwest::Client,
+}
+
+impl Default for ReqwestApi {
+    fn default() -> Self {
+        let client = reqwest::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Unable to build a client to send request to Twitch API");
+
+        ReqwestApi { client }
+    }
+}
+
+#[async_trait]
+impl HttpApi for ReqwestApi {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let response = match self.client.execute(request.try_into()?).await {
+            Ok(response) => response,
+            Err(e) => {
+                outage::record_failure(Source::Helix);
+                return Err(e.into());
+            }
+        };
+
+        outage::record_helix_status(response.status());
+
+        let mut builder = http::Response::builder().status(response.status());
+
+        for (name, value) in response.headers() {
+            builder = builder.header(name, value);
+        }
+
+        Ok(builder.body(response.bytes().await?.to_vec())?)
+    }
+}
+
+/// Production Twitch client sending its requests with reqwest
+pub type TwitchApiClient = ApiClient<ReqwestApi>;
+
+#[must_use]
+pub fn create_api_client() -> TwitchApiClient {
+    ApiClient::new(Arc::new(ReqwestApi::default()))
+}
+
+/// Helix endpoint `path`, e.g. `eventsub/subscriptions`, the base URL is taken from
+/// `TWITCH_HELIX_URL` if it is set
+///
+/// # Panics
+///
+/// Will panic if `path` is not a valid relative URL
+#[must_use]
+pub fn helix_url(path: &str) -> Url {
+    twitch_api::TWITCH_HELIX_URL
+        .join(path)
+        .expect("Invalid Helix path")
+}
+
+pub type WebSocketStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// [`WsTransport`] backed by tungstenite
+#[derive(Default)]
+pub struct TungsteniteTransport {
+    socket: Option<WebSocketStream>,
+}
+
+#[async_trait]
+impl WsTransport for TungsteniteTransport {
+    async fn connect(&mut self, url: &Url) -> Result<(), TransportError> {
+        let config = tungstenite::protocol::WebSocketConfig::default();
+        let (socket, _) =
+            tokio_tungstenite::connect_async_with_config(url, Some(config), false).await?;
+
+        self.socket = Some(socket);
+
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Option<Result<tungstenite::Message, tungstenite::Error>> {
+        self.socket.as_mut()?.next().await
+    }
+
+    async fn close(&mut self) {
+        if let Some(mut socket) = self.socket.take() {
+            let _ = socket.close(None).await;
+        }
+    }
+}
+
+/// Socket of the chat connection, either TLS or plain TCP
+pub trait ChatSocket: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin> ChatSocket for T {}
+
+/// Connects the chat client to Twitch over TLS, or to [`config::get_irc_addr`] over
+/// plain TCP if it is set
+pub struct ChatConnection;
+
+#[async_trait]
+impl MakeConnection for ChatConnection {
+    type Socket = Box<dyn ChatSocket>;
+
+    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
+        match config::get_irc_addr() {
+            Some(addr) => Ok(Box::new(TcpStream::connect(addr).await?)),
+            None => Ok(Box::new(TLS::new_socket().await?)),
+        }
+    }
+}
+
+pub type ChatTransport = TCPTransport<ChatConnection>;
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use tokio_tungstenite::tungstenite;
+    use url::Url;
+
+    use super::{HttpApi, HttpRequest, HttpResponse, TransportError, WsTransport};
+
+    type Handler = Box<dyn Fn(&HttpRequest) -> (u16, String) + Send + Sync>;
+
+    /// Request seen by [`MockHttp`]
+    #[derive(Debug, Clone)]
+    pub struct RecordedRequest {
+        pub method: String,
+        pub path: String,
+        pub body: String,
+    }
+
+    /// Answers every request with the status and JSON body returned by the handler
+    pub struct MockHttp {
+        handler: Handler,
+        requests: Mutex<Vec<RecordedRequest>>,
+    }
+
+    impl MockHttp {
+        pub fn new<F>(handler: F) -> Self
+        where
+            F: Fn(&HttpRequest) -> (u16, String) + Send + Sync + 'static,
+        {
+            MockHttp {
+                handler: Box::new(handler),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        pub fn requests(&self) -> Vec<RecordedRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl HttpApi for MockHttp {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+            let (status, body) = (self.handler)(&request);
+
+            self.requests.lock().unwrap().push(RecordedRequest {
+                method: request.method().to_string(),
+                path: request.uri().path().to_string(),
+                body: String::from_utf8_lossy(request.body()).into_owned(),
+            });
+
+            Ok(http::Response::builder()
+                .status(status)
+                .header("content-type", "application/json")
+                .body(body.into_bytes())?)
+        }
+    }
+
+    /// Replays the scripted messages, then reports the connection as closed
+    #[derive(Default)]
+    pub struct MockTransport {
+        pub script: VecDeque<tungstenite::Message>,
+        pub connected: Vec<Url>,
+        pub closed: usize,
+    }
+
+    impl MockTransport {
+        pub fn new<I: IntoIterator<Item = String>>(messages: I) -> Self {
+            MockTransport {
+                script: messages
+                    .into_iter()
+                    .map(tungstenite::Message::Text)
+                    .collect(),
+                ..MockTransport::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl WsTransport for MockTransport {
+        async fn connect(&mut self, url: &Url) -> Result<(), TransportError> {
+            self.connected.push(url.clone());
+
+            Ok(())
+        }
+
+        async fn recv(&mut self) -> Option<Result<tungstenite::Message, tungstenite::Error>> {
+            Some(
+                self.script
+                    .pop_front()
+                    .ok_or(tungstenite::Error::AlreadyClosed),
+            )
+        }
+
+        async fn close(&mut self) {
+            self.closed += 1;
+        }
+    }
+}
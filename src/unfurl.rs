@@ -0,0 +1,253 @@
+//! OpenGraph previews of the links posted in chat.
+//!
+//! Only links to the hosts in `HEWPME_UNFURL_HOSTS` are fetched, a redirect is
+//! followed only to an allowed host. The latest previews are cached for the session
+//! and pushed to the chat overlay.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{config, metrics};
+
+/// Metadata is in the head of the page, there is no need to read further
+const MAX_PAGE_SIZE: usize = 512 * 1024;
+const MAX_CACHED_PREVIEWS: usize = 256;
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl,
+    HostNotAllowed(String),
+    Fetch(reqwest::Error),
+    NotHtml,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidUrl => write!(f, "invalid link"),
+            Self::HostNotAllowed(host) => write!(f, "host {host} is not allowed"),
+            Self::Fetch(e) => write!(f, "unable to fetch link: {e}"),
+            Self::NotHtml => write!(f, "link is not an HTML page"),
+        }
+    }
+}
+
+/// Previews by link, the oldest one is dropped once the cache is full
+#[derive(Default)]
+struct PreviewCache {
+    /// `None` is cached for the pages without a preview
+    previews: HashMap<String, Option<LinkPreview>>,
+    order: VecDeque<String>,
+}
+
+impl PreviewCache {
+    fn get(&self, url: &str) -> Option<&Option<LinkPreview>> {
+        self.previews.get(url)
+    }
+
+    fn insert(&mut self, url: String, preview: Option<LinkPreview>) {
+        if self.previews.insert(url.clone(), preview).is_some() {
+            return;
+        }
+
+        self.order.push_back(url);
+
+        if self.order.len() > MAX_CACHED_PREVIEWS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.previews.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// The allowed hosts and the timeout are read on every link, they may change at runtime
+pub struct Unfurler {
+    client: reqwest::Client,
+    cache: Mutex<PreviewCache>,
+}
+
+pub type SafeUnfurler = Arc<Unfurler>;
+
+impl Unfurler {
+    fn new() -> Self {
+        let redirect = reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if let Err(e) = check_url(attempt.url().as_str()) {
+                attempt.error(e.to_string())
+            } else {
+                attempt.follow()
+            }
+        });
+
+        Unfurler {
+            client: reqwest::Client::builder()
+                .redirect(redirect)
+                .build()
+                .expect("Unable to create the HTTP client for the link previews"),
+            cache: Mutex::new(PreviewCache::default()),
+        }
+    }
+
+    /// Links in the chat message which can be unfurled
+    #[must_use]
+    pub fn find_links(&self, text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .filter(|word| word.starts_with("https://") || word.starts_with("http://"))
+            .filter(|word| check_url(word).is_ok())
+            .map(String::from)
+            .collect()
+    }
+
+    pub async fn preview(&self, url: &str) -> Result<Option<LinkPreview>, Error> {
+        let url = check_url(url)?;
+
+        if let Some(preview) = self.cache.lock().await.get(url.as_str()) {
+            return Ok(preview.clone());
+        }
+
+        let page = metrics::timed("unfurl_fetch", self.fetch(&url)).await?;
+        let preview = parse_preview(url.as_str(), &page);
+
+        self.cache
+            .lock()
+            .await
+            .insert(url.to_string(), preview.clone());
+
+        Ok(preview)
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<String, Error> {
+        let mut response = self
+            .client
+            .get(url.clone())
+            .timeout(config::get_unfurl_timeout())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(Error::Fetch)?;
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+
+        if !is_html {
+            return Err(Error::NotHtml);
+        }
+
+        let mut page = Vec::new();
+
+        while let Some(chunk) = response.chunk().await.map_err(Error::Fetch)? {
+            page.extend_from_slice(&chunk);
+
+            if page.len() >= MAX_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&page).into_owned())
+    }
+}
+
+/// # Panics
+///
+/// Panics if the HTTP client cannot be created, e.g. without the TLS backend
+pub fn create_unfurler() -> SafeUnfurler {
+    Arc::new(Unfurler::new())
+}
+
+/// The link if it is an HTTP(S) one to an allowed host
+fn check_url(url: &str) -> Result<Url, Error> {
+    let url = Url::parse(url).map_err(|_| Error::InvalidUrl)?;
+    let host = url.host_str().ok_or(Error::InvalidUrl)?;
+
+    if !matches!(url.scheme(), "https" | "http") {
+        return Err(Error::InvalidUrl);
+    }
+
+    if !config::get_unfurl_hosts()
+        .iter()
+        .any(|allowed| host == allowed || host.ends_with(&format!(".{allowed}")))
+    {
+        return Err(Error::HostNotAllowed(host.to_string()));
+    }
+
+    Ok(url)
+}
+
+fn parse_preview(url: &str, page: &str) -> Option<LinkPreview> {
+    let meta = meta_tags(page);
+    let title = meta.get("og:title").cloned().or_else(|| title_tag(page))?;
+
+    Some(LinkPreview {
+        url: url.to_string(),
+        title,
+        description: meta
+            .get("og:description")
+            .or_else(|| meta.get("description"))
+            .cloned(),
+        image: meta.get("og:image").cloned(),
+        site_name: meta.get("og:site_name").cloned(),
+    })
+}
+
+/// `property` or `name` of the `<meta>` tags to their `content`
+fn meta_tags(page: &str) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+
+    for tag in page.split("<meta").skip(1) {
+        let Some(end) = tag.find('>') else {
+            continue;
+        };
+        let attributes = &tag[..end];
+        let key = attribute(attributes, "property").or_else(|| attribute(attributes, "name"));
+
+        if let (Some(key), Some(content)) = (key, attribute(attributes, "content")) {
+            tags.entry(key.to_lowercase()).or_insert(content);
+        }
+    }
+
+    tags
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let start = attributes.find(&format!("{name}="))? + name.len() + 1;
+    let rest = &attributes[start..];
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    let end = value.find(quote)?;
+
+    Some(decode_entities(&value[..end]))
+}
+
+fn title_tag(page: &str) -> Option<String> {
+    let start = page.find("<title")?;
+    let rest = &page[start..];
+    let content = &rest[rest.find('>')? + 1..];
+    let title = content[..content.find("</title>")?].trim();
+
+    (!title.is_empty()).then(|| decode_entities(title))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,223 @@
+//! Twitch profiles of the users seen during the session.
+//!
+//! The chat and EventSub clients remember every user ID they see, the profiles
+//! are resolved lazily in batches through Helix Get Users before rendering credits.
+//! A user Get Users fails for is not asked for again for 10 minutes.
+//! The chat badges and the name color are kept from the latest chat message.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use twitch_api::helix::users::GetUsersRequest;
+use twitch_api::helix::HelixClient;
+use twitch_api::types::UserIdRef;
+
+use crate::badges::ChatStyle;
+use crate::eventsub::get_eventsub_token;
+use crate::image_cache::{self, CachedImage};
+use crate::metrics;
+use crate::transport::create_api_client;
+
+/// Get Users accepts up to 100 IDs per request
+const GET_USERS_BATCH_SIZE: usize = 100;
+const FAILED_RETRY_DELAY: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UserProfile {
+    pub id: String,
+    pub login: String,
+    pub display_name: String,
+    pub profile_image_url: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Default)]
+pub struct UserDirectory {
+    /// User ID to the name the user was seen with
+    seen: Mutex<HashMap<String, String>>,
+    profiles: Mutex<HashMap<String, UserProfile>>,
+    /// User ID to the time Get Users failed for it or did not return it
+    failed: Mutex<HashMap<String, Instant>>,
+    /// Profiles are being resolved for the account ages in the background
+    resolving: AtomicBool,
+    /// Chat badges and color of the chatters by user ID
+    styles: Mutex<HashMap<String, ChatStyle>>,
+}
+
+pub type SafeUserDirectory = Arc<UserDirectory>;
+
+impl UserDirectory {
+    pub async fn remember(&self, user_id: &str, name: &str) {
+        let mut guard = self.seen.lock().await;
+
+        if guard.get(user_id).map(String::as_str) != Some(name) {
+            guard.insert(user_id.to_string(), name.to_string());
+        }
+    }
+
+    /// Name the user was last seen with
+    pub async fn name_of(&self, user_id: &str) -> Option<String> {
+        self.seen.lock().await.get(user_id).cloned()
+    }
+
+    /// ID of the user last seen with the login
+    pub async fn id_of(&self, login: &str) -> Option<String> {
+        self.seen
+            .lock()
+            .await
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(login))
+            .map(|(user_id, _)| user_id.clone())
+    }
+
+    pub async fn remember_style(&self, user_id: &str, style: ChatStyle) {
+        self.styles.lock().await.insert(user_id.to_string(), style);
+    }
+
+    /// Chat badges and color of the chatters by user ID
+    pub async fn styles(&self) -> HashMap<String, ChatStyle> {
+        self.styles.lock().await.clone()
+    }
+
+    pub async fn forget(&self, user_id: &str) {
+        self.seen.lock().await.remove(user_id);
+        self.profiles.lock().await.remove(user_id);
+        self.failed.lock().await.remove(user_id);
+        self.styles.lock().await.remove(user_id);
+    }
+
+    /// Fetch profiles of all users which are not resolved yet
+    pub async fn resolve(&self) {
+        let missing: Vec<String> = {
+            let seen = self.seen.lock().await;
+            let profiles = self.profiles.lock().await;
+            let mut failed = self.failed.lock().await;
+
+            failed.retain(|_, failed_at| failed_at.elapsed() < FAILED_RETRY_DELAY);
+            seen.keys()
+                .filter(|user_id| {
+                    !profiles.contains_key(*user_id) && !failed.contains_key(*user_id)
+                })
+                .cloned()
+                .collect()
+        };
+
+        if missing.is_empty() {
+            return;
+        }
+
+        let Some(token) = get_eventsub_token().await else {
+            tracing::debug!("no EventSub token yet, user profiles are not resolved");
+            return;
+        };
+        let client = HelixClient::with_client(create_api_client());
+
+        for batch in missing.chunks(GET_USERS_BATCH_SIZE) {
+            let ids: Vec<&UserIdRef> = batch.iter().map(|id| id.as_str().into()).collect();
+            let response = metrics::timed(
+                "helix_get_users",
+                client.req_get(GetUsersRequest::ids(ids.as_slice()), &token),
+            )
+            .await;
+
+            let resolved = match response {
+                Ok(response) => {
+                    let mut profiles = self.profiles.lock().await;
+
+                    for user in response.data {
+                        profiles.insert(
+                            user.id.to_string(),
+                            UserProfile {
+                                id: user.id.to_string(),
+                                login: user.login.to_string(),
+                                display_name: user.display_name.to_string(),
+                                profile_image_url: user.profile_image_url,
+                                created_at: user.created_at.to_string(),
+                            },
+                        );
+                    }
+
+                    profiles
+                }
+                Err(e) => {
+                    tracing::warn!("Unable to resolve user profiles: {e}");
+                    self.profiles.lock().await
+                }
+            };
+            let mut failed = self.failed.lock().await;
+
+            for user_id in batch
+                .iter()
+                .filter(|user_id| !resolved.contains_key(*user_id))
+            {
+                failed.insert(user_id.clone(), Instant::now());
+            }
+        }
+    }
+
+    /// Resolved profiles of the users with the given IDs, in the same order
+    pub async fn profiles_of<I>(&self, user_ids: I) -> Vec<UserProfile>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let profiles = self.profiles.lock().await;
+
+        user_ids
+            .into_iter()
+            .filter_map(|user_id| profiles.get(user_id.as_ref()).cloned())
+            .collect()
+    }
+
+    /// Age of the account in days, `None` until the profile is resolved in the background
+    pub async fn account_age_days(self: &Arc<Self>, user_id: &str) -> Option<i64> {
+        let created_at = self
+            .profiles
+            .lock()
+            .await
+            .get(user_id)
+            .map(|profile| profile.created_at.clone());
+        let Some(created_at) = created_at else {
+            // the chat message is not held back by Helix
+            if !self.resolving.swap(true, Ordering::AcqRel) {
+                let users = Arc::clone(self);
+
+                tokio::spawn(async move {
+                    users.resolve().await;
+                    users.resolving.store(false, Ordering::Release);
+                });
+            }
+
+            return None;
+        };
+        let created_at = chrono::DateTime::parse_from_rfc3339(created_at.as_str()).ok()?;
+
+        Some((chrono::Utc::now() - created_at.with_timezone(&chrono::Utc)).num_days())
+    }
+
+    /// Profile image of the user served through the on-disk image cache
+    pub async fn avatar(&self, user_id: &str) -> Option<CachedImage> {
+        let url = self
+            .profiles
+            .lock()
+            .await
+            .get(user_id)?
+            .profile_image_url
+            .clone()?;
+
+        match image_cache::get(&url).await {
+            Ok(image) => Some(image),
+            Err(e) => {
+                tracing::warn!("Unable to get avatar {url}: {e}");
+                None
+            }
+        }
+    }
+}
+
+pub fn create_user_directory() -> SafeUserDirectory {
+    Arc::new(UserDirectory::default())
+}
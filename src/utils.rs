@@ -1,5 +1,4 @@
-mod auth;
-mod token;
+//! Authorization of the bot on Twitch.
+pub mod token;
 
-pub(crate) use auth::*;
 pub(crate) use token::*;
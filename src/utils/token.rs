@@ -1,321 +1,316 @@
+//! User tokens of the bot on top of [`twitch_auth`]: the token files of the clients and
+//! the tokens co-hosts granted the bot for their own channels.
 use core::time::Duration;
-use std::collections::HashMap;
-use std::fmt::Formatter;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
-use std::{env, fs, io};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fs, io};
 
 use chrono::{DateTime, Utc};
-use reqwest::{ClientBuilder, IntoUrl};
 use serde::{Deserialize, Serialize};
-use tokio::runtime::Handle;
-use twitch_api::types::{UserId, UserName};
-use twitch_irc::login::UserAccessToken;
-use twitch_oauth2::client::Client;
-use twitch_oauth2::{
-    AccessToken, ClientSecret, CsrfToken, RefreshToken, Scope, TwitchToken, UserToken,
-    UserTokenBuilder,
+use tokio::sync::Mutex;
+use twitch_auth::{
+    callback_channel, run_callback_server, Authorization, Callback, Credentials, FileStore,
+    TokenManager, DEFAULT_CALLBACK_ADDR,
 };
+use twitch_oauth2::{Scope, UserToken};
 use url::Url;
 
-use crate::config;
-use crate::utils::{create_auth_channel, run_auth_server};
+pub use twitch_auth::Token;
 
-pub struct Wrapper {
-    token: UserToken,
-}
+use crate::transport::create_api_client;
+use crate::{config, redact};
 
-impl Wrapper {
-    pub async fn new<T: IntoUrl>(ctx: CreateContext<'_, T>) -> Self {
-        Wrapper {
-            token: request_user_token(ctx).await,
-        }
-    }
+/// Permissions a co-host grants the bot for their own channel
+pub(crate) const DELEGATED_SCOPES: [Scope; 1] = [Scope::ClipsEdit];
+/// The callback server is stopped if the co-host does not authorize the bot in time
+const DELEGATION_TIMEOUT: Duration = Duration::from_secs(600);
 
-    pub fn get_user_token(&self) -> &UserToken {
-        &self.token
-    }
-}
+/// The callback server serves one authorization at a time
+static DELEGATION_PENDING: AtomicBool = AtomicBool::new(false);
+/// Serializes the updates of the delegated tokens file
+static DELEGATIONS: Mutex<()> = Mutex::const_new(());
 
+/// Token a co-host granted the bot for their own channel
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Token {
-    #[allow(clippy::struct_field_names)]
-    pub access_token: AccessToken,
-    #[allow(clippy::struct_field_names)]
-    pub refresh_token: Option<RefreshToken>,
-    pub created_at: DateTime<Utc>,
-    pub valid_till: DateTime<Utc>,
-    pub scopes: Option<Vec<Scope>>,
+pub struct Delegation {
+    pub login: String,
+    pub granted_at: DateTime<Utc>,
+    pub token: Token,
 }
 
-impl From<UserToken> for Token {
-    fn from(value: UserToken) -> Self {
-        From::from(&value)
-    }
+/// Co-host who granted the bot a token, without the token itself
+#[derive(Serialize, Debug)]
+pub struct DelegatedIdentity {
+    pub user_id: String,
+    pub login: String,
+    pub granted_at: DateTime<Utc>,
+    pub scopes: Vec<Scope>,
 }
 
-impl From<&UserToken> for Token {
-    fn from(value: &UserToken) -> Self {
-        let now = chrono::offset::Utc::now();
-        let valid_till = now + value.expires_in();
+/// # Panics
+///
+/// Will panic if the client ID or secret is not configured
+pub(crate) fn credentials() -> Credentials {
+    Credentials::new(config::get_client_id(), config::get_client_secret())
+}
 
-        Self {
-            access_token: value.access_token.clone(),
-            refresh_token: value.refresh_token.clone(),
-            created_at: now,
-            valid_till,
-            scopes: Some(value.scopes().to_vec()),
-        }
-    }
+/// Token kept in the file `token_file`, e.g. [`config::get_eventsub_config_file`]
+pub(crate) fn token_manager(token_file: PathBuf) -> TokenManager<FileStore> {
+    TokenManager::new(credentials(), FileStore::new(token_file))
 }
 
-impl From<UserAccessToken> for Token {
-    fn from(value: UserAccessToken) -> Self {
-        value.into()
-    }
+/// # Panics
+///
+/// Will panic if the configured redirect URL is not valid
+pub(crate) fn redirect_url() -> Url {
+    Url::parse(&config::get_redirect_url()).expect("Invalid redirect URL")
 }
 
-impl From<&UserAccessToken> for Token {
-    fn from(value: &UserAccessToken) -> Self {
-        let valid_till = value
-            .expires_at
-            .unwrap_or(value.created_at + Duration::from_secs(600));
+/// Start authorizing a co-host, returns the URL to send them
+///
+/// Returns `None` if another co-host is being authorized
+pub(crate) fn start_delegation() -> Option<Url> {
+    if DELEGATION_PENDING.swap(true, Ordering::AcqRel) {
+        return None;
+    }
 
-        Self {
-            access_token: value.access_token.clone().into(),
-            refresh_token: Some(value.refresh_token.clone().into()),
-            created_at: value.created_at,
-            valid_till,
-            scopes: None,
+    let redirect_url =
+        Url::parse(&config::get_delegation_redirect_url()).expect("Invalid redirect URL");
+    // the co-host may be logged in to Twitch as the broadcaster on the same browser
+    let authorization = Authorization::new(&credentials(), &DELEGATED_SCOPES, true, redirect_url);
+    let url = authorization.url().clone();
+    let (tx, mut rx) = callback_channel();
+
+    tokio::spawn(async move {
+        let auth_server = tokio::spawn(run_callback_server(DEFAULT_CALLBACK_ADDR, tx));
+
+        match tokio::time::timeout(DELEGATION_TIMEOUT, rx.recv()).await {
+            Ok(Some(callback)) => match complete_delegation(authorization, &callback).await {
+                Ok(login) => tracing::info!("{} granted the bot a token", redact::Name(&login)),
+                Err(e) => tracing::warn!("Unable to authorize the co-host: {e}"),
+            },
+            Ok(None) => tracing::warn!("The callback server stopped without a response"),
+            Err(_) => {
+                tracing::warn!("The co-host has not authorized the bot in time");
+                auth_server.abort();
+            }
         }
-    }
-}
 
-pub struct CreateContext<'a, T: IntoUrl> {
-    pub scopes: &'a [Scope],
-    pub force_verify: bool,
-    pub redirect_url: T,
-}
+        DELEGATION_PENDING.store(false, Ordering::Release);
+    });
 
-impl<'a, T: IntoUrl> CreateContext<'a, T> {
-    pub fn new(scopes: &'a [Scope], force_verify: bool, redirect_url: T) -> Self {
-        CreateContext {
-            scopes,
-            force_verify,
-            redirect_url,
-        }
-    }
+    Some(url)
 }
 
-#[derive(Debug)]
-enum Error {
-    NoCsrfToken,
-    CsrfTokenMismatch,
-}
+/// Exchange the code for the token and save it, returns the login of the co-host
+async fn complete_delegation(
+    authorization: Authorization,
+    callback: &Callback,
+) -> Result<String, twitch_auth::Error> {
+    let user_token = authorization
+        .complete(&create_api_client(), callback)
+        .await?;
+    let login = user_token.login.to_string();
+    let _lock = DELEGATIONS.lock().await;
+    let mut delegations = load_delegations();
+
+    delegations.insert(
+        user_token.user_id.to_string(),
+        Delegation {
+            login: login.clone(),
+            granted_at: Utc::now(),
+            token: Token::from(&user_token),
+        },
+    );
+    save_delegations(&delegations)?;
 
-impl core::fmt::Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::NoCsrfToken => write!(f, "No CSRF token in the response"),
-            Self::CsrfTokenMismatch => write!(
-                f,
-                "CSRF token in the response does not match one from the request"
-            ),
-        }
-    }
+    Ok(login)
 }
 
-impl Token {
-    pub fn save(&self, out: PathBuf) -> io::Result<()> {
-        let file = fs::File::create(out)?;
-        let writer = io::BufWriter::new(file);
+/// Co-hosts who granted the bot a token
+pub(crate) fn delegations() -> Vec<DelegatedIdentity> {
+    load_delegations()
+        .into_iter()
+        .map(|(user_id, delegation)| DelegatedIdentity {
+            user_id,
+            login: delegation.login,
+            granted_at: delegation.granted_at,
+            scopes: delegation.token.scopes.unwrap_or_default(),
+        })
+        .collect()
+}
 
-        serde_json::to_writer(writer, &self)?;
+/// Whether the co-host `login` granted the bot a token
+pub(crate) fn is_delegated(login: &str) -> bool {
+    load_delegations()
+        .values()
+        .any(|delegation| delegation.login.eq_ignore_ascii_case(login))
+}
 
-        Ok(())
-    }
+/// Token the co-host `login` granted for their own channel, refreshed if it is expired
+///
+/// Returns `None` if there is no token or it is no longer valid
+pub(crate) async fn delegated_token(login: &str) -> Option<UserToken> {
+    let _lock = DELEGATIONS.lock().await;
+    let mut delegations = load_delegations();
+    let user_id = delegations
+        .iter()
+        .find(|(_, delegation)| delegation.login.eq_ignore_ascii_case(login))
+        .map(|(user_id, _)| user_id.clone())?;
+    let delegation = delegations.get_mut(&user_id)?;
+
+    match delegation
+        .token
+        .validate(&create_api_client(), &credentials())
+        .await
+    {
+        Ok((user_token, refreshed)) => {
+            if refreshed {
+                delegation.token = Token::from(&user_token);
 
-    pub fn from_file(file: PathBuf) -> io::Result<Self> {
-        get_token_from_file(file)
-    }
+                if let Err(e) = save_delegations(&delegations) {
+                    tracing::warn!("Unable to store the refreshed co-host token: {e}");
+                }
+            }
 
-    pub async fn into_user_token(self) -> UserToken {
-        let client_secret = env::var("TWITCH_CLIENT_SECRET").unwrap();
-        let client = ClientBuilder::new()
-            .redirect(reqwest::redirect::Policy::none())
-            .build()
-            .expect("Unable to build a client to send request to Twitch API");
-        let is_expired = self.valid_till < chrono::Utc::now();
-
-        if is_expired {
-            let user_token = refresh_expired(&self, &client).await;
-            let token: Token = user_token.clone().into();
-
-            token
-                .save(config::get_eventsub_config_file())
-                .expect("Unable to store refreshed token");
-
-            user_token
-        } else {
-            from_existing(
-                &client,
-                &self.access_token,
-                &self.refresh_token,
-                client_secret.as_str(),
-            )
-            .await
+            Some(user_token)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "The token of co-host {} is not valid: {e}",
+                redact::Name(login)
+            );
+            None
         }
     }
 }
 
-async fn refresh_expired<C: Client>(token: &Token, client: &C) -> UserToken {
-    let client_id = env::var("TWITCH_CLIENT_ID").unwrap();
-    let client_secret = env::var("TWITCH_CLIENT_SECRET").unwrap();
-    let mut user_token = UserToken::from_existing_unchecked(
-        token.access_token.clone(),
-        token.refresh_token.clone(),
-        client_id,
-        Some(ClientSecret::from(client_secret.clone())),
-        UserName::from(""),
-        UserId::from(""),
-        Some(token.scopes.clone().unwrap()),
-        None,
-    );
-    user_token
-        .refresh_token(client)
-        .await
-        .expect("Unable to refresh token");
-    // need this to properly retrieve username, user ID and expires_in info
-    from_existing(
-        client,
-        &user_token.access_token,
-        &user_token.refresh_token,
-        client_secret.as_str(),
-    )
-    .await
-}
+/// Forget the token of the co-host, returns whether there was one
+///
+/// # Errors
+///
+/// Will return `Err` if the remaining tokens cannot be saved
+pub(crate) async fn remove_delegation(user_id: &str) -> io::Result<bool> {
+    let _lock = DELEGATIONS.lock().await;
+    let mut delegations = load_delegations();
+    let removed = delegations.remove(user_id).is_some();
+
+    if removed {
+        save_delegations(&delegations)?;
+    }
 
-async fn from_existing<C: Client>(
-    client: &C,
-    access_token: &AccessToken,
-    refresh_token: &Option<RefreshToken>,
-    client_secret: &str,
-) -> UserToken {
-    UserToken::from_existing(
-        client,
-        access_token.clone(),
-        refresh_token.clone(),
-        ClientSecret::from(client_secret),
-    )
-    .await
-    .expect("Unable to get token")
+    Ok(removed)
 }
 
-fn get_token_from_file(config_file: PathBuf) -> io::Result<Token> {
-    let file = fs::File::open(config_file)?;
-    let reader = io::BufReader::new(file);
+/// Delegated tokens keyed by the user ID of the co-host
+fn load_delegations() -> BTreeMap<String, Delegation> {
+    let Ok(file) = fs::File::open(config::get_delegated_config_file()) else {
+        return BTreeMap::new();
+    };
 
-    Ok(serde_json::from_reader(reader)?)
+    serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|e| {
+        tracing::warn!("Unable to read the delegated tokens: {e}");
+        BTreeMap::new()
+    })
 }
 
-fn create_token_context<T: IntoUrl>(ctx: CreateContext<'_, T>) -> UserTokenBuilder {
-    let redirect_url = ctx.redirect_url.into_url().expect("Invalid redirect URL");
-    let client_id = env::var("TWITCH_CLIENT_ID").unwrap();
-    let client_secret = env::var("TWITCH_CLIENT_SECRET").unwrap();
-    let mut builder = UserTokenBuilder::new(client_id, client_secret, redirect_url);
-
-    builder = builder.set_scopes(ctx.scopes.to_vec());
-    builder = builder.force_verify(ctx.force_verify); // Defaults to false
+fn save_delegations(delegations: &BTreeMap<String, Delegation>) -> io::Result<()> {
+    let file = fs::File::create(config::get_delegated_config_file())?;
 
-    builder
-}
+    serde_json::to_writer(io::BufWriter::new(file), delegations)?;
 
-fn generate_token_url(builder: &mut UserTokenBuilder) -> (Url, CsrfToken) {
-    builder.generate_url()
+    Ok(())
 }
 
-fn verify_csrf_token(
-    response: &HashMap<String, String>,
-    builder: &UserTokenBuilder,
-) -> Result<(), Error> {
-    let resp_csrf_token = response.get("state");
-
-    if let Some(csrf) = resp_csrf_token {
-        return if builder.csrf_is_valid(csrf) {
-            Ok(())
-        } else {
-            Err(Error::CsrfTokenMismatch)
-        };
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::Arc;
+
+    use twitch_auth::MemoryStore;
+    use twitch_oauth2::{AccessToken, RefreshToken};
+
+    use super::*;
+    use crate::transport::mock::MockHttp;
+    use crate::transport::ApiClient;
+
+    const VALIDATE_RESPONSE: &str = r#"{
+        "client_id": "client-id",
+        "login": "hewpme_test",
+        "scopes": ["moderator:read:followers"],
+        "user_id": "141981764",
+        "expires_in": 5520838
+    }"#;
+    const REFRESH_RESPONSE: &str = r#"{
+        "access_token": "refreshed-access",
+        "refresh_token": "refreshed-refresh",
+        "expires_in": 14400,
+        "scope": ["moderator:read:followers"],
+        "token_type": "bearer"
+    }"#;
+
+    fn twitch_oauth() -> Arc<MockHttp> {
+        env::set_var("TWITCH_CLIENT_ID", "client-id");
+        env::set_var("TWITCH_CLIENT_SECRET", "client-secret");
+
+        Arc::new(MockHttp::new(|request| match request.uri().path() {
+            "/oauth2/validate" => (200, VALIDATE_RESPONSE.to_string()),
+            "/oauth2/token" => (200, REFRESH_RESPONSE.to_string()),
+            _ => (
+                404,
+                String::from(r#"{"status": 404, "message": "not found"}"#),
+            ),
+        }))
     }
 
-    Err(Error::NoCsrfToken)
-}
-
-fn extract_pair<'a>(
-    query: &'a HashMap<String, String>,
-    key1: &str,
-    key2: &str,
-) -> (Option<&'a String>, Option<&'a String>) {
-    let value1 = query.get(key1);
-    let value2 = query.get(key2);
-
-    (value1, value2)
-}
-
-/// Extract the state and code from the URL a user was redirected to after authorizing the application.
-fn extract_url(query: &HashMap<String, String>) -> Result<(String, String), ()> {
-    if let (Some(error), Some(error_description)) =
-        extract_pair(query, "error", "error_description")
-    {
-        panic!("Unable to get token {error}, {error_description}")
-    } else if let (Some(state), Some(code)) = extract_pair(query, "state", "code") {
-        Ok((state.clone(), code.clone()))
-    } else {
-        Err(())
+    fn saved_token(valid_till: DateTime<Utc>) -> Token {
+        Token {
+            access_token: AccessToken::from("saved-access"),
+            refresh_token: Some(RefreshToken::from("saved-refresh")),
+            created_at: valid_till - chrono::Duration::hours(4),
+            valid_till,
+            scopes: Some(vec![Scope::ModeratorReadFollowers]),
+        }
     }
-}
 
-async fn request_user_token<T: IntoUrl>(ctx: CreateContext<'_, T>) -> UserToken {
-    // no token - retrieve it from Twitch API
-    // 1. run auth server
-    // 2. generate token URL
-    // 3. await response from Twitch API
-    // 4. create config dir and config file
-    // 5. serialize Token to this file
-    let (tx, mut rx) = create_auth_channel();
-    let handle = Handle::current();
-    let auth_server = handle.spawn(async move {
-        run_auth_server(tx).await;
-    });
-    let mut token_context = create_token_context(ctx);
-    let (url, csrf_token) = generate_token_url(&mut token_context);
-    // Make your user navigate to this URL, for example
-    println!("Visit this URL to authorize Twitch access: {url}");
-    let auth_response = rx
-        .recv()
-        .await
-        .expect("Unable to get authentication response");
-    tokio::task::spawn_blocking(move || {
-        handle.block_on(auth_server).unwrap();
-    });
-    println!("{auth_response:?}, {}", csrf_token.as_str());
-
-    if let Err(e) = verify_csrf_token(&auth_response, &token_context) {
-        tracing::error!("CSRF token error: {e}");
-        panic!("CSRF token error: {e}");
+    #[tokio::test]
+    async fn valid_token_is_only_validated() {
+        let http = twitch_oauth();
+        let tokens = TokenManager::new(
+            credentials(),
+            MemoryStore::with_token(saved_token(Utc::now() + chrono::Duration::hours(1))),
+        );
+        let token = tokens
+            .load(&ApiClient::new(Arc::clone(&http)))
+            .await
+            .unwrap()
+            .unwrap();
+        let requests = http.requests();
+
+        assert_eq!(token.access_token.as_str(), "saved-access");
+        assert_eq!(token.login.as_str(), "hewpme_test");
+        assert_eq!(token.user_id.as_str(), "141981764");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/oauth2/validate");
     }
 
-    match extract_url(&auth_response) {
-        Ok((ref state, ref code)) => {
-            let client = ClientBuilder::new()
-                .redirect(reqwest::redirect::Policy::none())
-                .build()
-                .expect("Unable to build a client to send request to Twitch API");
-
-            token_context
-                .get_user_token(&client, state, code)
-                .await
-                .expect("Failed to get user token from Twitch")
-        }
-        _ => todo!(),
+    #[tokio::test]
+    async fn expired_token_is_refreshed() {
+        let http = twitch_oauth();
+        let (token, refreshed) = saved_token(Utc::now() - chrono::Duration::hours(1))
+            .validate(&ApiClient::new(Arc::clone(&http)), &credentials())
+            .await
+            .unwrap();
+        let paths = http
+            .requests()
+            .into_iter()
+            .map(|request| request.path)
+            .collect::<Vec<_>>();
+
+        assert!(refreshed);
+        assert_eq!(token.access_token.as_str(), "refreshed-access");
+        assert_eq!(token.user_id.as_str(), "141981764");
+        assert_eq!(paths, ["/oauth2/token", "/oauth2/validate"]);
     }
 }
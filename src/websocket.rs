@@ -1,15 +1,33 @@
-/// Requires the following permissions:
-/// - channel:read:subscriptions
-/// - moderator:read:followers
+//! EventSub WebSocket client subscribing to the channel events.
+//!
+//! Requires the following permissions:
+//! - channel:read:subscriptions (channel.subscribe and channel.subscription.end)
+//! - channel:read:redemptions (channel.channel_points_custom_reward_redemption.add, only
+//!   with a reward to handle)
+//! - moderator:read:followers
+//! - bits:read (channel.cheer, skipped without the permission)
+//! - channel:read:ads (channel.ad_break.begin)
+//!
+//! channel.raid needs no permission.
 use std::error::Error;
 use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::time::Duration;
 
+use rand::Rng;
+use tokio::time::Instant;
 use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tracing::Instrument;
 use twitch_api::eventsub::channel::{
-    ChannelFollowV2, ChannelFollowV2Payload, ChannelSubscribeV1, ChannelSubscribeV1Payload,
+    ChannelCheerV1, ChannelFollowV2, ChannelFollowV2Payload,
+    ChannelPointsCustomRewardRedemptionAddV1, ChannelRaidV1, ChannelSubscribeV1,
+    ChannelSubscribeV1Payload, ChannelSubscriptionEndV1, ChannelSubscriptionGiftV1,
+    ChannelSubscriptionMessageV1, ChannelUpdateV2,
 };
-use twitch_api::types::UserId;
+use twitch_api::eventsub::stream::{StreamOfflineV1, StreamOnlineV1};
+use twitch_api::eventsub::EventSubscription;
+use twitch_api::types::{SubscriptionTier, UserId};
 use twitch_api::{
     eventsub::{
         self,
@@ -18,34 +36,195 @@ use twitch_api::{
     },
     HelixClient,
 };
-use twitch_oauth2::{TwitchToken, UserToken};
+use twitch_oauth2::{Scope, TwitchToken, UserToken};
 use url::Url;
 
-use crate::helper::SafeTwitchEventList;
+use crate::ad_breaks;
+use crate::events::{self, BotEvent};
+use crate::helper::BotState;
+use crate::lapses;
+use crate::notifications::NotificationKind;
+use crate::outage::{self, Source};
+use crate::raid_train;
+use crate::raids;
+use crate::rewards::{self, Redemption};
+use crate::sessions;
+use crate::transport::{helix_url, ApiClient, HttpApi, WsTransport};
+use crate::utils;
+use crate::wheel::{self, SpinSource};
+use crate::{config, metrics, redact};
 
-pub struct WSlient {
+/// How often the silence on the connection is checked
+const WATCHDOG_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Allowance for the network delay on top of the keepalive timeout of the session
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(2);
+
+const EVENTSUB_SUBSCRIPTIONS_PATH: &str = "eventsub/subscriptions";
+
+/// Subscriptions created for every session
+const SUBSCRIPTIONS: [&str; 9] = [
+    ChannelFollowV2::EVENT_TYPE.to_str(),
+    ChannelRaidV1::EVENT_TYPE.to_str(),
+    ChannelSubscribeV1::EVENT_TYPE.to_str(),
+    ChannelSubscriptionEndV1::EVENT_TYPE.to_str(),
+    ChannelSubscriptionGiftV1::EVENT_TYPE.to_str(),
+    ChannelSubscriptionMessageV1::EVENT_TYPE.to_str(),
+    ChannelUpdateV2::EVENT_TYPE.to_str(),
+    StreamOnlineV1::EVENT_TYPE.to_str(),
+    StreamOfflineV1::EVENT_TYPE.to_str(),
+];
+/// Subscriptions that are fine to miss, e.g. the token of an older install lacks the
+/// permission, see [`WSlient::optional_subscriptions`] for the ones created
+const OPTIONAL_SUBSCRIPTIONS: [&str; 3] = [
+    ad_breaks::EVENT_TYPE,
+    ChannelCheerV1::EVENT_TYPE.to_str(),
+    ChannelPointsCustomRewardRedemptionAddV1::EVENT_TYPE.to_str(),
+];
+
+/// How the lost EventSub connection is reestablished
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Failed attempts in a row before the client gives up, `0` for no limit
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Delay between the attempts during a Twitch outage, they do not count towards
+    /// `max_attempts`; `None` to retry as usual
+    pub outage_delay: Option<Duration>,
+}
+
+impl ReconnectPolicy {
+    #[must_use]
+    pub fn from_config() -> Self {
+        ReconnectPolicy {
+            max_attempts: config::get_eventsub_reconnect_attempts(),
+            initial_delay: config::get_eventsub_reconnect_delay(),
+            max_delay: config::get_eventsub_reconnect_max_delay(),
+            outage_delay: Some(config::get_outage_retry_delay()),
+        }
+    }
+
+    /// Exponential delay before the attempt, randomized so that the clients
+    /// disconnected at once do not reconnect at once
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    fn gives_up(&self, attempts: u32) -> bool {
+        self.max_attempts != 0 && attempts >= self.max_attempts
+    }
+}
+
+pub struct WSlient<H: HttpApi, T: WsTransport> {
     /// The session id of the websocket connection
     pub session_id: Option<String>,
     /// The token used to authenticate with the Twitch API
     pub token: UserToken,
     /// The client used to make requests to the Twitch API
-    pub client: HelixClient<'static, reqwest::Client>,
+    pub client: HelixClient<'static, ApiClient<H>>,
+    /// The websocket connection
+    pub transport: T,
     /// The user id of the channel we want to listen to
     pub user_id: UserId,
     /// The url to use for websocket
     pub connect_url: Url,
     // pub opts: Arc<crate::Opts>,
-    events_list: SafeTwitchEventList,
+    state: BotState,
+    reconnect: ReconnectPolicy,
+    /// Reconnection attempts since the last verified session
+    reconnect_attempts: u32,
+    /// Longest silence Twitch allows on the session, `None` before the welcome
+    keepalive_timeout: Option<Duration>,
+    /// Where the refreshed token is saved
+    token_file: PathBuf,
+    /// Reason of the last close by Twitch since the last verified session
+    last_close: Option<CloseReason>,
+}
+
+/// Why Twitch closed the EventSub connection, the session is gone in every case
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// 4000
+    InternalServerError,
+    /// 4001, the client must not send anything but pongs
+    ClientSentInboundTraffic,
+    /// 4002
+    ClientFailedPingPong,
+    /// 4003, no subscription was created in time after the welcome
+    ConnectionUnused,
+    /// 4004, the client did not move to the reconnect URL in time
+    ReconnectGraceTimeExpired,
+    /// 4005
+    NetworkTimeout,
+    /// 4006
+    NetworkError,
+    /// 4007
+    InvalidReconnect,
+    /// Any other code, e.g. 1000 for a normal closure
+    Other(u16),
+}
+
+impl CloseReason {
+    #[must_use]
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            4000 => CloseReason::InternalServerError,
+            4001 => CloseReason::ClientSentInboundTraffic,
+            4002 => CloseReason::ClientFailedPingPong,
+            4003 => CloseReason::ConnectionUnused,
+            4004 => CloseReason::ReconnectGraceTimeExpired,
+            4005 => CloseReason::NetworkTimeout,
+            4006 => CloseReason::NetworkError,
+            4007 => CloseReason::InvalidReconnect,
+            code => CloseReason::Other(code),
+        }
+    }
+}
+
+impl core::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::InternalServerError => write!(f, "internal server error"),
+            CloseReason::ClientSentInboundTraffic => write!(f, "client sent inbound traffic"),
+            CloseReason::ClientFailedPingPong => write!(f, "client failed ping-pong"),
+            CloseReason::ConnectionUnused => write!(f, "connection unused"),
+            CloseReason::ReconnectGraceTimeExpired => write!(f, "reconnect grace time expired"),
+            CloseReason::NetworkTimeout => write!(f, "network timeout"),
+            CloseReason::NetworkError => write!(f, "network error"),
+            CloseReason::InvalidReconnect => write!(f, "invalid reconnect"),
+            CloseReason::Other(code) => write!(f, "code {code}"),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct WSError {
     description: String,
+    close_reason: Option<CloseReason>,
+}
+
+impl WSError {
+    /// Reason of the last close by Twitch if the client gave up after it
+    #[must_use]
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason
+    }
 }
 
 impl core::fmt::Display for WSError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "error: {}", self.description)
+        write!(f, "error: {}", self.description)?;
+
+        if let Some(reason) = self.close_reason {
+            write!(f, ", last closed by Twitch: {reason}")?;
+        }
+
+        Ok(())
     }
 }
 
@@ -53,72 +232,215 @@ impl<T: Error> From<T> for WSError {
     fn from(value: T) -> Self {
         WSError {
             description: value.to_string(),
+            close_reason: None,
         }
     }
 }
 
-pub type WebSocketStream =
-    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
-
-impl WSlient {
+impl<H: HttpApi, T: WsTransport> WSlient<H, T> {
     pub fn new(
         session_id: Option<String>,
         token: UserToken,
-        client: HelixClient<'static, reqwest::Client>,
+        client: HelixClient<'static, ApiClient<H>>,
+        transport: T,
         user_id: UserId,
         connect_url: Url,
-        events_list: SafeTwitchEventList,
+        state: BotState,
     ) -> Self {
         WSlient {
             session_id,
             token,
             client,
+            transport,
             user_id,
             connect_url,
-            events_list,
+            state,
+            reconnect: ReconnectPolicy::from_config(),
+            reconnect_attempts: 0,
+            keepalive_timeout: None,
+            token_file: config::get_eventsub_config_file(),
+            last_close: None,
         }
     }
 
-    /// Connect to the websocket and return the stream
-    pub async fn connect(&self) -> Result<WebSocketStream, WSError> {
+    #[cfg(test)]
+    fn with_token_file(mut self, token_file: PathBuf) -> Self {
+        self.token_file = token_file;
+
+        self
+    }
+
+    #[cfg(test)]
+    fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+
+        self
+    }
+
+    /// Connect to the websocket
+    pub async fn connect(&mut self) -> Result<(), WSError> {
         tracing::info!("connecting to twitch");
-        let config = tungstenite::protocol::WebSocketConfig::default();
-        let (socket, _) =
-            tokio_tungstenite::connect_async_with_config(&self.connect_url, Some(config), false)
-                .await?;
+        self.transport.connect(&self.connect_url).await?;
 
-        Ok(socket)
-        // socket.Ok(socket)
+        Ok(())
     }
 
     /// Run the websocket subscriber
+    ///
+    /// Twitch sends a keepalive message when nothing else is sent within the keepalive
+    /// timeout of the welcome, a longer silence means the connection is dead and the
+    /// session is rebuilt. A live stream with
+    /// no messages for `HEWPME_EVENTSUB_SILENCE_MIN` means the session is stuck
+    /// and it is rebuilt from scratch. A failed first connection, a lost one and a
+    /// message that cannot be processed, e.g. as the subscriptions are not created,
+    /// are handled the same way following the reconnect policy. Returns only when the
+    /// policy gives up.
     #[tracing::instrument(name = "subscriber", skip_all, fields())]
     pub async fn run(mut self) -> Result<(), WSError> {
+        let initial_url = self.connect_url.clone();
+        let silence_limit = config::get_eventsub_silence_limit();
         // Establish the stream
-        let mut s = self.connect().await?;
+        if let Err(e) = self.connect().await {
+            tracing::warn!("Unable to connect to EventSub: {e}");
+            self.reconnect(&initial_url).await?;
+        }
+        let mut last_activity = Instant::now();
         // Loop over the stream, processing messages as they come in.
         loop {
-            if let Some(msg) = futures::StreamExt::next(&mut s).await {
-                let span = tracing::info_span!("message received: ", raw_message = ?msg);
-                let msg = match msg {
-                    Err(tungstenite::Error::Protocol(
-                        tungstenite::error::ProtocolError::ResetWithoutClosingHandshake,
-                    )) => {
-                        tracing::warn!(
-                            "connection was sent an unexpected frame or was reset, reestablishing it"
-                        );
-                        s = self.connect().instrument(span).await?;
-                        continue;
-                    }
-                    _ => msg?,
-                };
+            let check_interval = self
+                .keepalive_timeout
+                .map_or(WATCHDOG_CHECK_INTERVAL, |timeout| {
+                    (timeout + KEEPALIVE_GRACE).min(WATCHDOG_CHECK_INTERVAL)
+                });
+            let Ok(next) = tokio::time::timeout(check_interval, self.transport.recv()).await else {
+                let keepalive_missed = self
+                    .keepalive_timeout
+                    .is_some_and(|timeout| last_activity.elapsed() >= timeout + KEEPALIVE_GRACE);
 
-                let result = self.process_message(msg).instrument(span).await;
+                if keepalive_missed {
+                    tracing::warn!(
+                        "no EventSub keepalive for {} s, reconnecting",
+                        last_activity.elapsed().as_secs()
+                    );
+                    metrics::increment("eventsub_keepalive_missed");
+                    self.reconnect(&initial_url).await?;
+                    last_activity = Instant::now();
+                } else if self.state.stream.is_live() && last_activity.elapsed() >= silence_limit {
+                    tracing::warn!(
+                        "no EventSub messages for {} s while the stream is live, rebuilding the session",
+                        last_activity.elapsed().as_secs()
+                    );
+                    metrics::increment("eventsub_watchdog_reconnect");
+                    self.reconnect(&initial_url).await?;
+                    last_activity = Instant::now();
+                }
+
+                continue;
+            };
 
-                if let Err(err) = result {
-                    println!("Error: {err:?}");
-                    return Err(err);
+            let msg = match next {
+                Some(Ok(tungstenite::Message::Close(frame))) => {
+                    self.closed(frame.as_ref());
+                    None
+                }
+                Some(Ok(msg)) => Some(msg),
+                Some(Err(e)) => {
+                    tracing::warn!("EventSub connection is lost: {e}");
+                    None
                 }
+                None => None,
+            };
+            let Some(msg) = msg else {
+                self.reconnect(&initial_url).await?;
+                last_activity = Instant::now();
+                continue;
+            };
+
+            last_activity = Instant::now();
+            let span = tracing::info_span!(
+                "message received: ",
+                raw_message = %redact::Raw(&format_args!("{msg:?}"))
+            );
+            let result = metrics::timed("websocket_process_message", self.process_message(msg))
+                .instrument(span)
+                .await;
+
+            if let Err(err) = result {
+                tracing::warn!(
+                    "Unable to process an EventSub message, rebuilding the session: {err}"
+                );
+                metrics::increment("eventsub_message_failed");
+                self.reconnect(&initial_url).await?;
+                last_activity = Instant::now();
+            }
+        }
+    }
+
+    /// Log the close by Twitch, the connection is rebuilt by `run` whatever the reason
+    fn closed(&mut self, frame: Option<&CloseFrame<'_>>) {
+        let Some(frame) = frame else {
+            tracing::warn!("EventSub connection is closed without a reason");
+            return;
+        };
+        let reason = CloseReason::from_code(frame.code.into());
+
+        tracing::warn!(
+            "EventSub connection is closed by Twitch: {reason} {}",
+            frame.reason
+        );
+        metrics::increment("eventsub_closed");
+        self.last_close = Some(reason);
+    }
+
+    /// Open a new session after a backoff delay, a lost session cannot be resumed
+    /// so the subscriptions are created again on its welcome
+    async fn reconnect(&mut self, initial_url: &Url) -> Result<(), WSError> {
+        loop {
+            if self.reconnect.gives_up(self.reconnect_attempts) {
+                metrics::increment("eventsub_reconnect_gave_up");
+                return Err(WSError {
+                    description: format!(
+                        "EventSub connection is not restored after {} attempts",
+                        self.reconnect_attempts
+                    ),
+                    close_reason: self.last_close,
+                });
+            }
+
+            outage::record_failure(Source::EventSub);
+
+            let delay = match self.reconnect.outage_delay {
+                // Twitch is down, a quick retry would only add to the load
+                Some(outage_delay) if outage::is_active() => {
+                    tracing::warn!(
+                        "Twitch outage, reconnecting to EventSub in {} s",
+                        outage_delay.as_secs()
+                    );
+                    outage_delay
+                }
+                _ => {
+                    let delay = self.reconnect.delay(self.reconnect_attempts);
+
+                    self.reconnect_attempts += 1;
+                    tracing::warn!(
+                        "reconnecting to EventSub in {} ms, attempt {}",
+                        delay.as_millis(),
+                        self.reconnect_attempts
+                    );
+                    delay
+                }
+            };
+            metrics::increment("eventsub_reconnect_attempt");
+            tokio::time::sleep(delay).await;
+
+            self.transport.close().await;
+            self.session_id = None;
+            self.keepalive_timeout = None;
+            self.connect_url = initial_url.clone();
+
+            match self.connect().await {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::warn!("Unable to reconnect to EventSub: {e}"),
             }
         }
     }
@@ -127,11 +449,21 @@ impl WSlient {
     pub async fn process_message(&mut self, msg: tungstenite::Message) -> Result<(), WSError> {
         match msg {
             tungstenite::Message::Text(s) => {
-                tracing::info!("inside text: {s}");
+                tracing::info!("inside text: {}", redact::Raw(&s));
+
+                // unknown to `twitch_api`, see [`crate::ad_breaks`]
+                if let Some(ad_break) = ad_breaks::parse(&s) {
+                    ad_breaks::began(&self.state, &ad_break);
+                    return Ok(());
+                }
+
                 // Parse the message into a [twitch_api::eventsub::EventsubWebsocketData]
                 let result = Event::parse_websocket(&s);
 
-                tracing::info!("parsing result: {result:?}");
+                tracing::info!(
+                    "parsing result: {}",
+                    redact::Raw(&format_args!("{result:?}"))
+                );
                 if let Err(e) = result {
                     tracing::error!("parsing error: {e}");
                     return Err(e.into());
@@ -141,14 +473,14 @@ impl WSlient {
                     EventsubWebsocketData::Welcome {
                         payload: WelcomePayload { session },
                         ..
-                    }
-                    | EventsubWebsocketData::Reconnect {
-                        payload: ReconnectPayload { session },
-                        ..
                     } => {
                         self.process_welcome_message(session).await?;
                         Ok(())
                     }
+                    EventsubWebsocketData::Reconnect {
+                        payload: ReconnectPayload { session },
+                        ..
+                    } => self.process_reconnect_message(session).await,
                     // Here is where you would handle the events you want to listen to
                     EventsubWebsocketData::Notification {
                         metadata: _,
@@ -165,10 +497,15 @@ impl WSlient {
                         tracing::info!("got revocation event: {metadata:?}");
                         Ok(())
                     }
+                    // any message counts as activity for the keepalive watchdog of `run`
+                    EventsubWebsocketData::Keepalive { .. } => Ok(()),
                     _ => Ok(()),
                 }
             }
-            tungstenite::Message::Close(_) => todo!(),
+            tungstenite::Message::Close(frame) => {
+                self.closed(frame.as_ref());
+                Ok(())
+            }
             tungstenite::Message::Ping(_) => Ok(()),
             _ => {
                 tracing::warn!("Unhandled case");
@@ -178,23 +515,83 @@ impl WSlient {
     }
 
     pub async fn process_welcome_message(&mut self, data: SessionData<'_>) -> Result<(), WSError> {
+        // the welcome after a session reconnect keeps the subscriptions of the session
+        let resumed = self.session_id.as_deref() == Some(data.id.as_ref());
+
         self.session_id = Some(data.id.to_string());
+        // the welcome of a moved session does not repeat the timeout
+        if let Some(seconds) = data.keepalive_timeout_seconds {
+            self.keepalive_timeout = u64::try_from(seconds).ok().map(Duration::from_secs);
+        }
         if let Some(ref url) = data.reconnect_url {
             self.connect_url = url.parse()?;
         }
-        // check if the token is expired, if it is, request a new token. This only works if using an oauth service for getting a token
+        // the subscriptions are created and verified with the token
         if self.token.is_elapsed() {
-            todo!("Token expired - handle that somehow");
-            // self.token =
-            //     crate::util::get_access_token(self.client.get_client(), &self.opts).await?;
+            self.refresh_token().await?;
         }
 
-        self.make_eventsub_subscriptions(&data).await?;
+        let verified = if resumed {
+            self.verify_eventsub_subscriptions(&data.id).await
+        } else {
+            self.make_eventsub_subscriptions(&data).await?
+        };
+
+        if verified {
+            self.last_close = None;
+            outage::record_success(Source::EventSub);
+        }
+
+        // only the failures in a row count, a missing subscription is not one of them
+        if self.reconnect_attempts > 0 {
+            tracing::info!(
+                "EventSub session is restored after {} attempts",
+                self.reconnect_attempts
+            );
+            metrics::increment("eventsub_reconnected");
+            self.reconnect_attempts = 0;
+        }
 
         Ok(())
     }
 
-    async fn make_eventsub_subscriptions(&mut self, data: &SessionData<'_>) -> Result<(), WSError> {
+    /// Refresh the elapsed token and save it for the next start
+    async fn refresh_token(&mut self) -> Result<(), WSError> {
+        tracing::info!("EventSub token has expired, refreshing it");
+
+        if let Err(e) = utils::token_manager(self.token_file.clone())
+            .refresh(self.client.get_client(), &mut self.token)
+            .await
+        {
+            metrics::increment("eventsub_token_refresh_failed");
+            return Err(WSError {
+                description: format!("unable to refresh the EventSub token: {e}"),
+                close_reason: None,
+            });
+        }
+
+        metrics::increment("eventsub_token_refreshed");
+
+        Ok(())
+    }
+
+    /// Twitch moves the session to another server, the new connection keeps its subscriptions
+    async fn process_reconnect_message(&mut self, data: SessionData<'_>) -> Result<(), WSError> {
+        let Some(ref url) = data.reconnect_url else {
+            return Ok(());
+        };
+
+        tracing::info!("EventSub session is moved, reconnecting to {url}");
+        metrics::increment("eventsub_session_reconnect");
+        self.connect_url = url.parse()?;
+        self.connect().await
+    }
+
+    /// Returns whether every subscription is enabled afterwards
+    async fn make_eventsub_subscriptions(
+        &mut self,
+        data: &SessionData<'_>,
+    ) -> Result<bool, WSError> {
         let transport = eventsub::Transport::websocket(data.id.clone());
 
         println!(
@@ -203,30 +600,460 @@ impl WSlient {
             self.token.user_id.as_str()
         );
 
-        self.client
-            .create_eventsub_subscription(
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
                 ChannelFollowV2::new(self.user_id.clone(), self.token.user_id.clone()),
                 transport.clone(),
                 &self.token,
-            )
-            .await?;
-        self.client
-            .create_eventsub_subscription(
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                ChannelRaidV1::to_broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
                 ChannelSubscribeV1::broadcaster_user_id(self.user_id.clone()),
                 transport.clone(),
                 &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                ChannelSubscriptionEndV1::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                ChannelSubscriptionGiftV1::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                ChannelSubscriptionMessageV1::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                ChannelUpdateV2::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                StreamOnlineV1::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                StreamOfflineV1::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+
+        // a missing permission must not take the required subscriptions down with it
+        for event_type in self.optional_subscriptions() {
+            if let Err(e) = self
+                .create_optional_subscription(event_type, &transport, &data.id)
+                .await
+            {
+                tracing::warn!("Unable to subscribe to {event_type}: {e}");
+            }
+        }
+
+        Ok(self.verify_eventsub_subscriptions(&data.id).await)
+    }
+
+    /// Optional subscriptions the session needs: the redemptions only with a reward
+    /// to handle, the cheers only with the bits:read permission
+    fn optional_subscriptions(&self) -> Vec<&'static str> {
+        OPTIONAL_SUBSCRIPTIONS
+            .into_iter()
+            .filter(|&event_type| {
+                if event_type == ChannelPointsCustomRewardRedemptionAddV1::EVENT_TYPE.to_str() {
+                    rewards::is_configured()
+                } else if event_type == ChannelCheerV1::EVENT_TYPE.to_str() {
+                    self.token.scopes().contains(&Scope::BitsRead)
+                } else {
+                    true
+                }
+            })
+            .collect()
+    }
+
+    async fn create_optional_subscription(
+        &self,
+        event_type: &str,
+        transport: &eventsub::Transport,
+        session_id: &str,
+    ) -> Result<(), WSError> {
+        if event_type == ad_breaks::EVENT_TYPE {
+            return self.create_ad_break_subscription(session_id).await;
+        }
+
+        if event_type == ChannelCheerV1::EVENT_TYPE.to_str() {
+            metrics::timed(
+                "helix_create_eventsub_subscription",
+                self.client.create_eventsub_subscription(
+                    ChannelCheerV1::broadcaster_user_id(self.user_id.clone()),
+                    transport.clone(),
+                    &self.token,
+                ),
             )
             .await?;
 
+            return Ok(());
+        }
+
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.client.create_eventsub_subscription(
+                ChannelPointsCustomRewardRedemptionAddV1::broadcaster_user_id(self.user_id.clone()),
+                transport.clone(),
+                &self.token,
+            ),
+        )
+        .await?;
+
         Ok(())
     }
 
+    async fn create_ad_break_subscription(&self, session_id: &str) -> Result<(), WSError> {
+        let body = serde_json::json!({
+            "type": ad_breaks::EVENT_TYPE,
+            "version": ad_breaks::VERSION,
+            "condition": { "broadcaster_user_id": self.user_id.as_str() },
+            "transport": { "method": "websocket", "session_id": session_id },
+        });
+
+        metrics::timed(
+            "helix_create_eventsub_subscription",
+            self.raw_helix_request(
+                http::Method::POST,
+                helix_url(EVENTSUB_SUBSCRIPTIONS_PATH).as_str(),
+                Some(&body),
+            ),
+        )
+        .await
+        .map(drop)
+    }
+
+    /// Types of the enabled subscriptions of the session, the list is read from the raw
+    /// JSON as `twitch_api` rejects the event types it does not know
+    async fn session_subscription_types(&self, session_id: &str) -> Result<Vec<String>, WSError> {
+        #[derive(serde::Deserialize)]
+        struct Page {
+            data: Vec<Subscription>,
+            #[serde(default)]
+            pagination: Pagination,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Subscription {
+            #[serde(rename = "type")]
+            type_: String,
+            transport: SubscriptionTransport,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct SubscriptionTransport {
+            session_id: Option<String>,
+        }
+
+        #[derive(serde::Deserialize, Default)]
+        struct Pagination {
+            cursor: Option<String>,
+        }
+
+        let mut types = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut url = helix_url(EVENTSUB_SUBSCRIPTIONS_PATH);
+
+            url.query_pairs_mut().append_pair("status", "enabled");
+            if let Some(ref cursor) = cursor {
+                url.query_pairs_mut().append_pair("after", cursor);
+            }
+
+            let body = self
+                .raw_helix_request(http::Method::GET, url.as_str(), None)
+                .await?;
+            let page: Page = serde_json::from_slice(&body)?;
+
+            types.extend(
+                page.data
+                    .into_iter()
+                    .filter(|subscription| {
+                        subscription.transport.session_id.as_deref() == Some(session_id)
+                    })
+                    .map(|subscription| subscription.type_),
+            );
+
+            match page.pagination.cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => return Ok(types),
+            }
+        }
+    }
+
+    /// Helix request without a type in `twitch_api`, returns the body of a successful response
+    async fn raw_helix_request(
+        &self,
+        method: http::Method,
+        url: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Vec<u8>, WSError> {
+        let request = http::Request::builder()
+            .method(method)
+            .uri(url)
+            .header("Client-Id", self.token.client_id().as_str())
+            .header(
+                http::header::AUTHORIZATION,
+                format!("Bearer {}", self.token.access_token.secret()),
+            )
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(match body {
+                Some(body) => serde_json::to_vec(body)?,
+                None => Vec::new(),
+            })?;
+        let response = self.client.get_client().api().send(request).await?;
+
+        if !response.status().is_success() {
+            return Err(WSError {
+                description: format!(
+                    "Helix responded with {}: {}",
+                    response.status(),
+                    String::from_utf8_lossy(response.body())
+                ),
+                close_reason: None,
+            });
+        }
+
+        Ok(response.into_body().to_vec())
+    }
+
+    /// Check that every subscription is enabled for the session, missing ones are only reported
+    async fn verify_eventsub_subscriptions(&self, session_id: &str) -> bool {
+        let subscriptions = match metrics::timed(
+            "helix_get_eventsub_subscriptions",
+            self.session_subscription_types(session_id),
+        )
+        .await
+        {
+            Ok(subscriptions) => subscriptions,
+            Err(e) => {
+                tracing::warn!("Unable to verify EventSub subscriptions: {e}");
+                return false;
+            }
+        };
+        let mut verified = true;
+
+        for event_type in SUBSCRIPTIONS {
+            if !subscriptions.iter().any(|enabled| enabled == event_type) {
+                tracing::warn!("EventSub subscription {event_type} is not enabled");
+                metrics::increment("eventsub_subscription_missing");
+                verified = false;
+            }
+        }
+
+        for event_type in self.optional_subscriptions() {
+            if !subscriptions.iter().any(|enabled| enabled == event_type) {
+                tracing::info!("optional EventSub subscription {event_type} is not enabled");
+            }
+        }
+
+        verified
+    }
+
     async fn handle_notification(&self, event: Event) {
         match event {
             Event::ChannelFollowV2(payload) => self.handle_channel_follow_event(payload).await,
             Event::ChannelSubscribeV1(payload) => {
                 self.handle_channel_subscribe_event(payload).await;
             }
+            Event::ChannelSubscriptionEndV1(payload) => {
+                if let eventsub::Message::Notification(ref payload) = payload.message {
+                    let tier = match payload.tier {
+                        SubscriptionTier::Tier2 => 2,
+                        SubscriptionTier::Tier3 => 3,
+                        _ => 1,
+                    };
+
+                    lapses::subscription_ended(
+                        &self.state,
+                        payload.user_id.as_str(),
+                        payload.user_name.as_str(),
+                        tier,
+                        payload.is_gift,
+                    )
+                    .await;
+                }
+            }
+            Event::ChannelPointsCustomRewardRedemptionAddV1(payload) => {
+                if let eventsub::Message::Notification(ref payload) = payload.message {
+                    let redemption = Redemption {
+                        id: payload.id.as_str(),
+                        reward_id: payload.reward.id.as_str(),
+                        title: payload.reward.title.as_str(),
+                        user_id: payload.user_id.as_str(),
+                        user_login: payload.user_login.as_str(),
+                        user_name: payload.user_name.as_str(),
+                        input: payload.user_input.as_str(),
+                    };
+
+                    if rewards::redeemed(&self.state, &redemption).await {
+                        return;
+                    }
+
+                    if config::get_wheel_reward().is_some_and(|title| title == payload.reward.title)
+                    {
+                        if let Err(e) = wheel::spin(
+                            &self.state,
+                            payload.user_id.as_str(),
+                            payload.user_login.as_str(),
+                            payload.user_name.as_str(),
+                            SpinSource::Reward,
+                        )
+                        .await
+                        {
+                            tracing::warn!("Unable to spin the wheel for the redemption: {e}");
+                        }
+                    }
+                }
+            }
+            Event::ChannelSubscriptionMessageV1(payload) => {
+                if let eventsub::Message::Notification(ref payload) = payload.message {
+                    let months = u64::try_from(payload.cumulative_months).unwrap_or_default();
+
+                    tracing::info!(
+                        "{} resubscribed for {months} months",
+                        redact::Name(payload.user_name.as_str())
+                    );
+                    self.state
+                        .users
+                        .remember(payload.user_id.as_str(), payload.user_name.as_str())
+                        .await;
+                    self.state
+                        .subscriptions
+                        .record(payload.user_id.as_str(), Some(months))
+                        .await;
+                    events::publish(
+                        &self.state.bus,
+                        BotEvent::Resubscribe {
+                            user_id: payload.user_id.to_string(),
+                            user_name: payload.user_name.to_string(),
+                            months,
+                            streak: payload
+                                .streak_months
+                                .and_then(|streak| u64::try_from(streak).ok()),
+                        },
+                    );
+                }
+            }
+            Event::ChannelSubscriptionGiftV1(payload) => {
+                if let eventsub::Message::Notification(ref payload) = payload.message {
+                    // the recipients come as subscriptions
+                    if let Some(user_name) = &payload.user_name {
+                        tracing::info!(
+                            "{} gifted {} subscriptions",
+                            redact::Name(user_name.as_str()),
+                            payload.total
+                        );
+                    }
+
+                    events::publish(
+                        &self.state.bus,
+                        BotEvent::GiftSubscriptions {
+                            user_id: payload.user_id.as_ref().map(ToString::to_string),
+                            user_name: payload.user_name.as_ref().map(ToString::to_string),
+                            count: u64::try_from(payload.total).unwrap_or_default(),
+                        },
+                    );
+                }
+            }
+            Event::ChannelCheerV1(payload) => {
+                if let eventsub::Message::Notification(ref payload) = payload.message {
+                    if let Some(user_name) = &payload.user_name {
+                        tracing::info!(
+                            "{} cheered {} bits",
+                            redact::Name(user_name.as_str()),
+                            payload.bits
+                        );
+                    }
+
+                    events::publish(
+                        &self.state.bus,
+                        BotEvent::Cheer {
+                            user_id: payload.user_id.as_ref().map(ToString::to_string),
+                            user_name: payload.user_name.as_ref().map(ToString::to_string),
+                            bits: u64::try_from(payload.bits).unwrap_or_default(),
+                        },
+                    );
+                }
+            }
+            Event::ChannelRaidV1(payload) => {
+                if let eventsub::Message::Notification(ref payload) = payload.message {
+                    raids::landed(
+                        &self.state,
+                        payload.from_broadcaster_user_id.as_str(),
+                        payload.from_broadcaster_user_login.as_str(),
+                        payload.from_broadcaster_user_name.as_str(),
+                        u64::try_from(payload.viewers).unwrap_or_default(),
+                    )
+                    .await;
+                }
+            }
+            Event::ChannelUpdateV2(payload) => self.handle_channel_update_event(payload).await,
+            Event::StreamOnlineV1(payload) => {
+                // a redelivered notification does not start the stream again
+                if let eventsub::Message::Notification(_) = payload.message {
+                    if self.state.stream.set_live(true) {
+                        sessions::stream_started(&self.state).await;
+                        events::publish(&self.state.bus, BotEvent::StreamOnline);
+                    }
+                }
+            }
+            Event::StreamOfflineV1(payload) => {
+                if let eventsub::Message::Notification(_) = payload.message {
+                    if self.state.stream.set_live(false) {
+                        sessions::stream_ended(&self.state);
+                        raid_train::stream_ended(&self.state).await;
+                        events::publish(&self.state.bus, BotEvent::StreamOffline);
+                    }
+                }
+            }
             _ => (),
         }
     }
@@ -235,10 +1062,18 @@ impl WSlient {
         if let eventsub::Message::Notification(ref payload) = payload.message {
             tracing::info!(
                 "Got following name: {} {}",
-                payload.user_name,
+                redact::Name(payload.user_name.as_str()),
                 payload.user_id
             );
             self.put_follower_name(payload).await;
+            self.state
+                .notifier
+                .notify(
+                    NotificationKind::Follow,
+                    "New follower",
+                    payload.user_name.as_str(),
+                )
+                .await;
         }
     }
 
@@ -246,30 +1081,789 @@ impl WSlient {
         if let eventsub::Message::Notification(ref payload) = payload.message {
             tracing::info!(
                 "Got subscriber name: {} {}",
-                payload.user_name,
+                redact::Name(payload.user_name.as_str()),
                 payload.user_id
             );
             self.put_subscriber_name(payload).await;
+            self.state
+                .notifier
+                .notify(
+                    NotificationKind::Subscribe,
+                    "New subscriber",
+                    payload.user_name.as_str(),
+                )
+                .await;
+        }
+    }
+
+    async fn handle_channel_update_event(&self, payload: Payload<ChannelUpdateV2>) {
+        if let eventsub::Message::Notification(ref payload) = payload.message {
+            if self
+                .state
+                .stream
+                .set_category(payload.category_name.clone())
+                .await
+            {
+                events::publish(
+                    &self.state.bus,
+                    BotEvent::CategoryChanged {
+                        category: payload.category_name.clone(),
+                    },
+                );
+            }
+            // a tier change may come with new emotes
+            self.state.emotes.request_refresh();
         }
     }
 
     async fn put_follower_name(&self, payload: &ChannelFollowV2Payload) {
-        let follower = if cfg!(feature = "debug") {
-            format!("{}{}", payload.user_name, payload.user_id)
-        } else {
-            format!("{}", payload.user_name)
+        self.state
+            .users
+            .remember(payload.user_id.as_str(), payload.user_name.as_str())
+            .await;
+        events::publish(
+            &self.state.bus,
+            BotEvent::Follow {
+                user_id: payload.user_id.to_string(),
+                user_name: payload.user_name.to_string(),
+            },
+        );
+    }
+
+    async fn put_subscriber_name(&self, payload: &ChannelSubscribeV1Payload) {
+        self.state
+            .users
+            .remember(payload.user_id.as_str(), payload.user_name.as_str())
+            .await;
+        self.state
+            .subscriptions
+            .record(payload.user_id.as_str(), None)
+            .await;
+        events::publish(
+            &self.state.bus,
+            BotEvent::Subscribe {
+                user_id: payload.user_id.to_string(),
+                user_name: payload.user_name.to_string(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+    use twitch_oauth2::ClientSecret;
+
+    use super::*;
+    use crate::commands::CommandRegistry;
+    use crate::helper::{create_bot_state, ChatOutboxReceiver};
+    use crate::instance::SafeInstance;
+    use crate::overlay::OverlayEvent;
+    use crate::storage::create_memory_storage;
+    use crate::transport::mock::{MockHttp, MockTransport};
+
+    const SESSION_ID: &str = "AQoQILE98gtqShGmLD7AM6yJThAB";
+    const BROADCASTER_ID: &str = "1337";
+    const RECONNECT_ATTEMPTS: u32 = 3;
+
+    fn welcome(reconnect_url: Option<&str>) -> String {
+        json!({
+            "metadata": {
+                "message_id": "96a3f3b5-5dec-4eed-908e-e11ee657416c",
+                "message_type": if reconnect_url.is_some() { "session_reconnect" } else { "session_welcome" },
+                "message_timestamp": "2023-07-19T14:56:51.634234626Z"
+            },
+            "payload": {
+                "session": {
+                    "id": SESSION_ID,
+                    "status": if reconnect_url.is_some() { "reconnecting" } else { "connected" },
+                    "connected_at": "2023-07-19T14:56:51.616329898Z",
+                    "keepalive_timeout_seconds": if reconnect_url.is_some() { None } else { Some(10) },
+                    "reconnect_url": reconnect_url
+                }
+            }
+        })
+        .to_string()
+    }
+
+    fn notification(event_type: &str, version: &str, condition: &Value, event: &Value) -> String {
+        json!({
+            "metadata": {
+                "message_id": "befa7b53-d79d-478f-86b9-120f112b044e",
+                "message_type": "notification",
+                "message_timestamp": "2022-11-16T10:11:12.464757833Z",
+                "subscription_type": event_type,
+                "subscription_version": version
+            },
+            "payload": {
+                "subscription": subscription(event_type, version, condition),
+                "event": event
+            }
+        })
+        .to_string()
+    }
+
+    fn subscription(event_type: &str, version: &str, condition: &Value) -> Value {
+        json!({
+            "id": "f1c2a387-161a-49f9-a165-0f21d7a4e1c4",
+            "status": "enabled",
+            "type": event_type,
+            "version": version,
+            "cost": 0,
+            "condition": condition,
+            "transport": {
+                "method": "websocket",
+                "session_id": SESSION_ID,
+                "connected_at": "2022-11-16T10:11:12.464757833Z"
+            },
+            "created_at": "2022-11-16T10:11:12.464757833Z"
+        })
+    }
+
+    /// Helix that accepts every subscription and lists all of them as enabled
+    fn twitch_helix() -> Arc<MockHttp> {
+        let created = Arc::new(std::sync::Mutex::new(Vec::<Value>::new()));
+
+        Arc::new(MockHttp::new(move |request| {
+            let mut created = created.lock().unwrap();
+
+            if request.uri().path() == "/oauth2/token" {
+                (
+                    200,
+                    json!({
+                        "access_token": "refreshed-access",
+                        "refresh_token": "refreshed-refresh",
+                        "expires_in": 14400,
+                        "scope": ["moderator:read:followers"],
+                        "token_type": "bearer"
+                    })
+                    .to_string(),
+                )
+            } else if request.method() == http::Method::POST {
+                let body: Value = serde_json::from_slice(request.body()).unwrap();
+                let subscription = subscription(
+                    body["type"].as_str().unwrap(),
+                    body["version"].as_str().unwrap(),
+                    &body["condition"],
+                );
+
+                created.push(subscription.clone());
+                (
+                    202,
+                    json!({"data": [subscription], "total": 1, "total_cost": 0, "max_total_cost": 10})
+                        .to_string(),
+                )
+            } else {
+                (
+                    200,
+                    json!({
+                        "data": *created,
+                        "total": created.len(),
+                        "total_cost": 0,
+                        "max_total_cost": 10,
+                        "pagination": {}
+                    })
+                    .to_string(),
+                )
+            }
+        }))
+    }
+
+    /// Keep the session lists from the published events like the running bot does
+    async fn record_events(
+        state: &BotState,
+        events: &mut tokio::sync::broadcast::Receiver<BotEvent>,
+    ) -> Vec<BotEvent> {
+        let published: Vec<BotEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+
+        for event in published.clone() {
+            sessions::record(state, event).await;
+        }
+
+        published
+    }
+
+    fn client(
+        http: &Arc<MockHttp>,
+        messages: Vec<String>,
+    ) -> (WSlient<MockHttp, MockTransport>, ChatOutboxReceiver) {
+        client_with_scopes(http, messages, vec![Scope::ModeratorReadFollowers])
+    }
+
+    fn client_with_scopes(
+        http: &Arc<MockHttp>,
+        messages: Vec<String>,
+        scopes: Vec<Scope>,
+    ) -> (WSlient<MockHttp, MockTransport>, ChatOutboxReceiver) {
+        let token = UserToken::from_existing_unchecked(
+            "access",
+            None,
+            "client-id",
+            ClientSecret::new(String::from("client-secret")),
+            "hewpme_test".into(),
+            BROADCASTER_ID.into(),
+            Some(scopes),
+            Some(Duration::from_secs(3600)),
+        );
+        let (state, outbox) = create_bot_state(
+            CommandRegistry::default(),
+            SafeInstance::default(),
+            create_memory_storage(),
+        );
+        let ws = WSlient::new(
+            None,
+            token,
+            HelixClient::with_client(ApiClient::new(Arc::clone(http))),
+            MockTransport::new(messages),
+            BROADCASTER_ID.into(),
+            Url::parse("ws://127.0.0.1:8080/ws").unwrap(),
+            state,
+        )
+        .with_reconnect_policy(ReconnectPolicy {
+            max_attempts: RECONNECT_ATTEMPTS,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            outage_delay: None,
+        });
+
+        (ws, outbox)
+    }
+
+    #[tokio::test]
+    async fn welcome_creates_and_verifies_subscriptions() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+
+        ws.process_message(tungstenite::Message::Text(welcome(None)))
+            .await
+            .unwrap();
+
+        let requests = http.requests();
+        let created = requests
+            .iter()
+            .filter(|request| request.method == "POST")
+            .map(|request| serde_json::from_str::<Value>(&request.body).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ws.session_id.as_deref(), Some(SESSION_ID));
+        assert_eq!(ws.keepalive_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(
+            created.len(),
+            SUBSCRIPTIONS.len() + ws.optional_subscriptions().len()
+        );
+        assert!(created
+            .iter()
+            .all(|body| body["transport"]["session_id"] == SESSION_ID));
+        assert_eq!(
+            requests.last().map(|request| request.method.as_str()),
+            Some("GET")
+        );
+    }
+
+    #[tokio::test]
+    async fn cheers_are_subscribed_to_only_with_the_permission() {
+        let cheer = ChannelCheerV1::EVENT_TYPE.to_str();
+
+        for (scopes, subscribed) in [
+            (vec![Scope::ModeratorReadFollowers], false),
+            (vec![Scope::ModeratorReadFollowers, Scope::BitsRead], true),
+        ] {
+            let http = twitch_helix();
+            let (mut ws, _outbox) = client_with_scopes(&http, Vec::new(), scopes);
+
+            ws.process_message(tungstenite::Message::Text(welcome(None)))
+                .await
+                .unwrap();
+
+            let created = http
+                .requests()
+                .iter()
+                .filter(|request| request.method == "POST")
+                .map(|request| serde_json::from_str::<Value>(&request.body).unwrap())
+                .collect::<Vec<_>>();
+
+            assert_eq!(created.iter().any(|body| body["type"] == cheer), subscribed);
+            assert_eq!(ws.optional_subscriptions().contains(&cheer), subscribed);
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnect_switches_the_url() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let reconnect_url = "wss://eventsub.wss.twitch.tv/ws?reconnect";
+
+        ws.process_message(tungstenite::Message::Text(welcome(Some(reconnect_url))))
+            .await
+            .unwrap();
+
+        assert_eq!(ws.connect_url.as_str(), reconnect_url);
+    }
+
+    #[tokio::test]
+    async fn session_reconnect_keeps_the_subscriptions() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let reconnect_url = "wss://eventsub.wss.twitch.tv/ws?reconnect";
+
+        for message in [welcome(None), welcome(Some(reconnect_url)), welcome(None)] {
+            ws.process_message(tungstenite::Message::Text(message))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            ws.transport.connected.last().map(Url::as_str),
+            Some(reconnect_url)
+        );
+        assert_eq!(
+            http.requests()
+                .iter()
+                .filter(|request| request.method == "POST")
+                .count(),
+            SUBSCRIPTIONS.len() + ws.optional_subscriptions().len()
+        );
+    }
+
+    #[test]
+    fn reconnect_delay_grows_up_to_the_limit() {
+        let policy = ReconnectPolicy {
+            max_attempts: 0,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            outage_delay: None,
         };
 
-        self.events_list.add_follower(follower).await;
+        assert!(policy.delay(0) <= Duration::from_secs(1));
+        assert!(policy.delay(2) >= Duration::from_secs(2));
+        assert!(policy.delay(2) <= Duration::from_secs(4));
+        assert!(policy.delay(30) <= Duration::from_secs(10));
+        assert!(!policy.gives_up(u32::MAX));
     }
 
-    async fn put_subscriber_name(&self, payload: &ChannelSubscribeV1Payload) {
-        let subscriber = if cfg!(feature = "debug") {
-            format!("{}{}", payload.user_name, payload.user_id)
-        } else {
-            format!("{}", payload.user_name)
+    #[tokio::test]
+    async fn stream_online_and_offline_update_the_state() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let condition = json!({ "broadcaster_user_id": BROADCASTER_ID });
+        let broadcaster = json!({
+            "broadcaster_user_id": BROADCASTER_ID,
+            "broadcaster_user_login": "cool_user",
+            "broadcaster_user_name": "Cool_User"
+        });
+        let mut online = broadcaster.clone();
+
+        online["id"] = json!("9001");
+        online["type"] = json!("live");
+        online["started_at"] = json!("2020-10-11T10:11:12.123Z");
+
+        let chatter = || (Arc::from("1234"), Arc::from("Early_Viewer"));
+
+        ws.state.session.get_chatters().await.extend([chatter()]);
+        ws.process_message(tungstenite::Message::Text(notification(
+            "stream.online",
+            "1",
+            &condition,
+            &online,
+        )))
+        .await
+        .unwrap();
+        assert!(ws.state.stream.is_live());
+        // the chat before the stream is not credited
+        assert!(ws.state.session.get_chatters().await.is_empty());
+
+        ws.process_message(tungstenite::Message::Text(notification(
+            "stream.offline",
+            "1",
+            &condition,
+            &broadcaster,
+        )))
+        .await
+        .unwrap();
+        assert!(!ws.state.stream.is_live());
+
+        // back soon after going offline, the session continues
+        ws.state.session.get_chatters().await.extend([chatter()]);
+        ws.process_message(tungstenite::Message::Text(notification(
+            "stream.online",
+            "1",
+            &condition,
+            &online,
+        )))
+        .await
+        .unwrap();
+        assert_eq!(ws.state.session.get_chatters().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ad_break_warns_the_chat_and_the_overlays() {
+        let http = twitch_helix();
+        let (mut ws, mut outbox) = client(&http, Vec::new());
+        let ad_break = notification(
+            ad_breaks::EVENT_TYPE,
+            ad_breaks::VERSION,
+            &json!({ "broadcaster_user_id": BROADCASTER_ID }),
+            &json!({
+                "duration_seconds": 90,
+                "started_at": "2019-11-16T10:11:12.634234626Z",
+                "is_automatic": true,
+                "broadcaster_user_id": BROADCASTER_ID,
+                "broadcaster_user_login": "cool_user",
+                "broadcaster_user_name": "Cool_User",
+                "requester_user_id": BROADCASTER_ID,
+                "requester_user_login": "cool_user",
+                "requester_user_name": "Cool_User"
+            }),
+        );
+
+        ws.process_message(tungstenite::Message::Text(ad_break))
+            .await
+            .unwrap();
+
+        assert!(outbox.try_recv().unwrap().contains("90"));
+        assert!(ws.state.overlay.since(0).iter().any(|event| matches!(
+            event.event,
+            OverlayEvent::AdBreak {
+                seconds: 90,
+                automatic: true
+            }
+        )));
+    }
+
+    #[tokio::test]
+    async fn follow_is_published_on_the_bus() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let mut events = ws.state.bus.subscribe();
+        let follow = notification(
+            "channel.follow",
+            "2",
+            &json!({ "broadcaster_user_id": BROADCASTER_ID, "moderator_user_id": BROADCASTER_ID }),
+            &json!({
+                "user_id": "1234",
+                "user_login": "cool_follower",
+                "user_name": "Cool_Follower",
+                "broadcaster_user_id": BROADCASTER_ID,
+                "broadcaster_user_login": "cool_user",
+                "broadcaster_user_name": "Cool_User",
+                "followed_at": "2020-07-15T18:16:11.17106713Z"
+            }),
+        );
+
+        ws.process_message(tungstenite::Message::Text(follow))
+            .await
+            .unwrap();
+
+        let published = record_events(&ws.state, &mut events).await;
+
+        assert!(matches!(
+            published.first(),
+            Some(BotEvent::Follow { user_id, user_name }) if user_id == "1234" && user_name == "Cool_Follower"
+        ));
+        assert!(ws
+            .state
+            .session
+            .get_followers()
+            .await
+            .get("1234")
+            .is_some_and(|name| name == "Cool_Follower"));
+    }
+
+    #[tokio::test]
+    async fn cheers_are_tallied_per_user() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let mut events = ws.state.subscribe_events();
+        let cheer = |user: Option<(&str, &str)>, bits: u64| {
+            notification(
+                "channel.cheer",
+                "1",
+                &json!({ "broadcaster_user_id": BROADCASTER_ID }),
+                &json!({
+                    "is_anonymous": user.is_none(),
+                    "user_id": user.map(|(id, _)| id),
+                    "user_login": user.map(|(_, name)| name.to_lowercase()),
+                    "user_name": user.map(|(_, name)| name),
+                    "broadcaster_user_id": BROADCASTER_ID,
+                    "broadcaster_user_login": "cool_user",
+                    "broadcaster_user_name": "Cool_User",
+                    "message": "pogchamp",
+                    "bits": bits
+                }),
+            )
+        };
+
+        for message in [
+            cheer(Some(("1", "Small")), 100),
+            cheer(Some(("2", "Big")), 300),
+            cheer(Some(("1", "Small")), 100),
+            cheer(None, 1000),
+        ] {
+            ws.process_message(tungstenite::Message::Text(message))
+                .await
+                .unwrap();
+        }
+
+        record_events(&ws.state, &mut events).await;
+
+        assert_eq!(
+            ws.state
+                .session
+                .top_cheerers(10)
+                .await
+                .iter()
+                .map(|entry| (entry.name.as_str(), entry.bits))
+                .collect::<Vec<_>>(),
+            [("Big", 300), ("Small", 200)]
+        );
+    }
+
+    #[tokio::test]
+    async fn resubscribers_and_gifters_are_tracked_apart() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let mut events = ws.state.subscribe_events();
+        let broadcaster = json!({
+            "broadcaster_user_id": BROADCASTER_ID,
+            "broadcaster_user_login": "cool_user",
+            "broadcaster_user_name": "Cool_User",
+        });
+        let resub = |id: &str, name: &str, months: u64, streak: Option<u64>| {
+            let mut event = broadcaster.clone();
+
+            event.as_object_mut().unwrap().extend(
+                json!({
+                    "user_id": id,
+                    "user_login": name.to_lowercase(),
+                    "user_name": name,
+                    "tier": "1000",
+                    "message": { "text": "hi", "emotes": [] },
+                    "cumulative_months": months,
+                    "streak_months": streak,
+                    "duration_months": 1
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            );
+            notification(
+                "channel.subscription.message",
+                "1",
+                &json!({ "broadcaster_user_id": BROADCASTER_ID }),
+                &event,
+            )
         };
+        let gift = |user: Option<(&str, &str)>, total: u64| {
+            let mut event = broadcaster.clone();
+
+            event.as_object_mut().unwrap().extend(
+                json!({
+                    "user_id": user.map(|(id, _)| id),
+                    "user_login": user.map(|(_, name)| name.to_lowercase()),
+                    "user_name": user.map(|(_, name)| name),
+                    "tier": "1000",
+                    "total": total,
+                    "cumulative_total": null,
+                    "is_anonymous": user.is_none()
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            );
+            notification(
+                "channel.subscription.gift",
+                "1",
+                &json!({ "broadcaster_user_id": BROADCASTER_ID }),
+                &event,
+            )
+        };
+
+        for message in [
+            resub("1", "Loyal", 24, Some(12)),
+            resub("2", "Private", 3, None),
+            gift(Some(("3", "Santa")), 5),
+            gift(Some(("3", "Santa")), 1),
+            gift(None, 50),
+        ] {
+            ws.process_message(tungstenite::Message::Text(message))
+                .await
+                .unwrap();
+        }
+
+        let published = record_events(&ws.state, &mut events).await;
+
+        assert_eq!(
+            ws.state
+                .session
+                .resubscribers()
+                .await
+                .iter()
+                .map(|entry| (entry.name.as_str(), entry.months, entry.streak))
+                .collect::<Vec<_>>(),
+            [("Loyal", 24, Some(12)), ("Private", 3, None)]
+        );
+        assert_eq!(
+            ws.state
+                .session
+                .top_gifters(10)
+                .await
+                .iter()
+                .map(|entry| (entry.name.as_str(), entry.gifts))
+                .collect::<Vec<_>>(),
+            [("Santa", 6)]
+        );
+        assert!(ws.state.session.get_subscribers().await.is_empty());
+        // the anonymous gift is not credited but still published
+        assert_eq!(
+            published
+                .into_iter()
+                .filter_map(|event| match event {
+                    BotEvent::GiftSubscriptions {
+                        user_name, count, ..
+                    } => Some((user_name, count)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            [
+                (Some(String::from("Santa")), 5),
+                (Some(String::from("Santa")), 1),
+                (None, 50)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn raid_is_published_once() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, Vec::new());
+        let mut events = ws.state.bus.subscribe();
+        let raid = notification(
+            "channel.raid",
+            "1",
+            &json!({ "to_broadcaster_user_id": BROADCASTER_ID }),
+            &json!({
+                "from_broadcaster_user_id": "1234",
+                "from_broadcaster_user_login": "cool_raider",
+                "from_broadcaster_user_name": "Cool_Raider",
+                "to_broadcaster_user_id": BROADCASTER_ID,
+                "to_broadcaster_user_login": "cool_user",
+                "to_broadcaster_user_name": "Cool_User",
+                "viewers": 9001
+            }),
+        );
+
+        for _ in 0..2 {
+            ws.process_message(tungstenite::Message::Text(raid.clone()))
+                .await
+                .unwrap();
+        }
+
+        let published = record_events(&ws.state, &mut events).await;
+
+        assert!(matches!(
+            &published[..],
+            [BotEvent::Raid { user_name, viewers: 9001, .. }] if user_name == "Cool_Raider"
+        ));
+        assert!(ws
+            .state
+            .session
+            .get_raiders()
+            .await
+            .get("1234")
+            .is_some_and(|name| name == "Cool_Raider"));
+    }
+
+    #[tokio::test]
+    async fn run_connects_and_stops_when_the_connection_is_gone() {
+        let http = twitch_helix();
+        let (ws, _outbox) = client(&http, vec![welcome(None)]);
+        let expected = SUBSCRIPTIONS.len() + ws.optional_subscriptions().len();
+
+        assert!(ws.run().await.is_err());
+        assert_eq!(
+            http.requests()
+                .iter()
+                .filter(|request| request.method == "POST")
+                .count(),
+            expected
+        );
+        assert!(metrics::render_prometheus()
+            .contains("hewpme_events_total{event=\"eventsub_reconnect_gave_up\"}"));
+    }
+
+    #[tokio::test]
+    async fn run_rebuilds_the_session_after_a_failed_message() {
+        let http = twitch_helix();
+        let (ws, _outbox) = client(&http, vec![welcome(Some("not a url")), welcome(None)]);
+        let expected = SUBSCRIPTIONS.len() + ws.optional_subscriptions().len();
+
+        assert!(ws.run().await.is_err());
+        assert_eq!(
+            http.requests()
+                .iter()
+                .filter(|request| request.method == "POST")
+                .count(),
+            expected
+        );
+        assert!(metrics::render_prometheus()
+            .contains("hewpme_events_total{event=\"eventsub_message_failed\"}"));
+    }
+
+    #[tokio::test]
+    async fn elapsed_token_is_refreshed_and_saved() {
+        std::env::set_var("TWITCH_CLIENT_ID", "client-id");
+        std::env::set_var("TWITCH_CLIENT_SECRET", "client-secret");
+
+        let http = twitch_helix();
+        let token_file =
+            std::env::temp_dir().join(format!("hewpme-eventsub-{}.json", std::process::id()));
+        let (ws, _outbox) = client(&http, Vec::new());
+        let mut ws = ws.with_token_file(token_file.clone());
+
+        ws.token = UserToken::from_existing_unchecked(
+            "expired-access",
+            Some("saved-refresh".into()),
+            "client-id",
+            ClientSecret::new(String::from("client-secret")),
+            "hewpme_test".into(),
+            BROADCASTER_ID.into(),
+            Some(vec![Scope::ModeratorReadFollowers]),
+            Some(Duration::ZERO),
+        );
+
+        ws.process_message(tungstenite::Message::Text(welcome(None)))
+            .await
+            .unwrap();
+
+        let saved = fs::read_to_string(&token_file).unwrap();
+        let _ = fs::remove_file(&token_file);
+
+        assert_eq!(ws.token.access_token.as_str(), "refreshed-access");
+        assert!(saved.contains("refreshed-access"));
+        assert_eq!(
+            http.requests().first().map(|request| request.path.as_str()),
+            Some("/oauth2/token")
+        );
+    }
+
+    #[tokio::test]
+    async fn run_reports_the_close_reason_of_twitch() {
+        let http = twitch_helix();
+        let (mut ws, _outbox) = client(&http, vec![welcome(None)]);
+
+        ws.transport
+            .script
+            .push_back(tungstenite::Message::Close(Some(CloseFrame {
+                code: 4003.into(),
+                reason: "connection unused".into(),
+            })));
+
+        let error = ws.run().await.unwrap_err();
 
-        self.events_list.add_subscriber(subscriber).await;
+        assert_eq!(error.close_reason(), Some(CloseReason::ConnectionUnused));
+        assert_eq!(CloseReason::from_code(1000), CloseReason::Other(1000));
     }
 }
@@ -0,0 +1,265 @@
+//! Prize wheel spun by a channel point redemption or `!spin`.
+//!
+//! The prizes are configured in `wheel.json`, e.g.
+//!
+//! ```json
+//! [
+//!   { "label": "VIP for a day", "weight": 1 },
+//!   { "label": "Song request", "weight": 5 }
+//! ]
+//! ```
+//!
+//! The redemption of the `HEWPME_WHEEL_REWARD` reward spins the wheel, `!spin` costs
+//! `HEWPME_WHEEL_COST` loyalty points. The prize is picked by the bot: the first 8 bytes
+//! of SHA-256 of a random seed as a big-endian number modulo the total weight fall on
+//! one of the prizes in order. Every spin is kept with its seed and prizes in
+//! `wheel_spins.json` so that it can be checked, the `/wheel` overlay only animates it.
+//!
+//! Requires the following permissions:
+//! - channel:read:redemptions
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use std::{fmt, io};
+
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+use crate::helper::BotState;
+use crate::import::POINTS_FILE_NAME;
+use crate::overlay::{self, OverlayEvent};
+use crate::storage::JsonStore;
+use crate::{config, i18n, redact};
+
+const PRIZES_FILE_NAME: &str = "wheel.json";
+const SPINS_FILE_NAME: &str = "wheel_spins.json";
+const MAX_LOGGED_SPINS: usize = 500;
+const SEED_LEN: usize = 16;
+/// The result is announced in the chat once the overlay stops the wheel
+const SPIN_DURATION: Duration = Duration::from_secs(8);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Prize {
+    pub label: String,
+    /// Chance relative to the other prizes
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpinSource {
+    Reward,
+    Points,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Spin {
+    pub user_id: String,
+    pub user_name: String,
+    pub source: SpinSource,
+    /// Hex of the random seed
+    pub seed: String,
+    /// Prizes at the time of the spin
+    pub prizes: Vec<Prize>,
+    /// Index of the prize won
+    pub index: usize,
+    pub spun_at: DateTime<Utc>,
+}
+
+impl Spin {
+    #[must_use]
+    pub fn prize(&self) -> &str {
+        self.prizes[self.index].label.as_str()
+    }
+}
+
+#[derive(Debug)]
+pub enum SpinError {
+    NoPrizes,
+    NotEnoughPoints { balance: u64 },
+    Save(io::Error),
+}
+
+impl fmt::Display for SpinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpinError::NoPrizes => write!(f, "the wheel has no prizes"),
+            SpinError::NotEnoughPoints { balance } => {
+                write!(f, "not enough points, the balance is {balance}")
+            }
+            SpinError::Save(e) => write!(f, "unable to save the spin: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SpinError {}
+
+/// Spin the wheel for the user, the points are taken if the spin is paid with them
+///
+/// # Errors
+///
+/// Will return `Err` if there are no prizes, the user has not enough points or the
+/// spin cannot be saved
+pub async fn spin(
+    state: &BotState,
+    user_id: &str,
+    user_login: &str,
+    user_name: &str,
+    source: SpinSource,
+) -> Result<Spin, SpinError> {
+    let prizes = JsonStore::<Vec<Prize>>::open(PRIZES_FILE_NAME).to_vec();
+    let seed: [u8; SEED_LEN] = rand::random();
+    let index = pick(&seed, &prizes).ok_or(SpinError::NoPrizes)?;
+
+    if source == SpinSource::Points {
+        take_points(user_login, config::get_wheel_cost())?;
+    }
+
+    let spin = Spin {
+        user_id: user_id.to_string(),
+        user_name: user_name.to_string(),
+        source,
+        seed: seed.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        }),
+        prizes,
+        index,
+        spun_at: Utc::now(),
+    };
+
+    JsonStore::<Vec<Spin>>::open(SPINS_FILE_NAME)
+        .try_update(|spins| {
+            spins.push(spin.clone());
+
+            let overflow = spins.len().saturating_sub(MAX_LOGGED_SPINS);
+
+            spins.drain(..overflow);
+        })
+        .map_err(SpinError::Save)?;
+
+    tracing::info!(
+        "{} spun the wheel: {}",
+        redact::Name(user_name),
+        spin.prize()
+    );
+    overlay::push(
+        &state.overlay,
+        OverlayEvent::WheelSpin {
+            user_name: user_name.to_string(),
+            prizes: spin.prizes.clone(),
+            index,
+            duration_ms: u64::try_from(SPIN_DURATION.as_millis()).unwrap_or(u64::MAX),
+        },
+    );
+
+    let text = i18n::render(
+        state.languages.channel(),
+        "wheel.result",
+        &[("name", &user_name), ("prize", &spin.prize())],
+    );
+    let announcer = state.clone();
+
+    tokio::spawn(async move {
+        tokio::time::sleep(SPIN_DURATION).await;
+        announcer.say(text);
+    });
+
+    Ok(spin)
+}
+
+/// Logged spins, newest first
+#[must_use]
+pub fn spins() -> Vec<Spin> {
+    let mut spins = JsonStore::<Vec<Spin>>::open(SPINS_FILE_NAME).to_vec();
+
+    spins.reverse();
+    spins
+}
+
+/// Remove the user from the logged spins, returns how many were removed
+pub fn forget(user_id: &str) -> usize {
+    JsonStore::<Vec<Spin>>::open(SPINS_FILE_NAME).update(|spins| {
+        let len = spins.len();
+
+        spins.retain(|spin| spin.user_id != user_id);
+        len - spins.len()
+    })
+}
+
+/// Index of the prize the seed falls on, `None` if no prize has a weight
+fn pick(seed: &[u8], prizes: &[Prize]) -> Option<usize> {
+    let total = prizes
+        .iter()
+        .map(|prize| u64::from(prize.weight))
+        .sum::<u64>();
+
+    if total == 0 {
+        return None;
+    }
+
+    let digest = digest::digest(&digest::SHA256, seed);
+    let mut bytes = [0; 8];
+
+    bytes.copy_from_slice(&digest.as_ref()[..8]);
+
+    let mut roll = u64::from_be_bytes(bytes) % total;
+
+    prizes.iter().position(|prize| {
+        let weight = u64::from(prize.weight);
+
+        if roll < weight {
+            true
+        } else {
+            roll -= weight;
+            false
+        }
+    })
+}
+
+/// The points are kept by login, like the imported ones
+fn take_points(login: &str, cost: u64) -> Result<(), SpinError> {
+    JsonStore::<BTreeMap<String, u64>>::open(POINTS_FILE_NAME)
+        .try_update(|points| {
+            let balance = points.get(login).copied().unwrap_or_default();
+
+            if balance < cost {
+                return Err(SpinError::NotEnoughPoints { balance });
+            }
+
+            points.insert(login.to_string(), balance - cost);
+
+            Ok(())
+        })
+        .map_err(SpinError::Save)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prize(label: &str, weight: u32) -> Prize {
+        Prize {
+            label: label.to_string(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn seed_picks_the_same_prize() {
+        let prizes = [prize("VIP", 1), prize("nothing", 0), prize("song", 5)];
+        let seed = [42; SEED_LEN];
+        let index = pick(&seed, &prizes).unwrap();
+
+        assert_eq!(pick(&seed, &prizes), Some(index));
+        assert_ne!(index, 1);
+        assert_eq!(pick(&seed, &[prize("nothing", 0)]), None);
+        assert_eq!(pick(&seed, &[]), None);
+    }
+}
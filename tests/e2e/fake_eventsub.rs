@@ -0,0 +1,193 @@
+//! EventSub websocket server greeting every connection with a session welcome.
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+pub const SESSION_ID: &str = "e2e-session";
+
+#[derive(Clone)]
+pub struct FakeEventSub {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    addr: SocketAddr,
+    /// Messages to the latest connection of the bot
+    current: Mutex<Option<mpsc::UnboundedSender<String>>>,
+    /// Request URIs of the connections, in order
+    connections: Mutex<Vec<String>>,
+}
+
+impl FakeEventSub {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let eventsub = FakeEventSub {
+            inner: Arc::new(Inner {
+                addr: listener.local_addr().unwrap(),
+                current: Mutex::new(None),
+                connections: Mutex::new(Vec::new()),
+            }),
+        };
+        let server = eventsub.clone();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(server.clone().serve(socket));
+            }
+        });
+
+        eventsub
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}/ws", self.inner.addr)
+    }
+
+    pub fn connections(&self) -> Vec<String> {
+        self.inner.connections.lock().unwrap().clone()
+    }
+
+    /// Send a message to the latest connection
+    pub fn send(&self, message: String) {
+        if let Some(ref tx) = *self.inner.current.lock().unwrap() {
+            let _ = tx.send(message);
+        }
+    }
+
+    // the error response of the handshake callback is defined by tungstenite
+    #[allow(clippy::result_large_err)]
+    async fn serve(self, socket: TcpStream) {
+        let mut uri = String::new();
+        let Ok(socket) =
+            tokio_tungstenite::accept_hdr_async(socket, |request: &Request, response: Response| {
+                uri = request.uri().to_string();
+                Ok(response)
+            })
+            .await
+        else {
+            return;
+        };
+        let (mut sink, mut stream) = socket.split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+
+        self.inner.connections.lock().unwrap().push(uri);
+        *self.inner.current.lock().unwrap() = Some(tx.clone());
+        let _ = tx.send(welcome());
+
+        let writer = tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if sink.send(Message::Text(message)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        while let Some(Ok(message)) = stream.next().await {
+            if message.is_close() {
+                break;
+            }
+        }
+
+        writer.abort();
+    }
+}
+
+/// Welcome of a new session, the keepalive is long enough for the fake to stay silent
+fn welcome() -> String {
+    json!({
+        "metadata": {
+            "message_id": "e2e-welcome",
+            "message_type": "session_welcome",
+            "message_timestamp": "2026-01-01T00:00:00.000000000Z"
+        },
+        "payload": {
+            "session": {
+                "id": SESSION_ID,
+                "status": "connected",
+                "connected_at": "2026-01-01T00:00:00.000000000Z",
+                "keepalive_timeout_seconds": 600,
+                "reconnect_url": null
+            }
+        }
+    })
+    .to_string()
+}
+
+/// Twitch moves the session to `reconnect_url`
+pub fn reconnect(reconnect_url: &str) -> String {
+    json!({
+        "metadata": {
+            "message_id": "e2e-reconnect",
+            "message_type": "session_reconnect",
+            "message_timestamp": "2026-01-01T00:00:00.000000000Z"
+        },
+        "payload": {
+            "session": {
+                "id": SESSION_ID,
+                "status": "reconnecting",
+                "connected_at": "2026-01-01T00:00:00.000000000Z",
+                "keepalive_timeout_seconds": null,
+                "reconnect_url": reconnect_url
+            }
+        }
+    })
+    .to_string()
+}
+
+pub fn follow(broadcaster_id: &str, user_id: &str, login: &str, display_name: &str) -> String {
+    notification(
+        "channel.follow",
+        "2",
+        &json!({ "broadcaster_user_id": broadcaster_id, "moderator_user_id": broadcaster_id }),
+        &json!({
+            "user_id": user_id,
+            "user_login": login,
+            "user_name": display_name,
+            "broadcaster_user_id": broadcaster_id,
+            "broadcaster_user_login": "hewpme_e2e",
+            "broadcaster_user_name": "Hewpme_E2E",
+            "followed_at": "2026-01-01T00:00:00.000000000Z"
+        }),
+    )
+}
+
+fn notification(event_type: &str, version: &str, condition: &Value, event: &Value) -> String {
+    json!({
+        "metadata": {
+            "message_id": format!("e2e-{event_type}-{}", event["user_id"].as_str().unwrap_or_default()),
+            "message_type": "notification",
+            "message_timestamp": "2026-01-01T00:00:00.000000000Z",
+            "subscription_type": event_type,
+            "subscription_version": version
+        },
+        "payload": {
+            "subscription": subscription(event_type, version, condition),
+            "event": event
+        }
+    })
+    .to_string()
+}
+
+/// Subscription of the session as Helix reports it
+pub fn subscription(event_type: &str, version: &str, condition: &Value) -> Value {
+    json!({
+        "id": format!("e2e-{event_type}"),
+        "status": "enabled",
+        "type": event_type,
+        "version": version,
+        "cost": 0,
+        "condition": condition,
+        "transport": {
+            "method": "websocket",
+            "session_id": SESSION_ID,
+            "connected_at": "2026-01-01T00:00:00.000000000Z"
+        },
+        "created_at": "2026-01-01T00:00:00.000000000Z"
+    })
+}
@@ -0,0 +1,139 @@
+//! Chat server speaking enough of the Twitch IRC to join the channel and exchange messages.
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Chatter the messages of the fake chat are sent from
+pub struct Viewer {
+    pub id: &'static str,
+    pub login: &'static str,
+    pub display_name: &'static str,
+    pub moderator: bool,
+}
+
+#[derive(Clone)]
+pub struct FakeIrc {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    addr: SocketAddr,
+    /// Lines to the latest connection of the bot
+    current: Mutex<Option<mpsc::UnboundedSender<String>>>,
+    /// Lines the bot sent on any of its connections
+    received: Mutex<Vec<String>>,
+    connections: AtomicUsize,
+    message_ids: AtomicUsize,
+}
+
+impl FakeIrc {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let irc = FakeIrc {
+            inner: Arc::new(Inner {
+                addr: listener.local_addr().unwrap(),
+                current: Mutex::new(None),
+                received: Mutex::new(Vec::new()),
+                connections: AtomicUsize::new(0),
+                message_ids: AtomicUsize::new(0),
+            }),
+        };
+        let server = irc.clone();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(server.clone().serve(socket));
+            }
+        });
+
+        irc
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.inner.addr
+    }
+
+    /// Connections the bot has opened so far
+    pub fn connections(&self) -> usize {
+        self.inner.connections.load(Ordering::Acquire)
+    }
+
+    pub fn received(&self) -> Vec<String> {
+        self.inner.received.lock().unwrap().clone()
+    }
+
+    /// Send a raw line to the latest connection, e.g. `:tmi.twitch.tv RECONNECT`
+    pub fn send(&self, line: String) {
+        if let Some(ref tx) = *self.inner.current.lock().unwrap() {
+            let _ = tx.send(line);
+        }
+    }
+
+    /// Post `text` to the chat of `channel` on behalf of `viewer`
+    pub fn say(&self, viewer: &Viewer, channel: &str, text: &str) {
+        let message_id = self.inner.message_ids.fetch_add(1, Ordering::AcqRel);
+        let (badges, moderator) = if viewer.moderator {
+            ("moderator/1", 1)
+        } else {
+            ("", 0)
+        };
+
+        self.send(format!(
+            "@badge-info=;badges={badges};color=#1E90FF;display-name={display_name};emotes=;\
+             flags=;id=e2e-message-{message_id};mod={moderator};room-id=1337;subscriber=0;\
+             tmi-sent-ts=1700000000000;turbo=0;user-id={id};user-type= \
+             :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #{channel} :{text}",
+            display_name = viewer.display_name,
+            id = viewer.id,
+            login = viewer.login,
+        ));
+    }
+
+    async fn serve(self, socket: TcpStream) {
+        let (read, mut write) = socket.into_split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let writer = tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if write
+                    .write_all(format!("{line}\r\n").as_bytes())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        let mut lines = BufReader::new(read).lines();
+        let mut nick = String::new();
+
+        *self.inner.current.lock().unwrap() = Some(tx.clone());
+        self.inner.connections.fetch_add(1, Ordering::AcqRel);
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let mut words = line.split(' ');
+
+            match (words.next(), words.next()) {
+                (Some("NICK"), Some(name)) => nick = name.to_string(),
+                (Some("PING"), _) => {
+                    let _ = tx.send(String::from(
+                        ":tmi.twitch.tv PONG tmi.twitch.tv :tmi.twitch.tv",
+                    ));
+                }
+                (Some("JOIN"), Some(channel)) => {
+                    let _ = tx.send(format!(
+                        ":{nick}!{nick}@{nick}.tmi.twitch.tv JOIN {channel}"
+                    ));
+                }
+                _ => {}
+            }
+
+            self.inner.received.lock().unwrap().push(line);
+        }
+
+        writer.abort();
+    }
+}
@@ -0,0 +1,178 @@
+//! The bot started once per test binary against the fake services.
+use std::future::Future;
+use std::net::TcpListener;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use std::{env, fs, thread};
+
+use chrono::Utc;
+use hewpme::utils::token::Token;
+use tokio::runtime::Runtime;
+use twitch_oauth2::{AccessToken, RefreshToken};
+use wiremock::MockServer;
+
+use crate::fake_eventsub::FakeEventSub;
+use crate::fake_irc::FakeIrc;
+use crate::helix;
+
+/// Longest wait for the bot to react
+const TIMEOUT: Duration = Duration::from_secs(20);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Stack of the main thread the binary runs the bot on, the futures of a debug build
+/// do not fit into the default stack of a spawned thread
+const BOT_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+static HARNESS: OnceLock<Harness> = OnceLock::new();
+/// The bot is shared, the tests take turns so that a reconnect does not drop the
+/// messages of another test
+static TURN: Mutex<()> = Mutex::new(());
+
+pub struct Harness {
+    runtime: Runtime,
+    pub helix: MockServer,
+    pub irc: FakeIrc,
+    pub eventsub: FakeEventSub,
+    web_url: String,
+}
+
+/// The started bot and the turn of the calling test
+pub fn start() -> (&'static Harness, MutexGuard<'static, ()>) {
+    let turn = TURN.lock().unwrap_or_else(PoisonError::into_inner);
+
+    (HARNESS.get_or_init(Harness::start), turn)
+}
+
+impl Harness {
+    fn start() -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (helix, irc, eventsub) = runtime.block_on(async {
+            (
+                helix::start().await,
+                FakeIrc::start().await,
+                FakeEventSub::start().await,
+            )
+        });
+        let web_port = free_port();
+        let app_dir = env::temp_dir().join(format!("hewpme-e2e-{}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&app_dir);
+        fs::create_dir_all(&app_dir).unwrap();
+
+        for (variable, value) in [
+            ("HEWPME_APP_DIR", app_dir.display().to_string()),
+            ("TWITCH_CHANNEL", crate::CHANNEL.to_string()),
+            ("TWITCH_CLIENT_ID", crate::CLIENT_ID.to_string()),
+            ("TWITCH_CLIENT_SECRET", String::from("e2e-client-secret")),
+            ("HEWPME_BOT_ACCOUNT", crate::BOT_ACCOUNT.to_string()),
+            ("HEWPME_PORT", web_port.to_string()),
+            ("HEWPME_EVENTSUB_URL", eventsub.url()),
+            ("HEWPME_IRC_ADDR", irc.addr().to_string()),
+            ("TWITCH_HELIX_URL", format!("{}/helix/", helix.uri())),
+            ("TWITCH_OAUTH2_URL", format!("{}/oauth2/", helix.uri())),
+        ] {
+            env::set_var(variable, value);
+        }
+
+        save_token(&hewpme::config::get_eventsub_config_file(), "e2e-eventsub");
+        save_token(&hewpme::config::get_chat_config_file(), "e2e-chat");
+
+        thread::Builder::new()
+            .name(String::from("bot"))
+            .stack_size(BOT_STACK_SIZE)
+            .spawn(|| hewpme::Bot::new().run())
+            .unwrap();
+
+        let harness = Harness {
+            runtime,
+            helix,
+            irc,
+            eventsub,
+            web_url: format!("http://127.0.0.1:{web_port}"),
+        };
+
+        harness.block_on(harness.ready());
+
+        harness
+    }
+
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Page or API response of the web server, empty if it cannot be fetched
+    pub async fn get(&self, path: &str) -> String {
+        match reqwest::get(format!("{}{path}", self.web_url)).await {
+            Ok(response) => response.text().await.unwrap_or_default(),
+            Err(_) => String::new(),
+        }
+    }
+
+    /// The chat is joined, the subscriptions are created and the web server answers
+    async fn ready(&self) {
+        eventually("the chat join", || async {
+            self.irc
+                .received()
+                .iter()
+                .any(|line| line == &format!("JOIN #{}", crate::CHANNEL))
+                .then_some(())
+        })
+        .await;
+        eventually("the EventSub subscriptions", || async {
+            (!helix::requests(&self.helix, "POST", "/helix/eventsub/subscriptions")
+                .await
+                .is_empty())
+            .then_some(())
+        })
+        .await;
+        eventually("the web server", || async {
+            (!self.get("/healthz").await.is_empty()).then_some(())
+        })
+        .await;
+    }
+}
+
+/// Poll `check` until it returns `Some`
+///
+/// # Panics
+///
+/// Will panic if `check` keeps returning `None` for longer than the timeout
+pub async fn eventually<T, F, Fut>(what: &str, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let deadline = Instant::now() + TIMEOUT;
+
+    loop {
+        if let Some(value) = check().await {
+            return value;
+        }
+
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Token valid for hours, the bot neither refreshes nor requests a new one
+fn save_token(path: &std::path::Path, access_token: &str) {
+    let token = Token {
+        access_token: AccessToken::from(access_token),
+        refresh_token: Some(RefreshToken::from(format!("{access_token}-refresh"))),
+        created_at: Utc::now(),
+        valid_till: Utc::now() + chrono::Duration::hours(4),
+        scopes: None,
+    };
+
+    fs::write(path, serde_json::to_string(&token).unwrap()).unwrap();
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
@@ -0,0 +1,180 @@
+//! Helix and OAuth endpoints the bot needs, served by wiremock.
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::fake_eventsub;
+
+pub const BROADCASTER_ID: &str = "1337";
+
+/// Users Helix knows: the ID, the login and the display name
+const USERS: [(&str, &str, &str); 2] = [
+    (BROADCASTER_ID, crate::CHANNEL, "Hewpme_E2E"),
+    ("5100", "e2e_friend", "E2e_Friend"),
+];
+
+pub async fn start() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/oauth2/validate"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "client_id": crate::CLIENT_ID,
+            "login": crate::CHANNEL,
+            "scopes": [
+                "moderator:read:followers",
+                "moderator:read:chatters",
+                "moderator:manage:banned_users",
+                "moderator:manage:chat_settings",
+                "channel:read:subscriptions",
+                "channel:manage:redemptions",
+                "clips:edit",
+                "user:manage:whispers",
+                "moderator:manage:shoutouts",
+                "bits:read",
+                "channel:read:ads",
+                "channel:manage:raids",
+                "moderator:manage:announcements",
+                "chat:read",
+                "chat:edit"
+            ],
+            "user_id": BROADCASTER_ID,
+            "expires_in": 5_520_838
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/helix/users"))
+        .respond_with(Users)
+        .mount(&server)
+        .await;
+    Mock::given(path("/helix/eventsub/subscriptions"))
+        .respond_with(Subscriptions::default())
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/helix/streams"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [],
+            "pagination": {}
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/helix/chat/shoutouts"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/helix/moderation/bans"))
+        .respond_with(Bans)
+        .mount(&server)
+        .await;
+
+    server
+}
+
+/// Requests the bot has sent to `path`
+pub async fn requests(server: &MockServer, method: &str, path: &str) -> Vec<Request> {
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|request| request.method.as_str() == method && request.url.path() == path)
+        .collect()
+}
+
+/// Get Users looked up in [`USERS`] by the `id` and `login` queries
+struct Users;
+
+impl Respond for Users {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let data = request
+            .url
+            .query_pairs()
+            .filter_map(|(key, value)| {
+                USERS.iter().find(|(id, login, _)| match key.as_ref() {
+                    "id" => *id == value,
+                    "login" => login.eq_ignore_ascii_case(&value),
+                    _ => false,
+                })
+            })
+            .map(|(id, login, display_name)| {
+                json!({
+                    "id": id,
+                    "login": login,
+                    "display_name": display_name,
+                    "type": "",
+                    "broadcaster_type": "",
+                    "description": "",
+                    "profile_image_url": "",
+                    "offline_image_url": "",
+                    "view_count": 0,
+                    "created_at": "2016-12-14T20:32:28Z"
+                })
+            })
+            .collect::<Vec<_>>();
+
+        ResponseTemplate::new(200).set_body_json(json!({ "data": data }))
+    }
+}
+
+/// Accepts every subscription and lists all of them as enabled
+#[derive(Default)]
+struct Subscriptions {
+    created: Mutex<Vec<Value>>,
+}
+
+impl Respond for Subscriptions {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut created = self.created.lock().unwrap();
+
+        if request.method.as_str() == "POST" {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            let subscription = fake_eventsub::subscription(
+                body["type"].as_str().unwrap(),
+                body["version"].as_str().unwrap(),
+                &body["condition"],
+            );
+
+            created.push(subscription.clone());
+
+            ResponseTemplate::new(202).set_body_json(json!({
+                "data": [subscription],
+                "total": 1,
+                "total_cost": 0,
+                "max_total_cost": 10
+            }))
+        } else {
+            ResponseTemplate::new(200).set_body_json(json!({
+                "data": *created,
+                "total": created.len(),
+                "total_cost": 0,
+                "max_total_cost": 10,
+                "pagination": {}
+            }))
+        }
+    }
+}
+
+/// Ban User answering with the ban of the requested user
+struct Bans;
+
+impl Respond for Bans {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{
+                "broadcaster_id": BROADCASTER_ID,
+                "moderator_id": BROADCASTER_ID,
+                "user_id": body["data"]["user_id"],
+                "created_at": "2026-01-01T00:00:00Z",
+                "end_time": "2026-01-01T00:00:01Z"
+            }]
+        }))
+    }
+}
@@ -0,0 +1,208 @@
+//! End-to-end tests of the bot against fake Twitch services.
+//!
+//! The bot runs through [`hewpme::Bot::run`] like the binary does: Helix and OAuth are
+//! served by wiremock, the EventSub websocket and the IRC chat by the local servers of
+//! [`fake_eventsub`] and [`fake_irc`]. The config is read from the environment, so the
+//! bot is started once per test binary and the tests take turns on it.
+mod fake_eventsub;
+mod fake_irc;
+mod harness;
+mod helix;
+
+use serde_json::Value;
+
+use crate::fake_irc::Viewer;
+use crate::harness::eventually;
+
+const CHANNEL: &str = "hewpme_e2e";
+const CLIENT_ID: &str = "e2e-client-id";
+const BOT_ACCOUNT: &str = "hewpme_e2e_bot";
+
+const CHATTER: Viewer = Viewer {
+    id: "5001",
+    login: "e2e_chatter",
+    display_name: "E2e_Chatter",
+    moderator: false,
+};
+const MODERATOR: Viewer = Viewer {
+    id: "5002",
+    login: "e2e_moderator",
+    display_name: "E2e_Moderator",
+    moderator: true,
+};
+const VANISHER: Viewer = Viewer {
+    id: "5003",
+    login: "e2e_vanisher",
+    display_name: "E2e_Vanisher",
+    moderator: false,
+};
+const RETURNING_CHATTER: Viewer = Viewer {
+    id: "5004",
+    login: "e2e_returning",
+    display_name: "E2e_Returning",
+    moderator: false,
+};
+
+#[test]
+fn credits_list_the_chatters_and_the_followers() {
+    let (harness, _turn) = harness::start();
+
+    harness.block_on(async {
+        harness
+            .irc
+            .say(&CHATTER, CHANNEL, "hello from the e2e chat");
+        harness.eventsub.send(fake_eventsub::follow(
+            helix::BROADCASTER_ID,
+            "6001",
+            "e2e_follower",
+            "E2e_Follower",
+        ));
+
+        eventually("the chatter and the follower in the credits", || async {
+            let credits = harness.get("/").await;
+
+            (credits.contains("E2e_Chatter") && credits.contains("E2e_Follower")).then_some(())
+        })
+        .await;
+    });
+}
+
+#[test]
+fn shoutout_of_a_moderator_is_sent_to_helix() {
+    let (harness, _turn) = harness::start();
+
+    harness.block_on(async {
+        harness.irc.say(&MODERATOR, CHANNEL, "!so e2e_friend");
+
+        let shoutout = eventually("the shoutout request", || async {
+            helix::requests(&harness.helix, "POST", "/helix/chat/shoutouts")
+                .await
+                .pop()
+        })
+        .await;
+        let query = shoutout
+            .url
+            .query_pairs()
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+
+        assert!(query.contains(&(
+            String::from("from_broadcaster_id"),
+            helix::BROADCASTER_ID.to_string()
+        )));
+        assert!(query.contains(&(String::from("to_broadcaster_id"), String::from("5100"))));
+
+        eventually("the shoutout message in the chat", || async {
+            harness
+                .irc
+                .received()
+                .iter()
+                .any(|line| {
+                    line.contains(&format!("PRIVMSG #{CHANNEL} :")) && line.contains("e2e_friend")
+                })
+                .then_some(())
+        })
+        .await;
+    });
+}
+
+#[test]
+fn vanish_times_the_viewer_out_on_helix() {
+    let (harness, _turn) = harness::start();
+
+    harness.block_on(async {
+        harness.irc.say(&VANISHER, CHANNEL, "!vanish");
+
+        let ban = eventually("the timeout request", || async {
+            helix::requests(&harness.helix, "POST", "/helix/moderation/bans")
+                .await
+                .into_iter()
+                .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+                .find(|body| body["data"]["user_id"] == VANISHER.id)
+        })
+        .await;
+
+        assert!(ban["data"]["duration"].as_u64().is_some());
+    });
+}
+
+#[test]
+fn eventsub_follows_the_session_to_the_reconnect_url() {
+    let (harness, _turn) = harness::start();
+
+    harness.block_on(async {
+        let connections = harness.eventsub.connections().len();
+        let reconnect_url = format!("{}?reconnect={connections}", harness.eventsub.url());
+
+        harness
+            .eventsub
+            .send(fake_eventsub::reconnect(&reconnect_url));
+
+        let uri = eventually("the connection to the reconnect URL", || async {
+            harness.eventsub.connections().get(connections).cloned()
+        })
+        .await;
+
+        assert_eq!(uri, format!("/ws?reconnect={connections}"));
+
+        // the events of the moved session still reach the credits
+        harness.eventsub.send(fake_eventsub::follow(
+            helix::BROADCASTER_ID,
+            "6002",
+            "e2e_moved_follower",
+            "E2e_Moved_Follower",
+        ));
+        eventually(
+            "the follower of the moved session in the credits",
+            || async {
+                harness
+                    .get("/")
+                    .await
+                    .contains("E2e_Moved_Follower")
+                    .then_some(())
+            },
+        )
+        .await;
+    });
+}
+
+#[test]
+fn chat_rejoins_after_a_reconnect_request() {
+    let (harness, _turn) = harness::start();
+
+    harness.block_on(async {
+        let connections = harness.irc.connections();
+
+        harness.irc.send(String::from(":tmi.twitch.tv RECONNECT"));
+
+        eventually("a new chat connection", || async {
+            (harness.irc.connections() > connections).then_some(())
+        })
+        .await;
+        eventually("the join of the new connection", || async {
+            harness
+                .irc
+                .received()
+                .iter()
+                .filter(|line| *line == &format!("JOIN #{CHANNEL}"))
+                .nth(connections)
+                .map(drop)
+        })
+        .await;
+
+        harness
+            .irc
+            .say(&RETURNING_CHATTER, CHANNEL, "back after the reconnect");
+        eventually(
+            "the chatter of the new connection in the credits",
+            || async {
+                harness
+                    .get("/")
+                    .await
+                    .contains("E2e_Returning")
+                    .then_some(())
+            },
+        )
+        .await;
+    });
+}